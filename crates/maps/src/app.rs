@@ -9,8 +9,17 @@ use serde::{Deserialize, Serialize};
 use strum_macros::{Display, EnumString, VariantNames};
 
 pub use crate::app_impl::canvas_settings::CanvasOptions;
+pub use crate::app_impl::georef_settings::GeoMarker;
+pub use crate::app_impl::map_export::ExportOptions;
+pub use crate::app_impl::minimap::MinimapOptions;
 pub use crate::app_impl::pose_edit::PoseEditOptions;
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::app_impl::recent_files::RecentFilesState;
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::app_impl::session_import::SessionImportState;
+pub use crate::app_impl::statistics_window::StatisticsState;
 pub use crate::app_impl::tint_settings::TintOptions;
+pub use crate::app_impl::viewpoints::{Viewpoint, ViewpointTransition};
 pub use crate::error::{Error, Result};
 pub use crate::grid_options::GridOptions;
 pub use crate::lens::LensOptions;
@@ -18,13 +27,18 @@ pub use maps_io_ros::ColorMap;
 
 use crate::app_impl::CUSTOM_TITLEBAR_SUPPORTED;
 use crate::app_impl::central_panel::default_lens_window_size;
+use crate::app_impl::constants::PYRAMID_POLL_INTERVAL;
 use crate::draw_order::DrawOrder;
-use crate::map_state::MapState;
+use crate::map_state::{MapState, MissingMap};
 use crate::persistence::{PersistenceOptions, save_app_options};
+use crate::recent_files::RecentFiles;
 use crate::tiles::Tiles;
 use crate::tracing::Tracing;
-use maps_io_ros::Meta;
+use maps_io_ros::{GeoReference, Meta};
+use maps_rendering::image_pyramid::default_streaming_threshold;
 use maps_rendering::render_options::default_crop_threshold;
+use maps_rendering::texture_budget::default_texture_budget_mb;
+use maps_rendering::{PyramidLevels, TextureBudget};
 
 #[cfg(target_arch = "wasm32")]
 use crate::wasm::async_data::AsyncData;
@@ -58,6 +72,8 @@ pub enum ActiveTool {
     HoverLens,
     PlaceLens,
     Measure,
+    PlaceGeoMarker,
+    SelectRegion,
 }
 
 #[derive(Clone, Debug, Default, PartialEq)]
@@ -75,6 +91,12 @@ pub struct CollapsedState {
     pub lens_settings: bool,
     pub grid_settings: bool,
     pub tool_settings: bool,
+    #[serde(default)]
+    pub georef_settings: bool,
+    #[serde(default)]
+    pub map_export: bool,
+    #[serde(default)]
+    pub viewpoints: bool,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
@@ -106,10 +128,19 @@ pub struct AppOptions {
     pub active_movable: ActiveMovable,
     #[serde(skip)]
     pub active_tool: ActiveTool,
+    #[serde(skip)]
+    pub export: ExportOptions,
     #[serde(default)]
     pub collapsed: CollapsedState,
     #[serde(default)]
     pub display: DisplayOptions,
+    /// Files of user-defined colormaps that are loaded on startup.
+    #[serde(default)]
+    pub custom_colormaps: Vec<PathBuf>,
+    #[serde(default)]
+    pub recent_files: RecentFiles,
+    #[serde(default)]
+    pub minimap: MinimapOptions,
 }
 
 impl AppOptions {
@@ -140,11 +171,14 @@ pub struct StatusInfo {
     pub hover_position: Option<egui::Pos2>,
     pub quit_modal_active: bool,
     pub debug_window_active: bool,
+    pub statistics_window_active: bool,
+    pub missing_maps_window_active: bool,
     pub draw_order_edit_active: bool,
     pub unsaved_changes: bool,
     pub quit_after_save: bool,
     pub move_action: Option<String>,
     pub active_tool: Option<String>,
+    pub viewpoint_transition: Option<ViewpointTransition>,
 }
 
 #[derive(Default, Serialize, Deserialize)]
@@ -154,6 +188,17 @@ pub struct SessionData {
     #[serde(default)]
     pub draw_order: DrawOrder,
     pub grid_lenses: HashMap<String, egui::Pos2>,
+    #[serde(default)]
+    pub georeference: Option<GeoReference>,
+    #[serde(default)]
+    pub geo_markers: Vec<GeoMarker>,
+    /// Saved views of the grid, see [`Viewpoint`].
+    #[serde(default)]
+    pub viewpoints: Vec<Viewpoint>,
+    /// Maps of loaded sessions that could not be loaded.
+    /// They are written back when the session is saved, see [`crate::persistence::session_to_toml`].
+    #[serde(skip)]
+    pub missing_maps: BTreeMap<String, MissingMap>,
 
     #[cfg(target_arch = "wasm32")]
     #[serde(skip)]
@@ -170,10 +215,24 @@ pub struct SessionData {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AdvancedOptions {
     /// Threshold for cropping large textures in the main grid.
-    /// Too low values cause unnecessary cropping (CPU overhead),
+    /// Larger textures are shown as tiles of the visible region.
+    /// Too low values cause unnecessary tiling (CPU overhead),
     /// too high values lead to too high texture memory usage.
     #[serde(default = "default_crop_threshold")]
     pub grid_crop_threshold: u32,
+    /// Memory budget for the textures of all maps, grids and lenses in MiB.
+    /// Least recently used textures are freed when it's exceeded.
+    #[serde(default = "default_texture_budget_mb")]
+    pub texture_budget_mb: u32,
+    /// Cache the image pyramid levels of maps on disk, see [`crate::persistence::pyramid_cache_dir`].
+    /// Speeds up reopening sessions with large maps.
+    #[serde(default)]
+    pub pyramid_disk_cache: bool,
+    /// Size in pixels of the longer image side from which on map images are read
+    /// region by region from their file, instead of being loaded into memory completely.
+    /// Only applies to uncompressed PGM/PPM and TIFF files.
+    #[serde(default = "default_streaming_threshold")]
+    pub streaming_threshold: u32,
     /// Dry run mode. E.g. to not actually load images (e.g. in --init-only mode of CLI).
     #[serde(skip)]
     pub dry_run: bool,
@@ -186,6 +245,9 @@ impl Default for AdvancedOptions {
     fn default() -> Self {
         Self {
             grid_crop_threshold: default_crop_threshold(),
+            texture_budget_mb: default_texture_budget_mb(),
+            pyramid_disk_cache: false,
+            streaming_threshold: default_streaming_threshold(),
             dry_run: false,
             lens_window_size: default_lens_window_size(),
         }
@@ -202,6 +264,15 @@ pub struct AppState {
     pub tracing: Tracing,
     pub last_file_dir: Option<PathBuf>,
     pub tile_manager: Tiles,
+    pub statistics: StatisticsState,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub session_import: SessionImportState,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub recent_files: RecentFilesState,
+    /// Size of the pyramid disk cache in bytes and when it was determined,
+    /// to not scan the cache directory in every frame.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) pyramid_cache_size: Option<(std::time::Instant, u64)>,
 }
 
 impl AppState {
@@ -211,6 +282,8 @@ impl AppState {
             ..Default::default()
         };
         state.data.version = Some(state.options.version.clone());
+        #[cfg(not(target_arch = "wasm32"))]
+        state.register_custom_colormaps();
 
         let mut _default_dir = None;
         for meta in metas {
@@ -218,7 +291,7 @@ impl AppState {
             // this is usually more handy than cwd when file are passed via CLI.
             _default_dir = absolute(meta.yaml_path.parent().expect("No parent dir?")).ok();
 
-            state.load_map(meta)?;
+            state.load_map(meta, PyramidLevels::default())?;
         }
         for map in state.data.maps.values_mut() {
             map.tint = Some(state.options.tint_settings.tint_for_all);
@@ -247,6 +320,7 @@ impl eframe::App for AppState {
 
         ui.ctx()
             .set_theme(self.options.canvas_settings.theme_preference);
+        TextureBudget::update(ui.ctx(), self.options.advanced.texture_budget_mb);
 
         let mut central_rect = egui::Rect::ZERO;
 
@@ -263,10 +337,25 @@ impl eframe::App for AppState {
 
             self.info_window(ui);
             self.debug_window(ui);
+            self.statistics_window(ui);
+            self.missing_maps_window(ui);
+            #[cfg(not(target_arch = "wasm32"))]
+            self.session_import_window(ui);
         });
 
         self.handle_new_screenshot(ui.ctx(), &central_rect);
 
+        if self
+            .data
+            .maps
+            .values()
+            .any(|map| !map.image_pyramid.is_complete())
+        {
+            // Keep polling for pyramid levels that are built in the background,
+            // even if the menu with its spinners is collapsed.
+            ui.ctx().request_repaint_after(PYRAMID_POLL_INTERVAL);
+        }
+
         #[cfg(target_arch = "wasm32")]
         self.consume_wasm_io();
 
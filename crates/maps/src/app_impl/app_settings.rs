@@ -2,6 +2,16 @@ use eframe::egui;
 
 use crate::app::AppState;
 use crate::app_impl::ui_helpers::section_heading;
+#[cfg(not(target_arch = "wasm32"))]
+use {
+    crate::persistence::pyramid_cache_dir,
+    log::error,
+    maps_rendering::{PyramidDiskCache, pyramid_cache::DEFAULT_MAX_BYTES},
+};
+
+/// How often the size of the pyramid disk cache is determined while the settings are shown.
+#[cfg(not(target_arch = "wasm32"))]
+const CACHE_SIZE_REFRESH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
 
 impl AppState {
     pub(crate) fn app_settings(&mut self, ui: &mut egui::Ui) {
@@ -24,11 +34,106 @@ impl AppState {
                 .italics(),
         );
 
+        ui.end_row();
+        ui.label("Relative session paths").on_hover_text(
+            "Save map paths relative to the session file,\n\
+            such that sessions can be moved or shared together with the maps.",
+        );
+
+        #[cfg(not(target_arch = "wasm32"))]
+        ui.checkbox(&mut self.options.persistence.relative_session_paths, "");
+
+        #[cfg(target_arch = "wasm32")]
+        ui.label(
+            egui::RichText::new("Only supported in native builds.")
+                .weak()
+                .italics(),
+        );
+
         ui.end_row();
         ui.label("Show full paths").on_hover_text(
             "Show full absolute file paths in the UI.\n\
             If unchecked, only the file name is shown.",
         );
         ui.checkbox(&mut self.options.display.show_full_paths, "");
+
+        ui.end_row();
+        ui.label("Cache zoom levels").on_hover_text(
+            "Store the downscaled versions of map images on disk,\n\
+            such that reopening large maps doesn't generate them again.",
+        );
+
+        #[cfg(not(target_arch = "wasm32"))]
+        self.pyramid_cache_settings(ui);
+
+        #[cfg(target_arch = "wasm32")]
+        ui.label(
+            egui::RichText::new("Only supported in native builds.")
+                .weak()
+                .italics(),
+        );
+
+        ui.end_row();
+        ui.label("Stream images from").on_hover_text(
+            "Map images with a larger width or height are read region by region\n\
+            from their file instead of being loaded into memory completely.\n\
+            Only uncompressed PGM/PPM and TIFF files can be streamed.\n\
+            Applies to maps that are loaded afterwards.",
+        );
+
+        #[cfg(not(target_arch = "wasm32"))]
+        ui.add(
+            egui::DragValue::new(&mut self.options.advanced.streaming_threshold)
+                .range(1024..=u32::MAX)
+                .suffix(" px"),
+        );
+
+        #[cfg(target_arch = "wasm32")]
+        ui.label(
+            egui::RichText::new("Only supported in native builds.")
+                .weak()
+                .italics(),
+        );
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn pyramid_cache_settings(&mut self, ui: &mut egui::Ui) {
+        let Some(dir) = pyramid_cache_dir() else {
+            ui.label(
+                egui::RichText::new("No cache directory available.")
+                    .weak()
+                    .italics(),
+            );
+            return;
+        };
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.options.advanced.pyramid_disk_cache, "")
+                .on_hover_text(format!(
+                    "Cache directory: {}\nThe least recently used zoom levels are removed above {} GiB.",
+                    dir.display(),
+                    DEFAULT_MAX_BYTES / (1024 * 1024 * 1024)
+                ));
+            let cache = PyramidDiskCache::new(dir);
+            let size = match self.pyramid_cache_size {
+                Some((time, size)) if time.elapsed() < CACHE_SIZE_REFRESH_INTERVAL => size,
+                _ => {
+                    let size = cache.size_on_disk();
+                    self.pyramid_cache_size = Some((std::time::Instant::now(), size));
+                    size
+                }
+            };
+            let size_mb = size as f64 / (1024. * 1024.);
+            if ui
+                .add_enabled(size_mb > 0., egui::Button::new("Clear"))
+                .on_hover_text(format!("Delete the cached zoom levels ({size_mb:.1} MiB)."))
+                .clicked()
+            {
+                self.pyramid_cache_size = None;
+                if let Err(e) = cache.clear() {
+                    self.status.error = format!("Failed to clear the zoom level cache: {e}");
+                    error!("{}", self.status.error);
+                }
+            }
+        });
     }
 }
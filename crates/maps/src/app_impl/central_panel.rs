@@ -2,15 +2,25 @@ use eframe::egui;
 use log::{debug, error};
 use uuid::Uuid;
 
-use crate::app::{ActiveTool, AppState, ViewMode};
+use crate::app::{ActiveTool, AppState, GeoMarker, ViewMode};
 use crate::app_impl::constants::SPACE;
+use crate::app_impl::ui_helpers::display_path;
 use crate::grid::Grid;
 use crate::grid_options::{LineType, SubLineVisibility};
 use crate::lens::Lens;
+use crate::overlays::Overlays;
 use crate::tiles_behavior::MapsTreeBehavior;
 use maps_rendering::TextureRequest;
 
 const STACKED_TEXTURE_ID: &str = "stack";
+pub(crate) const GEO_MARKER_STROKE: egui::Stroke = egui::Stroke {
+    width: 2.,
+    color: egui::Color32::from_rgb(230, 60, 160),
+};
+const EXPORT_REGION_STROKE: egui::Stroke = egui::Stroke {
+    width: 2.,
+    color: egui::Color32::from_rgb(40, 170, 230),
+};
 
 pub(crate) fn default_lens_window_size() -> egui::Vec2 {
     egui::Vec2::splat(275.)
@@ -76,6 +86,7 @@ impl AppState {
     }
 
     fn show_grid(&mut self, ui: &mut egui::Ui) {
+        self.animate_viewpoint_transition(ui);
         let options = &mut self.options.grid;
 
         let grid = Grid::new(ui, "main_grid", options.scale)
@@ -85,7 +96,11 @@ impl AppState {
         // Handle input interaction and adapt mouse pointer to the active tool.
         if grid.response().hovered() {
             match self.options.active_tool {
-                ActiveTool::PlaceLens | ActiveTool::Measure | ActiveTool::HoverLens => {
+                ActiveTool::PlaceLens
+                | ActiveTool::Measure
+                | ActiveTool::HoverLens
+                | ActiveTool::PlaceGeoMarker
+                | ActiveTool::SelectRegion => {
                     ui.ctx().set_cursor_icon(egui::CursorIcon::Crosshair);
                 }
                 _ => {
@@ -113,6 +128,13 @@ impl AppState {
             ui.ctx()
                 .copy_text(format!("{{x: {:.2}, y: {:.2}, z: 0}}", pos.x, pos.y));
         }
+        self.show_geo_markers(&grid);
+        self.show_export_region(&grid);
+        self.show_overlays(&grid);
+        #[cfg(not(target_arch = "wasm32"))]
+        self.handle_svg_export_request(ui, &grid);
+        self.show_minimap(ui, &grid);
+        let options = &mut self.options.grid;
 
         if self.options.active_tool == ActiveTool::None {
             self.status.active_tool = None;
@@ -179,6 +201,99 @@ impl AppState {
         }
     }
 
+    fn show_geo_markers(&mut self, grid: &Grid) {
+        let Some(georef) = &self.data.georeference else {
+            if self.options.active_tool == ActiveTool::PlaceGeoMarker {
+                self.options.active_tool = ActiveTool::None;
+            }
+            return;
+        };
+        for marker in &self.data.geo_markers {
+            let pos = georef.wgs84_to_root(&marker.position);
+            grid.draw_marker(pos, &marker.name, GEO_MARKER_STROKE);
+        }
+
+        if self.options.active_tool != ActiveTool::PlaceGeoMarker {
+            return;
+        }
+        self.status.active_tool = Some("📍 Click to place a georeferenced marker".to_string());
+        if grid.response().clicked()
+            && let Some(pos) = self.status.hover_position
+        {
+            let marker = GeoMarker {
+                name: format!("Marker {}", self.data.geo_markers.len() + 1),
+                position: georef.to_wgs84(pos),
+            };
+            debug!("Placing geo marker {marker:?}.");
+            self.data.geo_markers.push(marker);
+            self.status.unsaved_changes = true;
+            self.options.active_tool = ActiveTool::None;
+        }
+    }
+
+    /// Draws the enabled overlays, they are also included in screenshots.
+    /// The overlays of the grid with the current options and maps,
+    /// drawn on screen and into SVG exports.
+    pub(crate) fn overlays(&self, points_per_meter: f32) -> Overlays<'_> {
+        let options = &self.options.grid;
+        let legend_entries = if options.overlays.legend {
+            self.data
+                .draw_order
+                .keys()
+                .iter()
+                .filter_map(|name| {
+                    let map = self.data.maps.get(name).filter(|map| map.visible)?;
+                    Some((
+                        display_path(name, self.options.display.show_full_paths),
+                        map.tint,
+                    ))
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        Overlays {
+            options,
+            points_per_meter,
+            // The heading is the angle of the x-axis counter-clockwise from east.
+            north_angle: self
+                .data
+                .georeference
+                .as_ref()
+                .map(|georef| std::f32::consts::FRAC_PI_2 - georef.heading),
+            legend_entries,
+        }
+    }
+
+    fn show_overlays(&self, grid: &Grid) {
+        grid.draw_overlays(&self.overlays(grid.points_per_meter));
+    }
+
+    fn show_export_region(&mut self, grid: &Grid) {
+        let export = &mut self.options.export;
+        if self.options.active_tool == ActiveTool::SelectRegion {
+            self.status.active_tool = Some("⬚ Click two corners of the export region".to_string());
+            if grid.response().clicked()
+                && let Some(pos) = self.status.hover_position
+            {
+                match export.region_start.take() {
+                    None => export.region_start = Some(pos),
+                    Some(start) => {
+                        export.region = Some(egui::Rect::from_two_pos(start, pos));
+                        self.options.active_tool = ActiveTool::None;
+                    }
+                }
+            }
+            if let (Some(start), Some(pos)) = (export.region_start, self.status.hover_position) {
+                grid.draw_rect(egui::Rect::from_two_pos(start, pos), EXPORT_REGION_STROKE);
+                return;
+            }
+        }
+        if let Some(region) = export.region {
+            grid.draw_rect(region, EXPORT_REGION_STROKE);
+        }
+    }
+
     pub(crate) fn show_grid_lens(
         &mut self,
         ui: &mut egui::Ui,
@@ -279,7 +394,10 @@ impl AppState {
                         ui.add_space(SPACE);
 
                         #[cfg(not(target_arch = "wasm32"))]
-                        self.load_session_button(ui);
+                        {
+                            self.load_session_button(ui);
+                            self.recent_files_load_screen(ui);
+                        }
                         #[cfg(target_arch = "wasm32")]
                         ui.add_enabled_ui(false, |ui| {
                             self.load_session_button(ui);
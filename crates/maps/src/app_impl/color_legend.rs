@@ -0,0 +1,111 @@
+//! Legend widget that explains the colors of a value interpretation.
+
+use eframe::egui;
+
+use maps_io_ros::value_interpretation::{Mode, ValueInterpretation};
+
+const GRADIENT_SIZE: egui::Vec2 = egui::vec2(160., 14.);
+const GRADIENT_STEPS: usize = 64;
+const SWATCH_SIZE: egui::Vec2 = egui::vec2(14., 14.);
+
+fn to_color32(color: image::Rgba<u8>) -> egui::Color32 {
+    let [r, g, b, a] = color.0;
+    egui::Color32::from_rgba_unmultiplied(r, g, b, a)
+}
+
+/// Paints a horizontal gradient of `color(t)` for `t` from 0 to 1.
+fn gradient_bar(ui: &mut egui::Ui, color: impl Fn(f32) -> egui::Color32) {
+    let (rect, _) = ui.allocate_exact_size(GRADIENT_SIZE, egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    let step_width = rect.width() / GRADIENT_STEPS as f32;
+    for i in 0..GRADIENT_STEPS {
+        let t = i as f32 / (GRADIENT_STEPS - 1) as f32;
+        let min = rect.left_top() + egui::vec2(i as f32 * step_width, 0.);
+        // Overlap by a bit to avoid seams between the steps.
+        let step = egui::Rect::from_min_size(min, egui::vec2(step_width + 0.5, rect.height()));
+        painter.rect_filled(step, 0., color(t));
+    }
+    painter.rect_stroke(
+        rect,
+        0.,
+        ui.visuals().widgets.noninteractive.bg_stroke,
+        egui::StrokeKind::Inside,
+    );
+}
+
+fn swatch(ui: &mut egui::Ui, color: image::Rgba<u8>, label: &str) {
+    let (rect, _) = ui.allocate_exact_size(SWATCH_SIZE, egui::Sense::hover());
+    ui.painter().rect_filled(rect, 0., to_color32(color));
+    ui.painter().rect_stroke(
+        rect,
+        0.,
+        ui.visuals().widgets.noninteractive.bg_stroke,
+        egui::StrokeKind::Inside,
+    );
+    ui.label(label);
+}
+
+/// Labels for the ends of the raw value range of an image.
+fn raw_range_labels(
+    value_interpretation: &ValueInterpretation,
+    image: Option<&image::DynamicImage>,
+) -> (String, String) {
+    if let Some(range) = value_interpretation.range
+        && image.is_none_or(maps_rendering::image::is_high_depth)
+    {
+        return (format!("{}", range.min), format!("{}", range.max));
+    }
+    match image.map(image::DynamicImage::color) {
+        Some(image::ColorType::Rgb32F | image::ColorType::Rgba32F) => ("0".into(), "1".into()),
+        Some(color) if color.bytes_per_pixel() > color.channel_count() => {
+            ("0".into(), "65535".into())
+        }
+        Some(_) => ("0".into(), "255".into()),
+        None => ("min".into(), "max".into()),
+    }
+}
+
+/// Shows how values are colored with the given interpretation and colormap.
+/// `image` is used to label the raw value range, `None` if unknown (e.g. for all maps).
+pub(crate) fn color_legend(
+    ui: &mut egui::Ui,
+    value_interpretation: &ValueInterpretation,
+    image: Option<&image::DynamicImage>,
+) {
+    let colormap = value_interpretation.colormap.get();
+    ui.vertical(|ui| match value_interpretation.mode {
+        Mode::Raw => {
+            let negate = value_interpretation.negate;
+            gradient_bar(ui, |t| {
+                to_color32(colormap.map_normalized(if negate { 1. - t } else { t }))
+            });
+            let (min, max) = raw_range_labels(value_interpretation, image);
+            ui.horizontal(|ui| {
+                ui.set_width(GRADIENT_SIZE.x);
+                ui.small(min);
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    ui.small(max);
+                });
+            });
+        }
+        Mode::Trinary => {
+            ui.horizontal(|ui| {
+                swatch(ui, colormap.map(0), "Free");
+                swatch(ui, colormap.map(100), "Occupied");
+                swatch(ui, colormap.map(255), "Unknown");
+            });
+        }
+        Mode::Scale => {
+            // Scale mode produces occupancy probabilities of 0..=100.
+            gradient_bar(ui, |t| to_color32(colormap.map((t * 100.).round() as u8)));
+            ui.horizontal(|ui| {
+                ui.set_width(GRADIENT_SIZE.x);
+                ui.small("Free");
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    ui.small("Occupied");
+                });
+            });
+            ui.horizontal(|ui| swatch(ui, colormap.map(255), "Unknown"));
+        }
+    });
+}
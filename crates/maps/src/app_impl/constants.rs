@@ -6,3 +6,7 @@ pub const ICON_SIZE: f32 = 20.;
 pub const CUSTOM_TITLEBAR_SUPPORTED: bool = cfg!(target_os = "macos");
 /// Indentation to make room for window controls when a custom titlebar is used.
 pub const HEADER_PANEL_INDENT: f32 = 75.; // 3 Mac buttons + padding
+/// Repaint interval while image pyramids are built in the background.
+pub const PYRAMID_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+/// Duration of the animated transition to a viewpoint, in seconds.
+pub const VIEWPOINT_TRANSITION_DURATION: f64 = 0.6;
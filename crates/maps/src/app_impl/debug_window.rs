@@ -1,6 +1,10 @@
 use eframe::egui;
 
 use crate::app::AppState;
+use maps_rendering::TextureBudget;
+use maps_rendering::texture_budget::MAX_TEXTURE_BUDGET_MB;
+
+const BYTES_PER_MB: f32 = 1024. * 1024.;
 
 impl AppState {
     pub(crate) fn debug_window(&mut self, ui: &mut egui::Ui) {
@@ -32,6 +36,38 @@ impl AppState {
                             ));
                             self.tracing.plot(ui);
                         });
+                    egui::CollapsingHeader::new("Texture budget")
+                        .default_open(true)
+                        .show(ui, |ui| {
+                            let usage = TextureBudget::usage(&ctx);
+                            ui.add(
+                                egui::ProgressBar::new(
+                                    usage.used_bytes as f32 / usage.max_bytes.max(1) as f32,
+                                )
+                                .text(format!(
+                                    "{:.1} / {:.0} MiB",
+                                    usage.used_bytes as f32 / BYTES_PER_MB,
+                                    usage.max_bytes as f32 / BYTES_PER_MB
+                                )),
+                            );
+                            ui.label(format!(
+                                "{} textures, {} evicted",
+                                usage.num_textures, usage.num_evicted
+                            ));
+                            ui.horizontal(|ui| {
+                                ui.label("Budget").on_hover_text(
+                                    "Least recently used textures are freed when the budget \
+                                    is exceeded, and uploaded again when needed.",
+                                );
+                                ui.add(
+                                    egui::DragValue::new(
+                                        &mut self.options.advanced.texture_budget_mb,
+                                    )
+                                    .range(64..=MAX_TEXTURE_BUDGET_MB)
+                                    .suffix(" MiB"),
+                                );
+                            });
+                        });
                     egui::CollapsingHeader::new("Textures")
                         .default_open(true)
                         .show(ui, |ui| {
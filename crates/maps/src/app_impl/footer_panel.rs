@@ -20,6 +20,17 @@ impl AppState {
         if let Some(pos) = self.status.hover_position {
             ui.label(format!("⌖ x: {:.3}m  y: {:.3}m", pos.x, pos.y,));
             ui.separator();
+            if let Some(georef) = &self.data.georeference
+                && self.options.view_mode == ViewMode::Aligned
+            {
+                let wgs84 = georef.to_wgs84(pos);
+                ui.label(format!(
+                    "🌐 {:.7}°  {:.7}°",
+                    wgs84.latitude, wgs84.longitude
+                ))
+                .on_hover_text("WGS84 latitude / longitude of the hover position.");
+                ui.separator();
+            }
         }
         if let Some(move_action) = &self.status.move_action
             && self.options.view_mode == ViewMode::Aligned
@@ -64,6 +75,14 @@ impl AppState {
                 self.options.help_visible = !self.options.help_visible;
             }
             ui.separator();
+            if ui
+                .add(egui::Button::new("📊").fill(ui.visuals().window_fill()))
+                .on_hover_text("Open the map statistics window.")
+                .clicked()
+            {
+                self.status.statistics_window_active = !self.status.statistics_window_active;
+            }
+            ui.separator();
             if log_enabled!(log::Level::Debug) {
                 if ui
                     .add(egui::Button::new("🛠").fill(ui.visuals().window_fill()))
@@ -0,0 +1,239 @@
+use std::f32::consts::PI;
+
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+
+use crate::app::AppState;
+use crate::app_impl::ui_helpers::section_heading;
+use maps_io_ros::GeoReference;
+use maps_io_ros::geo_reference::GeoAnchor;
+use maps_io_ros::utm::{Utm, Wgs84};
+
+#[cfg(not(target_arch = "wasm32"))]
+use {
+    crate::app_impl::ui_helpers::display_path,
+    log::{error, info},
+    maps_io_ros::geo_reference::WorldFile,
+};
+
+/// A named marker at a fixed geographic position.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct GeoMarker {
+    pub name: String,
+    pub position: Wgs84,
+}
+
+fn pick_wgs84(ui: &mut egui::Ui, wgs84: &mut Wgs84) {
+    ui.label("Latitude / longitude (°)");
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::DragValue::new(&mut wgs84.latitude)
+                .range(-90.0..=90.0)
+                .speed(1e-6)
+                .fixed_decimals(7),
+        );
+        ui.add(
+            egui::DragValue::new(&mut wgs84.longitude)
+                .range(-180.0..=180.0)
+                .speed(1e-6)
+                .fixed_decimals(7),
+        );
+    });
+}
+
+fn pick_utm(ui: &mut egui::Ui, utm: &mut Utm) {
+    ui.label("UTM zone");
+    ui.horizontal(|ui| {
+        ui.add(egui::DragValue::new(&mut utm.zone).range(1..=60));
+        ui.selectable_value(&mut utm.north, true, "N");
+        ui.selectable_value(&mut utm.north, false, "S");
+    });
+    ui.end_row();
+    ui.label("Easting / northing (m)");
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::DragValue::new(&mut utm.easting)
+                .speed(0.01)
+                .fixed_decimals(3),
+        );
+        ui.add(
+            egui::DragValue::new(&mut utm.northing)
+                .speed(0.01)
+                .fixed_decimals(3),
+        );
+    });
+}
+
+fn pick_anchor(ui: &mut egui::Ui, georef: &mut GeoReference) {
+    ui.label("Anchor").on_hover_text(
+        "Geographic coordinate of the anchor point.\n\
+        Either WGS84 latitude / longitude or UTM coordinates, e.g. from a survey.",
+    );
+    ui.horizontal(|ui| {
+        let is_wgs84 = matches!(georef.anchor, GeoAnchor::Wgs84(_));
+        if ui.selectable_label(is_wgs84, "WGS84").clicked() && !is_wgs84 {
+            georef.anchor = GeoAnchor::Wgs84(georef.anchor_utm().to_wgs84());
+        }
+        if ui.selectable_label(!is_wgs84, "UTM").clicked() && is_wgs84 {
+            georef.anchor = GeoAnchor::Utm(georef.anchor_utm());
+        }
+    });
+    ui.end_row();
+    match &mut georef.anchor {
+        GeoAnchor::Wgs84(wgs84) => pick_wgs84(ui, wgs84),
+        GeoAnchor::Utm(utm) => pick_utm(ui, utm),
+    }
+    ui.end_row();
+    ui.label("Anchor position x / y (m)")
+        .on_hover_text("Position in the grid that corresponds to the anchor coordinate.");
+    ui.horizontal(|ui| {
+        ui.add(egui::DragValue::new(&mut georef.anchor_xy.x).speed(0.01));
+        ui.add(egui::DragValue::new(&mut georef.anchor_xy.y).speed(0.01));
+    });
+    ui.end_row();
+    ui.label("Heading (rad)").on_hover_text(
+        "Direction of the grid's x-axis, counter-clockwise from east.\n\
+        0 means that x points east and y points north.",
+    );
+    ui.add(egui::Slider::new(&mut georef.heading, -PI..=PI));
+}
+
+impl AppState {
+    pub(crate) fn georef_settings(&mut self, ui: &mut egui::Ui) {
+        if !section_heading(
+            ui,
+            "Georeference",
+            &mut self.options.collapsed.georef_settings,
+        ) {
+            return;
+        }
+        ui.end_row();
+
+        let previous_georef = self.data.georeference.clone();
+        let previous_num_markers = self.data.geo_markers.len();
+
+        let mut enabled = self.data.georeference.is_some();
+        ui.label("Enable georeference").on_hover_text(
+            "Relate the grid to WGS84 / UTM coordinates, e.g. for outdoor robots.\n\
+            Saved in the session.",
+        );
+        if ui.checkbox(&mut enabled, "").changed() {
+            self.data.georeference = enabled.then(GeoReference::default);
+        }
+        ui.end_row();
+
+        if let Some(georef) = &mut self.data.georeference {
+            pick_anchor(ui, georef);
+            ui.end_row();
+        }
+        if self.data.georeference.is_some() {
+            self.geo_markers_list(ui);
+            ui.end_row();
+            self.world_file_buttons(ui);
+        }
+
+        if self.data.georeference != previous_georef
+            || self.data.geo_markers.len() != previous_num_markers
+        {
+            self.status.unsaved_changes = true;
+        }
+    }
+
+    fn geo_markers_list(&mut self, ui: &mut egui::Ui) {
+        ui.label("Markers").on_hover_text(
+            "Markers at fixed geographic positions.\n\
+            Use the 📍 tool to place markers in the grid.",
+        );
+        let mut to_delete = None;
+        ui.vertical(|ui| {
+            if self.data.geo_markers.is_empty() {
+                ui.label(egui::RichText::new("No markers placed.").weak().italics());
+            }
+            for (i, marker) in self.data.geo_markers.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    if ui
+                        .small_button("🗑")
+                        .on_hover_text("Delete marker")
+                        .clicked()
+                    {
+                        to_delete = Some(i);
+                    }
+                    ui.label(format!(
+                        "{}: {:.7}°, {:.7}°",
+                        marker.name, marker.position.latitude, marker.position.longitude
+                    ));
+                });
+            }
+        });
+        if let Some(i) = to_delete {
+            self.data.geo_markers.remove(i);
+        }
+    }
+
+    fn world_file_buttons(&mut self, ui: &mut egui::Ui) {
+        ui.label("Save world file for").on_hover_text(
+            "Save an ESRI world file (e.g. .pgw) with UTM coordinates for a map image,\n\
+            using its current pose in the grid.",
+        );
+
+        #[cfg(target_arch = "wasm32")]
+        ui.label(
+            egui::RichText::new("Only supported in native builds.")
+                .weak()
+                .italics(),
+        );
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let mut clicked = None;
+            ui.vertical(|ui| {
+                for name in self.data.draw_order.keys() {
+                    if ui
+                        .button(display_path(name, self.options.display.show_full_paths))
+                        .on_hover_text(format!("Save a world file for {name}"))
+                        .clicked()
+                    {
+                        clicked = Some(name.clone());
+                    }
+                }
+            });
+            if let Some(name) = clicked {
+                self.save_world_file(&name);
+            }
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn save_world_file(&mut self, map_name: &str) {
+        let (Some(georef), Some(map)) = (&self.data.georeference, self.data.maps.get(map_name))
+        else {
+            return;
+        };
+        let world_file = georef.world_file(&map.meta, &map.pose, map.image_pyramid.original_size);
+        let default_path = WorldFile::path_for_image(&map.meta.image_path);
+
+        let mut dialog = rfd::FileDialog::new().add_filter("World file", &["pgw", "jgw", "wld"]);
+        if let Some(file_name) = default_path.file_name().and_then(|f| f.to_str()) {
+            dialog = dialog.set_file_name(file_name);
+        }
+        if let Some(dir) = &self.last_file_dir {
+            dialog = dialog.set_directory(dir);
+        }
+        let Some(path) = dialog.save_file() else {
+            return;
+        };
+        match world_file.save(&path) {
+            Ok(()) => {
+                info!(
+                    "Saved world file to {path:?} (EPSG:{})",
+                    georef.anchor_utm().epsg_code()
+                );
+                self.last_file_dir = path.parent().map(std::path::Path::to_path_buf);
+            }
+            Err(e) => {
+                self.status.error = e.to_string();
+                error!("{e}");
+            }
+        }
+    }
+}
@@ -48,6 +48,30 @@ impl AppState {
             ui.end_row();
         }
         ui.end_row();
+        ui.label("Overlays").on_hover_text(
+            "Overlays on top of the grid, they are included in screenshots.\n\
+            The axes indicator also shows north if the grid is georeferenced.",
+        );
+        ui.horizontal(|ui| {
+            let overlays = &mut self.options.grid.overlays;
+            ui.checkbox(&mut overlays.scale_bar, "Scale bar");
+            ui.checkbox(&mut overlays.axes_indicator, "Axes");
+            ui.checkbox(&mut overlays.legend, "Legend");
+        });
+        ui.end_row();
+        ui.label("Show minimap").on_hover_text(
+            "Overview of all visible maps with the current view in a corner of the grid.\n\
+            Click or drag in the minimap to move the view.",
+        );
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.options.minimap.visible, "");
+            if self.options.minimap.visible {
+                ui.add(
+                    egui::Slider::new(&mut self.options.minimap.size, 100.0..=500.0).text("size"),
+                );
+            }
+        });
+        ui.end_row();
         ui.label("Grid scale (points per meter)");
         ui.add(egui::Slider::new(
             &mut self.options.grid.scale,
@@ -15,13 +15,22 @@ impl AppState {
                     );
                 ui.selectable_value(&mut self.options.active_tool, ActiveTool::Measure, "📏´")
                     .on_hover_text("Click two points on the grid to measure the distance.");
+                if self.data.georeference.is_some() {
+                    ui.selectable_value(
+                        &mut self.options.active_tool,
+                        ActiveTool::PlaceGeoMarker,
+                        "+📍",
+                    )
+                    .on_hover_text("Click on a grid position to place a georeferenced marker.");
+                }
             }
 
             let tool_usable = match self.options.active_tool {
                 ActiveTool::HoverLens => true, // Usable in all view modes.
-                ActiveTool::PlaceLens | ActiveTool::Measure => {
-                    self.options.view_mode == ViewMode::Aligned
-                }
+                ActiveTool::PlaceLens
+                | ActiveTool::Measure
+                | ActiveTool::PlaceGeoMarker
+                | ActiveTool::SelectRegion => self.options.view_mode == ViewMode::Aligned,
                 ActiveTool::None => false,
             };
 
@@ -39,6 +39,9 @@ impl AppState {
                         ui.label("1/2/3");
                         ui.label("Switch to fine, medium, or coarse movement sensitivity presets.");
                         ui.end_row();
+                        ui.label("Ctrl 1..9 (Cmd 1..9)");
+                        ui.label("Go to a saved viewpoint in 'Aligned' view.");
+                        ui.end_row();
                         ui.end_row();
 
                         // Mouse
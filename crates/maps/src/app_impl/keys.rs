@@ -2,25 +2,42 @@ use eframe::egui;
 
 use crate::app::{ActiveMovable, ActiveTool, AppState};
 use crate::app_impl::screenshot;
+use crate::app_impl::viewpoints::NUM_VIEWPOINT_SHORTCUTS;
 use crate::movable::{DragDirection, Draggable, MovableAmounts, Rotatable};
 
+/// Keys to go to the viewpoints, together with Ctrl (Cmd).
+const VIEWPOINT_KEYS: [egui::Key; NUM_VIEWPOINT_SHORTCUTS] = [
+    egui::Key::Num1,
+    egui::Key::Num2,
+    egui::Key::Num3,
+    egui::Key::Num4,
+    egui::Key::Num5,
+    egui::Key::Num6,
+    egui::Key::Num7,
+    egui::Key::Num8,
+    egui::Key::Num9,
+];
+
 impl AppState {
     fn dialogs_open(&self) -> bool {
         self.status.quit_modal_active || !self.status.error.is_empty()
     }
 
-    fn text_editing(&self) -> bool {
-        self.options.pose_edit.edit_map_frame || self.options.pose_edit.edit_root_frame
+    fn text_editing(&self, ui: &egui::Ui) -> bool {
+        self.options.pose_edit.edit_map_frame
+            || self.options.pose_edit.edit_root_frame
+            || ui.ctx().egui_wants_keyboard_input()
     }
 
     pub(crate) fn handle_key_shortcuts(&mut self, ui: &egui::Ui) {
-        if self.dialogs_open() || self.text_editing() {
+        if self.dialogs_open() || self.text_editing(ui) {
             self.status.move_action = None;
             return;
         }
 
         let mut screenshot_request: Option<screenshot::Viewport> = None;
         let mut selected_move_preset: Option<MovableAmounts> = None;
+        let mut selected_viewpoint: Option<usize> = None;
         let mut moving_via_keyboard = false;
         ui.input(|i| {
             if i.key_released(egui::Key::Escape) {
@@ -148,7 +165,9 @@ impl AppState {
                 moving_via_keyboard = true;
             }
 
-            if i.key_released(egui::Key::Num1) {
+            if i.modifiers.command {
+                selected_viewpoint = VIEWPOINT_KEYS.iter().position(|key| i.key_released(*key));
+            } else if i.key_released(egui::Key::Num1) {
                 selected_move_preset = Some(MovableAmounts::PRESET_FINE);
             } else if i.key_released(egui::Key::Num2) {
                 selected_move_preset = Some(MovableAmounts::PRESET_MEDIUM);
@@ -157,6 +176,10 @@ impl AppState {
             }
         });
 
+        if let Some(index) = selected_viewpoint {
+            self.go_to_viewpoint(index, ui.input(|i| i.time));
+        }
+
         if let Some(viewport) = screenshot_request {
             // Has to be called here outside of the input closure to not block.
             self.request_screenshot(ui, viewport);
@@ -6,29 +6,171 @@ use eframe::egui;
 use image::GenericImageView;
 use log::{debug, error, info};
 
-use crate::map_state::MapState;
+use crate::map_state::{MapState, MissingMap, session_draw_order_index};
 use crate::persistence;
+use crate::session_paths::PathResolver;
 use crate::tiles::Pane;
 use maps_io_ros::{Meta, load_image};
-use maps_rendering::{ImagePyramid, TextureFilter};
+use maps_rendering::{ImagePyramid, PyramidLevels, TextureFilter};
 
 use crate::app::{AppState, Error, ViewMode};
-use crate::app_impl::compat::migrate_old_egui_color;
 use maps_io_ros::MapPose;
 use maps_io_ros::value_interpretation;
+#[cfg(not(target_arch = "wasm32"))]
+use {
+    crate::bundle,
+    log::warn,
+    maps_io_ros::{
+        GeoReference, RegionReader,
+        geo_reference::{GeoAnchor, WorldFile},
+        geotiff,
+        utm::Utm,
+    },
+    maps_rendering::PyramidDiskCache,
+};
+
+/// Maps are identified by the path of their metadata file.
+fn map_name(meta: &Meta) -> String {
+    meta.yaml_path
+        .to_str()
+        .expect("invalid unicode path, can't use as map name")
+        .to_owned()
+}
+
+/// Loads map metadata from a ROS map YAML file or a GeoTIFF.
+/// GeoTIFFs are anchored close to their image, see [`AppState::load_geotiff`]
+/// for loading them relative to the georeference of the session.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn load_meta_file(path: &std::path::Path) -> maps_io_ros::Result<Meta> {
+    if geotiff::has_tiff_extension(path) {
+        geotiff::load_geotiff_meta(path, None).map(|geotiff_meta| geotiff_meta.meta)
+    } else {
+        Meta::load_from_file(path)
+    }
+}
+
+/// Resolves the image and YAML paths of a map from a session, see [`PathResolver`].
+/// Paths that can't be resolved are kept as they are, unless the user relocates them.
+pub(crate) fn resolve_map_paths(
+    meta: &mut Meta,
+    resolver: &mut PathResolver,
+    ask_to_relocate: bool,
+) {
+    if let Some(yaml_path) = resolver.resolve(&meta.yaml_path) {
+        meta.yaml_path = yaml_path;
+    }
+    // The image is usually next to the YAML file, also if it was moved.
+    let next_to_yaml = || {
+        let candidate = meta.yaml_path.parent()?.join(meta.image_path.file_name()?);
+        candidate.exists().then_some(candidate)
+    };
+    if let Some(image_path) = resolver.resolve(&meta.image_path).or_else(next_to_yaml) {
+        meta.image_path = image_path;
+        return;
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    if ask_to_relocate && let Some(image_path) = ask_for_relocated_file(&meta.image_path) {
+        if let Some(dir) = image_path.parent() {
+            // Other missing maps are likely in the same place.
+            resolver.add_search_dir(dir);
+            if let Some(yaml_path) = meta
+                .yaml_path
+                .file_name()
+                .map(|name| dir.join(name))
+                .filter(|path| path.exists())
+            {
+                meta.yaml_path = yaml_path;
+            }
+        }
+        meta.image_path = image_path;
+    }
+    #[cfg(target_arch = "wasm32")]
+    let _ = ask_to_relocate;
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn ask_for_relocated_file(missing: &std::path::Path) -> Option<PathBuf> {
+    let answer = rfd::MessageDialog::new()
+        .set_level(rfd::MessageLevel::Warning)
+        .set_title("Map file not found")
+        .set_description(format!(
+            "The session refers to a map image that does not exist:\n{}\n\n\
+            Do you want to locate it?",
+            missing.display()
+        ))
+        .set_buttons(rfd::MessageButtons::YesNo)
+        .show();
+    if answer != rfd::MessageDialogResult::Yes {
+        return None;
+    }
+    let mut dialog = rfd::FileDialog::new().set_title(format!("Locate {}", missing.display()));
+    if let Some(name) = missing.file_name() {
+        dialog = dialog.set_file_name(name.to_string_lossy());
+    }
+    dialog.pick_file()
+}
 
 impl AppState {
     #[cfg(not(target_arch = "wasm32"))]
-    fn load_meta(&mut self, yaml_path: &std::path::Path) -> Result<bool, Error> {
-        let meta = Meta::load_from_file(yaml_path)?;
-        self.load_map(meta)?;
+    pub(crate) fn load_meta(&mut self, yaml_path: &std::path::Path) -> Result<bool, Error> {
+        if geotiff::has_tiff_extension(yaml_path) {
+            self.load_geotiff(yaml_path)?;
+        } else {
+            self.load_map(Meta::load_from_file(yaml_path)?, PyramidLevels::default())?;
+        }
+        self.options.recent_files.add_map(yaml_path);
         Ok(true)
     }
 
+    /// Loads a GeoTIFF such that its model coordinates line up with the georeference.
+    ///
+    /// GeoTIFFs in the UTM zone of the georeference are anchored at the georeference's anchor
+    /// and placed with its offset and heading. Without georeference, a GeoTIFF with UTM
+    /// coordinates sets one up at its own anchor, such that further GeoTIFFs and geo markers
+    /// line up with it. Other coordinate systems can't be georeferenced.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn load_geotiff(&mut self, path: &std::path::Path) -> Result<String, Error> {
+        let georef_anchor = self.data.georeference.as_ref().and_then(|georef| {
+            let anchor = georef.anchor_utm();
+            let info = geotiff::read_geotiff_info(path).ok()?;
+            let zone = Utm::from_epsg_code(info.epsg_code?, 0., 0.)?;
+            (zone.zone == anchor.zone && zone.north == anchor.north).then_some(anchor)
+        });
+        let geotiff_meta = geotiff::load_geotiff_meta(
+            path,
+            georef_anchor.map(|anchor| (anchor.easting, anchor.northing)),
+        )?;
+        let anchor_utm = geotiff_meta.anchor_utm();
+        let (x, y) = geotiff_meta.anchor;
+        let name = self.load_map(geotiff_meta.meta, PyramidLevels::default())?;
+
+        if let (Some(georef), Some(_)) = (&self.data.georeference, georef_anchor) {
+            let mut pose = MapPose::default();
+            pose.with_vec2(georef.anchor_xy)
+                .with_rot2(egui::emath::Rot2::from_angle(-georef.heading));
+            self.add_map_pose(&name, pose);
+        } else if let (None, Some(anchor)) = (&self.data.georeference, anchor_utm) {
+            info!("Georeferencing the session at the UTM anchor of {path:?}.");
+            self.data.georeference = Some(GeoReference {
+                anchor: GeoAnchor::Utm(anchor),
+                ..Default::default()
+            });
+        } else if (x, y) != (0., 0.) {
+            warn!(
+                "{path:?} can't be georeferenced in this session, \
+                its map frame is the model coordinate system shifted to ({x}, {y})."
+            );
+        }
+        Ok(name)
+    }
+
     #[cfg(not(target_arch = "wasm32"))]
     pub(crate) fn load_meta_button(&mut self, ui: &mut egui::Ui) {
         if ui.button("📂 Load Maps").clicked() {
-            let mut dialog = rfd::FileDialog::new().add_filter("YAML", &["yaml", "yml"]);
+            let mut dialog = rfd::FileDialog::new()
+                .add_filter("YAML", &["yaml", "yml"])
+                .add_filter("GeoTIFF", &["tif", "tiff"]);
             if let Some(dir) = &self.last_file_dir {
                 dialog = dialog.set_directory(dir);
             }
@@ -49,10 +191,26 @@ impl AppState {
         }
     }
 
-    pub(crate) fn add_map(&mut self, name: &String, meta: Meta, image_pyramid: &Arc<ImagePyramid>) {
-        let use_interpretation = meta.value_interpretation.explicit_mode;
+    pub(crate) fn add_map(
+        &mut self,
+        name: &String,
+        mut meta: Meta,
+        image_pyramid: &Arc<ImagePyramid>,
+    ) {
+        if image_pyramid.is_high_depth() {
+            // High bit depth values, e.g. floats, would be clamped to 0..1 when
+            // converted to colors, so they are displayed within their value range.
+            // The range of streamed images is unknown until "Fit" is used.
+            meta.set_default_value_range(
+                image_pyramid
+                    .original()
+                    .and_then(|original| value_interpretation::ValueRange::from_image(original)),
+            );
+        }
+        let use_interpretation =
+            meta.value_interpretation.explicit_mode || image_pyramid.is_high_depth();
         if use_interpretation {
-            // This map has an explicitly specified value interpretation.
+            // This map has an explicitly specified value interpretation or needs one.
             // We need to set this to not loose the values in the next frame.
             self.options.tint_settings.active_tint_selection = Some(name.clone());
         }
@@ -69,6 +227,7 @@ impl AppState {
                 color_to_alpha: None,
                 texture_filter: TextureFilter::default(),
                 use_value_interpretation: use_interpretation,
+                pyramid_levels: image_pyramid.levels(),
             },
         );
         self.data.draw_order.add(name.clone());
@@ -79,7 +238,50 @@ impl AppState {
         }
     }
 
-    pub(crate) fn load_map(&mut self, meta: Meta) -> Result<String, Error> {
+    /// The disk cache for image pyramid levels, if enabled in the advanced options.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn pyramid_disk_cache(&self) -> Option<PyramidDiskCache> {
+        if !self.options.advanced.pyramid_disk_cache || self.options.advanced.dry_run {
+            return None;
+        }
+        persistence::pyramid_cache_dir().map(PyramidDiskCache::new)
+    }
+
+    /// Returns a streamed pyramid if the image is too large to be loaded completely
+    /// according to the streaming threshold, and its format supports region reads.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn streamed_pyramid(
+        &self,
+        meta: &Meta,
+        pyramid_levels: PyramidLevels,
+    ) -> Result<Option<Arc<ImagePyramid>>, Error> {
+        if self.options.advanced.dry_run {
+            return Ok(None);
+        }
+        let Some(reader) = RegionReader::open(&meta.image_path)? else {
+            return Ok(None);
+        };
+        let (width, height) = reader.dimensions();
+        if width.max(height) <= self.options.advanced.streaming_threshold {
+            return Ok(None);
+        }
+        info!(
+            "Streaming image {:?} {:?}, it exceeds the threshold of {} px.",
+            meta.image_path,
+            (width, height),
+            self.options.advanced.streaming_threshold
+        );
+        Ok(Some(ImagePyramid::streamed_in_background(
+            reader,
+            pyramid_levels,
+        )))
+    }
+
+    pub(crate) fn load_map(
+        &mut self,
+        meta: Meta,
+        pyramid_levels: PyramidLevels,
+    ) -> Result<String, Error> {
         if !meta.image_path.exists() {
             return Err(Error::app(format!(
                 "Image file doesn't exist: {:?}",
@@ -87,6 +289,13 @@ impl AppState {
             )));
         }
 
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(image_pyramid) = self.streamed_pyramid(&meta, pyramid_levels)? {
+            let name = map_name(&meta);
+            self.add_map(&name, meta, &image_pyramid);
+            return Ok(name);
+        }
+
         let image = if self.options.advanced.dry_run {
             info!("Dry-run mode, not loading image {:?}.", meta.image_path);
             Ok(image::DynamicImage::new_rgba8(0, 0))
@@ -100,12 +309,13 @@ impl AppState {
             image.dimensions()
         );
 
-        let image_pyramid = Arc::new(ImagePyramid::new(image));
-        let name = meta
-            .yaml_path
-            .to_str()
-            .expect("invalid unicode path, can't use as map name")
-            .to_owned();
+        // Build the pyramid levels in the background to keep the UI responsive.
+        #[cfg(not(target_arch = "wasm32"))]
+        let image_pyramid =
+            ImagePyramid::new_in_background(image, pyramid_levels, self.pyramid_disk_cache());
+        #[cfg(target_arch = "wasm32")]
+        let image_pyramid = Arc::new(ImagePyramid::with_levels(image, pyramid_levels));
+        let name = map_name(&meta);
         self.add_map(&name, meta, &image_pyramid);
         Ok(name)
     }
@@ -210,55 +420,91 @@ impl AppState {
         }
     }
 
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn save_geotiff_button(&mut self, ui: &mut egui::Ui, map_name: &str) {
+        if !ui
+            .button("💾 Export GeoTIFF")
+            .on_hover_text(
+                "Save the map image as GeoTIFF with the map pose applied.\n\
+                Uses UTM coordinates if a georeference is enabled.",
+            )
+            .clicked()
+        {
+            return;
+        }
+        let Some(map) = self.data.maps.get(map_name) else {
+            return;
+        };
+        let default_path = geotiff::default_geotiff_path(&map.meta);
+        let mut dialog = rfd::FileDialog::new().add_filter("GeoTIFF", &["tif", "tiff"]);
+        if let Some(file_name) = default_path.file_name().and_then(|f| f.to_str()) {
+            dialog = dialog.set_file_name(file_name);
+        }
+        if let Some(dir) = &self.last_file_dir {
+            dialog = dialog.set_directory(dir);
+        }
+        let Some(path) = dialog.save_file() else {
+            return;
+        };
+        ui.ctx().request_repaint();
+
+        let image_size = map.image_pyramid.original_size;
+        let (transform, epsg_code) = match &self.data.georeference {
+            Some(georef) => (
+                georef.world_file(&map.meta, &map.pose, image_size),
+                Some(georef.anchor_utm().epsg_code()),
+            ),
+            None => (WorldFile::local(&map.meta, &map.pose, image_size), None),
+        };
+        // Reload the original image to keep its pixel format.
+        let result = load_image(&map.meta.image_path)
+            .and_then(|image| geotiff::save_geotiff(&path, &image, &transform, epsg_code));
+        match result {
+            Ok(()) => {
+                info!("Saved GeoTIFF: {path:?}");
+                self.last_file_dir = path.parent().map(std::path::Path::to_path_buf);
+            }
+            Err(e) => {
+                self.status.error = e.to_string();
+                error!("{e}");
+            }
+        }
+    }
+
+    /// Loads a session file. Map files that don't exist at their saved paths
+    /// are searched relative to the session file, see [`PathResolver`].
     pub fn load_session(&mut self, path: &PathBuf) -> Result<(), Error> {
-        let deserialized_session = persistence::load_session(path)?;
+        self.load_session_with(path, false)
+    }
+
+    /// Like [`AppState::load_session`], optionally asks the user
+    /// to relocate map files that can't be found.
+    fn load_session_with(&mut self, path: &PathBuf, ask_to_relocate: bool) -> Result<(), Error> {
+        let mut deserialized_session = persistence::load_session(path)?;
+        let mut resolver = PathResolver::new(path);
 
         // Start from the same path the next time.
         self.last_file_dir = path.parent().map(std::path::Path::to_path_buf);
 
-        // Keep the draw order of the session, if it was saved.
-        // If it was not saved (older versions), add_map() will take care of it.
+        // Keep the draw order of the session.
+        // Older sessions without draw order were migrated when loading them.
         self.data
             .draw_order
             .extend(&deserialized_session.draw_order);
 
-        // If the session has no version field, it was saved with maps < 1.7.0.
-        // This means that the tint color was serialized with egui < 0.32 and
-        // might need migration.
-        let migrate_colors = deserialized_session.version.is_none();
-        if migrate_colors {
-            debug!("Session was saved with maps < 1.7.0, migrating serialized colors.");
-        }
-
         // Not everything gets serialized. Load actual data.
-        for (name, map) in deserialized_session.maps {
+        // Maps that fail to load are kept as placeholders, the others are loaded anyway.
+        // Relocated maps get new names.
+        let mut renamed: HashMap<String, String> = HashMap::new();
+        for (name, mut map) in std::mem::take(&mut deserialized_session.maps) {
             debug!("Restoring map state: {name}");
-            let map_name = self.load_map(map.meta).inspect_err(|_| {
-                // Make sure we have no dangling names in draw_order if we fail to load one map.
-                self.data
-                    .draw_order
-                    .retain(|key| self.data.maps.contains_key(key));
-            })?;
-            let map_state = self.data.maps.get_mut(&map_name).expect("missing map");
-            map_state.pose = map.pose;
-            map_state.visible = map.visible;
-            map_state.tint = map.tint;
-            if migrate_colors {
-                map_state.tint = migrate_old_egui_color(map_state.tint);
-            }
-            map_state.texture_filter = map.texture_filter;
-            if map_state.tint.is_some()
-                || map_state.meta.value_interpretation.mode != value_interpretation::Mode::Raw
-            {
-                // We need to set this because we would lose this map's tint
-                // in the next frame if "All" is selected in the settings panel.
-                self.options.tint_settings.active_tint_selection = Some(name.clone());
-            }
-            self.tile_manager
-                .set_visible(map_name.as_str(), map.visible);
-            map_state.color_to_alpha = map.color_to_alpha;
-            if migrate_colors {
-                map_state.color_to_alpha = migrate_old_egui_color(map_state.color_to_alpha);
+            resolve_map_paths(&mut map.meta, &mut resolver, ask_to_relocate);
+            let draw_order_index = self.data.draw_order.position(&name);
+            match self.restore_map(&name, map.meta.clone(), &map) {
+                Ok(map_name) => {
+                    renamed.insert(name, map_name);
+                }
+                Err(e) => self.add_missing_map(name, map, &e, draw_order_index),
             }
             self.status.unsaved_changes = false;
         }
@@ -268,9 +514,83 @@ impl AppState {
             self.data.grid_lenses.insert(id, lens_pos);
         }
 
+        if deserialized_session.georeference.is_some() {
+            self.data.georeference = deserialized_session.georeference;
+        }
+        // Skip markers that exist already, e.g. when the same session is loaded again.
+        for marker in deserialized_session.geo_markers {
+            if !self.data.geo_markers.contains(&marker) {
+                self.data.geo_markers.push(marker);
+            }
+        }
+
+        // Like markers, skip viewpoints that exist already.
+        for mut viewpoint in deserialized_session.viewpoints {
+            for name in viewpoint.visible_maps.iter_mut().flatten() {
+                if let Some(map_name) = renamed.get(name) {
+                    name.clone_from(map_name);
+                }
+            }
+            if !self.data.viewpoints.contains(&viewpoint) {
+                self.data.viewpoints.push(viewpoint);
+            }
+        }
+
         Ok(())
     }
 
+    /// Keeps a map of a session that failed to load as placeholder, see [`MissingMap`].
+    /// `draw_order_index` is the position of the map in the draw order of the loaded maps.
+    pub(crate) fn add_missing_map(
+        &mut self,
+        name: String,
+        map: MapState,
+        error: &Error,
+        draw_order_index: Option<usize>,
+    ) {
+        error!("Failed to load map {name} from session: {error}");
+        self.data.draw_order.remove(&name);
+        let draw_order_index =
+            draw_order_index.map(|index| session_draw_order_index(&self.data.missing_maps, index));
+        self.data.missing_maps.insert(
+            name,
+            MissingMap {
+                state: map,
+                error: error.to_string(),
+                draw_order_index,
+            },
+        );
+        self.status.missing_maps_window_active = true;
+    }
+
+    /// Loads a map and applies the state that was saved in a session to it.
+    /// `name` is the map name in the session, it can differ if the map was relocated.
+    pub(crate) fn restore_map(
+        &mut self,
+        name: &str,
+        meta: Meta,
+        map: &MapState,
+    ) -> Result<String, Error> {
+        let map_name = self.load_map(meta, map.pyramid_levels)?;
+        self.data.draw_order.rename(name, &map_name);
+        let map_state = self.data.maps.get_mut(&map_name).expect("missing map");
+        map_state.pose = map.pose.clone();
+        map_state.visible = map.visible;
+        map_state.tint = map.tint;
+        map_state.texture_filter = map.texture_filter;
+        if map_state.tint.is_some()
+            || map_state.meta.value_interpretation.mode != value_interpretation::Mode::Raw
+        {
+            // We need to set this because we would lose this map's tint
+            // in the next frame if "All" is selected in the settings panel.
+            self.options.tint_settings.active_tint_selection = Some(map_name.clone());
+        }
+        self.tile_manager
+            .set_visible(map_name.as_str(), map.visible);
+        map_state.color_to_alpha = map.color_to_alpha;
+        Ok(map_name)
+    }
+
     pub(crate) fn load_session_button(&mut self, ui: &mut egui::Ui) {
         if ui
             .button("📂 Load Session")
@@ -280,12 +600,14 @@ impl AppState {
         {
             #[cfg(not(target_arch = "wasm32"))]
             {
-                let mut dialog = rfd::FileDialog::new().add_filter("TOML", &["toml"]);
+                let mut dialog = rfd::FileDialog::new()
+                    .add_filter("TOML", &["toml"])
+                    .add_filter("Session bundle", &[bundle::BUNDLE_EXTENSION]);
                 if let Some(dir) = &self.last_file_dir {
                     dialog = dialog.set_directory(dir);
                 }
                 if let Some(path) = dialog.pick_file() {
-                    self.load_session(&path).unwrap_or_else(|e| {
+                    self.load_session_or_bundle(&path).unwrap_or_else(|e| {
                         self.status.error = e.to_string();
                         error!("{e}");
                     });
@@ -294,6 +616,57 @@ impl AppState {
         }
     }
 
+    /// Loads a session file, or extracts and loads a session bundle.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn load_session_or_bundle(&mut self, path: &PathBuf) -> Result<(), Error> {
+        if path
+            .extension()
+            .is_some_and(|ext| ext == bundle::BUNDLE_EXTENSION)
+        {
+            let session_path = bundle::extract_bundle(path, &bundle::extraction_dir(path))?;
+            self.load_session_with(&session_path, true)?;
+            // Start from the bundle's directory the next time, not from the extracted files.
+            self.last_file_dir = path.parent().map(std::path::Path::to_path_buf);
+        } else {
+            self.load_session_with(path, true)?;
+        }
+        self.options.recent_files.add_session(path);
+        Ok(())
+    }
+
+    /// Button to export the session with all map files as one archive.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn export_bundle_button(&mut self, ui: &mut egui::Ui) {
+        if !ui
+            .button("📦")
+            .on_hover_text(
+                "Export a session bundle: one archive with the session and all map files.\n\
+                It can be opened directly with \"Load Session\", e.g. by a colleague.",
+            )
+            .clicked()
+        {
+            return;
+        }
+        let mut dialog = rfd::FileDialog::new()
+            .add_filter("Session bundle", &[bundle::BUNDLE_EXTENSION])
+            .set_file_name(format!("maps_session.{}", bundle::BUNDLE_EXTENSION));
+        if let Some(dir) = &self.last_file_dir {
+            dialog = dialog.set_directory(dir);
+        }
+        let Some(path) = dialog.save_file() else {
+            return;
+        };
+        match bundle::export_bundle(&path, &self.data) {
+            Ok(()) => {
+                self.last_file_dir = path.parent().map(std::path::Path::to_path_buf);
+            }
+            Err(e) => {
+                self.status.error = e.to_string();
+                error!("{e}");
+            }
+        }
+    }
+
     pub(crate) fn save_session_button(&mut self, ui: &mut egui::Ui, quit_after_save: bool) {
         let text = if quit_after_save {
             "💾 Save Session and Quit"
@@ -322,7 +695,11 @@ impl AppState {
                     dialog = dialog.set_directory(dir);
                 }
                 if let Some(path) = dialog.save_file() {
-                    match persistence::save_session(&path, &self.data) {
+                    match persistence::save_session(
+                        &path,
+                        &self.data,
+                        self.options.persistence.relative_session_paths,
+                    ) {
                         Ok(_) => {
                             self.last_file_dir = path.parent().map(std::path::Path::to_path_buf);
                             self.status.unsaved_changes = false;
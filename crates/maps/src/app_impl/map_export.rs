@@ -0,0 +1,407 @@
+use eframe::egui;
+
+use crate::app::{ActiveTool, AppState, ViewMode};
+use crate::app_impl::ui_helpers::{display_path, section_heading};
+use maps_io_ros::map_merge::MergePolicy;
+
+#[cfg(not(target_arch = "wasm32"))]
+use {
+    image::DynamicImage,
+    log::{error, info},
+    maps_io_ros::map_edit::{PixelRect, pad_to_size, reframe, resample, save_map},
+    maps_io_ros::map_merge::{MergeInput, merge_maps},
+    maps_io_ros::statistics::MapStatistics,
+    maps_io_ros::{Meta, load_image},
+};
+
+/// Options of the export section, not persisted.
+#[derive(Debug, Default)]
+pub struct ExportOptions {
+    pub selected_map: Option<String>,
+    /// Target size in cells for padding.
+    pub pad_size: [u32; 2],
+    /// First corner of a region that is being selected in the grid (root frame).
+    pub region_start: Option<egui::Pos2>,
+    /// Selected region in the grid, in root frame coordinates.
+    pub region: Option<egui::Rect>,
+    /// Target resolution for resampling in meters per cell, 0 if not set yet.
+    pub resolution: f32,
+    pub merge_policy: MergePolicy,
+    /// Resolution of merged maps in meters per cell, 0 if not set yet.
+    pub merge_resolution: f32,
+    /// Save the Aligned view as SVG when the grid is shown next.
+    pub svg_requested: bool,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+enum Operation {
+    Trim,
+    CropToRegion(egui::Rect),
+    Pad([u32; 2]),
+    Resample(f32),
+}
+
+impl AppState {
+    pub(crate) fn map_export_settings(&mut self, ui: &mut egui::Ui) {
+        if !section_heading(ui, "Export", &mut self.options.collapsed.map_export) {
+            return;
+        }
+        ui.end_row();
+
+        #[cfg(target_arch = "wasm32")]
+        {
+            ui.label("Crop / pad / resample / merge");
+            ui.label(
+                egui::RichText::new("Only supported in native builds.")
+                    .weak()
+                    .italics(),
+            );
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        self.map_export_native(ui);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn map_export_native(&mut self, ui: &mut egui::Ui) {
+        let show_full_paths = self.options.display.show_full_paths;
+        let export = &mut self.options.export;
+        let previous_selection = export.selected_map.clone();
+        if export
+            .selected_map
+            .as_ref()
+            .is_none_or(|name| !self.data.maps.contains_key(name))
+        {
+            export.selected_map = self.data.maps.keys().next().cloned();
+        }
+        let Some(selected) = &mut export.selected_map else {
+            return;
+        };
+
+        ui.label("Map")
+            .on_hover_text("Map that the operations below are applied to.");
+        egui::ComboBox::from_id_salt("export_map")
+            .selected_text(display_path(selected, show_full_paths))
+            .show_ui(ui, |ui| {
+                for name in self.data.maps.keys() {
+                    ui.selectable_value(
+                        selected,
+                        name.clone(),
+                        display_path(name, show_full_paths),
+                    );
+                }
+            });
+        ui.end_row();
+        if export.selected_map != previous_selection {
+            // Defaults that depend on the map are taken from the new selection.
+            export.resolution = 0.;
+            export.pad_size = [0, 0];
+        }
+        let Some(selected) = &export.selected_map else {
+            return;
+        };
+
+        let mut operation = None;
+        ui.label("Trim to known space").on_hover_text(
+            "Crop the map to the bounding box of its known cells\n\
+            (with the current value interpretation) and save it as new image and YAML.\n\
+            The origin is updated such that the map stays in place.",
+        );
+        if ui.button("Trim & save…").clicked() {
+            operation = Some(Operation::Trim);
+        }
+        ui.end_row();
+
+        ui.label("Crop to region").on_hover_text(
+            "Crop the map to a region drawn in the Aligned view\n\
+            and save it as new image and YAML.",
+        );
+        ui.horizontal(|ui| {
+            let selecting = self.options.active_tool == ActiveTool::SelectRegion;
+            if ui
+                .add_enabled(
+                    self.options.view_mode == ViewMode::Aligned,
+                    egui::Button::new("⬚ Select").selected(selecting),
+                )
+                .on_hover_text("Click two corners of the region in the grid.")
+                .on_disabled_hover_text("Only available in the Aligned view.")
+                .clicked()
+            {
+                self.options.active_tool = if selecting {
+                    ActiveTool::None
+                } else {
+                    ActiveTool::SelectRegion
+                };
+                export.region_start = None;
+            }
+            if let Some(region) = export.region {
+                if ui.button("Crop & save…").clicked() {
+                    operation = Some(Operation::CropToRegion(region));
+                }
+                if ui.button("Clear").clicked() {
+                    export.region = None;
+                }
+            }
+        });
+        ui.end_row();
+
+        let original_size = self.data.maps[selected.as_str()]
+            .image_pyramid
+            .original_size;
+        let image_size = [original_size.x as u32, original_size.y as u32];
+        if export.pad_size == [0, 0] {
+            export.pad_size = image_size;
+        }
+        ui.label("Pad to size (cells)").on_hover_text(
+            "Pad the map with unknown cells to the given width and height,\n\
+            keeping it centered, and save it as new image and YAML.\n\
+            In scale mode, unknown cells are transparent, an alpha channel is added if needed.",
+        );
+        ui.horizontal(|ui| {
+            ui.add(egui::DragValue::new(&mut export.pad_size[0]).prefix("w "));
+            ui.add(egui::DragValue::new(&mut export.pad_size[1]).prefix("h "));
+            let larger = export.pad_size[0] > image_size[0] || export.pad_size[1] > image_size[1];
+            if ui
+                .add_enabled(larger, egui::Button::new("Pad & save…"))
+                .on_disabled_hover_text(format!(
+                    "The size must be larger than the image ({} x {}).",
+                    image_size[0], image_size[1]
+                ))
+                .clicked()
+            {
+                operation = Some(Operation::Pad(export.pad_size));
+            }
+        });
+        ui.end_row();
+
+        if export.resolution <= 0. {
+            export.resolution = self.data.maps[selected.as_str()].meta.resolution;
+        }
+        ui.label("Resample (m / cell)").on_hover_text(
+            "Resample the map to another resolution and save it as new image and YAML.\n\
+            Downsampling keeps obstacles: a cell is occupied if any covered cell is occupied,\n\
+            unknown if most covered cells are unknown. The origin stays the same.",
+        );
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::DragValue::new(&mut export.resolution)
+                    .speed(0.001)
+                    .range(0.001..=f32::MAX)
+                    .max_decimals(4),
+            );
+            if ui.button("Resample & save…").clicked() {
+                operation = Some(Operation::Resample(export.resolution));
+            }
+        });
+        ui.end_row();
+
+        if export.merge_resolution <= 0. {
+            export.merge_resolution = self
+                .data
+                .maps
+                .values()
+                .map(|map| map.meta.resolution)
+                .fold(f32::MAX, f32::min);
+        }
+        ui.label("Merge visible maps").on_hover_text(
+            "Fuse all visible maps in their aligned poses into one map\n\
+            at the given resolution and save it as new image and YAML.\n\
+            Maps later in the draw order have higher priority.",
+        );
+        let mut merge = false;
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt("merge_policy")
+                .selected_text(export.merge_policy.display_name())
+                .show_ui(ui, |ui| {
+                    for policy in MergePolicy::ALL {
+                        ui.selectable_value(&mut export.merge_policy, policy, policy.display_name())
+                            .on_hover_text(match policy {
+                                MergePolicy::OccupiedWins => {
+                                    "Obstacles of any map are kept."
+                                }
+                                MergePolicy::LatestWins => {
+                                    "Known cells of the map latest in the draw order win."
+                                }
+                                MergePolicy::LogOdds => {
+                                    "Occupancy probabilities are fused, agreeing maps reinforce each other."
+                                }
+                            });
+                    }
+                });
+            ui.add(
+                egui::DragValue::new(&mut export.merge_resolution)
+                    .speed(0.001)
+                    .range(0.001..=f32::MAX)
+                    .max_decimals(4)
+                    .suffix(" m"),
+            );
+            merge = ui.button("Merge & save…").clicked();
+        });
+        ui.end_row();
+
+        ui.label("Save view as SVG").on_hover_text(
+            "Save the current Aligned view as vector graphic, e.g. for printed plans.\n\
+            Maps are embedded at their native resolution with their poses as transforms,\n\
+            grid lines, markers, the measurement, labels and overlays are vectors.",
+        );
+        if ui
+            .add_enabled(
+                self.options.view_mode == ViewMode::Aligned,
+                egui::Button::new("💾 Save SVG…"),
+            )
+            .on_disabled_hover_text("Only available in the Aligned view.")
+            .clicked()
+        {
+            export.svg_requested = true;
+        }
+
+        if let Some(operation) = operation {
+            let name = selected.clone();
+            self.export_edited_map(&name, &operation);
+        }
+        if merge {
+            self.merge_visible_maps();
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn export_edited_map(&mut self, map_name: &str, operation: &Operation) {
+        let Some(map) = self.data.maps.get(map_name) else {
+            return;
+        };
+        // Reload the original image to keep its pixel format.
+        let image = match load_image(&map.meta.image_path) {
+            Ok(image) => image,
+            Err(e) => {
+                self.status.error = e.to_string();
+                error!("{e}");
+                return;
+            }
+        };
+        let meta = &map.meta;
+        let (suffix, rect) = match operation {
+            Operation::Trim => (
+                "trimmed",
+                MapStatistics::compute(meta, &image, image.color().has_alpha()).known_pixel_bounds,
+            ),
+            Operation::CropToRegion(region) => {
+                let height = image.height() as f32;
+                let corners = [
+                    region.left_top(),
+                    region.right_top(),
+                    region.left_bottom(),
+                    region.right_bottom(),
+                ]
+                .map(|p| meta.map_to_pixel(map.pose.inverse_transform(p), height));
+                (
+                    "cropped",
+                    PixelRect::from_points(&corners)
+                        .and_then(|rect| rect.intersect(&PixelRect::of_image(&image))),
+                )
+            }
+            Operation::Pad([width, height]) => {
+                let (meta, image) = pad_to_size(meta, &image, *width, *height);
+                self.save_edited_map(&meta, &image, "padded");
+                return;
+            }
+            Operation::Resample(resolution) => {
+                let has_alpha = image.color().has_alpha();
+                let (meta, image) = resample(meta, &image, has_alpha, *resolution);
+                self.save_edited_map(&meta, &image, "resampled");
+                return;
+            }
+        };
+        let Some(rect) = rect else {
+            self.status.error = format!("Nothing to export, the {suffix} map would be empty.");
+            error!("{}", self.status.error);
+            return;
+        };
+        let (meta, image) = reframe(meta, &image, rect);
+        self.save_edited_map(&meta, &image, suffix);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn merge_visible_maps(&mut self) {
+        let export = &self.options.export;
+        let maps: Vec<_> = self
+            .data
+            .draw_order
+            .keys()
+            .iter()
+            .filter_map(|name| self.data.maps.get(name))
+            .filter(|map| map.visible)
+            .collect();
+        // Reload the original images to keep their pixel format.
+        let images = match maps
+            .iter()
+            .map(|map| load_image(&map.meta.image_path))
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Ok(images) => images,
+            Err(e) => {
+                self.status.error = e.to_string();
+                error!("{e}");
+                return;
+            }
+        };
+        let inputs: Vec<MergeInput> = maps
+            .iter()
+            .zip(&images)
+            .map(|(map, image)| MergeInput {
+                meta: &map.meta,
+                image,
+                original_has_alpha: image.color().has_alpha(),
+                pose: &map.pose,
+            })
+            .collect();
+        let Some((mut meta, image)) =
+            merge_maps(&inputs, export.merge_resolution, export.merge_policy)
+        else {
+            self.status.error = "Nothing to merge, no map is visible.".to_string();
+            error!("{}", self.status.error);
+            return;
+        };
+        if let Some(first) = maps.first() {
+            meta.yaml_path.clone_from(&first.meta.yaml_path);
+        }
+        info!("Merged {} maps ({:?}).", inputs.len(), export.merge_policy);
+        self.save_edited_map(&meta, &image, "merged");
+    }
+
+    /// Asks for a YAML path and saves an edited map with its image next to it.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn save_edited_map(&mut self, meta: &Meta, image: &DynamicImage, suffix: &str) {
+        let stem = meta
+            .yaml_path
+            .file_stem()
+            .map_or_else(|| "map".to_string(), |s| s.to_string_lossy().to_string());
+        let mut dialog = rfd::FileDialog::new()
+            .add_filter("ROS map YAML", &["yaml", "yml"])
+            .set_file_name(format!("{stem}_{suffix}.yaml"));
+        if let Some(dir) = self
+            .last_file_dir
+            .as_deref()
+            .or_else(|| meta.yaml_path.parent())
+        {
+            dialog = dialog.set_directory(dir);
+        }
+        let Some(path) = dialog.save_file() else {
+            return;
+        };
+        match save_map(&path, meta, image) {
+            Ok(saved) => {
+                info!(
+                    "Saved {suffix} map ({} x {} cells) to {path:?} and {:?}",
+                    image.width(),
+                    image.height(),
+                    saved.image_path
+                );
+                self.last_file_dir = path.parent().map(std::path::Path::to_path_buf);
+            }
+            Err(e) => {
+                self.status.error = e.to_string();
+                error!("{e}");
+            }
+        }
+    }
+}
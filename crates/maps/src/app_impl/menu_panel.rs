@@ -79,6 +79,8 @@ impl AppState {
             return;
         };
         let warn_origin_rotation = map.meta.origin_theta.angle() != 0.;
+        let pyramid_progress =
+            (!map.image_pyramid.is_complete()).then(|| map.image_pyramid.progress());
 
         egui::Sides::new().show(
             ui,
@@ -100,6 +102,13 @@ impl AppState {
                 if ui_right.button("🗑").on_hover_text("Delete Map").clicked() {
                     to_delete.push(name.to_string());
                 }
+                if let Some(progress) = pyramid_progress {
+                    ui_right.spinner().on_hover_text(format!(
+                        "Building zoom levels in the background ({:.0} %).\n\
+                        Coarser levels are shown until then.",
+                        progress * 100.
+                    ));
+                }
                 if warn_origin_rotation {
                     ui_right
                         .label(
@@ -160,11 +169,19 @@ impl AppState {
                 ui.separator();
                 ui.add_enabled_ui(cfg!(not(target_arch = "wasm32")), |ui| {
                     self.load_session_button(ui);
+                    #[cfg(not(target_arch = "wasm32"))]
+                    {
+                        self.import_session_button(ui);
+                        self.recent_files_menu_button(ui);
+                    }
                 });
                 ui.add_enabled_ui(
-                    cfg!(not(target_arch = "wasm32")) && !self.data.maps.is_empty(),
+                    cfg!(not(target_arch = "wasm32"))
+                        && !(self.data.maps.is_empty() && self.data.missing_maps.is_empty()),
                     |ui| {
                         self.save_session_button(ui, false);
+                        #[cfg(not(target_arch = "wasm32"))]
+                        self.export_bundle_button(ui);
                     },
                 );
             })
@@ -173,6 +190,21 @@ impl AppState {
             .width();
         ui.separator();
 
+        if !self.data.missing_maps.is_empty()
+            && ui
+                .button(
+                    egui::RichText::new(format!(
+                        "⚠ {} missing map(s)",
+                        self.data.missing_maps.len()
+                    ))
+                    .color(egui::Color32::ORANGE),
+                )
+                .on_hover_text("Some maps of the session could not be loaded. Click for details.")
+                .clicked()
+        {
+            self.status.missing_maps_window_active = true;
+        }
+
         if self.data.maps.is_empty() {
             ui.label(egui::RichText::new("No maps loaded.").weak().italics());
             return;
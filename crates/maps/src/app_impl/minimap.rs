@@ -0,0 +1,122 @@
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+
+use crate::app::AppState;
+use crate::grid::Grid;
+use crate::grid_options::{GridOptions, MarkerVisibility};
+use crate::map_state::MapState;
+
+/// Distance of the minimap to the corner of the grid, in points.
+const MINIMAP_MARGIN: f32 = 10.;
+/// Empty space around the maps in the minimap, relative to their extent.
+const MINIMAP_PADDING: f32 = 0.05;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MinimapOptions {
+    pub visible: bool,
+    /// Maximum width and height of the minimap, in points.
+    pub size: f32,
+    pub background_color: egui::Color32,
+    pub viewport_stroke: egui::Stroke,
+}
+
+impl Default for MinimapOptions {
+    fn default() -> Self {
+        MinimapOptions {
+            visible: false,
+            size: 200.,
+            background_color: egui::Color32::from_black_alpha(160),
+            viewport_stroke: egui::Stroke::new(1.5, egui::Color32::ORANGE),
+        }
+    }
+}
+
+/// Corners of a map in the root frame, in meters.
+fn map_corners(map: &MapState) -> [egui::Pos2; 4] {
+    let size = map.image_pyramid.original_size;
+    [(0., 0.), (size.x, 0.), (0., size.y), (size.x, size.y)].map(|(x, y)| {
+        map.pose
+            .transform(map.meta.pixel_to_map(egui::pos2(x, y), size.y))
+    })
+}
+
+impl AppState {
+    /// Metric bounding box of all visible maps, `None` if no map is visible.
+    fn visible_maps_bounds(&self) -> Option<egui::Rect> {
+        let corners: Vec<egui::Pos2> = self
+            .data
+            .maps
+            .values()
+            .filter(|map| map.visible)
+            .flat_map(map_corners)
+            .collect();
+        (!corners.is_empty()).then(|| egui::Rect::from_points(&corners))
+    }
+
+    /// Overview of all visible maps in a corner of the main grid with the current viewport.
+    /// Clicking or dragging in the minimap moves the main grid there.
+    pub(crate) fn show_minimap(&mut self, ui: &mut egui::Ui, main_grid: &Grid) {
+        if !self.options.minimap.visible {
+            return;
+        }
+        let Some(bounds) = self.visible_maps_bounds() else {
+            return;
+        };
+        let bounds = bounds.expand(bounds.size().max_elem() * MINIMAP_PADDING);
+
+        // Fit the minimap to the aspect ratio of the maps.
+        let options = &self.options.minimap;
+        let points_per_meter = options.size / bounds.size().max_elem();
+        let size = (bounds.size() * points_per_meter).max(egui::Vec2::splat(MINIMAP_MARGIN));
+        let main_rect = main_grid.response().rect;
+        let rect = egui::Rect::from_min_size(
+            main_rect.right_bottom() - size - egui::Vec2::splat(MINIMAP_MARGIN),
+            size,
+        );
+        if !main_rect.contains_rect(rect) {
+            // Not enough space, e.g. in a small window.
+            return;
+        }
+
+        let mut minimap_ui = ui.new_child(egui::UiBuilder::new().max_rect(rect));
+        let minimap =
+            Grid::new(&mut minimap_ui, "minimap", points_per_meter).centered_at(bounds.center());
+        minimap.draw_background(options.background_color);
+        // Keep the minimap clean, without map origin markers.
+        let minimap_grid_options = GridOptions {
+            marker_visibility: MarkerVisibility::None,
+            ..Default::default()
+        };
+        minimap.show_maps(
+            &mut minimap_ui,
+            &mut self.data.maps,
+            &minimap_grid_options,
+            &self.data.draw_order,
+        );
+        let viewport = egui::Rect::from_two_pos(
+            main_grid.to_metric(main_rect.min),
+            main_grid.to_metric(main_rect.max),
+        );
+        minimap.draw_rect(viewport, options.viewport_stroke);
+        minimap_ui.painter().rect_stroke(
+            rect,
+            0.,
+            ui.visuals().window_stroke(),
+            egui::StrokeKind::Inside,
+        );
+
+        let response = minimap.response();
+        if (response.clicked() || response.dragged())
+            && let Some(pointer_pos) = response.interact_pointer_pos()
+        {
+            // Center the main grid at the clicked position.
+            let target = minimap.to_metric(pointer_pos);
+            self.options.grid.offset = egui::vec2(-target.x, target.y) * self.options.grid.scale;
+            self.status.viewpoint_transition = None;
+        }
+        if response.hovered() {
+            ui.ctx().set_cursor_icon(egui::CursorIcon::Crosshair);
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use eframe::egui;
+
+use crate::app::AppState;
+use crate::app_impl::ui_helpers::display_path;
+
+#[cfg(not(target_arch = "wasm32"))]
+use {
+    crate::app_impl::load_delete::load_meta_file, crate::map_state::loaded_draw_order_index,
+    log::error, std::path::Path,
+};
+
+enum MissingMapAction {
+    #[cfg(not(target_arch = "wasm32"))]
+    Locate(String),
+    Forget(String),
+}
+
+impl AppState {
+    /// Report of the maps of loaded sessions that could not be loaded.
+    pub(crate) fn missing_maps_window(&mut self, ui: &mut egui::Ui) {
+        if self.data.missing_maps.is_empty() {
+            self.status.missing_maps_window_active = false;
+        }
+        if !self.status.missing_maps_window_active {
+            return;
+        }
+        let mut action = None;
+        egui::Window::new("⚠ Missing maps")
+            .open(&mut self.status.missing_maps_window_active)
+            .default_width(500.)
+            .show(ui.ctx(), |ui| {
+                ui.label(
+                    "These maps of the session could not be loaded. \
+                    Their pose, tint and draw order are kept and saved with the session.",
+                );
+                ui.add_space(5.);
+                egui::Grid::new("missing_maps")
+                    .striped(true)
+                    .num_columns(3)
+                    .show(ui, |ui| {
+                        for (name, missing) in &self.data.missing_maps {
+                            ui.label(display_path(name, self.options.display.show_full_paths))
+                                .on_hover_text(name);
+                            ui.label(egui::RichText::new(&missing.error).weak().small());
+                            ui.horizontal(|ui| {
+                                #[cfg(not(target_arch = "wasm32"))]
+                                if ui
+                                    .button("📂 Locate…")
+                                    .on_hover_text("Point the map to a new file location.")
+                                    .clicked()
+                                {
+                                    action = Some(MissingMapAction::Locate(name.clone()));
+                                }
+                                if ui
+                                    .button("🗑")
+                                    .on_hover_text("Remove the map from the session.")
+                                    .clicked()
+                                {
+                                    action = Some(MissingMapAction::Forget(name.clone()));
+                                }
+                            });
+                            ui.end_row();
+                        }
+                    });
+            });
+
+        match action {
+            #[cfg(not(target_arch = "wasm32"))]
+            Some(MissingMapAction::Locate(name)) => self.locate_missing_map(&name),
+            Some(MissingMapAction::Forget(name)) => {
+                self.data.missing_maps.remove(&name);
+                self.status.unsaved_changes = true;
+            }
+            None => {}
+        }
+    }
+
+    /// Lets the user pick the new location of a missing map (YAML, GeoTIFF or image file)
+    /// and loads it with the state from the session.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn locate_missing_map(&mut self, name: &str) {
+        let Some(missing) = self.data.missing_maps.get(name) else {
+            return;
+        };
+        let mut dialog = rfd::FileDialog::new()
+            .set_title(format!("Locate {name}"))
+            .add_filter("Map YAML / GeoTIFF", &["yaml", "yml", "tif", "tiff"])
+            .add_filter("Image", &["png", "pgm", "jpg", "jpeg", "bmp"]);
+        if let Some(dir) = &self.last_file_dir {
+            dialog = dialog.set_directory(dir);
+        }
+        let Some(path) = dialog.pick_file() else {
+            return;
+        };
+
+        // Keep the metadata of the session, only the file locations change.
+        let mut meta = missing.state.meta.clone();
+        let is_meta_file = path.extension().is_some_and(|ext| {
+            ["yaml", "yml", "tif", "tiff"]
+                .iter()
+                .any(|e| ext.eq_ignore_ascii_case(e))
+        });
+        if is_meta_file {
+            match load_meta_file(&path) {
+                Ok(located) => {
+                    meta.image_path = located.image_path;
+                    meta.yaml_path = located.yaml_path;
+                }
+                Err(e) => {
+                    self.status.error = e.to_string();
+                    error!("{e}");
+                    return;
+                }
+            }
+        } else {
+            meta.image_path.clone_from(&path);
+        }
+
+        let missing = self.data.missing_maps.remove(name).expect("missing map");
+        match self.restore_map(name, meta, &missing.state) {
+            Ok(map_name) => {
+                if let Some(index) = missing.draw_order_index {
+                    let index = loaded_draw_order_index(&self.data.missing_maps, index);
+                    self.data.draw_order.insert_at(map_name, index);
+                }
+                self.last_file_dir = path.parent().map(Path::to_path_buf);
+                self.status.unsaved_changes = true;
+            }
+            Err(e) => {
+                self.status.error = e.to_string();
+                error!("{e}");
+                self.data.missing_maps.insert(name.to_string(), missing);
+            }
+        }
+    }
+}
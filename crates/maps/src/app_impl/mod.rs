@@ -1,23 +1,36 @@
 pub(crate) mod app_settings;
 pub(crate) mod canvas_settings;
 pub(crate) mod central_panel;
-mod compat;
+mod color_legend;
+pub(crate) mod compat;
 pub(crate) mod constants;
 pub(crate) mod debug_window;
 pub(crate) mod error_modal;
 pub(crate) mod footer_panel;
+pub(crate) mod georef_settings;
 mod grid_settings;
 pub(crate) mod header_panel;
 pub(crate) mod info_window;
 pub(crate) mod keys;
 mod lens_settings;
 pub(crate) mod load_delete;
+pub(crate) mod map_export;
 pub(crate) mod menu_panel;
+pub(crate) mod minimap;
+pub(crate) mod missing_maps;
 pub(crate) mod pose_edit;
 pub(crate) mod quit_modal;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) mod recent_files;
 pub(crate) mod screenshot;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) mod session_import;
 pub(crate) mod settings_panel;
+pub(crate) mod statistics_window;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) mod svg_export;
 pub(crate) mod tint_settings;
 pub(crate) mod ui_helpers;
+pub(crate) mod viewpoints;
 
 pub use constants::CUSTOM_TITLEBAR_SUPPORTED;
@@ -142,6 +142,10 @@ impl AppState {
                     self.save_map_pose_button(ui, map_name.as_str());
                 });
             });
+            #[cfg(not(target_arch = "wasm32"))]
+            ui.vertical_centered_justified(|ui| {
+                self.save_geotiff_button(ui, map_name.as_str());
+            });
 
             ui.add_space(SPACE);
 
@@ -0,0 +1,179 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use eframe::egui;
+use log::error;
+
+use crate::app::AppState;
+use crate::app_impl::constants::SPACE;
+use crate::app_impl::ui_helpers::display_path;
+
+enum RecentFileAction {
+    OpenSession(PathBuf),
+    OpenMap(PathBuf),
+    Remove(PathBuf),
+    RemoveMissing,
+}
+
+/// Recent files that no longer exist. Checked when the recent files are shown again
+/// after they were hidden, e.g. when the menu is opened, not in every frame.
+#[derive(Default)]
+pub struct RecentFilesState {
+    missing: HashSet<PathBuf>,
+    last_shown_frame: Option<u64>,
+}
+
+/// Lists recent files as buttons that open them.
+/// Files that no longer exist are disabled and can be removed from the list.
+fn recent_files_list(
+    ui: &mut egui::Ui,
+    files: &[PathBuf],
+    missing: &HashSet<PathBuf>,
+    show_full_paths: bool,
+    open: fn(PathBuf) -> RecentFileAction,
+    action: &mut Option<RecentFileAction>,
+) {
+    for path in files {
+        let exists = !missing.contains(path);
+        let path_str = path.to_string_lossy();
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(
+                    exists,
+                    egui::Button::new(display_path(&path_str, show_full_paths)).frame(false),
+                )
+                .on_hover_text(path_str.as_ref())
+                .on_disabled_hover_text(format!("{path_str} no longer exists."))
+                .clicked()
+            {
+                *action = Some(open(path.clone()));
+            }
+            if !exists
+                && ui
+                    .small_button("🗑")
+                    .on_hover_text("Remove from the list.")
+                    .clicked()
+            {
+                *action = Some(RecentFileAction::Remove(path.clone()));
+            }
+        });
+    }
+}
+
+impl AppState {
+    fn update_missing_recent_files(&mut self, ctx: &egui::Context) {
+        let frame = ctx.cumulative_frame_nr();
+        let state = &mut self.recent_files;
+        let shown_before = state.last_shown_frame.is_some_and(|last| last + 1 >= frame);
+        state.last_shown_frame = Some(frame);
+        if shown_before {
+            return;
+        }
+        let recent_files = &self.options.recent_files;
+        state.missing = recent_files
+            .sessions
+            .iter()
+            .chain(&recent_files.maps)
+            .filter(|path| !path.exists())
+            .cloned()
+            .collect();
+    }
+
+    fn recent_files_content(&self, ui: &mut egui::Ui) -> Option<RecentFileAction> {
+        let mut action = None;
+        let recent_files = &self.options.recent_files;
+        let show_full_paths = self.options.display.show_full_paths;
+        if !recent_files.sessions.is_empty() {
+            ui.label(egui::RichText::new("Sessions").strong());
+            recent_files_list(
+                ui,
+                &recent_files.sessions,
+                &self.recent_files.missing,
+                show_full_paths,
+                RecentFileAction::OpenSession,
+                &mut action,
+            );
+        }
+        if !recent_files.maps.is_empty() {
+            ui.label(egui::RichText::new("Maps").strong());
+            recent_files_list(
+                ui,
+                &recent_files.maps,
+                &self.recent_files.missing,
+                show_full_paths,
+                RecentFileAction::OpenMap,
+                &mut action,
+            );
+        }
+        if recent_files
+            .sessions
+            .iter()
+            .chain(&recent_files.maps)
+            .any(|path| self.recent_files.missing.contains(path))
+        {
+            ui.separator();
+            if ui
+                .button("Remove missing files")
+                .on_hover_text("Remove all files that no longer exist from the lists.")
+                .clicked()
+            {
+                action = Some(RecentFileAction::RemoveMissing);
+            }
+        }
+        action
+    }
+
+    fn handle_recent_file_action(&mut self, action: RecentFileAction) {
+        let result = match action {
+            RecentFileAction::OpenSession(path) => self.load_session_or_bundle(&path),
+            RecentFileAction::OpenMap(path) => self.load_meta(&path).map(|_| {
+                self.last_file_dir = path.parent().map(Path::to_path_buf);
+            }),
+            RecentFileAction::Remove(path) => {
+                self.options.recent_files.remove(&path);
+                Ok(())
+            }
+            RecentFileAction::RemoveMissing => {
+                self.options.recent_files.remove_missing();
+                Ok(())
+            }
+        };
+        if let Err(e) = result {
+            self.status.error = e.to_string();
+            error!("{e}");
+        }
+    }
+
+    /// Recently opened sessions and maps on the load screen.
+    pub(crate) fn recent_files_load_screen(&mut self, ui: &mut egui::Ui) {
+        if self.options.recent_files.is_empty() {
+            return;
+        }
+        ui.add_space(3. * SPACE);
+        ui.heading("Recent");
+        ui.add_space(SPACE);
+        self.update_missing_recent_files(ui.ctx());
+        if let Some(action) = self.recent_files_content(ui) {
+            self.handle_recent_file_action(action);
+        }
+    }
+
+    /// Menu button with the recently opened sessions and maps.
+    pub(crate) fn recent_files_menu_button(&mut self, ui: &mut egui::Ui) {
+        let mut action = None;
+        ui.add_enabled_ui(!self.options.recent_files.is_empty(), |ui| {
+            ui.menu_button("🕘", |ui| {
+                self.update_missing_recent_files(ui.ctx());
+                action = self.recent_files_content(ui);
+                if action.is_some() {
+                    ui.close();
+                }
+            })
+            .response
+            .on_hover_text("Recently opened sessions and maps.");
+        });
+        if let Some(action) = action {
+            self.handle_recent_file_action(action);
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use eframe::egui;
+use log::{error, info};
+
+use crate::app::{AppState, Error};
+use crate::app_impl::load_delete::resolve_map_paths;
+use crate::app_impl::ui_helpers::display_path;
+use crate::bundle;
+use crate::map_state::{MapState, loaded_draw_order_index};
+use crate::persistence;
+use crate::session_paths::PathResolver;
+
+/// How to handle imported maps that are already loaded.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+enum ConflictPolicy {
+    /// Keep the loaded map, don't import the map of the session.
+    #[default]
+    KeepLoaded,
+    /// Replace the loaded map with the map and state of the session.
+    Replace,
+}
+
+struct ImportEntry {
+    /// Name of the map in the imported session.
+    session_name: String,
+    map: MapState,
+    selected: bool,
+}
+
+impl ImportEntry {
+    /// Name of the map once it's loaded, see [`AppState::load_map`].
+    fn map_name(&self) -> String {
+        self.map.meta.yaml_path.to_string_lossy().into_owned()
+    }
+}
+
+/// A session file that maps are being imported from.
+struct SessionImport {
+    path: PathBuf,
+    /// Maps of the session, in the draw order of the session.
+    entries: Vec<ImportEntry>,
+    grid_lenses: HashMap<String, egui::Pos2>,
+}
+
+/// State of the window to import selected maps from another session.
+#[derive(Default)]
+pub struct SessionImportState {
+    import: Option<SessionImport>,
+    conflict_policy: ConflictPolicy,
+}
+
+impl AppState {
+    fn is_import_conflict(&self, entry: &ImportEntry) -> bool {
+        self.data.maps.contains_key(&entry.map_name())
+            || self.data.missing_maps.contains_key(&entry.session_name)
+    }
+
+    /// Button to pick a session file or bundle to import maps from.
+    pub(crate) fn import_session_button(&mut self, ui: &mut egui::Ui) {
+        if !ui
+            .button("📥")
+            .on_hover_text("Import selected maps from another session or session bundle.")
+            .clicked()
+        {
+            return;
+        }
+        let mut dialog = rfd::FileDialog::new()
+            .add_filter("TOML", &["toml"])
+            .add_filter("Session bundle", &[bundle::BUNDLE_EXTENSION]);
+        if let Some(dir) = &self.last_file_dir {
+            dialog = dialog.set_directory(dir);
+        }
+        if let Some(path) = dialog.pick_file() {
+            self.open_session_import(&path).unwrap_or_else(|e| {
+                self.status.error = e.to_string();
+                error!("{e}");
+            });
+        }
+    }
+
+    /// Reads a session (or extracts a bundle) and lists its maps in the import window.
+    fn open_session_import(&mut self, path: &Path) -> Result<(), Error> {
+        let session_path = if path
+            .extension()
+            .is_some_and(|ext| ext == bundle::BUNDLE_EXTENSION)
+        {
+            bundle::extract_bundle(path, &bundle::extraction_dir(path))?
+        } else {
+            path.to_path_buf()
+        };
+        // Migrates older sessions like when loading them, e.g. colors of maps < 1.7.0.
+        let mut session = persistence::load_session(&session_path)?;
+        let mut resolver = PathResolver::new(&session_path);
+
+        // Older sessions have no draw order, their remaining maps are appended.
+        let mut names: Vec<String> = session
+            .draw_order
+            .keys()
+            .iter()
+            .filter(|name| session.maps.contains_key(*name))
+            .cloned()
+            .collect();
+        names.extend(
+            session
+                .maps
+                .keys()
+                .filter(|name| !session.draw_order.contains(name))
+                .cloned(),
+        );
+        let entries = names
+            .into_iter()
+            .filter_map(|name| {
+                let mut map = session.maps.remove(&name)?;
+                resolve_map_paths(&mut map.meta, &mut resolver, false);
+                Some(ImportEntry {
+                    session_name: name,
+                    map,
+                    selected: true,
+                })
+            })
+            .collect();
+
+        self.last_file_dir = path.parent().map(Path::to_path_buf);
+        self.session_import.import = Some(SessionImport {
+            path: path.to_path_buf(),
+            entries,
+            grid_lenses: session.grid_lenses,
+        });
+        Ok(())
+    }
+
+    /// Lists the maps of the session to import and lets the user pick which to add.
+    pub(crate) fn session_import_window(&mut self, ui: &mut egui::Ui) {
+        let Some(import) = &self.session_import.import else {
+            return;
+        };
+        let conflicts: Vec<bool> = import
+            .entries
+            .iter()
+            .map(|entry| self.is_import_conflict(entry))
+            .collect();
+
+        let show_full_paths = self.options.display.show_full_paths;
+        let conflict_policy = &mut self.session_import.conflict_policy;
+        let Some(import) = &mut self.session_import.import else {
+            return;
+        };
+        let mut open = true;
+        let mut import_clicked = false;
+        egui::Window::new("📥 Import from session")
+            .open(&mut open)
+            .default_width(500.)
+            .show(ui.ctx(), |ui| {
+                let path = import.path.to_string_lossy();
+                ui.label(format!(
+                    "Select the maps to import from {}.",
+                    display_path(&path, show_full_paths)
+                ))
+                .on_hover_text(path.as_ref());
+                ui.horizontal(|ui| {
+                    if ui.button("All").clicked() {
+                        import.entries.iter_mut().for_each(|e| e.selected = true);
+                    }
+                    if ui.button("None").clicked() {
+                        import.entries.iter_mut().for_each(|e| e.selected = false);
+                    }
+                });
+                ui.add_space(5.);
+                egui::ScrollArea::vertical()
+                    .max_height(300.)
+                    .show(ui, |ui| {
+                        egui::Grid::new("session_import")
+                            .striped(true)
+                            .num_columns(2)
+                            .show(ui, |ui| {
+                                for (entry, conflict) in import.entries.iter_mut().zip(&conflicts) {
+                                    ui.checkbox(
+                                        &mut entry.selected,
+                                        display_path(&entry.session_name, show_full_paths),
+                                    )
+                                    .on_hover_text(&entry.session_name);
+                                    if *conflict {
+                                        ui.label(
+                                            egui::RichText::new("⚠ already loaded")
+                                                .color(egui::Color32::ORANGE),
+                                        );
+                                    } else if !entry.map.meta.image_path.exists() {
+                                        ui.label(egui::RichText::new("not found").weak())
+                                            .on_hover_text(
+                                                "The map is added as missing map and can be \
+                                            located later.",
+                                            );
+                                    } else {
+                                        ui.label("");
+                                    }
+                                    ui.end_row();
+                                }
+                            });
+                    });
+                ui.add_space(5.);
+                ui.horizontal(|ui| {
+                    ui.label("Already loaded maps:");
+                    ui.radio_value(conflict_policy, ConflictPolicy::KeepLoaded, "Keep")
+                        .on_hover_text("Keep the loaded map and its pose, tint etc.");
+                    ui.radio_value(conflict_policy, ConflictPolicy::Replace, "Replace")
+                        .on_hover_text(
+                            "Replace the loaded map with the pose, tint etc. from the session.\n\
+                            The map keeps its place in the draw order.",
+                        );
+                });
+                ui.separator();
+                let num_selected = import.entries.iter().filter(|e| e.selected).count();
+                import_clicked = ui
+                    .add_enabled(
+                        num_selected > 0,
+                        egui::Button::new(format!("📥 Import {num_selected} map(s)")),
+                    )
+                    .clicked();
+            });
+
+        if import_clicked {
+            let import = self.session_import.import.take().expect("session import");
+            self.import_maps(import);
+        } else if !open {
+            self.session_import.import = None;
+        }
+    }
+
+    /// Adds the selected maps of a session to the current one, with their poses
+    /// and relative draw order. Lenses of the session are added if their IDs are free.
+    fn import_maps(&mut self, import: SessionImport) {
+        let conflict_policy = self.session_import.conflict_policy;
+        let mut num_imported = 0;
+        for entry in import.entries.into_iter().filter(|e| e.selected) {
+            let map_name = entry.map_name();
+            let mut draw_order_index = None;
+            if self.is_import_conflict(&entry) {
+                if conflict_policy == ConflictPolicy::KeepLoaded {
+                    info!("Not importing {map_name}, it's already loaded.");
+                    continue;
+                }
+                draw_order_index = self.data.draw_order.position(&map_name).or_else(|| {
+                    self.data
+                        .missing_maps
+                        .get(&entry.session_name)
+                        .and_then(|missing| missing.draw_order_index)
+                        .map(|index| loaded_draw_order_index(&self.data.missing_maps, index))
+                });
+                self.delete(&vec![map_name.clone()]);
+                self.data.missing_maps.remove(&entry.session_name);
+            }
+            match self.restore_map(&map_name, entry.map.meta.clone(), &entry.map) {
+                Ok(map_name) => {
+                    if let Some(index) = draw_order_index {
+                        self.data.draw_order.insert_at(map_name, index);
+                    }
+                    num_imported += 1;
+                }
+                Err(e) => {
+                    self.add_missing_map(entry.session_name, entry.map, &e, draw_order_index);
+                }
+            }
+        }
+        for (id, lens_pos) in import.grid_lenses {
+            self.data.grid_lenses.entry(id).or_insert(lens_pos);
+        }
+        info!("Imported {num_imported} map(s) from {:?}", import.path);
+        self.status.unsaved_changes = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use maps_io_ros::Meta;
+
+    use super::*;
+    use crate::app::AppOptions;
+
+    const SESSION: &str = "tests/sessions/schema_v2_session.toml";
+    const LORES_MAP: &str = "data/dummy_maps/dummy_map_lores.yaml";
+    const ROT_MAP: &str = "data/dummy_maps/dummy_map_rot.yaml";
+
+    fn lores_map(state: &AppState) -> &MapState {
+        let name = &state.data.draw_order.keys()[0];
+        assert!(name.ends_with(LORES_MAP));
+        &state.data.maps[name]
+    }
+
+    /// Loads both maps of the session without their session state,
+    /// then imports the lores map from the session. The draw order must not change,
+    /// a replaced map keeps its position instead of being moved to the end.
+    fn import_lores_map(conflict_policy: ConflictPolicy) -> AppState {
+        let mut options = AppOptions::default();
+        options.advanced.dry_run = true;
+        let metas = [LORES_MAP, ROT_MAP]
+            .map(|path| Meta::load_from_file(&PathBuf::from(path)).expect("Failed to load map"));
+        let mut state = AppState::init(metas.into(), options).expect("Failed to init");
+        let loaded_names = state.data.draw_order.keys().clone();
+
+        let mut session =
+            persistence::load_session(&PathBuf::from(SESSION)).expect("Failed to load session");
+        let mut map = session.maps.remove(LORES_MAP).expect("map");
+        // Like a session saved with absolute paths.
+        for path in [&mut map.meta.yaml_path, &mut map.meta.image_path] {
+            *path = std::path::absolute(&path).expect("Failed to make path absolute");
+        }
+        let entries = vec![ImportEntry {
+            session_name: map.meta.yaml_path.to_string_lossy().into_owned(),
+            map,
+            selected: true,
+        }];
+        state.session_import.conflict_policy = conflict_policy;
+        state.import_maps(SessionImport {
+            path: PathBuf::from(SESSION),
+            entries,
+            grid_lenses: HashMap::new(),
+        });
+        assert_eq!(state.data.draw_order.keys(), &loaded_names);
+        state
+    }
+
+    #[test]
+    fn import_keeps_loaded_map() {
+        let state = import_lores_map(ConflictPolicy::KeepLoaded);
+        let map = lores_map(&state);
+        assert_eq!(map.pose.rotation.yaw, 0.);
+        assert_eq!(map.pose.root_frame, "");
+    }
+
+    #[test]
+    fn import_replaces_loaded_map_in_place() {
+        let state = import_lores_map(ConflictPolicy::Replace);
+        assert_eq!(state.data.maps.len(), 2);
+        let map = lores_map(&state);
+        assert_eq!(map.pose.rotation.yaw, 0.25);
+        assert_eq!(map.pose.root_frame, "foo");
+    }
+}
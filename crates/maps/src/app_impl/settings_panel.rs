@@ -26,6 +26,9 @@ impl AppState {
                                 self.tint_settings(ui);
                                 ui.end_row();
                                 ui.end_row();
+                                self.map_export_settings(ui);
+                                ui.end_row();
+                                ui.end_row();
                             }
 
                             if !matches!(
@@ -42,6 +45,12 @@ impl AppState {
                                 ui.end_row();
                                 ui.end_row();
                                 self.tool_settings(ui);
+                                ui.end_row();
+                                ui.end_row();
+                                self.viewpoint_settings(ui);
+                                ui.end_row();
+                                ui.end_row();
+                                self.georef_settings(ui);
                             }
                         });
                 });
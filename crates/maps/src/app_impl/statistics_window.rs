@@ -0,0 +1,330 @@
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+
+use eframe::egui;
+use egui_plot::{Bar, BarChart, Plot};
+
+use crate::app::AppState;
+use crate::app_impl::ui_helpers::display_path;
+use crate::map_state::MapState;
+use maps_io_ros::statistics::{HISTOGRAM_BINS, MapStatistics};
+use maps_io_ros::value_interpretation::ValueInterpretation;
+use maps_rendering::ImagePyramid;
+
+const MAX_COL_WIDTH: f32 = 300.0;
+
+/// Everything the statistics of a map depend on.
+#[derive(PartialEq)]
+struct StatisticsKey {
+    value_interpretation: ValueInterpretation,
+    resolution: f32,
+    origin_xy: egui::Vec2,
+    origin_theta: egui::emath::Rot2,
+}
+
+impl StatisticsKey {
+    fn new(map: &MapState) -> StatisticsKey {
+        StatisticsKey {
+            value_interpretation: map.meta.value_interpretation,
+            resolution: map.meta.resolution,
+            origin_xy: map.meta.origin_xy,
+            origin_theta: map.meta.origin_theta,
+        }
+    }
+}
+
+struct CachedStatistics {
+    key: StatisticsKey,
+    image_pyramid: Arc<ImagePyramid>,
+    pyramid_generation: usize,
+    /// Set by the background computation when it is done.
+    statistics: Arc<OnceLock<Arc<MapStatistics>>>,
+    /// Shown while the statistics are being recomputed.
+    previous: Option<Arc<MapStatistics>>,
+}
+
+impl CachedStatistics {
+    fn latest(&self) -> Option<Arc<MapStatistics>> {
+        self.statistics.get().or(self.previous.as_ref()).cloned()
+    }
+}
+
+/// State of the statistics window. Statistics are cached per map
+/// and only recomputed when the map or its value interpretation changes.
+/// Large maps take a while, so they are computed in a background thread.
+#[derive(Default)]
+pub struct StatisticsState {
+    pub selected: Option<String>,
+    pub log_scale: bool,
+    cache: HashMap<String, CachedStatistics>,
+}
+
+impl StatisticsState {
+    /// Returns the statistics of the map, or the outdated ones while they are recomputed.
+    /// `None` until the first computation for this map is done.
+    fn get(
+        &mut self,
+        ctx: &egui::Context,
+        name: &str,
+        map: &MapState,
+    ) -> Option<Arc<MapStatistics>> {
+        let key = StatisticsKey::new(map);
+        let pyramid_generation = map.image_pyramid.generation();
+        if let Some(cached) = self.cache.get(name) {
+            let up_to_date = cached.key == key
+                && Arc::ptr_eq(&cached.image_pyramid, &map.image_pyramid)
+                && cached.pyramid_generation == pyramid_generation;
+            // Don't pile up computations while e.g. a threshold is dragged,
+            // the next one starts when the pending one is done.
+            if up_to_date || cached.statistics.get().is_none() {
+                return cached.latest();
+            }
+        }
+        let previous = self.cache.get(name).and_then(CachedStatistics::latest);
+        // Streamed maps are not in memory in full resolution, their statistics are
+        // approximated with the finest pyramid level and the resolution scaled to it.
+        let image = map.image_pyramid.finest_image();
+        let mut meta = map.meta.clone();
+        meta.resolution *= map.image_pyramid.original_size.x / image.width().max(1) as f32;
+        let has_alpha = map.image_pyramid.original_has_alpha;
+        let statistics = Arc::new(OnceLock::new());
+        let compute = {
+            let statistics = statistics.clone();
+            let ctx = ctx.clone();
+            move || {
+                let _ = statistics.set(Arc::new(MapStatistics::compute(&meta, &image, has_alpha)));
+                ctx.request_repaint();
+            }
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        std::thread::spawn(compute);
+        #[cfg(target_arch = "wasm32")]
+        compute();
+        let cached = CachedStatistics {
+            key,
+            image_pyramid: map.image_pyramid.clone(),
+            pyramid_generation,
+            statistics,
+            previous,
+        };
+        let latest = cached.latest();
+        self.cache.insert(name.to_string(), cached);
+        latest
+    }
+}
+
+fn computing_label(ui: &mut egui::Ui) {
+    ui.horizontal(|ui| {
+        ui.spinner();
+        ui.label("Computing statistics...");
+    });
+}
+
+fn format_area(area: f64) -> String {
+    format!("{area:.2} m²")
+}
+
+fn format_count_area(stats: &MapStatistics, count: u64) -> String {
+    let percent = match stats.num_cells() {
+        0 => 0.,
+        n => count as f64 / n as f64 * 100.,
+    };
+    format!(
+        "{count} ({}, {percent:.1} %)",
+        format_area(stats.area(count))
+    )
+}
+
+fn format_bounds(stats: &MapStatistics) -> String {
+    match stats.known_bounds {
+        Some(bounds) => format!(
+            "x {:.2}..{:.2}, y {:.2}..{:.2} ({:.2} x {:.2} m)",
+            bounds.min.x,
+            bounds.max.x,
+            bounds.min.y,
+            bounds.max.y,
+            bounds.width(),
+            bounds.height()
+        ),
+        None => "no known cells".to_string(),
+    }
+}
+
+fn histogram_plot(ui: &mut egui::Ui, stats: &MapStatistics, log_scale: bool) {
+    let histogram = &stats.histogram;
+    let bin_width = ((histogram.range.max - histogram.range.min) / HISTOGRAM_BINS as f32)
+        .abs()
+        .max(f32::EPSILON) as f64;
+    let bars = histogram
+        .counts
+        .iter()
+        .enumerate()
+        .filter(|(_, count)| **count > 0)
+        .map(|(bin, &count)| {
+            let height = if log_scale {
+                (count as f64).log10() + 1.
+            } else {
+                count as f64
+            };
+            Bar::new(histogram.bin_value(bin) as f64, height)
+                .width(bin_width)
+                .name(format!("{count} pixels"))
+        })
+        .collect();
+    Plot::new("histogram")
+        .view_aspect(2.0)
+        .allow_scroll(false)
+        .show_y(!log_scale)
+        .show(ui, |plot_ui| {
+            plot_ui.bar_chart(BarChart::new("raw values", bars));
+        });
+}
+
+fn statistics_grid(ui: &mut egui::Ui, stats: &MapStatistics) {
+    egui::Grid::new("map_statistics")
+        .striped(true)
+        .num_columns(2)
+        .max_col_width(MAX_COL_WIDTH)
+        .show(ui, |ui| {
+            ui.label("Size");
+            ui.label(format!(
+                "{} x {} cells, {}",
+                stats.size.0,
+                stats.size.1,
+                format_area(stats.area(stats.num_cells()))
+            ));
+            ui.end_row();
+            ui.label("Free");
+            ui.label(format_count_area(stats, stats.free));
+            ui.end_row();
+            ui.label("Occupied");
+            ui.label(format_count_area(stats, stats.occupied));
+            ui.end_row();
+            if stats.intermediate > 0 {
+                ui.label("Intermediate")
+                    .on_hover_text("Cells between the free and occupied thresholds (scale mode).");
+                ui.label(format_count_area(stats, stats.intermediate));
+                ui.end_row();
+            }
+            ui.label("Unknown");
+            ui.label(format_count_area(stats, stats.unknown));
+            ui.end_row();
+            ui.label("Known bounds").on_hover_text(
+                "Bounding box of all known cells in map frame coordinates.\n\
+                Large differences to the map size indicate unnecessary unknown borders.",
+            );
+            ui.label(format_bounds(stats));
+            ui.end_row();
+            if stats.histogram.invalid > 0 {
+                ui.label("Invalid values");
+                ui.label(format!("{} (NaN)", stats.histogram.invalid));
+                ui.end_row();
+            }
+        });
+}
+
+impl AppState {
+    pub(crate) fn statistics_window(&mut self, ui: &mut egui::Ui) {
+        if !self.status.statistics_window_active {
+            return;
+        }
+        let mut open = true;
+        egui::Window::new("Statistics")
+            .open(&mut open)
+            .default_width(500.)
+            .show(ui.ctx(), |ui| {
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    self.statistics_content(ui);
+                });
+            });
+        self.status.statistics_window_active = open;
+    }
+
+    fn statistics_content(&mut self, ui: &mut egui::Ui) {
+        let statistics = &mut self.statistics;
+        statistics
+            .cache
+            .retain(|name, _| self.data.maps.contains_key(name));
+        if self.data.maps.is_empty() {
+            ui.label(egui::RichText::new("No maps loaded.").weak().italics());
+            return;
+        }
+        ui.label(
+            "Cells are counted with the current value interpretation of each map \
+            (thresholds as in trinary mode if the mode is raw).",
+        );
+
+        egui::CollapsingHeader::new("Map")
+            .default_open(true)
+            .show(ui, |ui| {
+                let show_full_paths = self.options.display.show_full_paths;
+                let selected = statistics.selected.get_or_insert_with(|| {
+                    self.data.maps.keys().next().cloned().unwrap_or_default()
+                });
+                if !self.data.maps.contains_key(selected.as_str()) {
+                    selected.clone_from(self.data.maps.keys().next().expect("no maps"));
+                }
+                egui::ComboBox::from_id_salt("statistics_map")
+                    .selected_text(display_path(selected, show_full_paths))
+                    .show_ui(ui, |ui| {
+                        for name in self.data.maps.keys() {
+                            ui.selectable_value(
+                                selected,
+                                name.clone(),
+                                display_path(name, show_full_paths),
+                            );
+                        }
+                    });
+                let name = selected.clone();
+                let Some(stats) = statistics.get(ui.ctx(), &name, &self.data.maps[&name]) else {
+                    computing_label(ui);
+                    return;
+                };
+                statistics_grid(ui, &stats);
+                ui.horizontal(|ui| {
+                    ui.label("Histogram of raw pixel values");
+                    ui.checkbox(&mut statistics.log_scale, "log scale");
+                });
+                histogram_plot(ui, &stats, statistics.log_scale);
+            });
+
+        egui::CollapsingHeader::new("Comparison")
+            .default_open(true)
+            .show(ui, |ui| {
+                egui::Grid::new("statistics_comparison")
+                    .striped(true)
+                    .max_col_width(MAX_COL_WIDTH)
+                    .show(ui, |ui| {
+                        for heading in [
+                            "Map",
+                            "Resolution",
+                            "Size (cells)",
+                            "Free (m²)",
+                            "Occupied (m²)",
+                            "Unknown (m²)",
+                            "Known",
+                        ] {
+                            ui.label(egui::RichText::new(heading).strong());
+                        }
+                        ui.end_row();
+                        for (name, map) in &self.data.maps {
+                            let label =
+                                ui.label(display_path(name, self.options.display.show_full_paths));
+                            let Some(stats) = statistics.get(ui.ctx(), name, map) else {
+                                computing_label(ui);
+                                ui.end_row();
+                                continue;
+                            };
+                            label.on_hover_text(format_bounds(&stats));
+                            ui.label(format!("{} m", stats.resolution));
+                            ui.label(format!("{} x {}", stats.size.0, stats.size.1));
+                            ui.label(format!("{:.2}", stats.area(stats.free)));
+                            ui.label(format!("{:.2}", stats.area(stats.occupied)));
+                            ui.label(format!("{:.2}", stats.area(stats.unknown)));
+                            ui.label(format!("{:.1} %", stats.known_fraction() * 100.));
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+}
@@ -0,0 +1,339 @@
+use eframe::egui;
+use log::{error, info};
+
+use crate::app::AppState;
+use crate::app_impl::central_panel::GEO_MARKER_STROKE;
+use crate::error::{Error, Result};
+use crate::grid::{Grid, LineDirection};
+use crate::grid_options::{GridOptions, LineType, SubLineVisibility};
+use crate::map_state::MapState;
+use crate::overlays::OverlayPainter;
+use crate::svg::SvgDocument;
+use maps_io_ros::MapPose;
+use maps_io_ros::map_edit::PixelRect;
+use maps_rendering::image::{apply_appearance, to_rgba8};
+
+/// Converts metric root frame positions to points of the SVG document,
+/// which covers exactly the area of the grid on screen.
+struct SvgView<'a> {
+    grid: &'a Grid,
+    rect: egui::Rect,
+}
+
+impl SvgView<'_> {
+    fn to_svg(&self, metric: egui::Pos2) -> egui::Pos2 {
+        self.grid.to_point(metric) - self.rect.min.to_vec2()
+    }
+}
+
+/// Draws the grid overlays into an SVG document, with the same layout as on screen.
+struct SvgOverlayPainter<'a> {
+    doc: &'a mut SvgDocument,
+    /// Measures texts with the UI fonts.
+    fonts: &'a egui::Painter,
+}
+
+impl OverlayPainter for SvgOverlayPainter<'_> {
+    fn rect_filled(&mut self, rect: egui::Rect, corner_radius: f32, color: egui::Color32) {
+        self.doc.rect_filled(rect, corner_radius, color);
+    }
+
+    fn rect_stroke(&mut self, rect: egui::Rect, corner_radius: f32, stroke: egui::Stroke) {
+        // SVG strokes are centered on the outline.
+        self.doc
+            .rect_stroke(rect.shrink(stroke.width / 2.), corner_radius, stroke);
+    }
+
+    fn line_segment(&mut self, points: [egui::Pos2; 2], stroke: egui::Stroke) {
+        self.doc.line_segment(points, stroke);
+    }
+
+    fn circle_filled(&mut self, center: egui::Pos2, radius: f32, color: egui::Color32) {
+        self.doc.circle_filled(center, radius, color);
+    }
+
+    fn arrow(&mut self, origin: egui::Pos2, vec: egui::Vec2, stroke: egui::Stroke) {
+        self.doc.arrow(origin, vec, stroke);
+    }
+
+    fn text(
+        &mut self,
+        pos: egui::Pos2,
+        anchor: egui::Align2,
+        text: &str,
+        font_id: &egui::FontId,
+        color: egui::Color32,
+    ) {
+        self.doc.text(pos, anchor, text, font_id, color);
+    }
+
+    fn text_width(&self, text: &str, font_id: &egui::FontId) -> f32 {
+        self.fonts.text_width(text, font_id)
+    }
+}
+
+/// Region of the map image that is visible in the view, in pixels of `image`.
+/// `scale` is the number of original pixels per image pixel, see [`map_image`].
+fn visible_pixels(
+    view: &SvgView,
+    map: &MapState,
+    image: &image::DynamicImage,
+    scale: egui::Vec2,
+) -> Option<PixelRect> {
+    let height = map.image_pyramid.original_size.y;
+    let corners = [
+        view.rect.left_top(),
+        view.rect.right_top(),
+        view.rect.left_bottom(),
+        view.rect.right_bottom(),
+    ]
+    .map(|point| {
+        let map_pos = map.pose.inverse_transform(view.grid.to_metric(point));
+        (map.meta.map_to_pixel(map_pos, height).to_vec2() / scale).to_pos2()
+    });
+    PixelRect::from_points(&corners)?.intersect(&PixelRect::of_image(image))
+}
+
+/// The visible region of a map image, with the same appearance as on screen.
+/// Only the region is processed and embedded, the rest of large maps is skipped.
+fn map_image(
+    map: &MapState,
+    image: &image::DynamicImage,
+    region: PixelRect,
+) -> image::DynamicImage {
+    let mut image = image.crop_imm(
+        region.x as u32,
+        region.y as u32,
+        region.width,
+        region.height,
+    );
+    apply_appearance(
+        &mut image,
+        map.color_to_alpha,
+        map.get_value_interpretation(),
+        map.image_pyramid.original_has_alpha,
+    );
+    let mut image = to_rgba8(image);
+    if let (Some(tint), Some(rgba)) = (map.tint, image.as_mut_rgba8()) {
+        // Same as the vertex color multiplication of the textures on screen.
+        let tint = tint.to_srgba_unmultiplied();
+        for pixel in rgba.pixels_mut() {
+            for (channel, factor) in pixel.0.iter_mut().zip(tint) {
+                *channel = (u16::from(*channel) * u16::from(factor) / 255) as u8;
+            }
+        }
+    }
+    image
+}
+
+fn axes(doc: &mut SvgDocument, view: &SvgView, options: &GridOptions, pose: Option<&MapPose>) {
+    let points_per_meter = view.grid.points_per_meter;
+    let width = options.marker_width_meters * points_per_meter;
+    let length = options.marker_length_meters * points_per_meter;
+    let (pos, rotation) = match pose {
+        Some(p) => (view.to_svg(p.vec2().to_pos2()), p.rot2().inverse()),
+        None => (view.to_svg(egui::Pos2::ZERO), egui::emath::Rot2::IDENTITY),
+    };
+    doc.line_segment(
+        [pos, pos + rotation * egui::vec2(length, 0.)],
+        egui::Stroke::new(width, options.marker_x_color),
+    );
+    doc.line_segment(
+        [pos, pos - rotation * egui::vec2(0., length)],
+        egui::Stroke::new(width, options.marker_y_color),
+    );
+    doc.circle_filled(pos, width / 2., options.marker_z_color);
+}
+
+fn grid_lines(doc: &mut SvgDocument, view: &SvgView, options: &GridOptions, line_type: &LineType) {
+    let grid = view.grid;
+    let spacing_points = grid.line_spacing_points(options, line_type);
+    let stroke = match line_type {
+        LineType::Main => options.line_stroke,
+        LineType::Sub => options.sub_lines_stroke,
+    };
+    let size = view.rect.size();
+    let font_size = Grid::tick_label_font_size(spacing_points);
+    let font_id = egui::FontId::new(font_size, egui::FontFamily::Monospace);
+    let labels_visible = *line_type == LineType::Main && options.tick_labels_visible;
+
+    for coord in grid.line_coords(spacing_points, &LineDirection::Vertical) {
+        let x = coord - view.rect.min.x;
+        doc.line_segment([egui::pos2(x, 0.), egui::pos2(x, size.y)], stroke);
+        if labels_visible {
+            let value = (coord - grid.origin_in_points.x) / grid.points_per_meter;
+            doc.text(
+                egui::pos2(x + stroke.width, size.y - font_size / 2.),
+                egui::Align2::LEFT_CENTER,
+                &format!("{value:.1}"),
+                &font_id,
+                options.tick_labels_color,
+            );
+        }
+    }
+    for coord in grid.line_coords(spacing_points, &LineDirection::Horizontal) {
+        let y = coord - view.rect.min.y;
+        doc.line_segment([egui::pos2(0., y), egui::pos2(size.x, y)], stroke);
+        if labels_visible {
+            let value = (grid.origin_in_points.y - coord) / grid.points_per_meter;
+            doc.text(
+                egui::pos2(0., y + stroke.width + font_size / 2.),
+                egui::Align2::LEFT_CENTER,
+                &format!("{value:.1}"),
+                &font_id,
+                options.tick_labels_color,
+            );
+        }
+    }
+}
+
+fn measurement(doc: &mut SvgDocument, view: &SvgView, options: &GridOptions) {
+    let (Some(start_metric), Some(end_metric)) = (options.measure_start, options.measure_end)
+    else {
+        return;
+    };
+    let stroke = options.measure_stroke;
+    let (start, end) = (view.to_svg(start_metric), view.to_svg(end_metric));
+    doc.circle_filled(start, stroke.width * 2., stroke.color);
+    doc.line_segment([start, end], stroke);
+    doc.circle_filled(end, stroke.width * 2., stroke.color);
+    doc.text(
+        end,
+        egui::Align2::LEFT_BOTTOM,
+        &format!("{:.3} m", (end_metric - start_metric).length()),
+        &egui::FontId::new(15., egui::FontFamily::Monospace),
+        stroke.color,
+    );
+}
+
+impl AppState {
+    /// Asks for a file and saves the grid as SVG, if requested in the export settings.
+    /// Called while the grid is shown, to export exactly the visible area.
+    pub(crate) fn handle_svg_export_request(&mut self, ui: &egui::Ui, grid: &Grid) {
+        if !std::mem::take(&mut self.options.export.svg_requested) {
+            return;
+        }
+        let mut dialog = rfd::FileDialog::new()
+            .add_filter("SVG", &["svg"])
+            .set_file_name("maps_view.svg");
+        if let Some(dir) = &self.last_file_dir {
+            dialog = dialog.set_directory(dir);
+        }
+        let Some(path) = dialog.save_file() else {
+            return;
+        };
+        let result = self.grid_to_svg(ui, grid).and_then(|svg| {
+            std::fs::write(&path, svg)
+                .map_err(|e| Error::io(format!("Failed to write SVG to {path:?}"), e))
+        });
+        match result {
+            Ok(()) => {
+                info!("Saved SVG of the aligned view to {path:?}");
+                self.last_file_dir = path.parent().map(std::path::Path::to_path_buf);
+            }
+            Err(e) => {
+                self.status.error = e.to_string();
+                error!("{e}");
+            }
+        }
+    }
+
+    /// Renders the visible area of the grid as SVG, with the same layers as on screen.
+    fn grid_to_svg(&self, ui: &egui::Ui, grid: &Grid) -> Result<String> {
+        let view = SvgView {
+            grid,
+            rect: grid.response().rect,
+        };
+        let options = &self.options.grid;
+        let mut doc = SvgDocument::new(view.rect.size());
+        doc.rect_filled(
+            egui::Rect::from_min_size(egui::Pos2::ZERO, view.rect.size()),
+            0.,
+            self.options
+                .canvas_settings
+                .background_color_or_default(ui.ctx()),
+        );
+
+        doc.begin_group("maps");
+        for name in self.data.draw_order.keys() {
+            let Some(map) = self.data.maps.get(name).filter(|map| map.visible) else {
+                continue;
+            };
+            // Pixel corners to points, the transform is affine.
+            let height = map.image_pyramid.original_size.y;
+            let to_svg = |x, y| {
+                view.to_svg(
+                    map.pose
+                        .transform(map.meta.pixel_to_map(egui::pos2(x, y), height)),
+                )
+            };
+            // Streamed maps are exported with their finest pyramid level.
+            let finest = map.image_pyramid.finest_image();
+            // Original pixels per image pixel, only differs for streamed maps.
+            let scale = egui::vec2(
+                map.image_pyramid.original_size.x / finest.width() as f32,
+                height / finest.height() as f32,
+            );
+            if let Some(region) = visible_pixels(&view, map, &finest, scale) {
+                let image = map_image(map, &finest, region);
+                let (x, y) = (region.x as f32 * scale.x, region.y as f32 * scale.y);
+                let origin = to_svg(x, y);
+                let x_axis = to_svg(x + scale.x, y) - origin;
+                let y_axis = to_svg(x, y + scale.y) - origin;
+                let crisp =
+                    map.texture_filter.to_egui().magnification == egui::TextureFilter::Nearest;
+                doc.image(
+                    &image,
+                    [x_axis.x, x_axis.y, y_axis.x, y_axis.y, origin.x, origin.y],
+                    crisp,
+                )?;
+            }
+            if options.marker_visibility.maps_visible() {
+                axes(&mut doc, &view, options, Some(&map.pose));
+            }
+        }
+        doc.end_group();
+
+        doc.begin_group("grid");
+        if options.lines_visible {
+            grid_lines(&mut doc, &view, options, &LineType::Main);
+            if options.sub_lines_visible == SubLineVisibility::Always {
+                grid_lines(&mut doc, &view, options, &LineType::Sub);
+            }
+        }
+        if options.marker_visibility.zero_visible() {
+            axes(&mut doc, &view, options, None);
+        }
+        doc.end_group();
+
+        doc.begin_group("markers");
+        if let Some(georef) = &self.data.georeference {
+            let stroke = GEO_MARKER_STROKE;
+            for marker in &self.data.geo_markers {
+                let pos = view.to_svg(georef.wgs84_to_root(&marker.position));
+                doc.circle_stroke(pos, stroke.width * 3., stroke);
+                doc.circle_filled(pos, stroke.width, stroke.color);
+                doc.text(
+                    pos + egui::vec2(stroke.width * 4., 0.),
+                    egui::Align2::LEFT_CENTER,
+                    &marker.name,
+                    &egui::FontId::new(13., egui::FontFamily::Proportional),
+                    stroke.color,
+                );
+            }
+        }
+        measurement(&mut doc, &view, options);
+        doc.end_group();
+
+        doc.begin_group("overlays");
+        self.overlays(view.grid.points_per_meter).draw(
+            &mut SvgOverlayPainter {
+                doc: &mut doc,
+                fonts: ui.painter(),
+            },
+            egui::Rect::from_min_size(egui::Pos2::ZERO, view.rect.size()),
+        );
+        doc.end_group();
+        Ok(doc.finish())
+    }
+}
@@ -4,12 +4,24 @@ use eframe::egui;
 use serde::{Deserialize, Serialize};
 
 use crate::app::AppState;
+use crate::app_impl::color_legend::color_legend;
 use crate::app_impl::ui_helpers::{display_path, section_heading};
 use maps_io_ros::{
     ColorMap,
-    value_interpretation::{Mode, Quirks, ValueInterpretation},
+    value_colormap::custom_colormaps,
+    value_interpretation::{Mode, Quirks, ValueInterpretation, ValueRange},
+};
+use maps_rendering::{
+    NO_TINT, PyramidLevels, TextureFilter, image::is_high_depth,
+    image_pyramid::default_min_level_size,
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use {
+    log::{error, info},
+    maps_io_ros::value_colormap::{LookupColorMap, register_custom_colormap},
+    std::path::Path,
 };
-use maps_rendering::{NO_TINT, TextureFilter};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TintOptions {
@@ -48,6 +60,8 @@ impl AppState {
         }
         ui.end_row();
 
+        #[cfg(not(target_arch = "wasm32"))]
+        let disk_cache = self.pyramid_disk_cache();
         let all_key = "< All >".to_string();
         let selected = self
             .options
@@ -101,6 +115,7 @@ impl AppState {
                 &mut self.options.tint_settings.use_value_interpretation_for_all,
                 value_interpretation,
                 texture_filter,
+                None,
             );
 
             if reset {
@@ -117,7 +132,7 @@ impl AppState {
                     map.meta.value_interpretation = *value_interpretation;
                 } else {
                     map.meta.reset_value_interpretation();
-                    map.use_value_interpretation = false;
+                    map.use_value_interpretation = map.image_pyramid.is_high_depth();
                 }
             }
         } else if let Some(map) = self.data.maps.get_mut(selected) {
@@ -127,9 +142,12 @@ impl AppState {
 
             if reset {
                 map.meta.reset_value_interpretation();
-                // If the map has an explicit value interpretation, enable it by default.
-                map.use_value_interpretation = map.meta.value_interpretation.explicit_mode;
+                // If the map has an explicit value interpretation or high bit depth values,
+                // enable it by default.
+                map.use_value_interpretation = map.meta.value_interpretation.explicit_mode
+                    || map.image_pyramid.is_high_depth();
             }
+            let image_pyramid = map.image_pyramid.clone();
             changes = pick(
                 ui,
                 reset,
@@ -139,15 +157,110 @@ impl AppState {
                 &mut map.use_value_interpretation,
                 &mut map.meta.value_interpretation,
                 &mut map.texture_filter,
+                Some(&image_pyramid.finest_image()),
             );
+            ui.end_row();
+
+            let prev_pyramid_levels = map.pyramid_levels;
+            if reset {
+                map.pyramid_levels = PyramidLevels::default();
+            }
+            pick_pyramid_levels(ui, &mut map.pyramid_levels);
+            if map.pyramid_levels != prev_pyramid_levels {
+                changes = true;
+                map.update_pyramid_levels(
+                    #[cfg(not(target_arch = "wasm32"))]
+                    disk_cache,
+                );
+            }
         } else {
             self.options.tint_settings.active_tint_selection = None;
         }
 
+        #[cfg(not(target_arch = "wasm32"))]
+        self.load_colormap_button(ui);
+
         if changes {
             self.status.unsaved_changes = true;
         }
     }
+
+    /// Registers the user-defined colormaps of the app options.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn register_custom_colormaps(&mut self) {
+        let mut failed = Vec::new();
+        for path in &self.options.custom_colormaps {
+            match LookupColorMap::load_from_file(path) {
+                Ok(colormap) => {
+                    register_custom_colormap(colormap);
+                }
+                Err(e) => {
+                    error!("{e}");
+                    failed.push(path.clone());
+                }
+            }
+        }
+        // Forget files that are gone, the error was logged.
+        self.options
+            .custom_colormaps
+            .retain(|p| !failed.contains(p));
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn load_colormap_button(&mut self, ui: &mut egui::Ui) {
+        ui.end_row();
+        ui.label("Custom colormap").on_hover_text(
+            "Load a user-defined gradient from a YAML or CSV file with rows of\n\
+            value (0-1), r, g, b[, a] (0-255). Loaded colormaps are remembered\n\
+            in the app options and can be selected in the coloring dropdown.",
+        );
+        if !ui.button("Load colormap…").clicked() {
+            return;
+        }
+        let mut dialog = rfd::FileDialog::new().add_filter("Colormap", &["yaml", "yml", "csv"]);
+        if let Some(dir) = &self.last_file_dir {
+            dialog = dialog.set_directory(dir);
+        }
+        let Some(path) = dialog.pick_file() else {
+            return;
+        };
+        self.load_colormap(&path);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn load_colormap(&mut self, path: &Path) {
+        let colormap = match LookupColorMap::load_from_file(path) {
+            Ok(colormap) => colormap,
+            Err(e) => {
+                self.status.error = e.to_string();
+                error!("{e}");
+                return;
+            }
+        };
+        info!("Loaded colormap {:?} from {path:?}", colormap.name);
+        let colormap = register_custom_colormap(colormap);
+        if !self.options.custom_colormaps.iter().any(|p| p == path) {
+            self.options.custom_colormaps.push(path.to_path_buf());
+        }
+        self.last_file_dir = path.parent().map(Path::to_path_buf);
+
+        // Select the new colormap for the current tint selection.
+        let selected = self.options.tint_settings.active_tint_selection.as_deref();
+        match selected.and_then(|name| self.data.maps.get_mut(name)) {
+            Some(map) => {
+                map.meta.value_interpretation.colormap = colormap;
+                map.use_value_interpretation = true;
+                map.meta.value_interpretation.explicit_mode = true;
+                self.status.unsaved_changes = true;
+            }
+            None => {
+                self.options
+                    .tint_settings
+                    .value_interpretation_for_all
+                    .colormap = colormap;
+            }
+        }
+    }
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -160,6 +273,7 @@ fn pick(
     edit_value_interpretation: &mut bool,
     value_interpretation: &mut ValueInterpretation,
     texture_filter: &mut TextureFilter,
+    image: Option<&image::DynamicImage>,
 ) -> bool {
     // TODO: change detection could be done more elegantly.
     let prev_tint = *tint;
@@ -201,7 +315,7 @@ fn pick(
     if *edit_value_interpretation {
         ui.end_row();
         ui.end_row();
-        pick_value_interpretation(ui, value_interpretation);
+        pick_value_interpretation(ui, value_interpretation, image);
     }
     // Remember when the user explicitly selects/deselect value interpretation.
     // This way it's picked up correctly when saved to a session and then reloaded.
@@ -270,7 +384,7 @@ fn pick_colormap(ui: &mut egui::Ui, colormap: &mut ColorMap) {
     ui.label("Coloring")
         .on_hover_text("Select a colormap for the visualization of interpreted pixels.");
     egui::ComboBox::from_label("")
-        .selected_text(colormap.to_string())
+        .selected_text(colormap.display_name())
         .show_ui(ui, |ui| {
             ui.selectable_value(colormap, ColorMap::RvizMap, "RViz \"Map\"")
                 .on_hover_text("Classic RViz map coloring.");
@@ -280,12 +394,82 @@ fn pick_colormap(ui: &mut egui::Ui, colormap: &mut ColorMap) {
                 .on_hover_text("No coloring.");
             ui.selectable_value(colormap, ColorMap::CoolCostmap, "Cool costmap")
                 .on_hover_text("Alternative costmap coloring with less screaming colors.");
+            ui.separator();
+            ui.selectable_value(colormap, ColorMap::Viridis, "Viridis")
+                .on_hover_text("Perceptually uniform gradient for continuous data.");
+            ui.selectable_value(colormap, ColorMap::Turbo, "Turbo")
+                .on_hover_text("Rainbow gradient for continuous data, e.g. elevation.");
+            ui.selectable_value(colormap, ColorMap::Inferno, "Inferno")
+                .on_hover_text("Perceptually uniform gradient from black to yellow.");
+            ui.selectable_value(colormap, ColorMap::GrayscaleInverted, "Grayscale inverted")
+                .on_hover_text("Gradient from white to black.");
+            let custom = custom_colormaps();
+            if !custom.is_empty() {
+                ui.separator();
+            }
+            for (custom_colormap, name) in custom {
+                ui.selectable_value(colormap, custom_colormap, name)
+                    .on_hover_text("User-defined colormap.");
+            }
         });
 }
 
-fn pick_value_interpretation(ui: &mut egui::Ui, value_interpretation: &mut ValueInterpretation) {
+fn pick_value_range(
+    ui: &mut egui::Ui,
+    range: &mut Option<ValueRange>,
+    image: Option<&image::DynamicImage>,
+) {
+    ui.label("Custom value range").on_hover_text(
+        "Range of raw pixel values that is mapped to the full value range.\n\
+        Only applies to 16-bit and float images, e.g. elevation or cost layers.\n\
+        If disabled, the full range of the pixel type is used.",
+    );
+    ui.horizontal(|ui| {
+        let mut custom = range.is_some();
+        if ui.checkbox(&mut custom, "").changed() {
+            *range = custom.then(|| {
+                image
+                    .and_then(ValueRange::from_image)
+                    .unwrap_or(ValueRange { min: 0., max: 1. })
+            });
+        }
+        if let Some(range) = range {
+            let speed = ((range.max - range.min).abs() / 1000.).max(1e-4);
+            ui.add(
+                egui::DragValue::new(&mut range.min)
+                    .speed(speed)
+                    .prefix("min "),
+            );
+            ui.add(
+                egui::DragValue::new(&mut range.max)
+                    .speed(speed)
+                    .prefix("max "),
+            );
+            if let Some(image) = image
+                && ui
+                    .button("Fit")
+                    .on_hover_text("Set to the minimum / maximum value of the image.")
+                    .clicked()
+                && let Some(fitted) = ValueRange::from_image(image)
+            {
+                *range = fitted;
+            }
+        }
+    });
+}
+
+fn pick_value_interpretation(
+    ui: &mut egui::Ui,
+    value_interpretation: &mut ValueInterpretation,
+    image: Option<&image::DynamicImage>,
+) {
     pick_mode(ui, &mut value_interpretation.mode);
     ui.end_row();
+    // The range is only relevant for high bit depth images, or when editing all maps.
+    if image.is_none_or(is_high_depth) {
+        pick_value_range(ui, &mut value_interpretation.range, image);
+        ui.end_row();
+    }
     ui.label("Free threshold")
         .on_hover_text("Threshold for free space interpretation.");
     ui.add(egui::Slider::new(&mut value_interpretation.free, 0.0..=1.0));
@@ -303,6 +487,10 @@ fn pick_value_interpretation(ui: &mut egui::Ui, value_interpretation: &mut Value
     ui.end_row();
     pick_colormap(ui, &mut value_interpretation.colormap);
     ui.end_row();
+    ui.label("Legend")
+        .on_hover_text("Colors of the interpreted values with the selected colormap.");
+    color_legend(ui, value_interpretation, image);
+    ui.end_row();
     pick_quirks(ui, &mut value_interpretation.quirks);
 }
 
@@ -321,3 +509,37 @@ fn pick_filter(ui: &mut egui::Ui, texture_filter: &mut TextureFilter) {
             );
     });
 }
+
+fn pick_pyramid_levels(ui: &mut egui::Ui, levels: &mut PyramidLevels) {
+    ui.label("Zoom levels").on_hover_text(
+        "How the downscaled versions of the image are generated,\n\
+        which are rendered when zoomed out. Changing this rebuilds them.",
+    );
+    ui.horizontal(|ui| {
+        ui.selectable_value(levels, PyramidLevels::Fixed, "Fixed")
+            .on_hover_text("Fixed sizes of 8000, 4000, 2000, 1000 and 500 pixels.");
+        let power_of_two = matches!(levels, PyramidLevels::PowerOfTwo { .. });
+        if ui
+            .selectable_label(power_of_two, "Power of two")
+            .on_hover_text(
+                "Halve the image size per level, down to a minimum size.\n\
+                Gives more levels for very large images.",
+            )
+            .clicked()
+            && !power_of_two
+        {
+            *levels = PyramidLevels::PowerOfTwo {
+                min_size: default_min_level_size(),
+            };
+        }
+        if let PyramidLevels::PowerOfTwo { min_size } = levels {
+            egui::ComboBox::from_id_salt("min_level_size")
+                .selected_text(format!("min. {min_size} px"))
+                .show_ui(ui, |ui| {
+                    for size in [64, 128, 256, 512, 1024, 2048] {
+                        ui.selectable_value(min_size, size, format!("{size} px"));
+                    }
+                });
+        }
+    });
+}
@@ -0,0 +1,226 @@
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+
+use crate::app::{AppState, ViewMode};
+use crate::app_impl::constants::VIEWPOINT_TRANSITION_DURATION;
+use crate::app_impl::ui_helpers::section_heading;
+
+/// Number of viewpoints that can be recalled with Ctrl + 1..9.
+pub(crate) const NUM_VIEWPOINT_SHORTCUTS: usize = 9;
+
+/// A named view of the grid in the 'Aligned' view, i.e. its offset and scale.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Viewpoint {
+    pub name: String,
+    pub offset: egui::Vec2,
+    pub scale: f32,
+    /// Names of the maps that are visible in this view, if visibility is part of the view.
+    #[serde(default)]
+    pub visible_maps: Option<Vec<String>>,
+}
+
+/// Animated transition of the grid to a viewpoint.
+#[derive(Debug)]
+pub struct ViewpointTransition {
+    from_offset: egui::Vec2,
+    from_scale: f32,
+    to_offset: egui::Vec2,
+    to_scale: f32,
+    start_time: f64,
+}
+
+impl ViewpointTransition {
+    /// Returns the grid offset and scale at `time`, and if the transition is finished.
+    fn interpolate(&self, time: f64) -> (egui::Vec2, f32, bool) {
+        let t = ((time - self.start_time) / VIEWPOINT_TRANSITION_DURATION).clamp(0., 1.) as f32;
+        // Smooth start and end.
+        let t_eased = t * t * (3. - 2. * t);
+        // Zoom exponentially to get a constant perceived zoom speed.
+        let scale = self.from_scale * (self.to_scale / self.from_scale).powf(t_eased);
+        // The offset scales with the zoom, interpolate the metric view center instead.
+        let center = egui::lerp(
+            (self.from_offset / self.from_scale)..=(self.to_offset / self.to_scale),
+            t_eased,
+        );
+        (center * scale, scale, t >= 1.)
+    }
+}
+
+impl AppState {
+    /// Saves the current grid view as a new viewpoint.
+    fn add_viewpoint(&mut self, with_visibility: bool) {
+        let viewpoint = Viewpoint {
+            name: format!("Viewpoint {}", self.data.viewpoints.len() + 1),
+            offset: self.options.grid.offset,
+            scale: self.options.grid.scale,
+            visible_maps: with_visibility.then(|| {
+                self.data
+                    .maps
+                    .iter()
+                    .filter(|(_, map)| map.visible)
+                    .map(|(name, _)| name.clone())
+                    .collect()
+            }),
+        };
+        self.data.viewpoints.push(viewpoint);
+        self.status.unsaved_changes = true;
+    }
+
+    /// Starts an animated transition to a viewpoint, in the 'Aligned' view.
+    pub(crate) fn go_to_viewpoint(&mut self, index: usize, time: f64) {
+        let Some(viewpoint) = self.data.viewpoints.get(index) else {
+            return;
+        };
+        self.options.view_mode = ViewMode::Aligned;
+        self.status.viewpoint_transition = Some(ViewpointTransition {
+            from_offset: self.options.grid.offset,
+            from_scale: self.options.grid.scale,
+            to_offset: viewpoint.offset,
+            to_scale: viewpoint.scale,
+            start_time: time,
+        });
+        if let Some(visible_maps) = &viewpoint.visible_maps {
+            for (name, map) in &mut self.data.maps {
+                map.visible = visible_maps.contains(name);
+                self.tile_manager.set_visible(name, map.visible);
+            }
+        }
+    }
+
+    /// Moves the grid along an active viewpoint transition.
+    pub(crate) fn animate_viewpoint_transition(&mut self, ui: &egui::Ui) {
+        let Some(transition) = &self.status.viewpoint_transition else {
+            return;
+        };
+        let (offset, scale, finished) = transition.interpolate(ui.input(|i| i.time));
+        self.options.grid.offset = offset;
+        self.options.grid.scale = scale;
+        if finished {
+            self.status.viewpoint_transition = None;
+        } else {
+            ui.ctx().request_repaint();
+        }
+    }
+
+    pub(crate) fn viewpoint_settings(&mut self, ui: &mut egui::Ui) {
+        if !section_heading(ui, "Viewpoints", &mut self.options.collapsed.viewpoints) {
+            return;
+        }
+        ui.end_row();
+
+        ui.label("Save current view").on_hover_text(
+            "Save the offset and zoom of the grid as a viewpoint, saved in the session.\n\
+            With map visibility, recalling the viewpoint also shows / hides the maps.",
+        );
+        ui.horizontal(|ui| {
+            if ui.button("➕ View").clicked() {
+                self.add_viewpoint(false);
+            }
+            if ui
+                .button("➕ View + visible maps")
+                .on_hover_text("Also save which maps are visible.")
+                .clicked()
+            {
+                self.add_viewpoint(true);
+            }
+        });
+        ui.end_row();
+
+        ui.label("Viewpoints").on_hover_text(format!(
+            "Click ⏵ or press Ctrl + 1..{NUM_VIEWPOINT_SHORTCUTS} to go to a viewpoint."
+        ));
+        let mut go_to = None;
+        let mut to_delete = None;
+        let mut changed = false;
+        ui.vertical(|ui| {
+            if self.data.viewpoints.is_empty() {
+                ui.label(egui::RichText::new("No viewpoints saved.").weak().italics());
+            }
+            for (i, viewpoint) in self.data.viewpoints.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    if ui
+                        .small_button("🗑")
+                        .on_hover_text("Delete viewpoint")
+                        .clicked()
+                    {
+                        to_delete = Some(i);
+                    }
+                    let hover = if i < NUM_VIEWPOINT_SHORTCUTS {
+                        format!("Go to viewpoint (Ctrl + {})", i + 1)
+                    } else {
+                        "Go to viewpoint".to_string()
+                    };
+                    if ui.small_button("⏵").on_hover_text(hover).clicked() {
+                        go_to = Some(i);
+                    }
+                    changed |= ui
+                        .add(egui::TextEdit::singleline(&mut viewpoint.name).desired_width(120.))
+                        .changed();
+                    if let Some(visible_maps) = &viewpoint.visible_maps {
+                        ui.label("👁").on_hover_text(format!(
+                            "Shows {} map(s) and hides the others.",
+                            visible_maps.len()
+                        ));
+                    }
+                });
+            }
+        });
+
+        if let Some(i) = go_to {
+            self.go_to_viewpoint(i, ui.input(|i| i.time));
+        }
+        if let Some(i) = to_delete {
+            self.data.viewpoints.remove(i);
+            changed = true;
+        }
+        if changed {
+            self.status.unsaved_changes = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transition(from: (egui::Vec2, f32), to: (egui::Vec2, f32)) -> ViewpointTransition {
+        ViewpointTransition {
+            from_offset: from.0,
+            from_scale: from.1,
+            to_offset: to.0,
+            to_scale: to.1,
+            start_time: 10.,
+        }
+    }
+
+    #[test]
+    fn interpolate_starts_and_ends_at_viewpoints() {
+        let from = (egui::vec2(10., -20.), 2.);
+        let to = (egui::vec2(-300., 40.), 0.5);
+        let transition = transition(from, to);
+        assert_eq!(transition.interpolate(10.), (from.0, from.1, false));
+        // Before the start, e.g. if the clock was behind.
+        assert_eq!(transition.interpolate(9.), (from.0, from.1, false));
+
+        let (offset, scale, finished) = transition.interpolate(10. + VIEWPOINT_TRANSITION_DURATION);
+        assert!(finished);
+        assert!((offset - to.0).length() < 1e-3, "{offset:?}");
+        assert!((scale - to.1).abs() < 1e-6);
+        assert!(transition.interpolate(100.).2);
+    }
+
+    #[test]
+    fn interpolate_zoom_keeps_metric_center() {
+        let transition = transition((egui::vec2(10., -20.), 2.), (egui::vec2(40., -80.), 8.));
+        let center = egui::vec2(5., -10.);
+        for i in 0..=10 {
+            let time = 10. + VIEWPOINT_TRANSITION_DURATION * f64::from(i) / 10.;
+            let (offset, scale, _) = transition.interpolate(time);
+            assert!((2. ..=8.).contains(&scale), "{scale}");
+            assert!(
+                (offset / scale - center).length() < 1e-5,
+                "{i}: {offset:?} {scale}"
+            );
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! Session bundles: a session together with all its map files in one archive,
+//! e.g. to share a session with colleagues.
+//!
+//! Bundles are uncompressed tar archives with the session file `session.toml`
+//! at the top level and the files of each map in a numbered subdirectory.
+//! All paths in the bundled session are relative to the archive root.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+use log::{debug, info};
+
+use crate::app::SessionData;
+use crate::error::{Error, Result};
+use crate::persistence::session_to_toml;
+
+/// File extension of session bundles.
+pub const BUNDLE_EXTENSION: &str = "tar";
+/// Name of the session file in a bundle.
+const SESSION_FILE_NAME: &str = "session.toml";
+
+fn invalid_data(context: impl ToString, message: &str) -> Error {
+    Error::io(
+        context,
+        std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string()),
+    )
+}
+
+/// Header for a regular file in a bundle. Long paths are stored with GNU extensions.
+fn file_header(size: u64) -> tar::Header {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(size);
+    header.set_mode(0o644);
+    header.set_entry_type(tar::EntryType::Regular);
+    header
+}
+
+/// Bundle paths of the files of one map.
+struct BundledMap {
+    image_path: PathBuf,
+    /// `None` for maps without YAML file, e.g. GeoTIFFs, that are identified by their image.
+    yaml_path: Option<PathBuf>,
+    pose_path: PathBuf,
+}
+
+/// Writes a session with all map images, YAML files and map poses into one archive.
+/// Files are streamed into the archive one by one, they are not read into memory.
+pub fn export_bundle(path: &Path, session: &SessionData) -> Result<()> {
+    info!("Exporting session bundle to {path:?}");
+    let mut bundled_maps = Vec::new();
+    // Paths of the map files in the bundle, by their paths in the session.
+    let mut bundle_paths: HashMap<PathBuf, PathBuf> = HashMap::new();
+    for (index, (name, map)) in session.maps.iter().enumerate() {
+        let dir = PathBuf::from(format!("maps/{index}"));
+        let file_name = |path: &Path| {
+            path.file_name().map(PathBuf::from).ok_or_else(|| {
+                Error::app(format!("Cannot bundle map {name}, invalid path {path:?}"))
+            })
+        };
+        let image_path = dir.join(file_name(&map.meta.image_path)?);
+        let yaml_path = dir.join(file_name(&map.meta.yaml_path)?);
+        let pose_path = dir.join(format!(
+            "{}_pose.yaml",
+            yaml_path.file_stem().unwrap_or_default().to_string_lossy()
+        ));
+        let has_yaml = map.meta.yaml_path != map.meta.image_path;
+
+        bundle_paths.insert(map.meta.image_path.clone(), image_path.clone());
+        if has_yaml {
+            bundle_paths.insert(map.meta.yaml_path.clone(), yaml_path.clone());
+        }
+        bundle_paths.insert(PathBuf::from(name), dir.join(file_name(Path::new(name))?));
+        bundled_maps.push(BundledMap {
+            image_path,
+            yaml_path: has_yaml.then_some(yaml_path),
+            pose_path,
+        });
+    }
+    let session_toml = session_to_toml(session, |path| {
+        bundle_paths
+            .get(path)
+            .cloned()
+            .unwrap_or_else(|| path.to_path_buf())
+    })?;
+
+    let write_error = |e| Error::io(format!("Cannot write bundle {path:?}"), e);
+    let file =
+        std::fs::File::create(path).map_err(|e| Error::io(format!("Cannot create {path:?}"), e))?;
+    let mut builder = tar::Builder::new(std::io::BufWriter::new(file));
+    let mut append_data = |name: &Path, data: &[u8]| {
+        builder
+            .append_data(&mut file_header(data.len() as u64), name, data)
+            .map_err(write_error)
+    };
+    append_data(Path::new(SESSION_FILE_NAME), session_toml.as_bytes())?;
+    for (map, bundled) in session.maps.values().zip(&bundled_maps) {
+        if let Some(yaml_path) = &bundled.yaml_path {
+            let mut bundled_meta = map.meta.clone();
+            bundled_meta.image_path.clone_from(&bundled.image_path);
+            append_data(yaml_path, bundled_meta.to_yaml(yaml_path)?.as_bytes())?;
+        }
+        append_data(&bundled.pose_path, &map.pose.to_bytes()?)?;
+    }
+    for (map, bundled) in session.maps.values().zip(&bundled_maps) {
+        let image_path = &map.meta.image_path;
+        debug!("Bundling {image_path:?}");
+        let mut image = std::fs::File::open(image_path)
+            .map_err(|e| Error::io(format!("Cannot read {image_path:?}"), e))?;
+        let size = image
+            .metadata()
+            .map_err(|e| Error::io(format!("Cannot read {image_path:?}"), e))?
+            .len();
+        builder
+            .append_data(&mut file_header(size), &bundled.image_path, &mut image)
+            .map_err(write_error)?;
+    }
+    builder
+        .into_inner()
+        .and_then(|mut writer| writer.flush())
+        .map_err(write_error)
+}
+
+/// Extracts a bundle into a directory and returns the path of its session file.
+/// Entries are streamed to their files one by one, other entry types than
+/// regular files are skipped.
+pub fn extract_bundle(path: &Path, target_dir: &Path) -> Result<PathBuf> {
+    info!("Extracting session bundle {path:?} to {target_dir:?}");
+    let read_error = |e| Error::io(format!("Cannot read bundle {path:?}"), e);
+    let file = std::fs::File::open(path).map_err(read_error)?;
+    let mut archive = tar::Archive::new(std::io::BufReader::new(file));
+
+    for entry in archive.entries().map_err(read_error)? {
+        let mut entry = entry.map_err(read_error)?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry.path().map_err(read_error)?.into_owned();
+        // Don't write anything outside of the target directory.
+        if !name
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(invalid_data(
+                format!("Invalid bundle {path:?}"),
+                "entry with absolute path or parent directory",
+            ));
+        }
+        let file_path = target_dir.join(&name);
+        debug!("Extracting {file_path:?}");
+        if let Some(dir) = file_path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| Error::io(format!("Cannot create {dir:?}"), e))?;
+        }
+        std::fs::File::create(&file_path)
+            .and_then(|mut file| std::io::copy(&mut entry, &mut file))
+            .map_err(|e| Error::io(format!("Cannot write {file_path:?}"), e))?;
+    }
+    let session_path = target_dir.join(SESSION_FILE_NAME);
+    if !session_path.exists() {
+        return Err(invalid_data(
+            format!("Invalid bundle {path:?}"),
+            "no session file",
+        ));
+    }
+    Ok(session_path)
+}
+
+/// Directory that bundles are extracted to when they are opened.
+pub fn extraction_dir(bundle_path: &Path) -> PathBuf {
+    let stem = bundle_path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy();
+    std::env::temp_dir()
+        .join("maps_bundles")
+        .join(format!("{stem}_{}", uuid::Uuid::new_v4().simple()))
+}
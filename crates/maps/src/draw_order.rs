@@ -26,6 +26,29 @@ impl DrawOrder {
         self.keys.as_ref()
     }
 
+    /// Renames a key in place, e.g. if a map was loaded from a different path.
+    /// Keeps the position of the old key, the new key is removed elsewhere.
+    pub fn rename(&mut self, old_name: &str, new_name: &str) {
+        if old_name == new_name || !self.contains(old_name) {
+            return;
+        }
+        self.remove(new_name);
+        if let Some(key) = self.keys.iter_mut().find(|key| *key == old_name) {
+            *key = new_name.to_string();
+        }
+    }
+
+    /// Index of a key in the draw order.
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.keys.iter().position(|key| key == name)
+    }
+
+    /// Moves or inserts a key to an index, or to the end if it's out of range.
+    pub fn insert_at(&mut self, name: String, index: usize) {
+        self.remove(name.as_str());
+        self.keys.insert(index.min(self.keys.len()), name);
+    }
+
     pub fn contains(&self, name: &str) -> bool {
         self.keys.contains(&name.to_string())
     }
@@ -18,6 +18,18 @@ pub enum Error {
     #[error(transparent)]
     Core(#[from] maps_io_ros::Error),
 
+    /// A file was saved with a newer, unsupported schema version, see [`crate::migration`].
+    #[error(
+        "{kind} was saved with {saved_by} (schema version {version}), \
+        this version of maps supports up to schema version {supported}. Please update maps."
+    )]
+    UnsupportedVersion {
+        kind: String,
+        version: u32,
+        supported: u32,
+        saved_by: String,
+    },
+
     /// TOML deserialization error with additional context.
     #[error("[TOML error] {context} ({source})")]
     TomlDeserialize {
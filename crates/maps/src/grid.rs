@@ -7,8 +7,9 @@ use crate::draw_order::DrawOrder;
 use crate::grid_options::{GridLineDimension, GridOptions, LineType};
 use crate::map_state::MapState;
 use crate::movable::Draggable;
+use crate::overlays::Overlays;
 use maps_io_ros::MapPose;
-use maps_rendering::{ImagePlacement, TextureRequest, TransformedTextureRequest};
+use maps_rendering::{ImagePlacement, TextureRequest, TransformedTextureRequest, flip};
 
 /// Grid area for displaying metric objects in screen space (points).
 pub struct Grid {
@@ -24,53 +25,12 @@ pub struct Grid {
     painter: egui::Painter,
 }
 
-/// Relations of a RHS metric coordinate map to the LHS point coordinate grid.
-struct GridMapRelation {
-    /// Size of the map scaled to the grid's zoom level, in points.
-    scaled_size: egui::Vec2,
-    /// Points occupied by a cell (pixel) of the map.
-    points_per_cell: f32,
-    /// Upper left image corner to map origin in points, without map pose translation.
-    ulc_to_origin_in_points: egui::Vec2,
-    /// Upper left image corner to map origin in points, including map pose translation.
-    ulc_to_origin_in_points_translated: egui::Vec2,
-}
-
-/// Switches from left- to right-handed coordinate system or vice versa.
-/// Screen is a LHS system.
-fn flip(vec: egui::Vec2) -> egui::Vec2 {
-    vec * egui::vec2(1., -1.)
-}
-
-impl GridMapRelation {
-    fn new(grid: &Grid, map: &mut MapState) -> GridMapRelation {
-        let points_per_meter = 1. / map.meta.resolution;
-        let scale_factor = grid.points_per_meter / points_per_meter;
-        let points_per_cell = points_per_meter * map.meta.resolution * scale_factor;
-        let scaled_size = map.image_pyramid.original_size * scale_factor;
-
-        // Meta origin is lower left corner of image in ROS.
-        let llc_to_origin_in_points = flip(map.meta.origin_xy) * points_per_meter * scale_factor;
-        let ulc_to_origin_in_points = llc_to_origin_in_points - egui::Vec2::new(0., scaled_size.y);
-
-        let translation_in_points = flip(map.pose.vec2()) * points_per_meter * scale_factor;
-        let ulc_to_origin_in_points_translated = translation_in_points + ulc_to_origin_in_points;
-
-        GridMapRelation {
-            scaled_size,
-            points_per_cell,
-            ulc_to_origin_in_points,
-            ulc_to_origin_in_points_translated,
-        }
-    }
-}
-
 struct LabelTextOptions {
     font_id: egui::FontId,
     offset: egui::Vec2,
 }
 
-enum LineDirection {
+pub(crate) enum LineDirection {
     Vertical,
     Horizontal,
 }
@@ -146,12 +106,15 @@ impl Grid {
             return;
         }
 
-        let relation = GridMapRelation::new(self, map);
-
-        let scaled_rect = egui::Rect::from_min_size(egui::Pos2::ZERO, relation.scaled_size);
+        let placement = ImagePlacement::from_map(
+            &map.meta,
+            &map.pose,
+            map.image_pyramid.original_size,
+            self.points_per_meter,
+            self.origin_in_points,
+        );
 
-        let pose_rotation = map.pose.rot2().inverse(); // RHS to LHS
-        let origin_rotation = map.meta.origin_theta.inverse();
+        let scaled_rect = egui::Rect::from_min_size(egui::Pos2::ZERO, placement.scaled_size());
 
         let base_request = TextureRequest::new(map_name.to_string(), scaled_rect)
             .with_tint(map.tint)
@@ -159,15 +122,6 @@ impl Grid {
             .with_thresholding(map.get_value_interpretation())
             .with_texture_options(map.texture_filter.to_egui());
 
-        let placement = ImagePlacement {
-            rotation: pose_rotation * origin_rotation,
-            translation: self.origin_in_points.to_vec2()
-                + relation.ulc_to_origin_in_points_translated,
-            rotation_center: relation.ulc_to_origin_in_points,
-            points_per_texel: relation.points_per_cell,
-            original_image_size: map.image_pyramid.original_size,
-        };
-
         let transformed_request = TransformedTextureRequest::from_visible(
             &self.painter,
             base_request,
@@ -246,23 +200,60 @@ impl Grid {
             .rect_filled(self.painter.clip_rect(), 0., color);
     }
 
+    /// Returns the spacing of grid lines of a line type, in points.
+    pub(crate) fn line_spacing_points(&self, options: &GridOptions, line_type: &LineType) -> f32 {
+        let spacing_points = match options.line_dimension {
+            GridLineDimension::Screen => options.line_spacing_points,
+            GridLineDimension::Metric => options.line_spacing_meters * self.points_per_meter,
+        };
+        match line_type {
+            LineType::Main => spacing_points,
+            LineType::Sub => spacing_points / options.sub_lines_factor as f32,
+        }
+    }
+
+    /// Font size of the tick labels of main grid lines with the given spacing.
+    pub(crate) fn tick_label_font_size(spacing_points: f32) -> f32 {
+        (spacing_points / 4.).min(15.)
+    }
+
+    /// Returns the screen coordinates of all grid lines in one direction within the grid area.
+    pub(crate) fn line_coords(&self, spacing_points: f32, direction: &LineDirection) -> Vec<f32> {
+        let (origin_coord, min_bound, max_bound) = match direction {
+            LineDirection::Vertical => (
+                self.origin_in_points.x,
+                self.ui_offset.x,
+                self.painter.clip_rect().width() + self.ui_offset.x,
+            ),
+            LineDirection::Horizontal => (
+                self.origin_in_points.y,
+                self.ui_offset.y,
+                self.painter.clip_rect().height() + self.ui_offset.y,
+            ),
+        };
+
+        // Calculate how many grid lines we need on each side of the origin.
+        let neg_lines = ((origin_coord - min_bound) / spacing_points).ceil() as i32;
+        let pos_lines = ((max_bound - origin_coord) / spacing_points).ceil() as i32;
+
+        // Use range integers to avoid floating point error accumulation.
+        (-neg_lines..=pos_lines)
+            .map(|i| origin_coord + (i as f32) * spacing_points)
+            .filter(|coord| *coord >= min_bound && *coord <= max_bound)
+            .collect()
+    }
+
     /// Draws vertical & horizontal grid lines according to the desired options and line type.
     pub fn draw_lines(&self, options: &GridOptions, line_type: &LineType) {
         if !options.lines_visible {
             return;
         }
 
-        let mut spacing_points = match options.line_dimension {
-            GridLineDimension::Screen => options.line_spacing_points,
-            GridLineDimension::Metric => options.line_spacing_meters * self.points_per_meter,
-        };
-        if *line_type == LineType::Sub {
-            spacing_points /= options.sub_lines_factor as f32;
-        }
+        let spacing_points = self.line_spacing_points(options, line_type);
 
         let label_text_options: Option<LabelTextOptions> = match line_type {
             LineType::Main => {
-                let label_font_size = (spacing_points / 4.).min(15.);
+                let label_font_size = Self::tick_label_font_size(spacing_points);
                 Some(LabelTextOptions {
                     font_id: egui::FontId::new(label_font_size, egui::FontFamily::Monospace),
                     offset: egui::vec2(0., label_font_size / 2.),
@@ -273,35 +264,14 @@ impl Grid {
 
         // Draw both vertical and horizontal lines.
         for direction in [LineDirection::Vertical, LineDirection::Horizontal] {
-            let (origin_coord, min_bound, max_bound) = match direction {
-                LineDirection::Vertical => (
-                    self.origin_in_points.x,
-                    self.ui_offset.x,
-                    self.painter.clip_rect().width() + self.ui_offset.x,
-                ),
-                LineDirection::Horizontal => (
-                    self.origin_in_points.y,
-                    self.ui_offset.y,
-                    self.painter.clip_rect().height() + self.ui_offset.y,
-                ),
-            };
-
-            // Calculate how many grid lines we need on each side of the origin.
-            let neg_lines = ((origin_coord - min_bound) / spacing_points).ceil() as i32;
-            let pos_lines = ((max_bound - origin_coord) / spacing_points).ceil() as i32;
-
-            // Draw lines using range integers to avoid floating point error accumulation.
-            for i in -neg_lines..=pos_lines {
-                let coord = origin_coord + (i as f32) * spacing_points;
-                if coord >= min_bound && coord <= max_bound {
-                    self.draw_line(
-                        coord,
-                        options,
-                        line_type,
-                        label_text_options.as_ref(),
-                        &direction,
-                    );
-                }
+            for coord in self.line_coords(spacing_points, &direction) {
+                self.draw_line(
+                    coord,
+                    options,
+                    line_type,
+                    label_text_options.as_ref(),
+                    &direction,
+                );
             }
         }
     }
@@ -435,4 +405,33 @@ impl Grid {
             );
         }
     }
+
+    /// Draws the outline of a metric rectangle.
+    pub fn draw_rect(&self, metric_rect: egui::Rect, stroke: egui::Stroke) {
+        let rect = egui::Rect::from_two_pos(
+            self.to_point(metric_rect.min),
+            self.to_point(metric_rect.max),
+        );
+        self.painter
+            .rect_stroke(rect, 0., stroke, egui::StrokeKind::Middle);
+    }
+
+    /// Draws a labeled point marker at a metric position.
+    pub fn draw_marker(&self, metric_pos: egui::Pos2, label: &str, stroke: egui::Stroke) {
+        let pos = self.to_point(metric_pos);
+        self.painter.circle_stroke(pos, stroke.width * 3., stroke);
+        self.painter.circle_filled(pos, stroke.width, stroke.color);
+        self.painter.text(
+            pos + egui::vec2(stroke.width * 4., 0.),
+            egui::Align2::LEFT_CENTER,
+            label,
+            egui::FontId::new(13., egui::FontFamily::Proportional),
+            stroke.color,
+        );
+    }
+
+    /// Draws overlays into the corners of the grid area, see [`Overlays::draw`].
+    pub(crate) fn draw_overlays(&self, overlays: &Overlays) {
+        overlays.draw(&mut self.painter.clone(), self.painter.clip_rect());
+    }
 }
@@ -74,6 +74,32 @@ impl MarkerVisibility {
     }
 }
 
+/// Optional overlays on top of the grid, e.g. to make screenshots self-explanatory.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OverlayOptions {
+    /// Metric scale bar that adapts to the zoom level.
+    pub scale_bar: bool,
+    /// Directions of the root frame axes, and north if the grid is georeferenced.
+    pub axes_indicator: bool,
+    /// Names and tint colors of the visible maps.
+    pub legend: bool,
+    pub text_color: egui::Color32,
+    pub background_color: egui::Color32,
+}
+
+impl default::Default for OverlayOptions {
+    fn default() -> Self {
+        OverlayOptions {
+            scale_bar: false,
+            axes_indicator: false,
+            legend: false,
+            text_color: egui::Color32::WHITE,
+            background_color: egui::Color32::from_black_alpha(140),
+        }
+    }
+}
+
 // Visualization options for the grid that are viewport-independent.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GridOptions {
@@ -111,6 +137,8 @@ pub struct GridOptions {
     pub lens_magnification: f32,
     #[serde(skip)]
     pub movable_amounts: MovableAmounts,
+    #[serde(default)]
+    pub overlays: OverlayOptions,
 }
 
 impl default::Default for GridOptions {
@@ -152,6 +180,7 @@ impl default::Default for GridOptions {
             measure_stroke: egui::Stroke::new(2., egui::Color32::ORANGE),
             lens_magnification: 5.,
             movable_amounts: MovableAmounts::default(),
+            overlays: OverlayOptions::default(),
         }
     }
 }
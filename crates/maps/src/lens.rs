@@ -6,14 +6,14 @@
 use std::default;
 
 use eframe::egui;
-use log::debug;
+use log::{debug, error};
 use serde::{Deserialize, Serialize};
 
 use crate::app::CanvasOptions;
 use crate::map_state::MapState;
 use maps_rendering::{
     TextureState,
-    image::{color_to_alpha, to_egui_image},
+    image::{apply_appearance, to_egui_image},
 };
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -94,9 +94,9 @@ impl<'a> Lens<'a> {
         // When partially visible, we deal with a UV rect inside an UV rect.
         let texture_uv = texture_state.desired_crop_uv;
 
-        let original_image = &texture_state.image_pyramid.original;
-        let original_width = original_image.width() as f32;
-        let original_height = original_image.height() as f32;
+        let image_pyramid = &texture_state.image_pyramid;
+        let original_width = image_pyramid.original_size.x;
+        let original_height = image_pyramid.original_size.y;
         let crop_width = original_width * (texture_uv[1].x - texture_uv[0].x);
         let crop_height = original_height * (texture_uv[1].y - texture_uv[0].y);
         let original_pos = egui::vec2(
@@ -115,14 +115,21 @@ impl<'a> Lens<'a> {
             debug!("Ignoring hover because region would be empty.");
             return false;
         }
-        let mut cropped_image = original_image.crop_imm(min_x, min_y, max_x - min_x, max_y - min_y);
-        color_to_alpha(&mut cropped_image, map.color_to_alpha);
-        if map.use_value_interpretation {
-            map.meta.value_interpretation.apply(
-                &mut cropped_image,
-                texture_state.image_pyramid.original_has_alpha,
-            );
-        }
+        let mut cropped_image =
+            match image_pyramid.read_original_region(min_x, min_y, max_x - min_x, max_y - min_y) {
+                Ok(cropped_image) => cropped_image,
+                Err(e) => {
+                    error!("{e}");
+                    return false;
+                }
+            };
+        apply_appearance(
+            &mut cropped_image,
+            map.color_to_alpha,
+            map.use_value_interpretation
+                .then_some(&map.meta.value_interpretation),
+            texture_state.image_pyramid.original_has_alpha,
+        );
         let cropped_size = egui::vec2(cropped_image.width() as f32, cropped_image.height() as f32);
 
         let overlay_texture_handle = ui.ctx().load_texture(
@@ -9,6 +9,8 @@
 
 pub mod app;
 mod app_impl;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod bundle;
 mod draw_order;
 pub mod error;
 mod grid;
@@ -16,9 +18,14 @@ mod grid_options;
 mod lens;
 mod map_pose_ext;
 mod map_state;
+pub mod migration;
 mod movable;
 pub mod os_helpers;
+mod overlays;
 pub mod persistence;
+pub mod recent_files;
+pub mod session_paths;
+pub mod svg;
 mod tiles;
 mod tiles_behavior;
 mod tracing;
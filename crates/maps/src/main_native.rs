@@ -5,7 +5,7 @@ use std::{
 };
 
 use {
-    clap::Parser,
+    clap::{Parser, Subcommand},
     eframe::egui,
     log::{LevelFilter, error, info, warn},
     strum::VariantNames,
@@ -15,9 +15,12 @@ use crate::{
     app::ViewMode,
     persistence::{load_app_options, save_session},
 };
+use maps_io_ros::geotiff::has_tiff_extension;
+use maps_io_ros::lint::{LintLevel, lint_file};
 use maps_io_ros::{MapPose, Meta};
 
 use crate::app::{AppOptions, AppState};
+use crate::app_impl::load_delete::load_meta_file;
 
 #[cfg(target_os = "linux")]
 use crate::os_helpers::write_desktop_file;
@@ -26,9 +29,21 @@ const MIN_SIZE: egui::Vec2 = egui::vec2(450., 200.);
 const APP_ID: &str = "maps";
 
 #[derive(Parser, Debug)]
-#[command(name = APP_ID, version, author = "Michael Grupp", about)]
+#[command(
+    name = APP_ID,
+    version,
+    author = "Michael Grupp",
+    about,
+    args_conflicts_with_subcommands = true
+)]
 struct Args {
-    #[clap(name = "yaml_files", help = "ROS map yaml files", required = false)]
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[clap(
+        name = "yaml_files",
+        help = "ROS map yaml files or GeoTIFF images",
+        required = false
+    )]
     yaml_files: Vec<String>,
     #[clap(
         short,
@@ -110,6 +125,40 @@ struct Args {
     write_desktop_file: bool,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check ROS map yaml files and their images for problems, without starting the GUI.
+    /// Exits with a non-zero code if errors are found, e.g. for usage in CI.
+    Lint {
+        #[clap(name = "yaml_files", help = "ROS map yaml files", required = true)]
+        yaml_files: Vec<PathBuf>,
+        #[clap(
+            long,
+            help = "Exit with a non-zero code also if only warnings are found."
+        )]
+        deny_warnings: bool,
+    },
+}
+
+/// Prints the lint issues of all files and returns the exit code.
+fn lint(yaml_files: &[PathBuf], deny_warnings: bool) -> i32 {
+    let (mut num_errors, mut num_warnings) = (0, 0);
+    for yaml_path in yaml_files {
+        for issue in lint_file(yaml_path) {
+            match issue.level {
+                LintLevel::Error => num_errors += 1,
+                LintLevel::Warning => num_warnings += 1,
+            }
+            println!("{}: {issue}", yaml_path.display());
+        }
+    }
+    println!(
+        "{num_errors} error(s), {num_warnings} warning(s) in {} file(s)",
+        yaml_files.len()
+    );
+    i32::from(num_errors > 0 || (deny_warnings && num_warnings > 0))
+}
+
 fn load_icon() -> egui::IconData {
     let (icon_rgba, icon_width, icon_height) = {
         cfg_if::cfg_if! {
@@ -163,6 +212,14 @@ pub fn main_native() -> eframe::Result {
     }
     info!("{build_info}");
 
+    if let Some(Command::Lint {
+        yaml_files,
+        deny_warnings,
+    }) = &args.command
+    {
+        exit(lint(yaml_files, *deny_warnings));
+    }
+
     #[cfg(target_os = "linux")]
     if crate::built_info::GIT_VERSION.is_some() && !args.write_desktop_file {
         info!(
@@ -179,11 +236,17 @@ pub fn main_native() -> eframe::Result {
     }
 
     let mut metas: Vec<Meta> = Vec::new();
+    // GeoTIFFs are loaded after the YAML maps, relative to the georeference of the app.
+    let mut geotiff_paths = Vec::new();
 
     for yaml_file in args.yaml_files {
         let yaml_path = Path::new(&yaml_file);
-        info!("Loading map YAML {}", yaml_path.display());
-        let meta = Meta::load_from_file(yaml_path)
+        if has_tiff_extension(yaml_path) {
+            geotiff_paths.push(yaml_path.to_path_buf());
+            continue;
+        }
+        info!("Loading map {}", yaml_path.display());
+        let meta = load_meta_file(yaml_path)
             .map_err(crate::error::Error::from)
             .unwrap_or_else(|e| {
                 error!("{e}");
@@ -236,6 +299,14 @@ pub fn main_native() -> eframe::Result {
         }
     };
 
+    for path in geotiff_paths {
+        info!("Loading GeoTIFF {}", path.display());
+        app_state.load_geotiff(&path).unwrap_or_else(|e| {
+            error!("{e}");
+            exit(1);
+        });
+    }
+
     if let Some(pose) = map_pose {
         for (name, map) in app_state.data.maps.iter_mut() {
             info!("Applying pose to map: {name}");
@@ -245,8 +316,9 @@ pub fn main_native() -> eframe::Result {
 
     if let Some(session) = &args.session {
         app_state.load_session(session).unwrap_or_else(|e| {
-            if !args.init_only {
+            if !args.init_only || session.exists() {
                 // Ignore missing session file in init_only mode to allow creating it in a script.
+                // Existing files are not overwritten, e.g. sessions of newer versions.
                 error!("{e}");
                 exit(1);
             }
@@ -254,10 +326,17 @@ pub fn main_native() -> eframe::Result {
 
         if args.init_only {
             // In init_only mode, directly save the (possibly updated) session.
-            save_session(session, &app_state.data).unwrap_or_else(|e| {
+            save_session(
+                session,
+                &app_state.data,
+                app_state.options.persistence.relative_session_paths,
+            )
+            .unwrap_or_else(|e| {
                 error!("{e}");
                 exit(1);
             });
+        } else {
+            app_state.options.recent_files.add_session(session);
         }
     }
 
@@ -1,12 +1,14 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::option::Option;
 use std::sync::Arc;
 
 use eframe::egui;
 
 use maps_io_ros::{MapPose, Meta, ValueInterpretation};
-use maps_rendering::{ImagePyramid, TextureFilter, TextureState};
+#[cfg(not(target_arch = "wasm32"))]
+use maps_rendering::PyramidDiskCache;
+use maps_rendering::{ImagePyramid, PyramidLevels, TextureFilter, TextureState};
 
 #[derive(Serialize, Deserialize)]
 pub struct MapState {
@@ -19,6 +21,9 @@ pub struct MapState {
     pub texture_filter: TextureFilter,
     #[serde(default)]
     pub use_value_interpretation: bool,
+    /// Levels of the image pyramid, see [`MapState::update_pyramid_levels`].
+    #[serde(default)]
+    pub pyramid_levels: PyramidLevels,
 
     // The image pyramid is an Arc to allow sharing it for multiple textures.
     #[serde(skip_serializing, skip_deserializing)]
@@ -39,6 +44,28 @@ impl MapState {
             .or_insert_with(|| TextureState::new(self.image_pyramid.clone()))
     }
 
+    /// Rebuilds the image pyramid if `pyramid_levels` differs from its levels.
+    /// The texture states are reset, they would still use the old pyramid.
+    pub fn update_pyramid_levels(
+        &mut self,
+        #[cfg(not(target_arch = "wasm32"))] disk_cache: Option<PyramidDiskCache>,
+    ) {
+        if self.image_pyramid.levels() == self.pyramid_levels {
+            return;
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.image_pyramid = self
+                .image_pyramid
+                .rebuild_in_background(self.pyramid_levels, disk_cache);
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            self.image_pyramid = Arc::new(self.image_pyramid.rebuild(self.pyramid_levels));
+        }
+        self.texture_states.clear();
+    }
+
     pub fn get_value_interpretation(&self) -> Option<&ValueInterpretation> {
         if self.use_value_interpretation {
             Some(&self.meta.value_interpretation)
@@ -47,3 +74,44 @@ impl MapState {
         }
     }
 }
+
+/// Placeholder for a map of a session that could not be loaded, e.g. because its files
+/// were moved. Keeps the map's state such that it is preserved when the session is saved.
+pub struct MissingMap {
+    pub state: MapState,
+    /// Why the map could not be loaded.
+    pub error: String,
+    /// Position of the map in the draw order of the session,
+    /// i.e. counting both loaded maps and placeholders.
+    pub draw_order_index: Option<usize>,
+}
+
+/// Converts an index in the draw order of the loaded maps to an index in the draw order
+/// of the session, which also contains the placeholders in `missing_maps`.
+pub fn session_draw_order_index(
+    missing_maps: &BTreeMap<String, MissingMap>,
+    loaded_index: usize,
+) -> usize {
+    let mut missing_indices: Vec<usize> = missing_maps
+        .values()
+        .filter_map(|missing| missing.draw_order_index)
+        .collect();
+    missing_indices.sort_unstable();
+    missing_indices
+        .into_iter()
+        .fold(loaded_index, |index, missing_index| {
+            index + usize::from(missing_index <= index)
+        })
+}
+
+/// Inverse of [`session_draw_order_index`].
+pub fn loaded_draw_order_index(
+    missing_maps: &BTreeMap<String, MissingMap>,
+    session_index: usize,
+) -> usize {
+    let num_missing_before = missing_maps
+        .values()
+        .filter(|missing| missing.draw_order_index.is_some_and(|i| i < session_index))
+        .count();
+    session_index - num_missing_before
+}
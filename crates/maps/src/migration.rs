@@ -0,0 +1,168 @@
+//! Schema versions of session and app options files, and migration of older files.
+//!
+//! Both file types store their schema version in a `schema_version` field.
+//! Files of older schema versions are migrated step by step to the current one,
+//! each step transforms the TOML value of the previous version before it is
+//! deserialized. Files of newer, unknown versions are rejected.
+
+use eframe::egui::Color32;
+use log::info;
+
+use crate::app_impl::compat::migrate_old_egui_color;
+use crate::error::{Error, Result};
+
+/// Name of the schema version field in session and app options files.
+pub const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+/// Current schema version of session files.
+///
+/// History:
+/// * 0: maps < 1.7.0, no `version` field. Colors are serialized with egui < 0.32.
+/// * 1: `version` field with the app version, `draw_order` is optional.
+/// * 2: `schema_version` field, `draw_order` is always present.
+pub const SESSION_SCHEMA_VERSION: u32 = 2;
+
+/// Current schema version of app options files.
+///
+/// History:
+/// * 0: Unused, options files always had a `version` field.
+/// * 1: Options without `schema_version` field.
+/// * 2: `schema_version` field.
+pub const APP_OPTIONS_SCHEMA_VERSION: u32 = 2;
+
+/// Transforms a file from schema version `N` (the index in the list of steps) to `N + 1`.
+type MigrationStep = fn(&mut toml::Table) -> Result<()>;
+
+const SESSION_MIGRATIONS: [MigrationStep; SESSION_SCHEMA_VERSION as usize] =
+    [migrate_session_colors, add_session_draw_order];
+
+const APP_OPTIONS_MIGRATIONS: [MigrationStep; APP_OPTIONS_SCHEMA_VERSION as usize] =
+    [no_changes, no_changes];
+
+/// Returns the schema version of a session, also for sessions from before schema versions.
+pub fn session_schema_version(session: &toml::Table) -> Result<u32> {
+    schema_version(session).map(|version| {
+        version.unwrap_or(if session.contains_key("version") {
+            1
+        } else {
+            0
+        })
+    })
+}
+
+/// Returns the schema version of app options, also for options from before schema versions.
+pub fn app_options_schema_version(options: &toml::Table) -> Result<u32> {
+    schema_version(options).map(|version| version.unwrap_or(1))
+}
+
+/// Migrates a serialized session to the current [`SESSION_SCHEMA_VERSION`].
+pub fn migrate_session(session: &mut toml::Table) -> Result<()> {
+    let version = session_schema_version(session)?;
+    migrate(session, "Session", version, &SESSION_MIGRATIONS)
+}
+
+/// Migrates serialized app options to the current [`APP_OPTIONS_SCHEMA_VERSION`].
+pub fn migrate_app_options(options: &mut toml::Table) -> Result<()> {
+    let version = app_options_schema_version(options)?;
+    migrate(options, "App options", version, &APP_OPTIONS_MIGRATIONS)
+}
+
+fn schema_version(table: &toml::Table) -> Result<Option<u32>> {
+    match table.get(SCHEMA_VERSION_KEY) {
+        None => Ok(None),
+        Some(toml::Value::Integer(version)) => u32::try_from(*version)
+            .map(Some)
+            .map_err(|_| Error::app(format!("Invalid schema version: {version}"))),
+        Some(value) => Err(Error::app(format!("Invalid schema version: {value}"))),
+    }
+}
+
+fn migrate(
+    table: &mut toml::Table,
+    kind: &str,
+    version: u32,
+    steps: &[MigrationStep],
+) -> Result<()> {
+    let current = steps.len() as u32;
+    if version > current {
+        return Err(Error::UnsupportedVersion {
+            kind: kind.to_string(),
+            version,
+            supported: current,
+            saved_by: match table.get("version") {
+                Some(toml::Value::String(app_version)) => format!("maps {app_version}"),
+                _ => "an unknown maps version".to_string(),
+            },
+        });
+    }
+    for (from, step) in steps.iter().enumerate().skip(version as usize) {
+        info!(
+            "Migrating {kind} from schema version {from} to {}.",
+            from + 1
+        );
+        step(table)?;
+    }
+    table.insert(
+        SCHEMA_VERSION_KEY.to_string(),
+        toml::Value::Integer(current.into()),
+    );
+    Ok(())
+}
+
+fn no_changes(_: &mut toml::Table) -> Result<()> {
+    Ok(())
+}
+
+/// 0 -> 1: Colors were serialized with egui < 0.32, see [`migrate_old_egui_color`].
+fn migrate_session_colors(session: &mut toml::Table) -> Result<()> {
+    let Some(toml::Value::Table(maps)) = session.get_mut("maps") else {
+        return Ok(());
+    };
+    for (name, map) in maps.iter_mut() {
+        let toml::Value::Table(map) = map else {
+            continue;
+        };
+        for key in ["tint", "color_to_alpha"] {
+            let Some(value) = map.get_mut(key) else {
+                continue;
+            };
+            let color: [u8; 4] = value.clone().try_into().map_err(|e| {
+                Error::toml_deserialize(format!("Invalid {key} color of map {name}"), e)
+            })?;
+            let [r, g, b, a] = color;
+            let migrated =
+                migrate_old_egui_color(Some(Color32::from_rgba_premultiplied(r, g, b, a)))
+                    .expect("color is some");
+            *value = toml::Value::Array(
+                migrated
+                    .to_array()
+                    .into_iter()
+                    .map(|c| toml::Value::Integer(c.into()))
+                    .collect(),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// 1 -> 2: Sessions without draw order had their maps drawn in the order of their names.
+fn add_session_draw_order(session: &mut toml::Table) -> Result<()> {
+    if session.contains_key("draw_order") {
+        return Ok(());
+    }
+    let keys = match session.get("maps") {
+        Some(toml::Value::Table(maps)) => {
+            let mut names: Vec<&String> = maps.keys().collect();
+            names.sort();
+            names
+                .into_iter()
+                .map(|name| toml::Value::String(name.clone()))
+                .collect()
+        }
+        _ => Vec::new(),
+    };
+    let mut draw_order = toml::Table::new();
+    draw_order.insert("keys".to_string(), toml::Value::Array(keys));
+    session.insert("draw_order".to_string(), toml::Value::Table(draw_order));
+    Ok(())
+}
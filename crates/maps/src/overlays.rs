@@ -0,0 +1,281 @@
+//! Overlays of the grid: scale bar, axes indicator and legend.
+//!
+//! The same layout is drawn on screen and into SVG exports, see [`OverlayPainter`].
+
+use eframe::egui;
+
+use crate::grid_options::GridOptions;
+use maps_rendering::flip;
+
+/// Distance of overlays to the grid border, in points.
+const OVERLAY_MARGIN: f32 = 10.;
+/// Inner padding of overlay backgrounds, in points.
+const OVERLAY_PADDING: f32 = 6.;
+/// Approximate length of the scale bar, in points.
+const SCALE_BAR_TARGET_POINTS: f32 = 120.;
+/// Length of the arrows of the axes indicator, in points.
+const AXES_INDICATOR_LENGTH: f32 = 30.;
+/// Font size of overlay texts, in points.
+const OVERLAY_FONT_SIZE: f32 = 13.;
+
+/// Largest "nice" length (1, 2 or 5 times a power of ten) that is not longer than `max_meters`.
+/// Returns the mantissa and the exponent.
+fn nice_length(max_meters: f32) -> (f32, i32) {
+    // Tolerate rounding errors, e.g. 0.005 as f32 is slightly less than 5 * 10^-3.
+    let max_meters = max_meters * (1. + 1e-5);
+    let exponent = max_meters.log10().floor() as i32;
+    let mantissa = max_meters / 10f32.powi(exponent);
+    let nice = [5., 2., 1.]
+        .into_iter()
+        .find(|nice| *nice <= mantissa)
+        .unwrap_or(1.);
+    (nice, exponent)
+}
+
+/// Formats a length given as mantissa and exponent with a readable unit.
+fn format_length(mantissa: f32, exponent: i32) -> String {
+    let (unit, unit_exponent) = match exponent {
+        3.. => ("km", 3),
+        0.. => ("m", 0),
+        -2.. => ("cm", -2),
+        _ => ("mm", -3),
+    };
+    let value = f64::from(mantissa) * 10f64.powi(exponent - unit_exponent);
+    format!("{value} {unit}")
+}
+
+fn overlay_font() -> egui::FontId {
+    egui::FontId::new(OVERLAY_FONT_SIZE, egui::FontFamily::Proportional)
+}
+
+/// Target that overlays are drawn to, e.g. the painter of a grid or an SVG document.
+/// Positions are in points relative to the same origin as the overlay area.
+pub(crate) trait OverlayPainter {
+    fn rect_filled(&mut self, rect: egui::Rect, corner_radius: f32, color: egui::Color32);
+    /// Outline of a rectangle, the stroke is inside of the rectangle.
+    fn rect_stroke(&mut self, rect: egui::Rect, corner_radius: f32, stroke: egui::Stroke);
+    fn line_segment(&mut self, points: [egui::Pos2; 2], stroke: egui::Stroke);
+    fn circle_filled(&mut self, center: egui::Pos2, radius: f32, color: egui::Color32);
+    fn arrow(&mut self, origin: egui::Pos2, vec: egui::Vec2, stroke: egui::Stroke);
+    fn text(
+        &mut self,
+        pos: egui::Pos2,
+        anchor: egui::Align2,
+        text: &str,
+        font_id: &egui::FontId,
+        color: egui::Color32,
+    );
+    /// Width of a single line of text, for the layout of the overlays.
+    fn text_width(&self, text: &str, font_id: &egui::FontId) -> f32;
+}
+
+impl OverlayPainter for egui::Painter {
+    fn rect_filled(&mut self, rect: egui::Rect, corner_radius: f32, color: egui::Color32) {
+        egui::Painter::rect_filled(self, rect, corner_radius, color);
+    }
+
+    fn rect_stroke(&mut self, rect: egui::Rect, corner_radius: f32, stroke: egui::Stroke) {
+        egui::Painter::rect_stroke(self, rect, corner_radius, stroke, egui::StrokeKind::Inside);
+    }
+
+    fn line_segment(&mut self, points: [egui::Pos2; 2], stroke: egui::Stroke) {
+        egui::Painter::line_segment(self, points, stroke);
+    }
+
+    fn circle_filled(&mut self, center: egui::Pos2, radius: f32, color: egui::Color32) {
+        egui::Painter::circle_filled(self, center, radius, color);
+    }
+
+    fn arrow(&mut self, origin: egui::Pos2, vec: egui::Vec2, stroke: egui::Stroke) {
+        egui::Painter::arrow(self, origin, vec, stroke);
+    }
+
+    fn text(
+        &mut self,
+        pos: egui::Pos2,
+        anchor: egui::Align2,
+        text: &str,
+        font_id: &egui::FontId,
+        color: egui::Color32,
+    ) {
+        egui::Painter::text(self, pos, anchor, text, font_id.clone(), color);
+    }
+
+    fn text_width(&self, text: &str, font_id: &egui::FontId) -> f32 {
+        self.layout_no_wrap(text.to_string(), font_id.clone(), egui::Color32::WHITE)
+            .size()
+            .x
+    }
+}
+
+/// Name and optional tint color of a map in the legend.
+pub(crate) type LegendEntry = (String, Option<egui::Color32>);
+
+/// The enabled overlays of a grid with their contents, see [`Overlays::draw`].
+pub(crate) struct Overlays<'a> {
+    pub options: &'a GridOptions,
+    /// Display scale of the grid, for the scale bar.
+    pub points_per_meter: f32,
+    /// Direction of north counter-clockwise from the x-axis in radians, if known.
+    pub north_angle: Option<f32>,
+    /// Entries of the legend in draw order. Entries without color get an empty color box.
+    pub legend_entries: Vec<LegendEntry>,
+}
+
+impl Overlays<'_> {
+    /// Draws the enabled overlays into the corners of `rect`, the area of the grid.
+    pub(crate) fn draw(&self, painter: &mut impl OverlayPainter, rect: egui::Rect) {
+        let overlays = &self.options.overlays;
+        if overlays.scale_bar {
+            self.draw_scale_bar(painter, rect);
+        }
+        if overlays.axes_indicator {
+            self.draw_axes_indicator(painter, rect);
+        }
+        if overlays.legend {
+            self.draw_legend(painter, rect);
+        }
+    }
+
+    /// Metric scale bar in the lower left corner, its length adapts to the zoom.
+    fn draw_scale_bar(&self, painter: &mut impl OverlayPainter, rect: egui::Rect) {
+        let overlays = &self.options.overlays;
+        let (mantissa, exponent) = nice_length(SCALE_BAR_TARGET_POINTS / self.points_per_meter);
+        let length = mantissa * 10f32.powi(exponent) * self.points_per_meter;
+        let text_height = overlay_font().size + OVERLAY_PADDING;
+
+        let start = rect.left_bottom()
+            + egui::vec2(
+                OVERLAY_MARGIN + OVERLAY_PADDING,
+                -(OVERLAY_MARGIN + OVERLAY_PADDING),
+            );
+        let end = start + egui::vec2(length, 0.);
+        let background = egui::Rect::from_min_max(
+            start - egui::vec2(OVERLAY_PADDING, OVERLAY_PADDING + text_height),
+            end + egui::vec2(OVERLAY_PADDING, OVERLAY_PADDING),
+        );
+        painter.rect_filled(background, OVERLAY_PADDING, overlays.background_color);
+
+        let stroke = egui::Stroke::new(2., overlays.text_color);
+        let tick = egui::vec2(0., -OVERLAY_PADDING);
+        painter.line_segment([start, end], stroke);
+        painter.line_segment([start, start + tick], stroke);
+        painter.line_segment([end, end + tick], stroke);
+        painter.text(
+            start + egui::vec2(length / 2., -OVERLAY_PADDING),
+            egui::Align2::CENTER_BOTTOM,
+            &format_length(mantissa, exponent),
+            &overlay_font(),
+            overlays.text_color,
+        );
+    }
+
+    /// Directions of the root frame's x- and y-axes (and north, if known)
+    /// in the upper right corner.
+    fn draw_axes_indicator(&self, painter: &mut impl OverlayPainter, rect: egui::Rect) {
+        let overlays = &self.options.overlays;
+        let radius = AXES_INDICATOR_LENGTH + 2. * OVERLAY_PADDING;
+        let center =
+            rect.right_top() + egui::vec2(-(OVERLAY_MARGIN + radius), OVERLAY_MARGIN + radius);
+        painter.circle_filled(center, radius, overlays.background_color);
+
+        let mut arrows = vec![
+            ("x", 0., self.options.marker_x_color),
+            (
+                "y",
+                std::f32::consts::FRAC_PI_2,
+                self.options.marker_y_color,
+            ),
+        ];
+        if let Some(north_angle) = self.north_angle {
+            arrows.push(("N", north_angle, overlays.text_color));
+        }
+        for (label, angle, color) in arrows {
+            let direction = flip(egui::Vec2::angled(angle));
+            let arrow = direction * (AXES_INDICATOR_LENGTH - OVERLAY_PADDING);
+            painter.arrow(center, arrow, egui::Stroke::new(2., color));
+            painter.text(
+                center + direction * AXES_INDICATOR_LENGTH,
+                egui::Align2::CENTER_CENTER,
+                label,
+                &overlay_font(),
+                color,
+            );
+        }
+    }
+
+    /// Legend with names and colors in the upper left corner.
+    fn draw_legend(&self, painter: &mut impl OverlayPainter, rect: egui::Rect) {
+        let overlays = &self.options.overlays;
+        let entries = &self.legend_entries;
+        if entries.is_empty() {
+            return;
+        }
+        let font_id = overlay_font();
+        let row_height = font_id.size + OVERLAY_PADDING;
+        let box_size = egui::Vec2::splat(font_id.size);
+        let text_width = entries
+            .iter()
+            .map(|(name, _)| painter.text_width(name, &font_id))
+            .fold(0., f32::max);
+
+        let min = rect.left_top() + egui::Vec2::splat(OVERLAY_MARGIN);
+        let background = egui::Rect::from_min_size(
+            min,
+            egui::vec2(
+                text_width + box_size.x + 3. * OVERLAY_PADDING,
+                entries.len() as f32 * row_height + OVERLAY_PADDING,
+            ),
+        );
+        painter.rect_filled(background, OVERLAY_PADDING, overlays.background_color);
+
+        for (i, (name, color)) in entries.iter().enumerate() {
+            let row_min =
+                min + egui::vec2(OVERLAY_PADDING, OVERLAY_PADDING + i as f32 * row_height);
+            let color_box = egui::Rect::from_min_size(row_min, box_size);
+            match color {
+                Some(color) => painter.rect_filled(color_box, 2., *color),
+                None => {
+                    painter.rect_stroke(color_box, 2., egui::Stroke::new(1., overlays.text_color))
+                }
+            }
+            painter.text(
+                row_min + egui::vec2(box_size.x + OVERLAY_PADDING, 0.),
+                egui::Align2::LEFT_TOP,
+                name,
+                &font_id,
+                overlays.text_color,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nice_length_mantissa() {
+        assert_eq!(nice_length(0.005), (5., -3));
+        assert_eq!(nice_length(0.2), (2., -1));
+        assert_eq!(nice_length(0.39), (2., -1));
+        assert_eq!(nice_length(0.99), (5., -1));
+        assert_eq!(nice_length(1.), (1., 0));
+        assert_eq!(nice_length(1.9), (1., 0));
+        assert_eq!(nice_length(7.), (5., 0));
+        assert_eq!(nice_length(1000.), (1., 3));
+        assert_eq!(nice_length(50_000.), (5., 4));
+    }
+
+    #[test]
+    fn format_length_units() {
+        assert_eq!(format_length(5., -3), "5 mm");
+        assert_eq!(format_length(1., -4), "0.1 mm");
+        assert_eq!(format_length(1., -2), "1 cm");
+        assert_eq!(format_length(2., -1), "20 cm");
+        assert_eq!(format_length(1., 0), "1 m");
+        assert_eq!(format_length(5., 2), "500 m");
+        assert_eq!(format_length(1., 3), "1 km");
+        assert_eq!(format_length(5., 4), "50 km");
+    }
+}
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use confy;
 use log::{error, info, warn};
@@ -7,6 +7,11 @@ use toml;
 
 use crate::app::{AppOptions, SessionData};
 use crate::error::{Error, Result};
+use crate::migration::{
+    APP_OPTIONS_SCHEMA_VERSION, SCHEMA_VERSION_KEY, SESSION_SCHEMA_VERSION, migrate_app_options,
+    migrate_session,
+};
+use crate::session_paths::{relative_to, rewrite_session_paths};
 
 const APP_NAME: &str = "maps";
 const APP_OPTIONS_NAME: &str = "app_options";
@@ -15,6 +20,9 @@ const APP_OPTIONS_NAME: &str = "app_options";
 pub struct PersistenceOptions {
     pub custom_config_path: Option<PathBuf>,
     pub autosave: bool,
+    /// Save map paths relative to the session file, see [`save_session`].
+    #[serde(default)]
+    pub relative_session_paths: bool,
 }
 
 impl Default for PersistenceOptions {
@@ -22,6 +30,7 @@ impl Default for PersistenceOptions {
         PersistenceOptions {
             custom_config_path: None,
             autosave: true,
+            relative_session_paths: false,
         }
     }
 }
@@ -33,11 +42,30 @@ fn resolve_path_or_die(custom_path: Option<PathBuf>) -> PathBuf {
     })
 }
 
+/// Loads app options from a file, older options files are migrated.
+/// Falls back to defaults if the options can't be loaded.
 pub fn load_app_options(custom_path: &Option<PathBuf>) -> AppOptions {
     let config_path = resolve_path_or_die(custom_path.clone());
     info!("Loading options from {config_path:?}");
-    match confy::load_path(config_path.as_path()) {
+    let result = if config_path.exists() {
+        read_app_options(&config_path)
+    } else {
+        // Creates the file with default options.
+        confy::load_path(config_path.as_path()).map_err(Error::app)
+    };
+    match result {
         Ok(options) => options,
+        Err(e @ Error::UnsupportedVersion { .. }) => {
+            error!("{e} Using defaults, autosave is disabled to keep {config_path:?}.");
+            AppOptions {
+                persistence: PersistenceOptions {
+                    custom_config_path: None,
+                    autosave: false,
+                    ..Default::default()
+                },
+                ..Default::default()
+            }
+        }
         Err(e) => {
             warn!("Error loading options from {config_path:?}: {e}. Using defaults.");
             // Don't use the custom path here, it might be from a different version
@@ -53,19 +81,103 @@ pub fn load_app_options(custom_path: &Option<PathBuf>) -> AppOptions {
     }
 }
 
+/// Directory of the on-disk cache of image pyramid levels, next to other caches of the OS.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn pyramid_cache_dir() -> Option<PathBuf> {
+    directories::ProjectDirs::from("rs", "", APP_NAME)
+        .map(|dirs| dirs.cache_dir().join("pyramid_levels"))
+}
+
+fn read_app_options(path: &Path) -> Result<AppOptions> {
+    let toml = std::fs::read_to_string(path)
+        .map_err(|e| Error::io(format!("Cannot load options from {path:?}"), e))?;
+    let mut table: toml::Table = toml::from_str(&toml).map_err(|e| {
+        Error::toml_deserialize(format!("Cannot deserialize options from {path:?}"), e)
+    })?;
+    migrate_app_options(&mut table)?;
+    toml::Value::Table(table).try_into().map_err(|e| {
+        Error::toml_deserialize(format!("Cannot deserialize options from {path:?}"), e)
+    })
+}
+
 pub fn save_app_options(options: &AppOptions) {
     let config_path = resolve_path_or_die(options.persistence.custom_config_path.clone());
     info!("Saving options to {config_path:?}");
-    match confy::store_path(config_path, options) {
+    let table = match toml::Table::try_from(options) {
+        Ok(mut table) => {
+            table.insert(
+                SCHEMA_VERSION_KEY.to_string(),
+                toml::Value::Integer(APP_OPTIONS_SCHEMA_VERSION.into()),
+            );
+            table
+        }
+        Err(e) => {
+            error!("Error serializing options: {e}");
+            return;
+        }
+    };
+    match confy::store_path(config_path, table) {
         Ok(_) => (),
         Err(e) => error!("Error saving options: {e}"),
     }
 }
 
-pub fn save_session(path: &PathBuf, session: &SessionData) -> Result<()> {
+/// Serializes a session to TOML, with all map paths passed through `rewrite_path`.
+/// Placeholders of missing maps are written like loaded maps.
+pub fn session_to_toml(
+    session: &SessionData,
+    rewrite_path: impl Fn(&Path) -> PathBuf,
+) -> Result<String> {
+    let mut table = toml::Table::try_from(session)
+        .map_err(|e| Error::toml_serialize("Cannot serialize session", e))?;
+    add_missing_maps(&mut table, session)?;
+    rewrite_session_paths(&mut table, rewrite_path);
+    table.insert(
+        SCHEMA_VERSION_KEY.to_string(),
+        toml::Value::Integer(SESSION_SCHEMA_VERSION.into()),
+    );
+    toml::to_string_pretty(&table).map_err(|e| Error::toml_serialize("Cannot serialize session", e))
+}
+
+fn add_missing_maps(table: &mut toml::Table, session: &SessionData) -> Result<()> {
+    if session.missing_maps.is_empty() {
+        return Ok(());
+    }
+    let mut keys = session.draw_order.keys().clone();
+    let mut missing: Vec<_> = session.missing_maps.iter().collect();
+    missing.sort_by_key(|(_, missing)| missing.draw_order_index);
+    for (name, missing) in missing {
+        let value = toml::Value::try_from(&missing.state).map_err(|e| {
+            Error::toml_serialize(format!("Cannot serialize missing map {name}"), e)
+        })?;
+        if let toml::Value::Table(maps) = table
+            .entry("maps")
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+        {
+            maps.insert(name.clone(), value);
+        }
+        let index = missing.draw_order_index.unwrap_or(keys.len());
+        keys.insert(index.min(keys.len()), name.clone());
+    }
+    let keys = keys.into_iter().map(toml::Value::String).collect();
+    let mut draw_order = toml::Table::new();
+    draw_order.insert("keys".to_string(), toml::Value::Array(keys));
+    table.insert("draw_order".to_string(), toml::Value::Table(draw_order));
+    Ok(())
+}
+
+/// Saves a session to a TOML file.
+/// With `relative_paths`, map paths are written relative to the session file,
+/// such that the session stays valid if it is moved together with the maps.
+pub fn save_session(path: &PathBuf, session: &SessionData, relative_paths: bool) -> Result<()> {
     info!("Saving session to {path:?}");
-    let toml = toml::to_string_pretty(session)
-        .map_err(|e| Error::toml_serialize(format!("Cannot serialize session to {path:?}"), e))?;
+    let session_dir = path.parent().unwrap_or(Path::new(""));
+    let toml = session_to_toml(session, |map_path| {
+        relative_paths
+            .then(|| relative_to(map_path, session_dir))
+            .flatten()
+            .unwrap_or_else(|| map_path.to_path_buf())
+    })?;
 
     std::fs::write(path, toml)
         .map_err(|e| Error::io(format!("Cannot save session to {path:?}"), e))?;
@@ -73,12 +185,102 @@ pub fn save_session(path: &PathBuf, session: &SessionData) -> Result<()> {
     Ok(())
 }
 
+/// Loads a session from a TOML file, older sessions are migrated, see [`crate::migration`].
 pub fn load_session(path: &PathBuf) -> Result<SessionData> {
     info!("Loading session from {path:?}");
     let toml = std::fs::read_to_string(path)
         .map_err(|e| Error::io(format!("Cannot load session from {path:?}"), e))?;
 
-    toml::from_str(&toml).map_err(|e| {
+    let mut table: toml::Table = toml::from_str(&toml).map_err(|e| {
+        Error::toml_deserialize(format!("Cannot deserialize session from {path:?}"), e)
+    })?;
+    migrate_session(&mut table)?;
+    toml::Value::Table(table).try_into().map_err(|e| {
         Error::toml_deserialize(format!("Cannot deserialize session from {path:?}"), e)
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use eframe::egui::Color32;
+
+    use super::*;
+    use crate::map_state::{MissingMap, loaded_draw_order_index, session_draw_order_index};
+
+    const SESSION: &str = "tests/sessions/schema_v2_session.toml";
+    const ROT_MAP: &str = "data/dummy_maps/dummy_map_rot.yaml";
+    const LORES_MAP: &str = "data/dummy_maps/dummy_map_lores.yaml";
+
+    fn to_toml(session: &SessionData) -> String {
+        session_to_toml(session, Path::to_path_buf).expect("Failed to serialize session")
+    }
+
+    /// Turns maps of a deserialized session into placeholders, like a failed load does,
+    /// see [`crate::app::AppState::add_missing_map`].
+    fn make_missing(session: &mut SessionData, names: &[&str]) {
+        for &name in names {
+            let state = session.maps.remove(name).expect("map");
+            let draw_order_index = session
+                .draw_order
+                .position(name)
+                .map(|index| session_draw_order_index(&session.missing_maps, index));
+            session.draw_order.remove(name);
+            session.missing_maps.insert(
+                name.to_string(),
+                MissingMap {
+                    state,
+                    error: "not found".to_string(),
+                    draw_order_index,
+                },
+            );
+        }
+    }
+
+    #[test]
+    fn missing_maps_survive_resave() {
+        let mut session = load_session(&PathBuf::from(SESSION)).expect("Failed to load session");
+        let mut placeholders = load_session(&PathBuf::from(SESSION)).expect("Failed to load");
+        for (name, tint) in [
+            ("missing_a.yaml", Color32::RED),
+            ("missing_b.yaml", Color32::BLUE),
+        ] {
+            let mut state = placeholders.maps.remove(ROT_MAP).expect("map");
+            state.pose.translation.x = 1.5;
+            state.pose.rotation.yaw = 0.5;
+            state.tint = Some(tint);
+            session.maps.insert(name.to_string(), state);
+            placeholders = load_session(&PathBuf::from(SESSION)).expect("Failed to load");
+        }
+        session.draw_order = crate::draw_order::DrawOrder::default();
+        for name in ["missing_a.yaml", ROT_MAP, "missing_b.yaml", LORES_MAP] {
+            session.draw_order.add(name.to_string());
+        }
+        make_missing(&mut session, &["missing_a.yaml", "missing_b.yaml"]);
+        assert_eq!(session.draw_order.keys(), &[ROT_MAP, LORES_MAP]);
+
+        // The placeholders are written at their draw order positions, interleaved
+        // with the loaded maps, and are the same after loading and saving again.
+        let saved = to_toml(&session);
+        let mut reloaded: SessionData = toml::from_str(&saved).expect("Failed to deserialize");
+        assert_eq!(
+            reloaded.draw_order.keys(),
+            &["missing_a.yaml", ROT_MAP, "missing_b.yaml", LORES_MAP]
+        );
+        let missing_a = &reloaded.maps["missing_a.yaml"];
+        assert_eq!(missing_a.pose.translation.x, 1.5);
+        assert_eq!(missing_a.pose.rotation.yaw, 0.5);
+        assert_eq!(missing_a.tint, Some(Color32::RED));
+        assert_eq!(reloaded.maps["missing_b.yaml"].tint, Some(Color32::BLUE));
+
+        make_missing(&mut reloaded, &["missing_a.yaml", "missing_b.yaml"]);
+        assert_eq!(to_toml(&reloaded), saved);
+        // A relocated placeholder goes back between the loaded maps.
+        let missing_b = reloaded
+            .missing_maps
+            .remove("missing_b.yaml")
+            .expect("missing");
+        let index = missing_b.draw_order_index.expect("index");
+        assert_eq!(index, 2);
+        assert_eq!(loaded_draw_order_index(&reloaded.missing_maps, index), 1);
+    }
+}
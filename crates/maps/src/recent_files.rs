@@ -0,0 +1,96 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of entries of each recent files list.
+pub const MAX_RECENT_FILES: usize = 10;
+
+/// Recently opened sessions and maps, most recent first.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RecentFiles {
+    pub sessions: Vec<PathBuf>,
+    pub maps: Vec<PathBuf>,
+}
+
+/// Moves (or adds) `path` to the front of a list and drops the oldest entries.
+fn push_front(list: &mut Vec<PathBuf>, path: &Path) {
+    // Absolute paths stay valid if the app is started from a different working directory.
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    list.retain(|p| *p != path);
+    list.insert(0, path);
+    list.truncate(MAX_RECENT_FILES);
+}
+
+impl RecentFiles {
+    /// Remembers a session file or session bundle that was opened.
+    pub fn add_session(&mut self, path: &Path) {
+        push_front(&mut self.sessions, path);
+    }
+
+    /// Remembers a map YAML or GeoTIFF file that was opened.
+    pub fn add_map(&mut self, path: &Path) {
+        push_front(&mut self.maps, path);
+    }
+
+    /// Removes a file from both lists.
+    pub fn remove(&mut self, path: &Path) {
+        self.sessions.retain(|p| p != path);
+        self.maps.retain(|p| p != path);
+    }
+
+    /// Removes all files that no longer exist.
+    pub fn remove_missing(&mut self) {
+        self.sessions.retain(|p| p.exists());
+        self.maps.retain(|p| p.exists());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty() && self.maps.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(names: &[&str]) -> Vec<PathBuf> {
+        names
+            .iter()
+            .map(|name| std::path::absolute(name).expect("absolute path"))
+            .collect()
+    }
+
+    #[test]
+    fn push_front_moves_duplicates_to_front() {
+        let mut list = Vec::new();
+        for name in ["a.yaml", "b.yaml", "c.yaml", "a.yaml"] {
+            push_front(&mut list, Path::new(name));
+        }
+        assert_eq!(list, paths(&["a.yaml", "c.yaml", "b.yaml"]));
+        // Relative and absolute paths of the same file are one entry.
+        push_front(
+            &mut list,
+            &std::path::absolute("b.yaml").expect("absolute path"),
+        );
+        assert_eq!(list, paths(&["b.yaml", "a.yaml", "c.yaml"]));
+    }
+
+    #[test]
+    fn push_front_drops_oldest() {
+        let names: Vec<String> = (0..MAX_RECENT_FILES + 2)
+            .map(|i| format!("{i}.yaml"))
+            .collect();
+        let mut list = Vec::new();
+        for name in &names {
+            push_front(&mut list, Path::new(name));
+        }
+        assert_eq!(list.len(), MAX_RECENT_FILES);
+        let newest: Vec<&str> = names
+            .iter()
+            .rev()
+            .take(MAX_RECENT_FILES)
+            .map(String::as_str)
+            .collect();
+        assert_eq!(list, paths(&newest));
+    }
+}
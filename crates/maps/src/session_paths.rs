@@ -0,0 +1,139 @@
+//! Portable file paths in session files.
+//!
+//! Sessions can store map paths relative to the session file, and missing
+//! map files are searched relative to the session file when it is loaded,
+//! e.g. after a directory with maps and session was moved or shared.
+
+use std::path::{Component, Path, PathBuf};
+
+use log::debug;
+
+/// Returns `path` relative to `base_dir`, or `None` if there's no relative path,
+/// e.g. for different drives on Windows. Both are made absolute first.
+pub fn relative_to(path: &Path, base_dir: &Path) -> Option<PathBuf> {
+    let path = std::path::absolute(path).ok()?;
+    let base_dir = std::path::absolute(base_dir).ok()?;
+    let path_components: Vec<Component> = path.components().collect();
+    let base_components: Vec<Component> = base_dir.components().collect();
+    if path_components.first() != base_components.first() {
+        return None;
+    }
+    let common = path_components
+        .iter()
+        .zip(&base_components)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut relative = PathBuf::new();
+    for _ in common..base_components.len() {
+        relative.push("..");
+    }
+    relative.extend(&path_components[common..]);
+    Some(relative)
+}
+
+/// Finds map files of a session, also if the session was moved to a different place.
+pub struct PathResolver {
+    search_dirs: Vec<PathBuf>,
+}
+
+impl PathResolver {
+    /// Creates a resolver that searches relative to the directory of the session file.
+    pub fn new(session_path: &Path) -> PathResolver {
+        PathResolver {
+            search_dirs: session_path
+                .parent()
+                .map(Path::to_path_buf)
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    /// Adds a directory to search in, e.g. where the user relocated a missing file.
+    /// Added directories are searched first.
+    pub fn add_search_dir(&mut self, dir: &Path) {
+        if !self.search_dirs.iter().any(|d| d == dir) {
+            self.search_dirs.insert(0, dir.to_path_buf());
+        }
+    }
+
+    /// Returns an existing path for `path`, or `None` if it can't be found.
+    ///
+    /// Existing absolute paths are kept. Relative paths are resolved against the search
+    /// directories first, and against the working directory only if they are not found
+    /// there. Otherwise, the trailing components of the path (down to the file name)
+    /// are searched for in the search directories.
+    pub fn resolve(&self, path: &Path) -> Option<PathBuf> {
+        if path.is_absolute() && path.exists() {
+            return Some(path.to_path_buf());
+        }
+        let found = |candidate: PathBuf| {
+            candidate.exists().then(|| {
+                debug!("Resolved {path:?} to {candidate:?}");
+                candidate
+            })
+        };
+        if path.is_relative() {
+            if let Some(candidate) = self
+                .search_dirs
+                .iter()
+                .find_map(|dir| found(dir.join(path)))
+            {
+                return Some(candidate);
+            }
+            if path.exists() {
+                return Some(path.to_path_buf());
+            }
+        }
+        let components: Vec<Component> = path
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_) | Component::ParentDir))
+            .collect();
+        self.search_dirs.iter().find_map(|dir| {
+            (0..components.len())
+                .find_map(|start| found(dir.join(components[start..].iter().collect::<PathBuf>())))
+        })
+    }
+}
+
+/// Rewrites all map paths in a serialized session, i.e. map names (YAML paths),
+/// image and YAML paths of the metadata, the visible maps of viewpoints and the draw order.
+pub fn rewrite_session_paths(session: &mut toml::Table, rewrite: impl Fn(&Path) -> PathBuf) {
+    let rewrite_str = |s: &str| rewrite(Path::new(s)).to_string_lossy().into_owned();
+
+    if let Some(toml::Value::Table(maps)) = session.remove("maps") {
+        let mut rewritten = toml::Table::new();
+        for (name, mut map) in maps {
+            if let Some(toml::Value::Table(meta)) = map.get_mut("meta") {
+                for key in ["image_path", "yaml_path"] {
+                    if let Some(toml::Value::String(path)) = meta.get_mut(key) {
+                        *path = rewrite_str(path);
+                    }
+                }
+            }
+            rewritten.insert(rewrite_str(&name), map);
+        }
+        session.insert("maps".to_string(), toml::Value::Table(rewritten));
+    }
+
+    if let Some(toml::Value::Array(viewpoints)) = session.get_mut("viewpoints") {
+        for viewpoint in viewpoints {
+            if let Some(toml::Value::Array(names)) = viewpoint.get_mut("visible_maps") {
+                for name in names {
+                    if let toml::Value::String(name) = name {
+                        *name = rewrite_str(name);
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some(toml::Value::Table(draw_order)) = session.get_mut("draw_order")
+        && let Some(toml::Value::Array(keys)) = draw_order.get_mut("keys")
+    {
+        for key in keys {
+            if let toml::Value::String(name) = key {
+                *name = rewrite_str(name);
+            }
+        }
+    }
+}
@@ -0,0 +1,239 @@
+//! Minimal SVG writer for vector exports of the grid.
+//!
+//! Shapes are given in points, like with an [`egui::Painter`], one point is one
+//! user unit of the SVG document. Raster images are embedded as base64 PNG data.
+
+use std::fmt::Write as _;
+use std::io::Cursor;
+
+use eframe::egui;
+
+use crate::error::{Error, Result};
+
+/// An SVG document of a fixed size that shapes are added to, in drawing order.
+pub struct SvgDocument {
+    size: egui::Vec2,
+    body: String,
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Standard base64 encoding with padding, as used in data URLs.
+pub fn base64_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let triple = u32::from(b[0]) << 16 | u32::from(b[1]) << 8 | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (triple >> (18 - 6 * i)) & 0x3f;
+                encoded.push(BASE64_ALPHABET[index as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Escapes text for XML content and attribute values.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Formats a coordinate compactly, with at most two decimals.
+fn num(value: f32) -> String {
+    let formatted = format!("{value:.2}");
+    let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
+    match trimmed {
+        "-0" | "" => "0".to_string(),
+        _ => trimmed.to_string(),
+    }
+}
+
+/// Color and opacity attributes of a fill or stroke.
+fn paint(attribute: &str, color: egui::Color32) -> String {
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    let mut paint = format!(r##"{attribute}="#{r:02x}{g:02x}{b:02x}""##);
+    if a < 255 {
+        let _ = write!(
+            paint,
+            r#" {attribute}-opacity="{}""#,
+            num(f32::from(a) / 255.)
+        );
+    }
+    paint
+}
+
+fn stroke_paint(stroke: egui::Stroke) -> String {
+    format!(
+        r#"fill="none" {} stroke-width="{}""#,
+        paint("stroke", stroke.color),
+        num(stroke.width)
+    )
+}
+
+impl SvgDocument {
+    pub fn new(size: egui::Vec2) -> SvgDocument {
+        SvgDocument {
+            size,
+            body: String::new(),
+        }
+    }
+
+    /// Starts a named group, e.g. to get separate layers in vector graphics editors.
+    pub fn begin_group(&mut self, id: &str) {
+        let _ = writeln!(self.body, r#"<g id="{}">"#, escape(id));
+    }
+
+    pub fn end_group(&mut self) {
+        self.body.push_str("</g>\n");
+    }
+
+    pub fn line_segment(&mut self, points: [egui::Pos2; 2], stroke: egui::Stroke) {
+        let [a, b] = points;
+        let _ = writeln!(
+            self.body,
+            r#"<line x1="{}" y1="{}" x2="{}" y2="{}" {} stroke-linecap="round"/>"#,
+            num(a.x),
+            num(a.y),
+            num(b.x),
+            num(b.y),
+            stroke_paint(stroke)
+        );
+    }
+
+    /// Arrow from `origin` along `vec`, with the same tip as [`egui::Painter::arrow`].
+    pub fn arrow(&mut self, origin: egui::Pos2, vec: egui::Vec2, stroke: egui::Stroke) {
+        let rot = egui::emath::Rot2::from_angle(std::f32::consts::TAU / 10.);
+        let tip_length = vec.length() / 4.;
+        let tip = origin + vec;
+        let dir = vec.normalized();
+        self.line_segment([origin, tip], stroke);
+        self.line_segment([tip, tip - tip_length * (rot * dir)], stroke);
+        self.line_segment([tip, tip - tip_length * (rot.inverse() * dir)], stroke);
+    }
+
+    pub fn rect_filled(&mut self, rect: egui::Rect, corner_radius: f32, color: egui::Color32) {
+        self.rect(rect, corner_radius, &paint("fill", color));
+    }
+
+    /// Rectangle outline, centered on the rectangle's border.
+    pub fn rect_stroke(&mut self, rect: egui::Rect, corner_radius: f32, stroke: egui::Stroke) {
+        self.rect(rect, corner_radius, &stroke_paint(stroke));
+    }
+
+    fn rect(&mut self, rect: egui::Rect, corner_radius: f32, paint: &str) {
+        let _ = writeln!(
+            self.body,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" rx="{}" {paint}/>"#,
+            num(rect.min.x),
+            num(rect.min.y),
+            num(rect.width()),
+            num(rect.height()),
+            num(corner_radius)
+        );
+    }
+
+    pub fn circle_filled(&mut self, center: egui::Pos2, radius: f32, color: egui::Color32) {
+        self.circle(center, radius, &paint("fill", color));
+    }
+
+    pub fn circle_stroke(&mut self, center: egui::Pos2, radius: f32, stroke: egui::Stroke) {
+        self.circle(center, radius, &stroke_paint(stroke));
+    }
+
+    fn circle(&mut self, center: egui::Pos2, radius: f32, paint: &str) {
+        let _ = writeln!(
+            self.body,
+            r#"<circle cx="{}" cy="{}" r="{}" {paint}/>"#,
+            num(center.x),
+            num(center.y),
+            num(radius)
+        );
+    }
+
+    /// Single line of text, anchored at `pos` like with [`egui::Painter::text`].
+    pub fn text(
+        &mut self,
+        pos: egui::Pos2,
+        anchor: egui::Align2,
+        text: &str,
+        font_id: &egui::FontId,
+        color: egui::Color32,
+    ) {
+        let text_anchor = match anchor.x() {
+            egui::Align::Min => "start",
+            egui::Align::Center => "middle",
+            egui::Align::Max => "end",
+        };
+        let baseline = match anchor.y() {
+            egui::Align::Min => "hanging",
+            egui::Align::Center => "central",
+            egui::Align::Max => "text-after-edge",
+        };
+        let font_family = match font_id.family {
+            egui::FontFamily::Monospace => "monospace",
+            _ => "sans-serif",
+        };
+        let _ = writeln!(
+            self.body,
+            r#"<text x="{}" y="{}" text-anchor="{text_anchor}" dominant-baseline="{baseline}" font-family="{font_family}" font-size="{}" {}>{}</text>"#,
+            num(pos.x),
+            num(pos.y),
+            num(font_id.size),
+            paint("fill", color),
+            escape(text)
+        );
+    }
+
+    /// Embeds an image as PNG. `transform` maps pixel coordinates of the image to points,
+    /// as SVG matrix `[a, b, c, d, e, f]`: `x' = a x + c y + e`, `y' = b x + d y + f`.
+    /// Crisp images are rendered with sharp pixels, otherwise they are interpolated.
+    pub fn image(
+        &mut self,
+        image: &image::DynamicImage,
+        transform: [f32; 6],
+        crisp: bool,
+    ) -> Result<()> {
+        let mut png = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .map_err(|e| Error::app(format!("Failed to encode image as PNG: {e}")))?;
+        // The matrix needs more precision than other coordinates, e.g. for small cells.
+        let matrix = transform.map(|v| v.to_string()).join(" ");
+        let rendering = if crisp {
+            r#" image-rendering="pixelated" style="image-rendering:pixelated""#
+        } else {
+            ""
+        };
+        let _ = writeln!(
+            self.body,
+            r#"<image width="{}" height="{}" preserveAspectRatio="none" transform="matrix({matrix})"{rendering} xlink:href="data:image/png;base64,{}"/>"#,
+            image.width(),
+            image.height(),
+            base64_encode(&png)
+        );
+        Ok(())
+    }
+
+    /// Returns the complete SVG document.
+    pub fn finish(self) -> String {
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+            <svg xmlns=\"http://www.w3.org/2000/svg\" xmlns:xlink=\"http://www.w3.org/1999/xlink\" \
+            width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n{}</svg>\n",
+            self.body,
+            w = num(self.size.x),
+            h = num(self.size.y),
+        )
+    }
+}
@@ -0,0 +1,187 @@
+use std::path::{Path, PathBuf};
+
+use maps::bundle::{export_bundle, extract_bundle};
+use maps::persistence::load_session;
+use maps::session_paths::PathResolver;
+use maps_io_ros::MapPose;
+use maps_io_ros::geo_reference::WorldFile;
+use maps_io_ros::geotiff::{load_geotiff_meta, save_geotiff};
+
+const SESSION: &str = "tests/sessions/schema_v1_session.toml";
+
+/// Empty directory for a test, removed again by [`TestDir::drop`].
+struct TestDir(PathBuf);
+
+impl TestDir {
+    fn new(name: &str) -> TestDir {
+        let dir = std::env::temp_dir().join(format!("maps_test_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("Failed to create test directory");
+        TestDir(dir)
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Writes a tar archive with the names as they are, also invalid ones.
+fn tar_bytes(entries: &[(String, Vec<u8>)]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(Vec::new());
+    for (name, data) in entries {
+        let mut header = tar::Header::new_old();
+        header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append(&header, data.as_slice())
+            .expect("Failed to write tar");
+    }
+    builder.into_inner().expect("Failed to write tar")
+}
+
+/// Sorted paths of the files in a bundle.
+fn bundle_files(bundle: &Path) -> Vec<PathBuf> {
+    let file = std::fs::File::open(bundle).expect("Failed to open bundle");
+    let mut archive = tar::Archive::new(file);
+    let mut files: Vec<PathBuf> = archive
+        .entries()
+        .expect("Failed to read bundle")
+        .map(|entry| {
+            let entry = entry.expect("Failed to read bundle");
+            entry.path().expect("Invalid path").into_owned()
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn extract_rejects_truncated_archives() {
+    let dir = TestDir::new("bundle_truncated");
+    let data = tar_bytes(&[
+        ("session.toml".to_string(), Vec::new()),
+        ("map.png".to_string(), vec![1; 600]),
+    ]);
+    let bundle = dir.0.join("truncated.tar");
+    std::fs::write(&bundle, &data[..1024]).expect("Failed to write bundle");
+    assert!(extract_bundle(&bundle, &dir.0.join("extracted")).is_err());
+}
+
+#[test]
+fn extract_rejects_paths_outside_of_target() {
+    let dir = TestDir::new("bundle_escape");
+    let target = dir.0.join("extracted");
+    for name in ["../escaped.txt", "maps/../../escaped.txt", "/escaped.txt"] {
+        let bundle = dir.0.join("malicious.tar");
+        let entries = [
+            ("session.toml".to_string(), Vec::new()),
+            (name.to_string(), b"escaped".to_vec()),
+        ];
+        std::fs::write(&bundle, tar_bytes(&entries)).expect("Failed to write bundle");
+
+        let error = extract_bundle(&bundle, &target).expect_err(name);
+        assert!(
+            error.to_string().contains("Invalid bundle"),
+            "{name}: {error}"
+        );
+        assert!(!dir.0.join("escaped.txt").exists());
+    }
+    assert!(!Path::new("/escaped.txt").exists());
+}
+
+#[test]
+fn extract_requires_session_file() {
+    let dir = TestDir::new("bundle_no_session");
+    let bundle = dir.0.join("no_session.tar");
+    std::fs::write(&bundle, tar_bytes(&[("map.png".to_string(), vec![1])]))
+        .expect("Failed to write bundle");
+    assert!(extract_bundle(&bundle, &dir.0.join("extracted")).is_err());
+}
+
+#[test]
+fn export_and_extract_bundle() {
+    let dir = TestDir::new("bundle_export");
+    let mut session = load_session(&PathBuf::from(SESSION)).expect("Failed to load session");
+    let names: Vec<String> = session.maps.keys().cloned().collect();
+
+    // A GeoTIFF map is identified by its image path, it has no YAML file.
+    let mut geotiff_map = session.maps.remove(&names[0]).expect("map");
+    let geotiff_path = dir.0.join("geo.tif");
+    let image = image::DynamicImage::new_luma8(4, 3);
+    let transform = WorldFile::local(&geotiff_map.meta, &MapPose::default(), [4., 3.].into());
+    save_geotiff(&geotiff_path, &image, &transform, None).expect("Failed to save GeoTIFF");
+    geotiff_map.meta = load_geotiff_meta(&geotiff_path, None)
+        .expect("Failed to load GeoTIFF")
+        .meta;
+    assert_eq!(geotiff_map.meta.yaml_path, geotiff_map.meta.image_path);
+    let geotiff_name = geotiff_path.to_string_lossy().into_owned();
+    session.maps.insert(geotiff_name, geotiff_map);
+
+    let bundle = dir.0.join("session.tar");
+    export_bundle(&bundle, &session).expect("Failed to export bundle");
+    let files = bundle_files(&bundle);
+    // Maps are numbered in the order of their names, the GeoTIFF path is absolute.
+    let expected: Vec<PathBuf> = [
+        "maps/0/geo.tif",
+        "maps/0/geo_pose.yaml",
+        "maps/1/dummy_map_lores.png",
+        "maps/1/dummy_map_rot.yaml",
+        "maps/1/dummy_map_rot_pose.yaml",
+        "session.toml",
+    ]
+    .iter()
+    .map(PathBuf::from)
+    .collect();
+    assert_eq!(files, expected);
+
+    let session_path =
+        extract_bundle(&bundle, &dir.0.join("extracted")).expect("Failed to extract bundle");
+    let extracted = load_session(&session_path).expect("Failed to load extracted session");
+    let resolver = PathResolver::new(&session_path);
+    let geotiff = &extracted.maps["maps/0/geo.tif"];
+    assert_eq!(geotiff.meta.yaml_path, PathBuf::from("maps/0/geo.tif"));
+    assert_eq!(geotiff.meta.image_path, geotiff.meta.yaml_path);
+    let rot = &extracted.maps["maps/1/dummy_map_rot.yaml"];
+    assert_eq!(
+        rot.meta.image_path,
+        PathBuf::from("maps/1/dummy_map_lores.png")
+    );
+    for map in extracted.maps.values() {
+        for path in [&map.meta.image_path, &map.meta.yaml_path] {
+            let resolved = resolver.resolve(path).expect("Bundled file not found");
+            assert!(resolved.starts_with(dir.0.join("extracted")));
+        }
+    }
+}
+
+#[test]
+fn bundle_long_file_names() {
+    let dir = TestDir::new("bundle_long_names");
+    let mut session = load_session(&PathBuf::from(SESSION)).expect("Failed to load session");
+    let name = session.maps.keys().next().cloned().expect("map");
+    session.maps.retain(|n, _| *n == name);
+    let map = session.maps.get_mut(&name).expect("map");
+    // Longer than the 100 bytes of the name field in plain tar headers.
+    let long_image_path = dir.0.join(format!("{}.png", "long".repeat(40)));
+    std::fs::copy(&map.meta.image_path, &long_image_path).expect("Failed to copy image");
+    map.meta.image_path.clone_from(&long_image_path);
+
+    let bundle = dir.0.join("session.tar");
+    export_bundle(&bundle, &session).expect("Failed to export bundle");
+    let bundled_image = PathBuf::from("maps/0").join(long_image_path.file_name().expect("name"));
+    assert!(bundle_files(&bundle).contains(&bundled_image));
+
+    let session_path =
+        extract_bundle(&bundle, &dir.0.join("extracted")).expect("Failed to extract bundle");
+    let extracted = load_session(&session_path).expect("Failed to load extracted session");
+    let map = extracted.maps.values().next().expect("map");
+    assert_eq!(map.meta.image_path, bundled_image);
+    assert_eq!(
+        std::fs::read(dir.0.join("extracted").join(&bundled_image)).expect("Image not extracted"),
+        std::fs::read(&long_image_path).expect("Failed to read image")
+    );
+}
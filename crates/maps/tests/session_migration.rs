@@ -0,0 +1,122 @@
+use std::path::PathBuf;
+
+use eframe::egui::Color32;
+
+use maps::error::Error;
+use maps::migration::{SESSION_SCHEMA_VERSION, app_options_schema_version, session_schema_version};
+use maps::persistence::{load_app_options, load_session, session_to_toml};
+
+const LORES_MAP: &str = "data/dummy_maps/dummy_map_lores.yaml";
+const ROT_MAP: &str = "data/dummy_maps/dummy_map_rot.yaml";
+/// Semi-transparent tint of the rotated map in all session files.
+const ROT_MAP_TINT: [u8; 4] = [154, 177, 63, 141];
+
+fn schema_version_of(path: &str) -> u32 {
+    let table: toml::Table =
+        toml::from_str(&std::fs::read_to_string(path).expect("read")).expect("parse");
+    session_schema_version(&table).expect("schema version")
+}
+
+#[test]
+fn session_v0_without_version() {
+    let path = "tests/sessions/custom_session.toml";
+    assert_eq!(schema_version_of(path), 0);
+    let session = load_session(&PathBuf::from(path)).expect("Failed to load session");
+
+    // Colors of egui < 0.32 are migrated, opaque colors are unchanged.
+    let rot_tint = session.maps[ROT_MAP].tint.expect("tint");
+    assert_ne!(rot_tint.to_array(), ROT_MAP_TINT);
+    assert_eq!(rot_tint.a(), ROT_MAP_TINT[3]);
+    assert_eq!(
+        session.maps[LORES_MAP].tint,
+        Some(Color32::from_rgba_premultiplied(218, 68, 68, 255))
+    );
+    // Without saved draw order, maps are drawn in the order of their names.
+    assert_eq!(session.draw_order.keys(), &[LORES_MAP, ROT_MAP]);
+    assert_eq!(session.grid_lenses.len(), 1);
+}
+
+#[test]
+fn session_v1_with_app_version() {
+    let path = "tests/sessions/schema_v1_session.toml";
+    assert_eq!(schema_version_of(path), 1);
+    let session = load_session(&PathBuf::from(path)).expect("Failed to load session");
+
+    assert_eq!(
+        session.maps[ROT_MAP].tint.expect("tint").to_array(),
+        ROT_MAP_TINT
+    );
+    assert_eq!(session.draw_order.keys(), &[ROT_MAP, LORES_MAP]);
+}
+
+#[test]
+fn session_v2_current() {
+    let path = "tests/sessions/schema_v2_session.toml";
+    assert_eq!(schema_version_of(path), SESSION_SCHEMA_VERSION);
+    let session = load_session(&PathBuf::from(path)).expect("Failed to load session");
+    assert_eq!(
+        session.maps[ROT_MAP].tint.expect("tint").to_array(),
+        ROT_MAP_TINT
+    );
+    assert_eq!(session.draw_order.keys(), &[ROT_MAP, LORES_MAP]);
+
+    // Saved sessions have the current schema version and are loaded unchanged.
+    let saved: toml::Table = toml::from_str(
+        &session_to_toml(&session, std::path::Path::to_path_buf).expect("serialize"),
+    )
+    .expect("parse");
+    assert_eq!(
+        session_schema_version(&saved).expect("schema version"),
+        SESSION_SCHEMA_VERSION
+    );
+    assert_eq!(
+        saved["maps"][ROT_MAP]["tint"],
+        toml::Value::try_from(ROT_MAP_TINT).unwrap()
+    );
+}
+
+#[test]
+fn session_unsupported_version() {
+    let result = load_session(&PathBuf::from(
+        "tests/sessions/schema_unsupported_session.toml",
+    ));
+    let Err(Error::UnsupportedVersion {
+        version,
+        supported,
+        saved_by,
+        ..
+    }) = result
+    else {
+        panic!("Expected unsupported version error");
+    };
+    assert_eq!(version, 999);
+    assert_eq!(supported, SESSION_SCHEMA_VERSION);
+    assert_eq!(saved_by, "maps 99.0.0");
+}
+
+#[test]
+fn app_options_v1_without_schema_version() {
+    let path = "tests/sessions/custom_options.toml";
+    let table: toml::Table =
+        toml::from_str(&std::fs::read_to_string(path).expect("read")).expect("parse");
+    assert_eq!(
+        app_options_schema_version(&table).expect("schema version"),
+        1
+    );
+
+    let options = load_app_options(&Some(PathBuf::from(path)));
+    assert_eq!(
+        options.persistence.custom_config_path,
+        Some(PathBuf::from(path))
+    );
+}
+
+#[test]
+fn app_options_unsupported_version() {
+    let options = load_app_options(&Some(PathBuf::from(
+        "tests/sessions/schema_unsupported_options.toml",
+    )));
+    // Defaults are used, without overwriting the newer options file.
+    assert_eq!(options.persistence.custom_config_path, None);
+    assert!(!options.persistence.autosave);
+}
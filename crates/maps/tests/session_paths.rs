@@ -0,0 +1,115 @@
+use std::path::{Path, PathBuf};
+
+use maps::session_paths::{PathResolver, relative_to};
+
+#[test]
+fn relative_paths() {
+    let relative = |path: &str, base_dir: &str| relative_to(Path::new(path), Path::new(base_dir));
+    assert_eq!(
+        relative("/data/maps/map.yaml", "/data/maps"),
+        Some(PathBuf::from("map.yaml"))
+    );
+    assert_eq!(
+        relative("/data/maps/map.yaml", "/data/sessions/2024"),
+        Some(PathBuf::from("../../maps/map.yaml"))
+    );
+    assert_eq!(
+        relative("/data/maps/map.yaml", "/"),
+        Some(PathBuf::from("data/maps/map.yaml"))
+    );
+    assert_eq!(relative("/data", "/data"), Some(PathBuf::new()));
+    // Relative paths are relative to the working directory.
+    assert_eq!(
+        relative("data/maps/map.yaml", "data/sessions"),
+        Some(PathBuf::from("../maps/map.yaml"))
+    );
+    assert_eq!(
+        relative_to(
+            Path::new("map.yaml"),
+            &std::env::current_dir().expect("working directory")
+        ),
+        Some(PathBuf::from("map.yaml"))
+    );
+}
+
+/// Directory with `session.toml` and `maps/map.yaml`, removed when dropped.
+struct SessionDir(PathBuf);
+
+impl SessionDir {
+    fn new(name: &str) -> SessionDir {
+        let dir = std::env::temp_dir().join(format!("maps_test_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("maps")).expect("Failed to create test directory");
+        std::fs::write(dir.join("session.toml"), "").expect("Failed to write session");
+        std::fs::write(dir.join("maps/map.yaml"), "").expect("Failed to write map");
+        SessionDir(dir)
+    }
+}
+
+impl Drop for SessionDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn resolve_paths_of_moved_session() {
+    let dir = SessionDir::new("resolve_moved");
+    let resolver = PathResolver::new(&dir.0.join("session.toml"));
+    let expected = Some(dir.0.join("maps/map.yaml"));
+
+    // Existing paths are kept.
+    assert_eq!(resolver.resolve(&dir.0.join("maps/map.yaml")), expected);
+    // Relative to the session file.
+    assert_eq!(resolver.resolve(Path::new("maps/map.yaml")), expected);
+    // Absolute paths of the old location are matched by their trailing components.
+    assert_eq!(
+        resolver.resolve(Path::new("/old/location/maps/map.yaml")),
+        expected
+    );
+    assert_eq!(resolver.resolve(Path::new("/old/location/map.yaml")), None);
+    assert_eq!(resolver.resolve(Path::new("maps/missing.yaml")), None);
+}
+
+#[test]
+fn resolve_in_added_search_dirs_first() {
+    let session_dir = SessionDir::new("resolve_session");
+    let relocated_dir = SessionDir::new("resolve_relocated");
+    let mut resolver = PathResolver::new(&session_dir.0.join("session.toml"));
+    let path = Path::new("/old/location/maps/map.yaml");
+    assert_eq!(
+        resolver.resolve(path),
+        Some(session_dir.0.join("maps/map.yaml"))
+    );
+
+    resolver.add_search_dir(&relocated_dir.0);
+    assert_eq!(
+        resolver.resolve(path),
+        Some(relocated_dir.0.join("maps/map.yaml"))
+    );
+    // Search dirs are only added once, the session directory is still searched.
+    resolver.add_search_dir(&relocated_dir.0);
+    std::fs::remove_file(relocated_dir.0.join("maps/map.yaml")).expect("Failed to remove map");
+    assert_eq!(
+        resolver.resolve(path),
+        Some(session_dir.0.join("maps/map.yaml"))
+    );
+}
+
+#[test]
+fn resolve_relative_to_session_before_working_directory() {
+    let dir = SessionDir::new("resolve_decoy");
+    let resolver = PathResolver::new(&dir.0.join("session.toml"));
+    // Tests run in the package directory, its Cargo.toml is a decoy for the session's.
+    assert!(Path::new("Cargo.toml").exists());
+    std::fs::write(dir.0.join("Cargo.toml"), "").expect("Failed to write file");
+    assert_eq!(
+        resolver.resolve(Path::new("Cargo.toml")),
+        Some(dir.0.join("Cargo.toml"))
+    );
+    // Falls back to the working directory.
+    assert_eq!(
+        resolver.resolve(Path::new("build.rs")),
+        Some(PathBuf::from("build.rs"))
+    );
+}
@@ -0,0 +1,46 @@
+use eframe::egui;
+
+use maps::svg::{SvgDocument, base64_encode};
+
+#[test]
+fn base64_padding() {
+    assert_eq!(base64_encode(b""), "");
+    assert_eq!(base64_encode(b"m"), "bQ==");
+    assert_eq!(base64_encode(b"ma"), "bWE=");
+    assert_eq!(base64_encode(b"map"), "bWFw");
+    assert_eq!(base64_encode(b"maps!"), "bWFwcyE=");
+    assert_eq!(base64_encode(&[0xff, 0xfe, 0xfd]), "//79");
+}
+
+#[test]
+fn svg_document() {
+    let mut doc = SvgDocument::new(egui::vec2(200., 100.5));
+    doc.begin_group("grid");
+    doc.line_segment(
+        [egui::pos2(0., 10.), egui::pos2(200., 10.)],
+        egui::Stroke::new(1.5, egui::Color32::from_black_alpha(128)),
+    );
+    doc.text(
+        egui::pos2(5., 5.),
+        egui::Align2::LEFT_TOP,
+        "<map> & \"name\"",
+        &egui::FontId::new(13., egui::FontFamily::Proportional),
+        egui::Color32::WHITE,
+    );
+    doc.end_group();
+    let image = image::DynamicImage::new_rgba8(2, 3);
+    doc.image(&image, [0.5, 0., 0., 0.5, 10., 20.], true)
+        .expect("Failed to embed image");
+    let svg = doc.finish();
+
+    assert!(svg.contains(r#"width="200" height="100.5" viewBox="0 0 200 100.5""#));
+    assert!(svg.contains(
+        r##"<line x1="0" y1="10" x2="200" y2="10" fill="none" stroke="#000000" stroke-opacity="0.5" stroke-width="1.5""##
+    ));
+    assert!(svg.contains("&lt;map&gt; &amp; &quot;name&quot;</text>"));
+    assert!(svg.contains(r#"text-anchor="start" dominant-baseline="hanging""#));
+    assert!(svg.contains(r#"<image width="2" height="3" preserveAspectRatio="none" transform="matrix(0.5 0 0 0.5 10 20)""#));
+    assert!(svg.contains("data:image/png;base64,iVBORw0KGgo"));
+    assert_eq!(svg.matches("<g ").count(), svg.matches("</g>").count());
+    assert!(svg.trim_end().ends_with("</svg>"));
+}
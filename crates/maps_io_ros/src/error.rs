@@ -24,6 +24,14 @@ pub enum Error {
         source: image::ImageError,
     },
 
+    /// TIFF decoding or encoding error with additional context, e.g. for GeoTIFF tags.
+    #[error("[TIFF error] {context} ({source})")]
+    Tiff {
+        context: String,
+        #[source]
+        source: tiff::TiffError,
+    },
+
     /// YAML serialization or deserialization error with additional context.
     #[error("[YAML error] {context} ({source})")]
     Yaml {
@@ -54,6 +62,7 @@ impl Error {
     impl_error_constructors! {
         io => Io, std::io::Error;
         image => Image, image::ImageError;
+        tiff => Tiff, tiff::TiffError;
         yaml => Yaml, serde_yaml_ng::Error;
     }
 }
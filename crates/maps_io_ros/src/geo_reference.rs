@@ -0,0 +1,244 @@
+//! Georeferencing of metric map coordinates.
+//!
+//! A [`GeoReference`] relates the root frame of aligned maps to the earth
+//! with an anchor point (WGS84 or UTM) and a heading. Coordinates in between
+//! are computed in the plane of the anchor's UTM zone, which is sufficient
+//! for the extents of typical robot maps.
+
+use std::path::{Path, PathBuf};
+
+use emath;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::map_pose::MapPose;
+use crate::meta::Meta;
+use crate::utm::{Utm, Wgs84};
+
+/// Geographic anchor of a georeference.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum GeoAnchor {
+    /// Anchor given as WGS84 latitude / longitude in degrees.
+    Wgs84(Wgs84),
+    /// Anchor given as UTM coordinate, e.g. from a survey.
+    Utm(Utm),
+}
+
+impl Default for GeoAnchor {
+    fn default() -> Self {
+        GeoAnchor::Wgs84(Wgs84::default())
+    }
+}
+
+/// Relates metric root frame coordinates to WGS84 / UTM.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct GeoReference {
+    pub anchor: GeoAnchor,
+    /// Position in the root frame (meters) that corresponds to the anchor.
+    #[serde(default)]
+    pub anchor_xy: emath::Vec2,
+    /// Heading of the root frame's x-axis in radians,
+    /// counter-clockwise from east (ENU convention as in ROS REP 103).
+    #[serde(default)]
+    pub heading: f32,
+}
+
+/// Affine pixel-to-world transformation of an ESRI world file (e.g. `.pgw`).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WorldFile {
+    /// Change of the world x coordinate per pixel column.
+    pub a: f64,
+    /// Change of the world y coordinate per pixel column.
+    pub d: f64,
+    /// Change of the world x coordinate per pixel row.
+    pub b: f64,
+    /// Change of the world y coordinate per pixel row.
+    pub e: f64,
+    /// World x coordinate of the center of the upper left pixel.
+    pub c: f64,
+    /// World y coordinate of the center of the upper left pixel.
+    pub f: f64,
+}
+
+impl GeoReference {
+    /// UTM coordinate of the anchor. WGS84 anchors are projected into their standard zone.
+    pub fn anchor_utm(&self) -> Utm {
+        match self.anchor {
+            GeoAnchor::Wgs84(wgs84) => wgs84.to_utm(),
+            GeoAnchor::Utm(utm) => utm,
+        }
+    }
+
+    /// Converts a root frame position to UTM (in the zone of the anchor).
+    pub fn to_utm(&self, pos: emath::Pos2) -> Utm {
+        let anchor = self.anchor_utm();
+        let (sin, cos) = (self.heading as f64).sin_cos();
+        let dx = (pos.x - self.anchor_xy.x) as f64;
+        let dy = (pos.y - self.anchor_xy.y) as f64;
+        Utm {
+            easting: anchor.easting + cos * dx - sin * dy,
+            northing: anchor.northing + sin * dx + cos * dy,
+            ..anchor
+        }
+    }
+
+    /// Converts a root frame position to WGS84.
+    pub fn to_wgs84(&self, pos: emath::Pos2) -> Wgs84 {
+        self.to_utm(pos).to_wgs84()
+    }
+
+    /// Converts a UTM coordinate to a root frame position.
+    /// The coordinate is reprojected if it is not in the anchor's zone.
+    pub fn utm_to_root(&self, utm: &Utm) -> emath::Pos2 {
+        let anchor = self.anchor_utm();
+        let utm = if utm.zone != anchor.zone || utm.north != anchor.north {
+            utm.to_wgs84().to_utm_zone(anchor.zone, anchor.north)
+        } else {
+            *utm
+        };
+        let (sin, cos) = (self.heading as f64).sin_cos();
+        let de = utm.easting - anchor.easting;
+        let dn = utm.northing - anchor.northing;
+        emath::pos2(
+            (cos * de + sin * dn) as f32 + self.anchor_xy.x,
+            (-sin * de + cos * dn) as f32 + self.anchor_xy.y,
+        )
+    }
+
+    /// Converts a WGS84 coordinate to a root frame position.
+    pub fn wgs84_to_root(&self, wgs84: &Wgs84) -> emath::Pos2 {
+        let anchor = self.anchor_utm();
+        self.utm_to_root(&wgs84.to_utm_zone(anchor.zone, anchor.north))
+    }
+
+    /// Creates the UTM world file for the image of a map placed at `pose`.
+    /// `image_size` is the size of the map image in pixels.
+    pub fn world_file(&self, meta: &Meta, pose: &MapPose, image_size: emath::Vec2) -> WorldFile {
+        WorldFile::from_pixel_fn(|pixel| {
+            let utm = self.to_utm(pose.transform(meta.pixel_to_map(pixel, image_size.y)));
+            (utm.easting, utm.northing)
+        })
+    }
+}
+
+impl WorldFile {
+    /// Creates the world file of a map placed at `pose` in root frame coordinates,
+    /// i.e. without georeference. `image_size` is the size of the map image in pixels.
+    pub fn local(meta: &Meta, pose: &MapPose, image_size: emath::Vec2) -> WorldFile {
+        WorldFile::from_pixel_fn(|pixel| {
+            let pos = pose.transform(meta.pixel_to_map(pixel, image_size.y));
+            (pos.x as f64, pos.y as f64)
+        })
+    }
+
+    /// Samples the affine transformation from a function that maps
+    /// continuous image coordinates to world coordinates.
+    fn from_pixel_fn(world: impl Fn(emath::Pos2) -> (f64, f64)) -> WorldFile {
+        let ulc = world(emath::pos2(0.5, 0.5));
+        let next_col = world(emath::pos2(1.5, 0.5));
+        let next_row = world(emath::pos2(0.5, 1.5));
+        WorldFile {
+            a: next_col.0 - ulc.0,
+            d: next_col.1 - ulc.1,
+            b: next_row.0 - ulc.0,
+            e: next_row.1 - ulc.1,
+            c: ulc.0,
+            f: ulc.1,
+        }
+    }
+
+    /// World coordinate of continuous image coordinates, e.g. `(0, 0)` for the upper left corner.
+    pub fn apply(&self, col: f64, row: f64) -> (f64, f64) {
+        // c, f refer to the center of the upper left pixel.
+        let (col, row) = (col - 0.5, row - 0.5);
+        (
+            self.a * col + self.b * row + self.c,
+            self.d * col + self.e * row + self.f,
+        )
+    }
+
+    /// Whether the image axes are aligned with the world axes (no rotation or shear).
+    pub fn is_axis_aligned(&self) -> bool {
+        let scale = self.a.abs().max(self.e.abs());
+        self.b.abs() <= scale * 1e-9 && self.d.abs() <= scale * 1e-9
+    }
+
+    /// Text content in the line order of the world file format.
+    pub fn to_text(&self) -> String {
+        format!(
+            "{:.10}\n{:.10}\n{:.10}\n{:.10}\n{:.6}\n{:.6}\n",
+            self.a, self.d, self.b, self.e, self.c, self.f
+        )
+    }
+
+    /// Path of the world file that belongs to an image, e.g. `map.png` -> `map.pgw`.
+    pub fn path_for_image(image_path: &Path) -> PathBuf {
+        let extension = image_path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+        let world_extension = match (extension.chars().next(), extension.chars().last()) {
+            (Some(first), Some(last)) => format!("{first}{last}w"),
+            _ => "wld".to_string(),
+        };
+        image_path.with_extension(world_extension)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.to_text())
+            .map_err(|e| Error::io(format!("Cannot write world file {path:?}"), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPS: f32 = 1e-3;
+
+    fn georef(heading: f32) -> GeoReference {
+        GeoReference {
+            anchor: GeoAnchor::Wgs84(Wgs84::new(48.1, 11.5)),
+            anchor_xy: emath::vec2(10., -5.),
+            heading,
+        }
+    }
+
+    #[test]
+    fn anchor_maps_to_itself() {
+        let georef = georef(0.3);
+        let wgs84 = georef.to_wgs84(emath::pos2(10., -5.));
+        assert!((wgs84.latitude - 48.1).abs() < 1e-8);
+        assert!((wgs84.longitude - 11.5).abs() < 1e-8);
+    }
+
+    #[test]
+    fn heading_rotates_axes() {
+        // x-axis pointing north.
+        let georef = georef(std::f32::consts::FRAC_PI_2);
+        let anchor = georef.anchor_utm();
+        let utm = georef.to_utm(emath::pos2(11., -5.));
+        assert!((utm.northing - anchor.northing - 1.).abs() < 1e-6);
+        assert!((utm.easting - anchor.easting).abs() < 1e-6);
+    }
+
+    #[test]
+    fn roundtrip() {
+        let georef = georef(-1.2);
+        let pos = emath::pos2(123.4, -56.7);
+        let back = georef.wgs84_to_root(&georef.to_wgs84(pos));
+        assert!((back - pos).length() < EPS);
+    }
+
+    #[test]
+    fn world_file_path() {
+        assert_eq!(
+            WorldFile::path_for_image(Path::new("/a/map.png")),
+            PathBuf::from("/a/map.pgw")
+        );
+        assert_eq!(
+            WorldFile::path_for_image(Path::new("map.tiff")),
+            PathBuf::from("map.tfw")
+        );
+    }
+}
@@ -0,0 +1,490 @@
+//! Reading and writing of GeoTIFF map images.
+//!
+//! Supports the common subset of GeoTIFF that is needed for grid maps:
+//! the affine raster-to-model transformation given either by a tie point with
+//! pixel scale, or by a model transformation matrix. Model coordinates are
+//! interpreted as metric map coordinates, geographic (lat/lon) CRS are rejected.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use tiff::decoder::Decoder;
+use tiff::encoder::colortype::{self, ColorType};
+use tiff::encoder::compression::DeflateLevel;
+use tiff::encoder::{Compression, TiffEncoder, TiffValue};
+use tiff::tags::Tag;
+
+use crate::error::{Error, Result};
+use crate::geo_reference::WorldFile;
+use crate::image::load_image;
+use crate::meta::Meta;
+use crate::os_helpers::resolve_symlink;
+use crate::utm::Utm;
+use crate::value_interpretation::color_average;
+
+// GeoKey IDs and values from the GeoTIFF specification.
+const GT_MODEL_TYPE_GEO_KEY: u16 = 1024;
+const GT_RASTER_TYPE_GEO_KEY: u16 = 1025;
+const PROJECTED_CS_TYPE_GEO_KEY: u16 = 3072;
+const PROJ_LINEAR_UNITS_GEO_KEY: u16 = 3076;
+const MODEL_TYPE_PROJECTED: u16 = 1;
+const MODEL_TYPE_GEOGRAPHIC: u16 = 2;
+const RASTER_PIXEL_IS_AREA: u16 = 1;
+const RASTER_PIXEL_IS_POINT: u16 = 2;
+const USER_DEFINED: u16 = 32767;
+const LINEAR_METER: u16 = 9001;
+
+/// Georeferencing information read from the tags of a GeoTIFF.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GeoTiffInfo {
+    /// Raster-to-model transformation.
+    pub transform: WorldFile,
+    /// EPSG code of the projected coordinate system, if specified.
+    pub epsg_code: Option<u16>,
+    /// Width and height of the image in pixels.
+    pub size: (u32, u32),
+}
+
+/// Returns true if the path has a `.tif` or `.tiff` extension.
+pub fn has_tiff_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("tif") || e.eq_ignore_ascii_case("tiff"))
+}
+
+fn invalid_data(path: &Path, message: &str) -> Error {
+    Error::io(
+        format!("Unsupported GeoTIFF {}", path.display()),
+        std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string()),
+    )
+}
+
+fn geo_key(geo_keys: &[u16], key_id: u16) -> Option<u16> {
+    // Header: version, revision, minor revision, number of keys.
+    // Then per key: ID, tag location (0 = value is inline), count, value.
+    geo_keys
+        .get(4..)?
+        .chunks_exact(4)
+        .find(|key| key[0] == key_id && key[1] == 0)
+        .map(|key| key[3])
+}
+
+/// Reads the georeferencing tags of a GeoTIFF.
+pub fn read_geotiff_info(path: &Path) -> Result<GeoTiffInfo> {
+    let path = resolve_symlink(path);
+    let file = File::open(&path).map_err(|e| Error::io(format!("Cannot open {path:?}"), e))?;
+    let tiff_error = |e| Error::tiff(format!("Cannot read GeoTIFF tags of {path:?}"), e);
+    let mut decoder = Decoder::new(BufReader::new(file)).map_err(tiff_error)?;
+    let size = decoder.dimensions().map_err(tiff_error)?;
+
+    if !matches!(
+        decoder.colortype().map_err(tiff_error)?,
+        tiff::ColorType::Gray(_)
+    ) {
+        return Err(invalid_data(&path, "only single-band images are supported"));
+    }
+
+    let geo_keys = decoder
+        .find_tag(Tag::GeoKeyDirectoryTag)
+        .map_err(tiff_error)?
+        .map(tiff::decoder::ifd::Value::into_u16_vec)
+        .transpose()
+        .map_err(tiff_error)?
+        .unwrap_or_default();
+    if geo_key(&geo_keys, GT_MODEL_TYPE_GEO_KEY) == Some(MODEL_TYPE_GEOGRAPHIC) {
+        return Err(invalid_data(
+            &path,
+            "geographic (lat/lon) coordinate systems are not supported, \
+            reproject to a metric coordinate system first",
+        ));
+    }
+
+    let mut find_f64_vec = |tag| {
+        decoder
+            .find_tag(tag)
+            .and_then(|value| {
+                value
+                    .map(tiff::decoder::ifd::Value::into_f64_vec)
+                    .transpose()
+            })
+            .map_err(tiff_error)
+    };
+    let transformation = find_f64_vec(Tag::ModelTransformationTag)?;
+    let pixel_scale = find_f64_vec(Tag::ModelPixelScaleTag)?;
+    let tie_points = find_f64_vec(Tag::ModelTiepointTag)?;
+
+    // Coefficients of the upper left corner (PixelIsArea) or center (PixelIsPoint).
+    let (a, b, c, d, e, f) = match (transformation, pixel_scale, tie_points) {
+        (Some(m), _, _) if m.len() >= 8 => (m[0], m[1], m[3], m[4], m[5], m[7]),
+        (None, Some(scale), Some(tie)) if scale.len() >= 2 && tie.len() >= 5 => {
+            let (i, j, x, y) = (tie[0], tie[1], tie[3], tie[4]);
+            (
+                scale[0],
+                0.,
+                x - i * scale[0],
+                0.,
+                -scale[1],
+                y + j * scale[1],
+            )
+        }
+        _ => {
+            return Err(invalid_data(
+                &path,
+                "no model transformation or tie point with pixel scale found",
+            ));
+        }
+    };
+    let pixel_is_point = geo_key(&geo_keys, GT_RASTER_TYPE_GEO_KEY) == Some(RASTER_PIXEL_IS_POINT);
+    let (c, f) = if pixel_is_point {
+        (c, f)
+    } else {
+        (c + 0.5 * (a + b), f + 0.5 * (d + e))
+    };
+
+    Ok(GeoTiffInfo {
+        transform: WorldFile { a, d, b, e, c, f },
+        epsg_code: geo_key(&geo_keys, PROJECTED_CS_TYPE_GEO_KEY)
+            .filter(|code| *code != USER_DEFINED),
+        size,
+    })
+}
+
+/// Map metadata of a GeoTIFF, see [`load_geotiff_meta`].
+#[derive(Clone, Debug)]
+pub struct GeoTiffMeta {
+    pub meta: Meta,
+    /// Model coordinate of the map frame origin, the origin of the metadata is relative to it.
+    pub anchor: (f64, f64),
+    /// EPSG code of the projected coordinate system, if specified.
+    pub epsg_code: Option<u16>,
+}
+
+impl GeoTiffMeta {
+    /// The anchor as UTM coordinate, if the GeoTIFF uses a WGS84 / UTM coordinate system.
+    pub fn anchor_utm(&self) -> Option<Utm> {
+        let (easting, northing) = self.anchor;
+        Utm::from_epsg_code(self.epsg_code?, easting, northing)
+    }
+}
+
+/// Default anchor of a GeoTIFF: its lower left corner, rounded to whole kilometers.
+/// I.e. small local coordinates are kept as they are.
+fn default_anchor(llc: (f64, f64)) -> (f64, f64) {
+    let round = |coordinate: f64| (coordinate / 1000.).round() * 1000. + 0.;
+    (round(llc.0), round(llc.1))
+}
+
+/// Derives the ROS map metadata (resolution and origin) of a GeoTIFF.
+///
+/// The map frame is the model coordinate system, shifted to `anchor`
+/// (by default close to the image, see [`GeoTiffMeta::anchor`]).
+/// The shift is computed in 64 bit, because metadata uses 32 bit floats that
+/// can't represent large projected coordinates (e.g. UTM northings) precisely.
+pub fn load_geotiff_meta(path: &Path, anchor: Option<(f64, f64)>) -> Result<GeoTiffMeta> {
+    let info = read_geotiff_info(path)?;
+    let (_, height) = info.size;
+
+    let WorldFile { a, d, b, e, .. } = info.transform;
+    let resolution = a.hypot(d);
+    let theta = d.atan2(a);
+    // Rows must point opposite to the map's y-axis with the same scale, as in ROS maps.
+    let row_error = (b - resolution * theta.sin()).hypot(e + resolution * theta.cos());
+    if resolution <= 0. || row_error > resolution * 1e-3 {
+        return Err(invalid_data(
+            path,
+            "only square pixels without shear or mirroring are supported",
+        ));
+    }
+
+    let llc = info.transform.apply(0., height as f64);
+    let anchor = anchor.unwrap_or_else(|| default_anchor(llc));
+    let llc_to_anchor = emath::vec2((llc.0 - anchor.0) as f32, (llc.1 - anchor.1) as f32);
+    let origin_theta = emath::Rot2::from_angle(theta as f32);
+    let origin_xy = origin_theta.inverse() * llc_to_anchor;
+    Ok(GeoTiffMeta {
+        meta: Meta::new(
+            path.to_path_buf(),
+            path.to_path_buf(),
+            resolution as f32,
+            origin_xy,
+            origin_theta,
+        ),
+        anchor,
+        epsg_code: info.epsg_code,
+    })
+}
+
+/// Loads a single-band GeoTIFF as map metadata and image, with the default anchor.
+pub fn load_geotiff(path: &Path) -> Result<(GeoTiffMeta, image::DynamicImage)> {
+    let geotiff_meta = load_geotiff_meta(path, None)?;
+    let image = load_image(&geotiff_meta.meta.image_path)?;
+    Ok((geotiff_meta, image))
+}
+
+fn geo_key_directory(epsg_code: Option<u16>) -> Vec<u16> {
+    let mut keys = vec![
+        [GT_MODEL_TYPE_GEO_KEY, 0, 1, MODEL_TYPE_PROJECTED],
+        [GT_RASTER_TYPE_GEO_KEY, 0, 1, RASTER_PIXEL_IS_AREA],
+        [
+            PROJECTED_CS_TYPE_GEO_KEY,
+            0,
+            1,
+            epsg_code.unwrap_or(USER_DEFINED),
+        ],
+    ];
+    if epsg_code.is_none() {
+        keys.push([PROJ_LINEAR_UNITS_GEO_KEY, 0, 1, LINEAR_METER]);
+    }
+    let mut directory = vec![1, 1, 0, keys.len() as u16];
+    directory.extend(keys.into_iter().flatten());
+    directory
+}
+
+fn write_geotiff<C: ColorType>(
+    path: &Path,
+    size: (u32, u32),
+    data: &[C::Inner],
+    transform: &WorldFile,
+    epsg_code: Option<u16>,
+) -> tiff::TiffResult<()>
+where
+    [C::Inner]: TiffValue,
+{
+    let file = File::create(path)?;
+    let mut encoder = TiffEncoder::new(BufWriter::new(file))?
+        .with_compression(Compression::Deflate(DeflateLevel::Balanced));
+    let mut image = encoder.new_image::<C>(size.0, size.1)?;
+
+    // Upper left corner, GeoTIFF uses PixelIsArea here.
+    let (x, y) = transform.apply(0., 0.);
+    let tags = image.encoder();
+    if transform.is_axis_aligned() {
+        tags.write_tag(
+            Tag::ModelPixelScaleTag,
+            &[transform.a, -transform.e, 0.][..],
+        )?;
+        tags.write_tag(Tag::ModelTiepointTag, &[0., 0., 0., x, y, 0.][..])?;
+    } else {
+        #[rustfmt::skip]
+        let matrix = [
+            transform.a, transform.b, 0., x,
+            transform.d, transform.e, 0., y,
+            0., 0., 0., 0.,
+            0., 0., 0., 1.,
+        ];
+        tags.write_tag(Tag::ModelTransformationTag, &matrix[..])?;
+    }
+    tags.write_tag(
+        Tag::GeoKeyDirectoryTag,
+        geo_key_directory(epsg_code).as_slice(),
+    )?;
+    image.write_data(data)
+}
+
+/// Saves an image as single-band GeoTIFF with the given raster-to-model transformation,
+/// e.g. [`WorldFile::local`] to apply a map pose.
+/// RGB images are saved with the average of their color channels, like map_server
+/// interprets them. Other multi-band images are rejected, because they couldn't be
+/// loaded again with [`load_geotiff`].
+/// Set `epsg_code` if the transformation yields coordinates of a known
+/// projected coordinate system, e.g. UTM from [`crate::GeoReference::world_file`].
+pub fn save_geotiff(
+    path: &Path,
+    image: &image::DynamicImage,
+    transform: &WorldFile,
+    epsg_code: Option<u16>,
+) -> Result<()> {
+    let size = (image.width(), image.height());
+    match image {
+        image::DynamicImage::ImageLuma8(buffer) => {
+            write_geotiff::<colortype::Gray8>(path, size, buffer, transform, epsg_code)
+        }
+        image::DynamicImage::ImageLuma16(buffer) => {
+            write_geotiff::<colortype::Gray16>(path, size, buffer, transform, epsg_code)
+        }
+        // Single-band float images are loaded with identical RGB channels.
+        image::DynamicImage::ImageRgb32F(buffer) => {
+            let values: Vec<f32> = buffer.pixels().map(|p| p[0]).collect();
+            write_geotiff::<colortype::Gray32Float>(path, size, &values, transform, epsg_code)
+        }
+        image::DynamicImage::ImageRgb8(buffer) => {
+            let values: Vec<u8> = buffer
+                .pixels()
+                .map(|p| color_average(&p.0, false).round() as u8)
+                .collect();
+            write_geotiff::<colortype::Gray8>(path, size, &values, transform, epsg_code)
+        }
+        _ => {
+            return Err(Error::io(
+                format!("Cannot write GeoTIFF {path:?}"),
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!(
+                        "{:?} images are not supported, only single-band images \
+                        (gray or float) and RGB images without alpha",
+                        image.color()
+                    ),
+                ),
+            ));
+        }
+    }
+    .map_err(|e| Error::tiff(format!("Cannot write GeoTIFF {path:?}"), e))
+}
+
+/// Default GeoTIFF path for a map, e.g. `map.yaml` -> `map.tif`.
+pub fn default_geotiff_path(meta: &Meta) -> PathBuf {
+    meta.yaml_path.with_extension("tif")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::map_pose::MapPose;
+
+    fn test_image() -> image::DynamicImage {
+        image::DynamicImage::ImageLuma8(image::GrayImage::from_fn(4, 3, |x, y| {
+            image::Luma([(x * 10 + y) as u8])
+        }))
+    }
+
+    fn test_meta() -> Meta {
+        Meta::new(
+            PathBuf::from("map.png"),
+            PathBuf::from("map.yaml"),
+            0.05,
+            emath::vec2(-1., 2.),
+            emath::Rot2::IDENTITY,
+        )
+    }
+
+    fn assert_meta_eq(expected: &Meta, actual: &Meta) {
+        assert!((expected.resolution - actual.resolution).abs() < 1e-6);
+        assert!((expected.origin_xy - actual.origin_xy).length() < 1e-4);
+        assert!((expected.origin_theta.angle() - actual.origin_theta.angle()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn roundtrip_axis_aligned() {
+        let path = std::env::temp_dir().join("maps_io_ros_test_axis_aligned.tif");
+        let image = test_image();
+        let meta = test_meta();
+        let size = emath::vec2(image.width() as f32, image.height() as f32);
+        let transform = WorldFile::local(&meta, &MapPose::default(), size);
+        save_geotiff(&path, &image, &transform, None).unwrap();
+
+        let info = read_geotiff_info(&path).unwrap();
+        assert_eq!(info.epsg_code, None);
+        let (loaded, loaded_image) = load_geotiff(&path).unwrap();
+        assert_eq!(loaded.anchor, (0., 0.));
+        assert_meta_eq(&meta, &loaded.meta);
+        assert_eq!(loaded_image.to_luma8(), image.to_luma8());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn roundtrip_with_pose() {
+        let path = std::env::temp_dir().join("maps_io_ros_test_with_pose.tif");
+        let image = test_image();
+        let meta = test_meta();
+        let mut pose = MapPose::default();
+        pose.translation.x = 3.;
+        pose.rotation.yaw = 0.5;
+        let size = emath::vec2(image.width() as f32, image.height() as f32);
+        let transform = WorldFile::local(&meta, &pose, size);
+        save_geotiff(&path, &image, &transform, Some(32632)).unwrap();
+
+        let info = read_geotiff_info(&path).unwrap();
+        assert_eq!(info.epsg_code, Some(32632));
+        let loaded_meta = load_geotiff_meta(&path, None).unwrap().meta;
+        // The pose is baked into the origin of the loaded map.
+        let pixel = emath::pos2(1., 2.);
+        let expected = pose.transform(meta.pixel_to_map(pixel, size.y));
+        let actual = loaded_meta.pixel_to_map(pixel, size.y);
+        assert!((expected - actual).length() < 1e-4);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn utm_coordinates_are_relative_to_anchor() {
+        let path = std::env::temp_dir().join("maps_io_ros_test_utm.tif");
+        let image = test_image();
+        let size = emath::vec2(image.width() as f32, image.height() as f32);
+        // Upper left pixel center at a precision that 32 bit floats can't represent.
+        let transform = WorldFile {
+            a: 0.05,
+            d: 0.,
+            b: 0.,
+            e: -0.05,
+            c: 512_345.675,
+            f: 5_403_210.125,
+        };
+        save_geotiff(&path, &image, &transform, Some(32632)).unwrap();
+        assert!((5_403_210.125_f32 as f64 - transform.f).abs() > 0.1);
+
+        let loaded = load_geotiff_meta(&path, None).unwrap();
+        assert_eq!(loaded.anchor, (512_000., 5_403_000.));
+        let utm = loaded.anchor_utm().unwrap();
+        assert_eq!((utm.zone, utm.north), (32, true));
+        // Pixel centers are at the model coordinates relative to the anchor.
+        let pixel = emath::pos2(2.5, 1.5);
+        let expected = transform.apply(pixel.x as f64, pixel.y as f64);
+        let actual = loaded.meta.pixel_to_map(pixel, size.y);
+        assert!((actual.x as f64 + loaded.anchor.0 - expected.0).abs() < 1e-4);
+        assert!((actual.y as f64 + loaded.anchor.1 - expected.1).abs() < 1e-4);
+
+        // Relative to another anchor, e.g. of a georeference.
+        let loaded = load_geotiff_meta(&path, Some((512_300., 5_403_200.))).unwrap();
+        let actual = loaded.meta.pixel_to_map(pixel, size.y);
+        assert!((actual.x as f64 + 512_300. - expected.0).abs() < 1e-4);
+        assert!((actual.y as f64 + 5_403_200. - expected.1).abs() < 1e-4);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn geo_keys() {
+        let directory = geo_key_directory(Some(32632));
+        assert_eq!(geo_key(&directory, GT_MODEL_TYPE_GEO_KEY), Some(1));
+        assert_eq!(geo_key(&directory, PROJECTED_CS_TYPE_GEO_KEY), Some(32632));
+        assert_eq!(geo_key(&directory, PROJ_LINEAR_UNITS_GEO_KEY), None);
+    }
+
+    #[test]
+    fn roundtrip_float() {
+        let path = std::env::temp_dir().join("maps_io_ros_test_float.tif");
+        let values = [-1.5, 0., 1234.25, f32::MAX];
+        let image = image::DynamicImage::ImageRgb32F(image::ImageBuffer::from_fn(2, 2, |x, y| {
+            image::Rgb([values[(y * 2 + x) as usize]; 3])
+        }));
+        let transform = WorldFile::local(&test_meta(), &MapPose::default(), emath::vec2(2., 2.));
+        save_geotiff(&path, &image, &transform, None).unwrap();
+
+        let (_, loaded_image) = load_geotiff(&path).unwrap();
+        assert_eq!(loaded_image, image);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rgb_is_saved_as_single_band() {
+        let path = std::env::temp_dir().join("maps_io_ros_test_rgb.tif");
+        let image = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(2, 1, |x, _| {
+            image::Rgb([10 * x as u8, 20, 30])
+        }));
+        let transform = WorldFile::local(&test_meta(), &MapPose::default(), emath::vec2(2., 1.));
+        save_geotiff(&path, &image, &transform, None).unwrap();
+
+        let (_, loaded_image) = load_geotiff(&path).unwrap();
+        assert_eq!(loaded_image.to_luma8().into_raw(), vec![17, 20]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rgba_is_rejected() {
+        let path = std::env::temp_dir().join("maps_io_ros_test_rgba.tif");
+        let image = image::DynamicImage::new_rgba8(2, 2);
+        let transform = WorldFile::local(&test_meta(), &MapPose::default(), emath::vec2(2., 2.));
+        assert!(save_geotiff(&path, &image, &transform, None).is_err());
+        assert!(!path.exists());
+    }
+}
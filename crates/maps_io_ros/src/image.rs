@@ -1,16 +1,43 @@
 //! Functions for loading / saving images.
 
+use std::io::{BufReader, Cursor, Read, Seek};
 use std::path::Path;
 
 use image::ImageReader;
+use tiff::decoder::{Decoder, DecodingResult, Limits};
 
 use crate::error::{Error, Result};
+use crate::geotiff::has_tiff_extension;
 use crate::os_helpers::resolve_symlink;
 
+const TIFF_MAGIC: [&[u8]; 2] = [b"II*\0", b"MM\0*"];
+
+/// The image crate can't decode single-band float TIFFs (e.g. elevation data).
+/// Decodes those directly and stores them as RGB32F image with identical channels.
+/// Returns `None` for any other kind of image.
+fn decode_gray_float_tiff(reader: impl Read + Seek) -> Option<image::DynamicImage> {
+    let mut decoder = Decoder::new(reader).ok()?.with_limits(Limits::unlimited());
+    if decoder.colortype().ok()? != tiff::ColorType::Gray(32) {
+        return None;
+    }
+    let (width, height) = decoder.dimensions().ok()?;
+    let DecodingResult::F32(values) = decoder.read_image().ok()? else {
+        return None;
+    };
+    let rgb = values.into_iter().flat_map(|v| [v; 3]).collect();
+    image::Rgb32FImage::from_raw(width, height, rgb).map(image::DynamicImage::ImageRgb32F)
+}
+
 /// Load an image from the given path.
 /// Symlinks are resolved automatically.
 pub fn load_image(path: &Path) -> Result<image::DynamicImage> {
     let path = resolve_symlink(path);
+    if has_tiff_extension(&path)
+        && let Ok(file) = std::fs::File::open(&path)
+        && let Some(img) = decode_gray_float_tiff(BufReader::new(file))
+    {
+        return Ok(img);
+    }
     let mut reader =
         ImageReader::open(&path).map_err(|e| Error::io(format!("Cannot open {path:?}"), e))?;
 
@@ -25,7 +52,12 @@ pub fn load_image(path: &Path) -> Result<image::DynamicImage> {
 /// Load an image from a bytes stream (e.g. in wasm applications).
 /// The image format is guessed automatically.
 pub fn load_image_from_bytes(bytes: &[u8]) -> Result<image::DynamicImage> {
-    let mut reader = ImageReader::new(std::io::Cursor::new(bytes))
+    if TIFF_MAGIC.iter().any(|magic| bytes.starts_with(magic))
+        && let Some(img) = decode_gray_float_tiff(Cursor::new(bytes))
+    {
+        return Ok(img);
+    }
+    let mut reader = ImageReader::new(Cursor::new(bytes))
         .with_guessed_format()
         .map_err(|e| Error::io("Cannot create image reader from bytes", e))?;
 
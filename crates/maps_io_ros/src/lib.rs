@@ -1,5 +1,5 @@
 //! `maps_io_ros` provides fundamental I/O for 2D ROS grid maps including:
-//! metadata parsing, value interpretation, colormaps and map poses.
+//! metadata parsing, value interpretation, colormaps, map poses, georeferencing and GeoTIFF.
 //!
 //! See the [maps](https://crates.io/crates/maps) app crate for a full
 //! GUI application that builds on top of this I/O library.
@@ -8,17 +8,27 @@
 //! other robotics applications that work with ROS map files.
 
 pub mod error;
+pub mod geo_reference;
+pub mod geotiff;
 pub mod image;
+pub mod lint;
+pub mod map_edit;
+pub mod map_merge;
 pub mod map_pose;
 pub mod meta;
 mod os_helpers;
+pub mod region_reader;
+pub mod statistics;
+pub mod utm;
 pub mod value_colormap;
 pub mod value_interpretation;
 
 // Re-export commonly used types.
 pub use error::{Error, Result};
+pub use geo_reference::GeoReference;
 pub use image::{load_image, load_image_from_bytes, save_image};
 pub use map_pose::MapPose;
 pub use meta::Meta;
+pub use region_reader::RegionReader;
 pub use value_colormap::ColorMap;
 pub use value_interpretation::ValueInterpretation;
@@ -0,0 +1,272 @@
+//! Validation of map files, e.g. to catch broken maps in CI before they reach robots.
+//!
+//! Besides plain errors (unreadable files, invalid thresholds), this points out
+//! pitfalls where map_server interprets a map differently than one might expect,
+//! see also [`Quirks`](crate::value_interpretation::Quirks).
+
+use std::fmt;
+use std::path::Path;
+
+use image::DynamicImage;
+
+use crate::image::load_image;
+use crate::meta::Meta;
+use crate::statistics::MapStatistics;
+use crate::value_interpretation::{Mode, Quirks};
+
+/// Resolutions outside of this range (meters per cell) are likely a mistake.
+const PLAUSIBLE_RESOLUTION: std::ops::RangeInclusive<f32> = 0.001..=1.0;
+/// Warn if more than this fraction of the map lies outside the bounds of known cells.
+const MAX_UNKNOWN_BORDER_FRACTION: f64 = 0.5;
+
+/// Severity of a [`LintIssue`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LintLevel {
+    /// The map is likely not interpreted as intended.
+    Warning,
+    /// The map can't be loaded or is invalid.
+    Error,
+}
+
+/// A problem found in a map.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LintIssue {
+    pub level: LintLevel,
+    pub message: String,
+}
+
+impl LintIssue {
+    fn warning(message: impl Into<String>) -> LintIssue {
+        LintIssue {
+            level: LintLevel::Warning,
+            message: message.into(),
+        }
+    }
+
+    fn error(message: impl Into<String>) -> LintIssue {
+        LintIssue {
+            level: LintLevel::Error,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self.level {
+            LintLevel::Warning => "warning",
+            LintLevel::Error => "error",
+        };
+        write!(f, "{level}: {}", self.message)
+    }
+}
+
+/// Loads a map YAML file and its image and checks them for problems.
+/// Loading errors are reported as issues.
+pub fn lint_file(yaml_path: &Path) -> Vec<LintIssue> {
+    let meta = match Meta::load_from_file(yaml_path) {
+        Ok(meta) => meta,
+        Err(e) => return vec![LintIssue::error(e.to_string())],
+    };
+    let mut issues = lint_meta(&meta);
+    match load_image(&meta.image_path) {
+        Ok(img) => issues.extend(lint_image(&meta, &img)),
+        Err(e) => issues.push(LintIssue::error(e.to_string())),
+    }
+    issues
+}
+
+/// Checks the metadata of a map.
+pub fn lint_meta(meta: &Meta) -> Vec<LintIssue> {
+    let mut issues = Vec::new();
+    let vi = &meta.value_interpretation;
+
+    if !meta.resolution.is_finite() || meta.resolution <= 0. {
+        issues.push(LintIssue::error(format!(
+            "resolution must be positive, got {}",
+            meta.resolution
+        )));
+    } else if !PLAUSIBLE_RESOLUTION.contains(&meta.resolution) {
+        issues.push(LintIssue::warning(format!(
+            "unusual resolution of {} m per cell, expected {} to {}",
+            meta.resolution,
+            PLAUSIBLE_RESOLUTION.start(),
+            PLAUSIBLE_RESOLUTION.end()
+        )));
+    }
+    if !meta.origin_xy.x.is_finite()
+        || !meta.origin_xy.y.is_finite()
+        || !meta.origin_theta.angle().is_finite()
+    {
+        issues.push(LintIssue::error("origin contains non-finite values"));
+    }
+
+    for (name, value) in [("free_thresh", vi.free), ("occupied_thresh", vi.occupied)] {
+        if !(0.0..=1.0).contains(&value) {
+            issues.push(LintIssue::error(format!(
+                "{name} must be in [0, 1], got {value}"
+            )));
+        }
+    }
+    if vi.free >= vi.occupied {
+        issues.push(LintIssue::error(format!(
+            "free_thresh ({}) must be less than occupied_thresh ({})",
+            vi.free, vi.occupied
+        )));
+    }
+    issues
+}
+
+/// Checks the image of a map, given its metadata.
+pub fn lint_image(meta: &Meta, img: &DynamicImage) -> Vec<LintIssue> {
+    let mut issues = Vec::new();
+    let mode = meta.value_interpretation.mode;
+    let color = img.color();
+
+    if color.bytes_per_pixel() > color.channel_count() {
+        issues.push(LintIssue::warning(format!(
+            "{color:?} image: map_server converts it to 8 bits per channel, \
+            precision is lost"
+        )));
+    }
+    if color.has_alpha() && mode == Mode::Scale {
+        issues.push(LintIssue::warning(
+            "image has an alpha channel: in scale mode map_server treats every \
+            pixel that is not fully opaque as unknown",
+        ));
+    }
+    if mode != Mode::Raw {
+        let num_different = count_quirk_differences(meta, img);
+        if num_different > 0 {
+            let reason = if mode == Mode::Trinary {
+                "map_server averages alpha into the color channels"
+            } else {
+                "map_server scales intermediate values to 1..=99 instead of 0..=99"
+            };
+            issues.push(LintIssue::warning(format!(
+                "{num_different} cells are interpreted differently by map_server than \
+                documented in the ROS Wiki ({reason})"
+            )));
+        }
+    }
+    if color.has_color() && has_colored_pixels(img) {
+        issues.push(LintIssue::warning(
+            "image has colored pixels: map_server averages the color channels",
+        ));
+    }
+    if mode == Mode::Raw && has_invalid_raw_values(img) {
+        issues.push(LintIssue::warning(
+            "raw mode with pixel values in 101..=254: these are no valid \
+            occupancy grid values (0..=100, or 255 / -1 for unknown)",
+        ));
+    }
+
+    let stats = MapStatistics::compute(meta, img, color.has_alpha());
+    match stats.known_bounds {
+        None => issues.push(LintIssue::warning(
+            "map has no known cells with the given thresholds",
+        )),
+        Some(bounds) => {
+            let map_area = stats.area(stats.num_cells());
+            let border_fraction = 1. - (bounds.area() as f64 / map_area).min(1.);
+            if border_fraction > MAX_UNKNOWN_BORDER_FRACTION {
+                issues.push(LintIssue::warning(format!(
+                    "{:.0} % of the map is unknown border around the known cells, \
+                    consider cropping the image",
+                    border_fraction * 100.
+                )));
+            }
+        }
+    }
+    issues
+}
+
+/// Number of cells whose occupancy differs between the ROS Wiki and map_server quirks.
+fn count_quirk_differences(meta: &Meta, img: &DynamicImage) -> usize {
+    let has_alpha = img.color().has_alpha();
+    let mut wiki = Vec::with_capacity(img.width() as usize * img.height() as usize);
+    meta.value_interpretation
+        .with_quirks(Quirks::Ros1Wiki)
+        .for_each_occupancy(img, has_alpha, |_, _, occupancy| wiki.push(occupancy));
+    let mut wiki = wiki.into_iter();
+    let mut num_different = 0;
+    meta.value_interpretation
+        .with_quirks(Quirks::Ros1MapServer)
+        .for_each_occupancy(img, has_alpha, |_, _, occupancy| {
+            if wiki.next() != Some(occupancy) {
+                num_different += 1;
+            }
+        });
+    num_different
+}
+
+fn has_colored_pixels(img: &DynamicImage) -> bool {
+    img.to_rgb8()
+        .pixels()
+        .any(|pixel| pixel[0] != pixel[1] || pixel[1] != pixel[2])
+}
+
+fn has_invalid_raw_values(img: &DynamicImage) -> bool {
+    img.to_luma8()
+        .pixels()
+        .any(|pixel| (101..=254).contains(&pixel[0]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayAlphaImage, GrayImage, LumaA};
+
+    fn meta() -> Meta {
+        Meta::new(
+            "map.png".into(),
+            "map.yaml".into(),
+            0.05,
+            emath::Vec2::ZERO,
+            emath::Rot2::IDENTITY,
+        )
+    }
+
+    #[test]
+    fn invalid_meta() {
+        let mut meta = meta();
+        meta.resolution = 0.;
+        meta.value_interpretation.free = 0.7;
+        meta.value_interpretation.occupied = 0.65;
+        let issues = lint_meta(&meta);
+        assert_eq!(issues.len(), 2);
+        assert!(issues.iter().all(|issue| issue.level == LintLevel::Error));
+    }
+
+    #[test]
+    fn clean_map() {
+        let img = DynamicImage::ImageLuma8(GrayImage::from_pixel(10, 10, image::Luma([254])));
+        assert_eq!(lint_meta(&meta()), vec![]);
+        assert_eq!(lint_image(&meta(), &img), vec![]);
+    }
+
+    #[test]
+    fn alpha_and_unknown_border() {
+        // Unknown image with a single free pixel and an alpha channel.
+        // Averaging the opaque alpha into the unknown pixels makes them lighter,
+        // but not light enough to be free.
+        let mut img = GrayAlphaImage::from_pixel(10, 10, LumaA([128, 255]));
+        img.put_pixel(5, 5, LumaA([254, 255]));
+        let issues = lint_image(&meta(), &DynamicImage::ImageLumaA8(img));
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].message.contains("99 %"));
+    }
+
+    #[test]
+    fn alpha_averaging_changes_cells() {
+        // Occupied with the ROS Wiki, unknown when alpha is averaged in by map_server.
+        let mut img = GrayAlphaImage::from_pixel(10, 10, LumaA([254, 255]));
+        for x in 0..10 {
+            img.put_pixel(x, 0, LumaA([60, 255]));
+        }
+        let issues = lint_image(&meta(), &DynamicImage::ImageLumaA8(img));
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].message.starts_with("10 cells"));
+        assert!(issues[0].message.contains("alpha"));
+    }
+}
@@ -0,0 +1,532 @@
+//! Editing operations that produce new maps, e.g. cropping, padding or resampling.
+//!
+//! The operations keep the map in place: the metadata of the result is
+//! adapted such that its cells are at the same metric map frame positions.
+
+use std::ops::Range;
+use std::path::Path;
+
+use image::{ColorType, DynamicImage, ImageBuffer, Rgba};
+
+use crate::error::Result;
+use crate::image::save_image;
+use crate::meta::Meta;
+use crate::value_interpretation::{Mode, TRINARY_OCCUPIED, TRINARY_UNKNOWN};
+
+/// Rectangle in pixel coordinates of an image, may extend beyond the image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PixelRect {
+    /// Column of the upper left pixel.
+    pub x: i64,
+    /// Row of the upper left pixel.
+    pub y: i64,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl PixelRect {
+    /// The rectangle covering a full image.
+    pub fn of_image(img: &DynamicImage) -> PixelRect {
+        PixelRect {
+            x: 0,
+            y: 0,
+            width: img.width(),
+            height: img.height(),
+        }
+    }
+
+    /// Smallest rectangle containing all given continuous pixel coordinates.
+    pub fn from_points(points: &[emath::Pos2]) -> Option<PixelRect> {
+        let rect = emath::Rect::from_points(points);
+        if !rect.is_finite() || points.is_empty() {
+            return None;
+        }
+        let (x0, y0) = (rect.min.x.floor() as i64, rect.min.y.floor() as i64);
+        let (x1, y1) = (rect.max.x.ceil() as i64, rect.max.y.ceil() as i64);
+        Some(PixelRect {
+            x: x0,
+            y: y0,
+            width: (x1 - x0) as u32,
+            height: (y1 - y0) as u32,
+        })
+    }
+
+    /// Intersection of two rectangles, `None` if they don't overlap.
+    pub fn intersect(&self, other: &PixelRect) -> Option<PixelRect> {
+        let x0 = self.x.max(other.x);
+        let y0 = self.y.max(other.y);
+        let x1 = (self.x + self.width as i64).min(other.x + other.width as i64);
+        let y1 = (self.y + self.height as i64).min(other.y + other.height as i64);
+        (x1 > x0 && y1 > y0).then(|| PixelRect {
+            x: x0,
+            y: y0,
+            width: (x1 - x0) as u32,
+            height: (y1 - y0) as u32,
+        })
+    }
+}
+
+/// Copies `top` into `bottom` at the given position, keeping the pixel type if both match.
+fn replace_same_type(bottom: &mut DynamicImage, top: &DynamicImage, x: i64, y: i64) {
+    macro_rules! replace {
+        ($($variant:ident),*) => {
+            match (bottom, top) {
+                $(
+                    (DynamicImage::$variant(bottom), DynamicImage::$variant(top)) => {
+                        image::imageops::replace(bottom, top, x, y);
+                    }
+                )*
+                (bottom, top) => image::imageops::replace(bottom, top, x, y),
+            }
+        };
+    }
+    replace!(
+        ImageLuma8,
+        ImageLumaA8,
+        ImageRgb8,
+        ImageRgba8,
+        ImageLuma16,
+        ImageLumaA16,
+        ImageRgb16,
+        ImageRgba16,
+        ImageRgb32F,
+        ImageRgba32F
+    );
+}
+
+/// Adds an alpha channel to the image if it has none, keeping the channel depth.
+fn with_alpha(img: &DynamicImage) -> DynamicImage {
+    match img {
+        DynamicImage::ImageLuma8(_) => DynamicImage::ImageLumaA8(img.to_luma_alpha8()),
+        DynamicImage::ImageRgb8(_) => DynamicImage::ImageRgba8(img.to_rgba8()),
+        DynamicImage::ImageLuma16(_) => DynamicImage::ImageLumaA16(img.to_luma_alpha16()),
+        DynamicImage::ImageRgb16(_) => DynamicImage::ImageRgba16(img.to_rgba16()),
+        DynamicImage::ImageRgb32F(_) => DynamicImage::ImageRgba32F(img.to_rgba32f()),
+        _ => img.clone(),
+    }
+}
+
+/// Creates an image of the given size and color type where every pixel is `fill`,
+/// see [`ValueInterpretation::unknown_pixel`](crate::value_interpretation::ValueInterpretation::unknown_pixel).
+fn filled(width: u32, height: u32, color: ColorType, fill: Rgba<f32>) -> DynamicImage {
+    // Let the image crate convert the fill pixel into the target type.
+    let fill_pixel = DynamicImage::ImageRgba32F(ImageBuffer::from_pixel(1, 1, fill));
+    macro_rules! filled {
+        ($($variant:ident => $convert:ident),*) => {
+            match color {
+                $(
+                    ColorType::$variant => {
+                        let pixel = *fill_pixel.$convert().get_pixel(0, 0);
+                        ImageBuffer::from_pixel(width, height, pixel).into()
+                    }
+                )*
+                _ => ImageBuffer::from_pixel(width, height, *fill_pixel.to_rgba8().get_pixel(0, 0))
+                    .into(),
+            }
+        };
+    }
+    filled!(
+        L8 => to_luma8,
+        La8 => to_luma_alpha8,
+        Rgb8 => to_rgb8,
+        L16 => to_luma16,
+        La16 => to_luma_alpha16,
+        Rgb16 => to_rgb16,
+        Rgba16 => to_rgba16,
+        Rgb32F => to_rgb32f,
+        Rgba32F => to_rgba32f
+    )
+}
+
+/// Creates a map that covers `rect` of the given map, cropping and / or padding it.
+/// Padded pixels are unknown according to the value interpretation of the map. In scale
+/// mode, unknown pixels are transparent, so an alpha channel is added if necessary.
+/// The origin is adapted such that the map stays in place.
+pub fn reframe(meta: &Meta, img: &DynamicImage, rect: PixelRect) -> (Meta, DynamicImage) {
+    let mut new_meta = meta.clone();
+    // The origin refers to the lower left image corner.
+    let lower_left = emath::vec2(
+        rect.x as f32,
+        img.height() as f32 - (rect.y + rect.height as i64) as f32,
+    );
+    new_meta.origin_xy += lower_left * meta.resolution;
+
+    let inside = rect.intersect(&PixelRect::of_image(img));
+    let cropped = inside.map(|r| img.crop_imm(r.x as u32, r.y as u32, r.width, r.height));
+    if inside == Some(rect)
+        && let Some(cropped) = cropped
+    {
+        return (new_meta, cropped);
+    }
+
+    let vi = &meta.value_interpretation;
+    let color = match (vi.mode, img.color()) {
+        (Mode::Scale, ColorType::L8) => ColorType::La8,
+        (Mode::Scale, ColorType::Rgb8) => ColorType::Rgba8,
+        (Mode::Scale, ColorType::L16) => ColorType::La16,
+        (Mode::Scale, ColorType::Rgb16) => ColorType::Rgba16,
+        (Mode::Scale, ColorType::Rgb32F) => ColorType::Rgba32F,
+        (_, color) => color,
+    };
+    let mut new_img = filled(rect.width, rect.height, color, vi.unknown_pixel(color));
+    if let (Some(inside), Some(cropped)) = (inside, cropped) {
+        let cropped = if color == img.color() {
+            cropped
+        } else {
+            with_alpha(&cropped)
+        };
+        replace_same_type(&mut new_img, &cropped, inside.x - rect.x, inside.y - rect.y);
+    }
+    (new_meta, new_img)
+}
+
+/// Pads a map with unknown cells to at least the given size in pixels, keeping it centered.
+/// See [`reframe`] for the padded pixels.
+pub fn pad_to_size(
+    meta: &Meta,
+    img: &DynamicImage,
+    width: u32,
+    height: u32,
+) -> (Meta, DynamicImage) {
+    let (width, height) = (width.max(img.width()), height.max(img.height()));
+    let rect = PixelRect {
+        x: -(((width - img.width()) / 2) as i64),
+        y: -(((height - img.height()) / 2) as i64),
+        width,
+        height,
+    };
+    reframe(meta, img, rect)
+}
+
+/// Creates an image of the given size with pixels copied from `img`,
+/// `source` gives the source pixel for each pixel. Keeps the pixel type.
+fn gather(
+    img: &DynamicImage,
+    width: u32,
+    height: u32,
+    source: impl Fn(u32, u32) -> (u32, u32),
+) -> DynamicImage {
+    macro_rules! gather {
+        ($($variant:ident),*) => {
+            match img {
+                $(
+                    DynamicImage::$variant(buffer) => {
+                        DynamicImage::$variant(ImageBuffer::from_fn(width, height, |x, y| {
+                            let (x, y) = source(x, y);
+                            *buffer.get_pixel(x, y)
+                        }))
+                    }
+                )*
+                _ => gather(&DynamicImage::ImageRgba8(img.to_rgba8()), width, height, source),
+            }
+        };
+    }
+    gather!(
+        ImageLuma8,
+        ImageLumaA8,
+        ImageRgb8,
+        ImageRgba8,
+        ImageLuma16,
+        ImageLumaA16,
+        ImageRgb16,
+        ImageRgba16,
+        ImageRgb32F,
+        ImageRgba32F
+    )
+}
+
+/// Resamples a map to a different resolution (meters per cell), keeping its origin.
+///
+/// Unlike a box filter, downsampling is occupancy-aware: a cell becomes occupied if
+/// any covered cell is occupied, unknown if the majority of covered cells is unknown,
+/// and otherwise takes the known cell with the highest occupancy. Cells keep the pixel
+/// values of the source image, the value interpretation stays valid. Upsampling uses
+/// the nearest cell. `original_has_alpha` tells if the source image had an alpha channel.
+pub fn resample(
+    meta: &Meta,
+    img: &DynamicImage,
+    original_has_alpha: bool,
+    resolution: f32,
+) -> (Meta, DynamicImage) {
+    let (width, height) = (img.width(), img.height());
+    // Source cells per target cell.
+    let scale = resolution as f64 / meta.resolution as f64;
+    let new_width = ((width as f64 / scale).ceil() as u32).max(1);
+    let new_height = ((height as f64 / scale).ceil() as u32).max(1);
+
+    let mut occupancy = vec![TRINARY_UNKNOWN; width as usize * height as usize];
+    meta.value_interpretation
+        .for_each_occupancy(img, original_has_alpha, |x, y, value| {
+            occupancy[y as usize * width as usize + x as usize] = value;
+        });
+
+    // Source cell indices covered by a target cell index, counted from the origin.
+    let covered = |i: u32, n: u32| -> Range<u32> {
+        let start = ((i as f64 * scale).floor() as u32).min(n - 1);
+        let end = (((i + 1) as f64 * scale).ceil() as u32).clamp(start + 1, n);
+        start..end
+    };
+    // The origin is in the lower left corner, the last target row may be partially covered.
+    let mut source = vec![(0, 0); new_width as usize * new_height as usize];
+    for y in 0..new_height {
+        let rows = covered(new_height - 1 - y, height);
+        for x in 0..new_width {
+            let (mut total, mut unknown) = (0, 0);
+            let mut first_unknown = None;
+            let mut most_occupied: Option<(u8, (u32, u32))> = None;
+            for row in rows.clone() {
+                let src_y = height - 1 - row;
+                for src_x in covered(x, width) {
+                    let value = occupancy[src_y as usize * width as usize + src_x as usize];
+                    total += 1;
+                    if value == TRINARY_UNKNOWN {
+                        unknown += 1;
+                        first_unknown.get_or_insert((src_x, src_y));
+                    } else if most_occupied.is_none_or(|(max, _)| value > max) {
+                        most_occupied = Some((value, (src_x, src_y)));
+                    }
+                }
+            }
+            source[y as usize * new_width as usize + x as usize] = match most_occupied {
+                Some((TRINARY_OCCUPIED, pixel)) => pixel,
+                Some((_, pixel)) if unknown * 2 <= total => pixel,
+                _ => first_unknown
+                    .or(most_occupied.map(|(_, pixel)| pixel))
+                    .unwrap_or_default(),
+            };
+        }
+    }
+
+    let mut new_meta = meta.clone();
+    new_meta.resolution = resolution;
+    let new_img = gather(img, new_width, new_height, |x, y| {
+        source[y as usize * new_width as usize + x as usize]
+    });
+    (new_meta, new_img)
+}
+
+/// Saves a map as image and ROS YAML file pair.
+/// The image is written next to the YAML file with the same name, as PNG
+/// or as TIFF for float images. Returns the metadata with the new paths.
+pub fn save_map(yaml_path: &Path, meta: &Meta, img: &DynamicImage) -> Result<Meta> {
+    let extension = match img {
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => "tiff",
+        _ => "png",
+    };
+    let mut meta = meta.clone();
+    meta.yaml_path = yaml_path.to_path_buf();
+    meta.image_path = yaml_path.with_extension(extension);
+    save_image(&meta.image_path, img)?;
+    meta.save_to_file(yaml_path)?;
+    Ok(meta)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayAlphaImage, GrayImage, ImageBuffer, Luma, LumaA};
+
+    use crate::value_interpretation::{Quirks, ValueRange};
+
+    fn map() -> (Meta, DynamicImage) {
+        let meta = Meta::new(
+            "map.png".into(),
+            "map.yaml".into(),
+            0.5,
+            emath::vec2(-1., 2.),
+            emath::Rot2::from_angle(0.3),
+        );
+        let img = GrayImage::from_fn(4, 3, |x, y| Luma([(10 * y + x) as u8]));
+        (meta, DynamicImage::ImageLuma8(img))
+    }
+
+    #[test]
+    fn crop_keeps_cells_in_place() {
+        let (meta, img) = map();
+        let rect = PixelRect {
+            x: 1,
+            y: 1,
+            width: 2,
+            height: 1,
+        };
+        let (new_meta, new_img) = reframe(&meta, &img, rect);
+        assert_eq!((new_img.width(), new_img.height()), (2, 1));
+        assert_eq!(new_img.as_luma8().unwrap().get_pixel(0, 0), &Luma([11]));
+
+        let old_pos = meta.pixel_to_map(emath::pos2(1.5, 1.5), 3.);
+        let new_pos = new_meta.pixel_to_map(emath::pos2(0.5, 0.5), 1.);
+        assert!((old_pos - new_pos).length() < 1e-5);
+    }
+
+    #[test]
+    fn pad_fills_and_keeps_cells_in_place() {
+        let (meta, img) = map();
+        let (new_meta, new_img) = pad_to_size(&meta, &img, 8, 5);
+        let new_img = new_img.as_luma8().unwrap();
+        assert_eq!(new_img.dimensions(), (8, 5));
+        assert_eq!(new_img.get_pixel(0, 0), &Luma([147]));
+        assert_eq!(new_img.get_pixel(2, 1), &Luma([0]));
+
+        let old_pos = meta.pixel_to_map(emath::pos2(0.5, 0.5), 3.);
+        let new_pos = new_meta.pixel_to_map(emath::pos2(2.5, 1.5), 5.);
+        assert!((old_pos - new_pos).length() < 1e-5);
+    }
+
+    /// Pads the map by one pixel on each side and returns the occupancy grid values
+    /// of a padded pixel and of the upper left pixel of the original image.
+    fn padded_occupancy(meta: &Meta, img: &DynamicImage) -> (u8, u8) {
+        let (_, padded) = pad_to_size(meta, img, img.width() + 2, img.height() + 2);
+        let has_alpha = padded.color().has_alpha();
+        let mut occupancy = (None, None);
+        meta.value_interpretation
+            .for_each_occupancy(&padded, has_alpha, |x, y, value| match (x, y) {
+                (0, 0) => occupancy.0 = Some(value),
+                (1, 1) => occupancy.1 = Some(value),
+                _ => {}
+            });
+        (occupancy.0.unwrap(), occupancy.1.unwrap())
+    }
+
+    #[test]
+    fn pad_unknown_trinary() {
+        let (mut meta, _) = map();
+        let gray = DynamicImage::ImageLuma8(GrayImage::from_pixel(2, 2, Luma([0])));
+        let gray_alpha =
+            DynamicImage::ImageLumaA8(GrayAlphaImage::from_pixel(2, 2, LumaA([0, 255])));
+        for quirks in [Quirks::Ros1Wiki, Quirks::Ros1MapServer] {
+            for negate in [false, true] {
+                meta.value_interpretation.quirks = quirks;
+                meta.value_interpretation.negate = negate;
+                for img in [&gray, &gray_alpha] {
+                    let (padded, _) = padded_occupancy(&meta, img);
+                    assert_eq!(
+                        padded,
+                        TRINARY_UNKNOWN,
+                        "{quirks:?} {negate} {:?}",
+                        img.color()
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pad_unknown_scale_adds_alpha() {
+        let (mut meta, img) = map();
+        meta.value_interpretation.mode = Mode::Scale;
+        let (_, padded_img) = pad_to_size(&meta, &img, 6, 5);
+        assert_eq!(padded_img.color(), ColorType::La8);
+        assert_eq!(
+            padded_img.as_luma_alpha8().unwrap().get_pixel(1, 1),
+            &LumaA([0, 255])
+        );
+        let (padded, original) = padded_occupancy(&meta, &img);
+        assert_eq!(padded, TRINARY_UNKNOWN);
+        assert_eq!(original, TRINARY_OCCUPIED);
+    }
+
+    #[test]
+    fn pad_unknown_raw() {
+        let (mut meta, img) = map();
+        meta.value_interpretation.mode = Mode::Raw;
+        let (_, padded) = pad_to_size(&meta, &img, 6, 5);
+        // 255 is -1 (unknown) in the occupancy grid.
+        assert_eq!(padded.as_luma8().unwrap().get_pixel(0, 0), &Luma([255]));
+    }
+
+    #[test]
+    fn pad_unknown_high_depth() {
+        let (mut meta, _) = map();
+        meta.value_interpretation.range = Some(ValueRange {
+            min: 1000.,
+            max: 2000.,
+        });
+        let img = DynamicImage::ImageLuma16(ImageBuffer::from_pixel(2, 2, Luma([1000u16])));
+        assert_eq!(
+            padded_occupancy(&meta, &img),
+            (TRINARY_UNKNOWN, TRINARY_OCCUPIED)
+        );
+        let img = DynamicImage::ImageRgb32F(ImageBuffer::from_pixel(2, 2, image::Rgb([0.; 3])));
+        meta.value_interpretation.range = None;
+        assert_eq!(
+            padded_occupancy(&meta, &img),
+            (TRINARY_UNKNOWN, TRINARY_OCCUPIED)
+        );
+        meta.value_interpretation.mode = Mode::Scale;
+        assert_eq!(
+            padded_occupancy(&meta, &img),
+            (TRINARY_UNKNOWN, TRINARY_OCCUPIED)
+        );
+    }
+
+    #[test]
+    fn pixel_rect_from_points() {
+        let rect = PixelRect::from_points(&[emath::pos2(-0.5, 1.2), emath::pos2(2.1, 3.)]);
+        let expected = PixelRect {
+            x: -1,
+            y: 1,
+            width: 4,
+            height: 2,
+        };
+        assert_eq!(rect, Some(expected));
+        assert_eq!(
+            expected.intersect(&PixelRect::of_image(&map().1)),
+            Some(PixelRect {
+                x: 0,
+                y: 1,
+                width: 3,
+                height: 2
+            })
+        );
+    }
+
+    #[test]
+    fn save_and_reload() {
+        let (mut meta, img) = map();
+        meta.value_interpretation.free = 0.2;
+        let yaml_path = std::env::temp_dir().join("maps_io_ros_test_save_map.yaml");
+        let saved = save_map(&yaml_path, &meta, &img).unwrap();
+        assert_eq!(saved.image_path, yaml_path.with_extension("png"));
+
+        let loaded = Meta::load_from_file(&yaml_path).unwrap();
+        assert_eq!(loaded.image_path, saved.image_path);
+        assert_eq!(loaded.origin_xy, meta.origin_xy);
+        assert!((loaded.origin_theta.angle() - 0.3).abs() < 1e-6);
+        assert_eq!(loaded.value_interpretation.free, 0.2);
+        assert_eq!(crate::image::load_image(&loaded.image_path).unwrap(), img);
+    }
+
+    #[test]
+    fn resample_occupancy_aware() {
+        // 5x4 map at 0.5 m with free (254), occupied (0) and unknown (205) cells.
+        // Rows from top, the origin is at the lower left corner.
+        let rows: [[u8; 5]; 4] = [
+            [205, 205, 205, 254, 254],
+            [205, 205, 254, 254, 254],
+            [254, 254, 254, 254, 205],
+            [254, 0, 254, 254, 205],
+        ];
+        let img = GrayImage::from_fn(5, 4, |x, y| Luma([rows[y as usize][x as usize]]));
+        let (meta, _) = map();
+        let (new_meta, new_img) = resample(&meta, &DynamicImage::ImageLuma8(img), false, 1.);
+        assert_eq!(new_meta.resolution, 1.);
+        assert_eq!(new_meta.origin_xy, meta.origin_xy);
+
+        // The partial column on the right only covers existing cells.
+        let new_img = new_img.as_luma8().unwrap();
+        assert_eq!(new_img.dimensions(), (3, 2));
+        let values: Vec<u8> = new_img.pixels().map(|p| p[0]).collect();
+        // Top row: unknown majority, free with 1 of 4 unknown, free.
+        // Bottom row: occupied wins, free, 2 of 2 unknown.
+        assert_eq!(values, vec![205, 254, 254, 0, 254, 205]);
+
+        // Upsampling uses the nearest cell.
+        let (_, upsampled) = resample(
+            &new_meta,
+            &DynamicImage::ImageLuma8(new_img.clone()),
+            false,
+            0.5,
+        );
+        assert_eq!(upsampled.as_luma8().unwrap().get_pixel(1, 3), &Luma([0]));
+    }
+}
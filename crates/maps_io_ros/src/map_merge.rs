@@ -0,0 +1,255 @@
+//! Fusing multiple aligned maps into a single map, e.g. for navigation.
+
+use image::{DynamicImage, GrayImage, Luma};
+use serde::{Deserialize, Serialize};
+
+use crate::map_edit::resample;
+use crate::map_pose::MapPose;
+use crate::meta::Meta;
+use crate::value_interpretation::{TRINARY_OCCUPIED, TRINARY_UNKNOWN};
+
+/// Pixel value of unknown cells in merged maps, as written by map_saver.
+const UNKNOWN_VALUE: u8 = 205;
+/// Probabilities are clamped to this range before summing log-odds,
+/// such that a single certain cell can still be outweighed.
+const LOG_ODDS_PROBABILITY_RANGE: std::ops::RangeInclusive<f32> = 0.01..=0.99;
+
+/// How overlapping cells of different maps are combined.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MergePolicy {
+    /// The most occupied known cell wins, obstacles of any map are kept.
+    #[default]
+    OccupiedWins,
+    /// The known cell of the map with the highest priority wins.
+    LatestWins,
+    /// Occupancy probabilities of known cells are fused by summing their log-odds.
+    LogOdds,
+}
+
+impl MergePolicy {
+    pub const ALL: [MergePolicy; 3] = [
+        MergePolicy::OccupiedWins,
+        MergePolicy::LatestWins,
+        MergePolicy::LogOdds,
+    ];
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            MergePolicy::OccupiedWins => "Occupied wins",
+            MergePolicy::LatestWins => "Latest wins",
+            MergePolicy::LogOdds => "Log-odds",
+        }
+    }
+}
+
+/// A map that takes part in a merge.
+pub struct MergeInput<'a> {
+    pub meta: &'a Meta,
+    pub image: &'a DynamicImage,
+    /// Whether the source image had an alpha channel, see
+    /// [`ValueInterpretation::apply`](crate::value_interpretation::ValueInterpretation::apply).
+    pub original_has_alpha: bool,
+    /// Pose of the map in the common root frame.
+    pub pose: &'a MapPose,
+}
+
+/// Occupancy values of an input map at the merge resolution.
+struct OccupancyLayer<'a> {
+    meta: Meta,
+    pose: &'a MapPose,
+    width: u32,
+    height: u32,
+    values: Vec<u8>,
+}
+
+impl<'a> OccupancyLayer<'a> {
+    fn new(input: &MergeInput<'a>, resolution: f32) -> OccupancyLayer<'a> {
+        // Downsample finer maps first to keep thin obstacles, coarser maps are sampled as is.
+        let resampled;
+        let (meta, image) = if input.meta.resolution < resolution {
+            resampled = resample(
+                input.meta,
+                input.image,
+                input.original_has_alpha,
+                resolution,
+            );
+            (resampled.0, &resampled.1)
+        } else {
+            (input.meta.clone(), input.image)
+        };
+        let (width, height) = (image.width(), image.height());
+        let mut values = vec![TRINARY_UNKNOWN; width as usize * height as usize];
+        meta.value_interpretation.for_each_occupancy(
+            image,
+            input.original_has_alpha,
+            |x, y, value| {
+                values[y as usize * width as usize + x as usize] = value;
+            },
+        );
+        OccupancyLayer {
+            meta,
+            pose: input.pose,
+            width,
+            height,
+            values,
+        }
+    }
+
+    /// Corners of the map in the root frame.
+    fn corners(&self) -> [emath::Pos2; 4] {
+        let (width, height) = (self.width as f32, self.height as f32);
+        [(0., 0.), (width, 0.), (0., height), (width, height)].map(|(x, y)| {
+            self.pose
+                .transform(self.meta.pixel_to_map(emath::pos2(x, y), height))
+        })
+    }
+
+    /// Occupancy value of the cell at a root frame position, `None` if outside of the map.
+    fn sample(&self, root_pos: emath::Pos2) -> Option<u8> {
+        let map_pos = self.pose.inverse_transform(root_pos);
+        let pixel = self.meta.map_to_pixel(map_pos, self.height as f32);
+        if pixel.x < 0. || pixel.y < 0. {
+            return None;
+        }
+        let (x, y) = (pixel.x as u32, pixel.y as u32);
+        (x < self.width && y < self.height)
+            .then(|| self.values[y as usize * self.width as usize + x as usize])
+    }
+}
+
+/// Combines the occupancy values of a cell, ordered from lowest to highest priority.
+fn combine(policy: MergePolicy, values: impl Iterator<Item = u8>) -> u8 {
+    let known: Vec<u8> = values.filter(|value| *value != TRINARY_UNKNOWN).collect();
+    match (policy, known.as_slice()) {
+        (_, []) => TRINARY_UNKNOWN,
+        // Keep single cells as they are, instead of clamping them for log-odds.
+        (MergePolicy::LatestWins, [.., last]) | (MergePolicy::LogOdds, [last]) => *last,
+        (MergePolicy::OccupiedWins, _) => known.iter().copied().max().unwrap_or(TRINARY_UNKNOWN),
+        (MergePolicy::LogOdds, _) => {
+            let log_odds: f32 = known
+                .iter()
+                .map(|&value| {
+                    let p = (value as f32 / TRINARY_OCCUPIED as f32).clamp(
+                        *LOG_ODDS_PROBABILITY_RANGE.start(),
+                        *LOG_ODDS_PROBABILITY_RANGE.end(),
+                    );
+                    (p / (1. - p)).ln()
+                })
+                .sum();
+            let p = 1. / (1. + (-log_odds).exp());
+            (p * TRINARY_OCCUPIED as f32).round() as u8
+        }
+    }
+}
+
+/// Pixel value of an occupancy value, as written by map_saver (not negated).
+fn occupancy_to_pixel(value: u8) -> u8 {
+    if value == TRINARY_UNKNOWN {
+        return UNKNOWN_VALUE;
+    }
+    let p = value as f32 / TRINARY_OCCUPIED as f32;
+    ((1. - p) * 255.).round() as u8
+}
+
+/// Merges maps in their aligned poses into a single grid at the given resolution.
+///
+/// The inputs are ordered from lowest to highest priority, e.g. in draw order.
+/// Each map is interpreted with its own value interpretation and finer maps are
+/// downsampled occupancy-aware (see [`resample`]) before the merge.
+/// The merged map is axis-aligned in the root frame and covers all inputs,
+/// its image is 8-bit grayscale with the default ROS thresholds.
+/// Returns `None` if there are no inputs.
+pub fn merge_maps(
+    inputs: &[MergeInput],
+    resolution: f32,
+    policy: MergePolicy,
+) -> Option<(Meta, DynamicImage)> {
+    let layers: Vec<OccupancyLayer> = inputs
+        .iter()
+        .map(|input| OccupancyLayer::new(input, resolution))
+        .collect();
+    let corners: Vec<emath::Pos2> = layers.iter().flat_map(OccupancyLayer::corners).collect();
+    if corners.is_empty() {
+        return None;
+    }
+    let bounds = emath::Rect::from_points(&corners);
+    let width = ((bounds.width() / resolution).ceil() as u32).max(1);
+    let height = ((bounds.height() / resolution).ceil() as u32).max(1);
+
+    let img = GrayImage::from_fn(width, height, |x, y| {
+        // Cell center in the root frame, rows are counted from the top.
+        let root_pos = emath::pos2(
+            bounds.min.x + (x as f32 + 0.5) * resolution,
+            bounds.min.y + ((height - y) as f32 - 0.5) * resolution,
+        );
+        let values = layers.iter().filter_map(|layer| layer.sample(root_pos));
+        Luma([occupancy_to_pixel(combine(policy, values))])
+    });
+    let meta = Meta::new(
+        "merged.png".into(),
+        "merged.yaml".into(),
+        resolution,
+        bounds.min.to_vec2(),
+        emath::Rot2::IDENTITY,
+    );
+    Some((meta, DynamicImage::ImageLuma8(img)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input_map(values: [[u8; 2]; 1], x: f32) -> (Meta, DynamicImage, MapPose) {
+        let meta = Meta::new(
+            "map.png".into(),
+            "map.yaml".into(),
+            1.,
+            emath::Vec2::ZERO,
+            emath::Rot2::IDENTITY,
+        );
+        let img = GrayImage::from_fn(2, 1, |px, py| Luma([values[py as usize][px as usize]]));
+        let mut pose = MapPose::default();
+        pose.with_vec2(emath::vec2(x, 0.));
+        (meta, DynamicImage::ImageLuma8(img), pose)
+    }
+
+    fn merge(maps: &[(Meta, DynamicImage, MapPose)], policy: MergePolicy) -> Vec<u8> {
+        let inputs: Vec<MergeInput> = maps
+            .iter()
+            .map(|(meta, image, pose)| MergeInput {
+                meta,
+                image,
+                original_has_alpha: false,
+                pose,
+            })
+            .collect();
+        let (meta, img) = merge_maps(&inputs, 1., policy).unwrap();
+        assert_eq!(meta.origin_xy, emath::Vec2::ZERO);
+        img.as_luma8().unwrap().pixels().map(|p| p[0]).collect()
+    }
+
+    #[test]
+    fn policies() {
+        // Two 2x1 maps that overlap in the middle cell: free / occupied.
+        let maps = [input_map([[254, 254]], 0.), input_map([[0, 205]], 1.)];
+        assert_eq!(merge(&maps, MergePolicy::OccupiedWins), vec![255, 0, 205]);
+        assert_eq!(merge(&maps, MergePolicy::LatestWins), vec![255, 0, 205]);
+
+        let maps = [input_map([[254, 0]], 0.), input_map([[254, 205]], 1.)];
+        assert_eq!(merge(&maps, MergePolicy::OccupiedWins), vec![255, 0, 205]);
+        assert_eq!(merge(&maps, MergePolicy::LatestWins), vec![255, 255, 205]);
+        // Certain free and occupied cells cancel out.
+        assert_eq!(merge(&maps, MergePolicy::LogOdds), vec![255, 128, 205]);
+    }
+
+    #[test]
+    fn fine_maps_are_downsampled() {
+        let (mut meta, _, pose) = input_map([[0, 0]], 0.);
+        meta.resolution = 0.5;
+        // 4x2 map at 0.5 m with a single occupied cell.
+        let mut img = GrayImage::from_pixel(4, 2, Luma([254]));
+        img.put_pixel(3, 0, Luma([0]));
+        let maps = [(meta, DynamicImage::ImageLuma8(img), pose)];
+        assert_eq!(merge(&maps, MergePolicy::OccupiedWins), vec![255, 0]);
+    }
+}
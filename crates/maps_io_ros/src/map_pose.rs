@@ -98,6 +98,16 @@ impl MapPose {
         emath::vec2(self.translation.x, self.translation.y)
     }
 
+    /// Transforms a position from the map frame into the root frame.
+    pub fn transform(&self, map_pos: emath::Pos2) -> emath::Pos2 {
+        (self.rot2() * map_pos.to_vec2() + self.vec2()).to_pos2()
+    }
+
+    /// Transforms a position from the root frame into the map frame.
+    pub fn inverse_transform(&self, root_pos: emath::Pos2) -> emath::Pos2 {
+        (self.rot2().inverse() * (root_pos.to_vec2() - self.vec2())).to_pos2()
+    }
+
     fn normalized(mut self) -> MapPose {
         self.rotation.yaw = emath::normalized_angle(self.rotation.yaw);
         self
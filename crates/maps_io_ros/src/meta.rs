@@ -8,10 +8,10 @@ use std::path::{Path, PathBuf};
 
 use crate::error::{Error, Result};
 use crate::os_helpers::resolve_symlink;
-use crate::value_interpretation::{Mode, ValueInterpretation};
+use crate::value_interpretation::{Mode, ValueInterpretation, ValueRange};
 
 /// Plain ROS map metadata yaml file format.
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct MetaYaml {
     pub image: PathBuf,
     pub resolution: f32,
@@ -19,6 +19,7 @@ pub struct MetaYaml {
     pub negate: i32,
     pub occupied_thresh: f32,
     pub free_thresh: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub mode: Option<Mode>,
 }
 
@@ -109,13 +110,95 @@ impl Meta {
         Ok(meta)
     }
 
+    /// Creates metadata for a map image that has no ROS YAML file, e.g. a GeoTIFF.
+    /// The `yaml_path` identifies the map and is used when the metadata is saved.
+    pub fn new(
+        image_path: PathBuf,
+        yaml_path: PathBuf,
+        resolution: f32,
+        origin_xy: emath::Vec2,
+        origin_theta: emath::Rot2,
+    ) -> Meta {
+        Meta {
+            image_path,
+            yaml_path,
+            resolution,
+            origin_xy,
+            origin_theta,
+            value_interpretation: ValueInterpretation::default(),
+            original_value_interpretation: ValueInterpretation::default(),
+        }
+    }
+
     pub fn load_from_bytes(bytes: &[u8], yaml_name: &str) -> Result<Meta> {
         let meta_yaml_annotated = MetaYamlAnnotated::from_bytes(bytes, yaml_name)?;
         let meta = Meta::from(meta_yaml_annotated);
         Ok(meta)
     }
 
+    /// Saves the metadata as ROS map YAML file, with the current value interpretation.
+    /// The image path is written relative to the YAML file if it's in the same directory.
+    pub fn save_to_file(&self, yaml_path: &Path) -> Result<()> {
+        let yaml = self.to_yaml(yaml_path)?;
+        std::fs::write(yaml_path, yaml)
+            .map_err(|e| Error::io(format!("Cannot write {yaml_path:?}"), e))
+    }
+
+    /// Serializes the metadata like [`Meta::save_to_file`], for a YAML file at `yaml_path`.
+    pub fn to_yaml(&self, yaml_path: &Path) -> Result<String> {
+        let vi = &self.value_interpretation;
+        let image = match (self.image_path.parent(), yaml_path.parent()) {
+            (Some(image_dir), Some(yaml_dir)) if image_dir == yaml_dir => self
+                .image_path
+                .file_name()
+                .map_or_else(|| self.image_path.clone(), PathBuf::from),
+            _ => self.image_path.clone(),
+        };
+        let meta_yaml = MetaYaml {
+            image,
+            resolution: self.resolution,
+            origin: [
+                self.origin_xy.x,
+                self.origin_xy.y,
+                self.origin_theta.angle(),
+            ],
+            negate: i32::from(vi.negate),
+            occupied_thresh: vi.occupied,
+            free_thresh: vi.free,
+            mode: vi.explicit_mode.then_some(vi.mode),
+        };
+        serde_yaml_ng::to_string(&meta_yaml)
+            .map_err(|e| Error::yaml(format!("Cannot serialize metadata for {yaml_path:?}"), e))
+    }
+
     pub fn reset_value_interpretation(&mut self) {
         self.value_interpretation = self.original_value_interpretation;
     }
+
+    /// Sets the value range if the metadata specifies none, e.g. the range of the
+    /// values of a float image. It is kept when the value interpretation is reset.
+    pub fn set_default_value_range(&mut self, range: Option<ValueRange>) {
+        if self.original_value_interpretation.range.is_none() {
+            self.original_value_interpretation.range = range;
+        }
+        if self.value_interpretation.range.is_none() {
+            self.value_interpretation.range = range;
+        }
+    }
+
+    /// Converts continuous image coordinates (origin in the upper left image corner,
+    /// y pointing down) to metric coordinates in the map frame.
+    /// `image_height` is the height of the map image in pixels.
+    pub fn pixel_to_map(&self, pixel: emath::Pos2, image_height: f32) -> emath::Pos2 {
+        let llc_offset = emath::vec2(pixel.x, image_height - pixel.y) * self.resolution;
+        (self.origin_theta * (self.origin_xy + llc_offset)).to_pos2()
+    }
+
+    /// Converts metric map frame coordinates to continuous image coordinates.
+    /// Inverse of [`Meta::pixel_to_map`].
+    pub fn map_to_pixel(&self, map_pos: emath::Pos2, image_height: f32) -> emath::Pos2 {
+        let llc_offset = self.origin_theta.inverse() * map_pos.to_vec2() - self.origin_xy;
+        let pixel = llc_offset / self.resolution;
+        emath::pos2(pixel.x, image_height - pixel.y)
+    }
 }
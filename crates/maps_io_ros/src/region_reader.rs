@@ -0,0 +1,521 @@
+//! Decoding of rectangular regions of map images, without decoding the whole image.
+//!
+//! Huge maps (e.g. 30k x 30k pixels) take gigabytes of memory when decoded completely.
+//! Formats that allow random access can be read region by region instead:
+//! binary PGM / PPM files and TIFFs that are split into strips or tiles.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use tiff::decoder::{ChunkType, Decoder, DecodingResult, Limits};
+use tiff::tags::{PlanarConfiguration, Tag};
+
+use crate::error::{Error, Result};
+use crate::geotiff::has_tiff_extension;
+use crate::os_helpers::resolve_symlink;
+
+/// TIFF chunks larger than this are not decoded region by region,
+/// e.g. files with a single strip would have to be decoded completely for every region.
+const MAX_TIFF_CHUNK_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Pixel layout of the regions that are read, matching what [`crate::load_image`] returns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PixelFormat {
+    Luma8,
+    Luma16,
+    Rgb8,
+    Rgba8,
+    /// Single-band float, stored as RGB32F with identical channels.
+    GrayF32,
+}
+
+impl PixelFormat {
+    fn channels(self) -> usize {
+        match self {
+            PixelFormat::Luma8 | PixelFormat::Luma16 => 1,
+            PixelFormat::Rgb8 | PixelFormat::GrayF32 => 3,
+            PixelFormat::Rgba8 => 4,
+        }
+    }
+}
+
+/// Raw samples of a region, before they are converted to an image.
+enum Samples {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    F32(Vec<f32>),
+}
+
+enum Source {
+    /// Binary PGM (P5) or PPM (P6) with the offset of the pixel data in the file.
+    Pnm {
+        file: Mutex<File>,
+        data_offset: u64,
+        maxval: u16,
+    },
+    /// TIFF with multiple strips or tiles of the given size.
+    Tiff {
+        decoder: Box<Mutex<Decoder<BufReader<File>>>>,
+        chunk_size: (u32, u32),
+        chunk_type: ChunkType,
+    },
+}
+
+/// Reads rectangular regions of an image file on demand.
+pub struct RegionReader {
+    path: PathBuf,
+    width: u32,
+    height: u32,
+    format: PixelFormat,
+    source: Source,
+}
+
+impl RegionReader {
+    /// Opens an image file for region reading. Only the header is read.
+    /// Returns `None` if the format doesn't support decoding regions,
+    /// then the image has to be loaded completely with [`crate::load_image`].
+    pub fn open(path: &Path) -> Result<Option<RegionReader>> {
+        let path = resolve_symlink(path);
+        let mut file =
+            File::open(&path).map_err(|e| Error::io(format!("Cannot open {path:?}"), e))?;
+        let mut magic = [0; 2];
+        if file.read_exact(&mut magic).is_err() {
+            return Ok(None);
+        }
+        file.seek(SeekFrom::Start(0))
+            .map_err(|e| Error::io(format!("Cannot read {path:?}"), e))?;
+        match &magic {
+            b"P5" | b"P6" => Self::open_pnm(path, file),
+            b"II" | b"MM" if has_tiff_extension(&path) => Self::open_tiff(path, file),
+            _ => Ok(None),
+        }
+    }
+
+    fn open_pnm(path: PathBuf, file: File) -> Result<Option<RegionReader>> {
+        let mut reader = BufReader::new(file);
+        let header = read_pnm_header(&mut reader)
+            .map_err(|e| Error::io(format!("Cannot read PNM header of {path:?}"), e))?;
+        let Some([magic, width, height, maxval]) = header else {
+            return Ok(None);
+        };
+        let format = match (magic, maxval) {
+            (5, 1..=255) => PixelFormat::Luma8,
+            (5, 256..=65535) => PixelFormat::Luma16,
+            (6, 1..=255) => PixelFormat::Rgb8,
+            // 16-bit RGB is not supported by the rendering, which expects RGBA8 in that case.
+            _ => return Ok(None),
+        };
+        let data_offset = reader
+            .stream_position()
+            .map_err(|e| Error::io(format!("Cannot read {path:?}"), e))?;
+        Ok(Some(RegionReader {
+            path,
+            width,
+            height,
+            format,
+            source: Source::Pnm {
+                file: Mutex::new(reader.into_inner()),
+                data_offset,
+                maxval: maxval as u16,
+            },
+        }))
+    }
+
+    fn open_tiff(path: PathBuf, file: File) -> Result<Option<RegionReader>> {
+        let tiff_error = |e| Error::tiff(format!("Cannot read TIFF {path:?}"), e);
+        let mut decoder = Decoder::new(BufReader::new(file))
+            .map_err(tiff_error)?
+            .with_limits(Limits::unlimited());
+        let (width, height) = decoder.dimensions().map_err(tiff_error)?;
+        let format = match decoder.colortype().map_err(tiff_error)? {
+            tiff::ColorType::Gray(8) => PixelFormat::Luma8,
+            tiff::ColorType::Gray(16) => PixelFormat::Luma16,
+            tiff::ColorType::Gray(32) => PixelFormat::GrayF32,
+            tiff::ColorType::RGB(8) => PixelFormat::Rgb8,
+            tiff::ColorType::RGBA(8) => PixelFormat::Rgba8,
+            _ => return Ok(None),
+        };
+        // Only the first plane of planar images could be read per chunk.
+        let planar_config = decoder
+            .find_tag_unsigned::<u16>(Tag::PlanarConfiguration)
+            .map_err(tiff_error)?;
+        if format.channels() > 1 && planar_config == Some(PlanarConfiguration::Planar.to_u16()) {
+            return Ok(None);
+        }
+        let chunk_size = decoder.chunk_dimensions();
+        let bytes_per_pixel = match format {
+            PixelFormat::Luma16 => 2,
+            PixelFormat::GrayF32 => 4,
+            _ => format.channels() as u64,
+        };
+        if u64::from(chunk_size.0) * u64::from(chunk_size.1) * bytes_per_pixel
+            > MAX_TIFF_CHUNK_BYTES
+        {
+            return Ok(None);
+        }
+        let chunk_type = decoder.get_chunk_type();
+        Ok(Some(RegionReader {
+            path,
+            width,
+            height,
+            format,
+            source: Source::Tiff {
+                decoder: Box::new(Mutex::new(decoder)),
+                chunk_size,
+                chunk_type,
+            },
+        }))
+    }
+
+    /// Opens another reader of the same file with its own file handle, such that
+    /// regions can be read from multiple threads without waiting for each other.
+    pub fn reopen(&self) -> Result<RegionReader> {
+        let changed = || {
+            Error::io(
+                format!("Cannot reopen {:?}", self.path),
+                std::io::Error::new(std::io::ErrorKind::InvalidData, "the file has changed"),
+            )
+        };
+        let reader = RegionReader::open(&self.path)?.ok_or_else(changed)?;
+        if reader.dimensions() != self.dimensions() || reader.format != self.format {
+            return Err(changed());
+        }
+        Ok(reader)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Width and height of the whole image in pixels.
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Color type of the regions that are read.
+    pub fn color_type(&self) -> image::ColorType {
+        match self.format {
+            PixelFormat::Luma8 => image::ColorType::L8,
+            PixelFormat::Luma16 => image::ColorType::L16,
+            PixelFormat::Rgb8 => image::ColorType::Rgb8,
+            PixelFormat::Rgba8 => image::ColorType::Rgba8,
+            PixelFormat::GrayF32 => image::ColorType::Rgb32F,
+        }
+    }
+
+    /// Returns true if the image has an alpha channel.
+    pub fn has_alpha(&self) -> bool {
+        self.format == PixelFormat::Rgba8
+    }
+
+    /// Decodes a region of the image. The region is clamped to the image bounds.
+    pub fn read_region(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> Result<image::DynamicImage> {
+        let x = x.min(self.width);
+        let y = y.min(self.height);
+        let width = width.min(self.width - x);
+        let height = height.min(self.height - y);
+        let samples = match &self.source {
+            Source::Pnm {
+                file,
+                data_offset,
+                maxval,
+            } => self.read_pnm_region(file, *data_offset, *maxval, [x, y, width, height]),
+            Source::Tiff {
+                decoder,
+                chunk_size,
+                chunk_type,
+            } => self.read_tiff_region(decoder, *chunk_size, *chunk_type, [x, y, width, height]),
+        }?;
+        self.to_image(samples, width, height)
+    }
+
+    fn read_pnm_region(
+        &self,
+        file: &Mutex<File>,
+        data_offset: u64,
+        maxval: u16,
+        [x, y, width, height]: [u32; 4],
+    ) -> Result<Samples> {
+        let io_error = |e| Error::io(format!("Cannot read region of {:?}", self.path), e);
+        let sample_bytes: u64 = if maxval > 255 { 2 } else { 1 };
+        let pixel_bytes = sample_bytes * self.format.channels() as u64;
+        let row_bytes = (u64::from(width) * pixel_bytes) as usize;
+        let mut bytes = vec![0; row_bytes * height as usize];
+        {
+            let mut file = file.lock().expect("poisoned region reader lock");
+            for (row, row_data) in bytes.chunks_exact_mut(row_bytes.max(1)).enumerate() {
+                let offset = data_offset
+                    + ((u64::from(y) + row as u64) * u64::from(self.width) + u64::from(x))
+                        * pixel_bytes;
+                file.seek(SeekFrom::Start(offset)).map_err(io_error)?;
+                file.read_exact(row_data).map_err(io_error)?;
+            }
+        }
+        // Scale samples to the full range if it's not saturated, like the image crate does.
+        if sample_bytes == 1 {
+            if maxval != 255 {
+                let factor = 255. / f32::from(maxval);
+                for v in &mut bytes {
+                    *v = (f32::from(*v) * factor).round() as u8;
+                }
+            }
+            return Ok(Samples::U8(bytes));
+        }
+        let factor = 65535. / f32::from(maxval);
+        Ok(Samples::U16(
+            bytes
+                .chunks_exact(2)
+                .map(|b| {
+                    let v = u16::from_be_bytes([b[0], b[1]]);
+                    if maxval == u16::MAX {
+                        v
+                    } else {
+                        (f32::from(v) * factor).round() as u16
+                    }
+                })
+                .collect(),
+        ))
+    }
+
+    fn read_tiff_region(
+        &self,
+        decoder: &Mutex<Decoder<BufReader<File>>>,
+        (chunk_width, chunk_height): (u32, u32),
+        chunk_type: ChunkType,
+        [x, y, width, height]: [u32; 4],
+    ) -> Result<Samples> {
+        let tiff_error = |e| Error::tiff(format!("Cannot read region of {:?}", self.path), e);
+        let channels = match self.format {
+            // Decoded as single channel, expanded when converted to an image.
+            PixelFormat::GrayF32 => 1,
+            format => format.channels(),
+        };
+        let mut samples = match self.format {
+            PixelFormat::Luma16 => Samples::U16(vec![0; width as usize * height as usize]),
+            PixelFormat::GrayF32 => Samples::F32(vec![0.; width as usize * height as usize]),
+            _ => Samples::U8(vec![0; width as usize * height as usize * channels]),
+        };
+        if width == 0 || height == 0 {
+            return Ok(samples);
+        }
+        let chunks_across = match chunk_type {
+            ChunkType::Strip => 1,
+            ChunkType::Tile => self.width.div_ceil(chunk_width),
+        };
+        let chunk_width = match chunk_type {
+            ChunkType::Strip => self.width,
+            ChunkType::Tile => chunk_width,
+        };
+
+        let mut decoder = decoder.lock().expect("poisoned region reader lock");
+        for chunk_row in y / chunk_height..=(y + height - 1) / chunk_height {
+            for chunk_column in x / chunk_width..=(x + width - 1) / chunk_width {
+                let index = chunk_row * chunks_across + chunk_column;
+                let (data_width, data_height) = decoder.chunk_data_dimensions(index);
+                let chunk = decoder.read_chunk(index).map_err(tiff_error)?;
+                // Copy the intersection of the chunk and the region.
+                let chunk_min = [chunk_column * chunk_width, chunk_row * chunk_height];
+                let min = [x.max(chunk_min[0]), y.max(chunk_min[1])];
+                let max = [
+                    (x + width).min(chunk_min[0] + data_width),
+                    (y + height).min(chunk_min[1] + data_height),
+                ];
+                if min[0] >= max[0] || min[1] >= max[1] {
+                    continue;
+                }
+                let row_len = (max[0] - min[0]) as usize * channels;
+                for row in min[1]..max[1] {
+                    let src = ((row - chunk_min[1]) as usize * data_width as usize
+                        + (min[0] - chunk_min[0]) as usize)
+                        * channels;
+                    let dst =
+                        ((row - y) as usize * width as usize + (min[0] - x) as usize) * channels;
+                    match (&mut samples, &chunk) {
+                        (Samples::U8(dst_data), DecodingResult::U8(src_data)) => dst_data
+                            [dst..dst + row_len]
+                            .copy_from_slice(&src_data[src..src + row_len]),
+                        (Samples::U16(dst_data), DecodingResult::U16(src_data)) => dst_data
+                            [dst..dst + row_len]
+                            .copy_from_slice(&src_data[src..src + row_len]),
+                        (Samples::F32(dst_data), DecodingResult::F32(src_data)) => dst_data
+                            [dst..dst + row_len]
+                            .copy_from_slice(&src_data[src..src + row_len]),
+                        _ => {
+                            return Err(Error::io(
+                                format!("Cannot read region of {:?}", self.path),
+                                std::io::Error::new(
+                                    std::io::ErrorKind::InvalidData,
+                                    "unexpected TIFF sample format",
+                                ),
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(samples)
+    }
+
+    fn to_image(&self, samples: Samples, width: u32, height: u32) -> Result<image::DynamicImage> {
+        let image = match (self.format, samples) {
+            (PixelFormat::Luma8, Samples::U8(data)) => {
+                image::GrayImage::from_raw(width, height, data).map(image::DynamicImage::from)
+            }
+            (PixelFormat::Luma16, Samples::U16(data)) => {
+                image::ImageBuffer::from_raw(width, height, data)
+                    .map(image::DynamicImage::ImageLuma16)
+            }
+            (PixelFormat::Rgb8, Samples::U8(data)) => {
+                image::RgbImage::from_raw(width, height, data).map(image::DynamicImage::from)
+            }
+            (PixelFormat::Rgba8, Samples::U8(data)) => {
+                image::RgbaImage::from_raw(width, height, data).map(image::DynamicImage::from)
+            }
+            (PixelFormat::GrayF32, Samples::F32(data)) => {
+                let rgb = data.into_iter().flat_map(|v| [v; 3]).collect();
+                image::Rgb32FImage::from_raw(width, height, rgb).map(image::DynamicImage::from)
+            }
+            _ => None,
+        };
+        image.ok_or_else(|| {
+            Error::io(
+                format!("Cannot read region of {:?}", self.path),
+                std::io::Error::new(std::io::ErrorKind::InvalidData, "unexpected sample count"),
+            )
+        })
+    }
+}
+
+/// Reads the header of a binary PGM / PPM file: magic number (5 or 6), width, height
+/// and maxval. Leaves the reader at the start of the pixel data.
+/// Returns `None` if the header is not valid.
+fn read_pnm_header(reader: &mut impl BufRead) -> std::io::Result<Option<[u32; 4]>> {
+    let mut magic = [0; 2];
+    reader.read_exact(&mut magic)?;
+    let magic = match &magic {
+        b"P5" => 5,
+        b"P6" => 6,
+        _ => return Ok(None),
+    };
+    let mut values = [magic, 0, 0, 0];
+    for value in &mut values[1..] {
+        let mut token = String::new();
+        loop {
+            let mut byte = [0];
+            reader.read_exact(&mut byte)?;
+            match byte[0] {
+                // Comments run until the end of the line.
+                b'#' if token.is_empty() => {
+                    reader.read_line(&mut String::new())?;
+                }
+                b if b.is_ascii_whitespace() => {
+                    if !token.is_empty() {
+                        break;
+                    }
+                }
+                b if b.is_ascii_digit() => token.push(b as char),
+                _ => return Ok(None),
+            }
+        }
+        // A single whitespace character after the maxval separates the pixel data.
+        match token.parse() {
+            Ok(parsed) => *value = parsed,
+            Err(_) => return Ok(None),
+        }
+    }
+    if values[1] == 0 || values[2] == 0 || values[3] == 0 || values[3] > 65535 {
+        return Ok(None);
+    }
+    Ok(Some(values))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use image::GenericImageView as _;
+
+    use crate::image::load_image;
+
+    fn assert_region_eq(reader: &RegionReader, full: &image::DynamicImage, region: [u32; 4]) {
+        let [x, y, width, height] = region;
+        let expected = full.crop_imm(x, y, width, height);
+        let actual = reader.read_region(x, y, width, height).unwrap();
+        assert_eq!(expected.color(), actual.color());
+        assert_eq!(expected.dimensions(), actual.dimensions());
+        assert_eq!(expected.as_bytes(), actual.as_bytes());
+    }
+
+    #[test]
+    fn pgm_regions_match_full_decode() {
+        let path = std::env::temp_dir().join("maps_io_ros_test_region.pgm");
+        let mut bytes = b"P5\n# comment\n37 23\n200\n".to_vec();
+        bytes.extend((0..37 * 23).map(|i| (i % 201) as u8));
+        std::fs::write(&path, bytes).unwrap();
+
+        let reader = RegionReader::open(&path).unwrap().unwrap();
+        let full = load_image(&path).unwrap();
+        assert_eq!(reader.dimensions(), (37, 23));
+        assert_region_eq(&reader, &full, [0, 0, 37, 23]);
+        assert_region_eq(&reader, &full, [5, 7, 10, 3]);
+        assert_region_eq(&reader, &full, [36, 22, 1, 1]);
+        assert_region_eq(&reader.reopen().unwrap(), &full, [5, 7, 10, 3]);
+        // Clamped to the image bounds.
+        let clamped = reader.read_region(30, 20, 100, 100).unwrap();
+        assert_eq!(clamped.dimensions(), (7, 3));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn pgm_16_bit_regions_match_full_decode() {
+        let path = std::env::temp_dir().join("maps_io_ros_test_region_16.pgm");
+        let mut bytes = b"P5 19 11 1000\n".to_vec();
+        bytes.extend((0..19 * 11).flat_map(|i: u16| (i * 4).to_be_bytes()));
+        std::fs::write(&path, bytes).unwrap();
+
+        let reader = RegionReader::open(&path).unwrap().unwrap();
+        let full = load_image(&path).unwrap();
+        assert_region_eq(&reader, &full, [0, 0, 19, 11]);
+        assert_region_eq(&reader, &full, [3, 4, 8, 5]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn tiff_strips_match_full_decode() {
+        let path = std::env::temp_dir().join("maps_io_ros_test_region_strips.tif");
+        let (width, height) = (300, 200);
+        let data: Vec<u16> = (0..width * height).map(|i| (i * 7) as u16).collect();
+        {
+            let file = std::fs::File::create(&path).unwrap();
+            let mut encoder = tiff::encoder::TiffEncoder::new(file).unwrap();
+            let mut image = encoder
+                .new_image::<tiff::encoder::colortype::Gray16>(width, height)
+                .unwrap();
+            image.rows_per_strip(16).unwrap();
+            image.write_data(&data).unwrap();
+        }
+
+        let reader = RegionReader::open(&path).unwrap().unwrap();
+        let full = load_image(&path).unwrap();
+        assert_region_eq(&reader, &full, [0, 0, width, height]);
+        assert_region_eq(&reader, &full, [10, 15, 50, 40]);
+        assert_region_eq(&reader, &full, [250, 190, 50, 10]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn unsupported_formats_return_none() {
+        let path = std::env::temp_dir().join("maps_io_ros_test_region.png");
+        image::DynamicImage::new_luma8(4, 4).save(&path).unwrap();
+        assert!(RegionReader::open(&path).unwrap().is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
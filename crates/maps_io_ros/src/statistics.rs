@@ -0,0 +1,219 @@
+//! Occupancy statistics of maps, e.g. to sanity-check maps before a release.
+
+use image::{DynamicImage, ImageBuffer, Pixel};
+
+use crate::map_edit::PixelRect;
+use crate::meta::Meta;
+use crate::value_interpretation::{
+    TRINARY_FREE, TRINARY_OCCUPIED, TRINARY_UNKNOWN, ValueRange, color_average,
+};
+
+/// Number of bins of a [`Histogram`].
+pub const HISTOGRAM_BINS: usize = 256;
+
+/// Histogram of raw pixel values (average of the color channels).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    /// Value range covered by the bins.
+    /// 0 to 255 for 8-bit images, the minimum / maximum value otherwise.
+    pub range: ValueRange,
+    /// Pixel counts of [`HISTOGRAM_BINS`] equally sized bins.
+    pub counts: Vec<u64>,
+    /// Number of pixels without valid value (NaN).
+    pub invalid: u64,
+}
+
+impl Histogram {
+    pub fn from_image(img: &DynamicImage) -> Histogram {
+        fn fill<P>(buffer: &ImageBuffer<P, Vec<P::Subpixel>>, range: ValueRange) -> Histogram
+        where
+            P: Pixel,
+            P::Subpixel: Into<f32>,
+        {
+            let mut histogram = Histogram {
+                range,
+                counts: vec![0; HISTOGRAM_BINS],
+                invalid: 0,
+            };
+            for pixel in buffer.pixels() {
+                match range.normalize(color_average(pixel.channels(), P::HAS_ALPHA)) {
+                    Some(t) => {
+                        let bin = (t * (HISTOGRAM_BINS - 1) as f32).round() as usize;
+                        histogram.counts[bin] += 1;
+                    }
+                    None => histogram.invalid += 1,
+                }
+            }
+            histogram
+        }
+
+        let color = img.color();
+        let range = if color.bytes_per_pixel() > color.channel_count() {
+            ValueRange::from_image(img).unwrap_or(ValueRange { min: 0., max: 1. })
+        } else {
+            ValueRange { min: 0., max: 255. }
+        };
+        match img {
+            DynamicImage::ImageLuma16(buffer) => fill(buffer, range),
+            DynamicImage::ImageLumaA16(buffer) => fill(buffer, range),
+            DynamicImage::ImageRgb16(buffer) => fill(buffer, range),
+            DynamicImage::ImageRgba16(buffer) => fill(buffer, range),
+            DynamicImage::ImageRgb32F(buffer) => fill(buffer, range),
+            DynamicImage::ImageRgba32F(buffer) => fill(buffer, range),
+            _ => fill(&img.to_rgba8(), range),
+        }
+    }
+
+    /// Raw value at the center of a bin.
+    pub fn bin_value(&self, bin: usize) -> f32 {
+        self.range.min
+            + (self.range.max - self.range.min) * bin as f32 / (HISTOGRAM_BINS - 1) as f32
+    }
+}
+
+/// Cell counts, areas and extent of a map under its value interpretation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MapStatistics {
+    /// Image size in pixels (width, height).
+    pub size: (u32, u32),
+    /// Map resolution in meters per cell.
+    pub resolution: f32,
+    pub histogram: Histogram,
+    pub free: u64,
+    pub occupied: u64,
+    /// Cells between the free and occupied thresholds (only in scale mode).
+    pub intermediate: u64,
+    pub unknown: u64,
+    /// Bounding box of all known (non-unknown) cells in pixel coordinates,
+    /// `None` if there are none.
+    pub known_pixel_bounds: Option<PixelRect>,
+    /// Axis-aligned bounding box of all known cells
+    /// in map frame coordinates (meters), `None` if there are none.
+    pub known_bounds: Option<emath::Rect>,
+}
+
+impl MapStatistics {
+    /// Computes the statistics of a map image with the value interpretation of `meta`.
+    /// `original_has_alpha` tells if the source image had an alpha channel.
+    pub fn compute(meta: &Meta, img: &DynamicImage, original_has_alpha: bool) -> MapStatistics {
+        let mut stats = MapStatistics {
+            size: (img.width(), img.height()),
+            resolution: meta.resolution,
+            histogram: Histogram::from_image(img),
+            free: 0,
+            occupied: 0,
+            intermediate: 0,
+            unknown: 0,
+            known_pixel_bounds: None,
+            known_bounds: None,
+        };
+        // Known pixel bounds as (min x, min y, max x, max y).
+        let mut pixel_bounds: Option<(u32, u32, u32, u32)> = None;
+        meta.value_interpretation
+            .for_each_occupancy(img, original_has_alpha, |x, y, occupancy| {
+                match occupancy {
+                    TRINARY_UNKNOWN => {
+                        stats.unknown += 1;
+                        return;
+                    }
+                    TRINARY_FREE => stats.free += 1,
+                    TRINARY_OCCUPIED => stats.occupied += 1,
+                    _ => stats.intermediate += 1,
+                }
+                pixel_bounds = Some(match pixel_bounds {
+                    None => (x, y, x, y),
+                    Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+                });
+            });
+
+        stats.known_pixel_bounds = pixel_bounds.map(|(x0, y0, x1, y1)| PixelRect {
+            x: x0 as i64,
+            y: y0 as i64,
+            width: x1 - x0 + 1,
+            height: y1 - y0 + 1,
+        });
+        stats.known_bounds = pixel_bounds.map(|(x0, y0, x1, y1)| {
+            let height = img.height() as f32;
+            // Pixel corners, the max pixel extends by one.
+            let corners = [(x0, y0), (x1 + 1, y0), (x0, y1 + 1), (x1 + 1, y1 + 1)]
+                .map(|(x, y)| meta.pixel_to_map(emath::pos2(x as f32, y as f32), height));
+            emath::Rect::from_points(&corners)
+        });
+        stats
+    }
+
+    pub fn num_cells(&self) -> u64 {
+        self.size.0 as u64 * self.size.1 as u64
+    }
+
+    /// Area of a number of cells in square meters.
+    pub fn area(&self, num_cells: u64) -> f64 {
+        num_cells as f64 * (self.resolution as f64).powi(2)
+    }
+
+    /// Fraction of known cells, in `[0, 1]`.
+    pub fn known_fraction(&self) -> f64 {
+        match self.num_cells() {
+            0 => 0.,
+            n => (n - self.unknown) as f64 / n as f64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Luma, Rgb, RgbImage};
+
+    #[test]
+    fn counts_and_bounds() {
+        // 4x3 map: unknown border, free pixels and one occupied pixel.
+        let mut img = GrayImage::from_pixel(4, 3, Luma([205]));
+        img.put_pixel(1, 1, Luma([254]));
+        img.put_pixel(2, 1, Luma([0]));
+        let mut meta = Meta::new(
+            "map.png".into(),
+            "map.yaml".into(),
+            0.5,
+            emath::vec2(1., 2.),
+            emath::Rot2::IDENTITY,
+        );
+        meta.value_interpretation.free = 0.196;
+        meta.value_interpretation.occupied = 0.65;
+
+        let stats = MapStatistics::compute(&meta, &DynamicImage::ImageLuma8(img), false);
+        assert_eq!((stats.free, stats.occupied, stats.unknown), (1, 1, 10));
+        assert_eq!(stats.intermediate, 0);
+        assert_eq!(stats.area(stats.free), 0.25);
+        assert_eq!(stats.histogram.counts[205], 10);
+        assert_eq!(stats.histogram.bin_value(255), 255.);
+
+        // Known pixels are x in 1..3 of the middle row y = 1.
+        let bounds = stats.known_bounds.unwrap();
+        assert_eq!(bounds.min, emath::pos2(1.5, 2.5));
+        assert_eq!(bounds.max, emath::pos2(2.5, 3.));
+    }
+
+    #[test]
+    fn rgb_map_has_no_alpha_quirk() {
+        // An opaque dark gray pixel is occupied, but would be unknown if alpha
+        // was averaged into the color channels like for images with alpha.
+        let img = RgbImage::from_pixel(1, 1, Rgb([60, 60, 60]));
+        let mut meta = Meta::new(
+            "map.png".into(),
+            "map.yaml".into(),
+            0.5,
+            emath::Vec2::ZERO,
+            emath::Rot2::IDENTITY,
+        );
+        meta.value_interpretation.free = 0.196;
+        meta.value_interpretation.occupied = 0.65;
+
+        // The GUI passes the RGBA8 copy of the image together with the original alpha flag.
+        let rgba = DynamicImage::ImageRgb8(img).to_rgba8().into();
+        let stats = MapStatistics::compute(&meta, &rgba, false);
+        assert_eq!((stats.occupied, stats.unknown), (1, 0));
+        let stats = MapStatistics::compute(&meta, &rgba, true);
+        assert_eq!((stats.occupied, stats.unknown), (0, 1));
+    }
+}
@@ -0,0 +1,246 @@
+//! Conversions between WGS84 geographic coordinates and UTM.
+//!
+//! Implements the Transverse Mercator series of Snyder
+//! ("Map Projections - A Working Manual", USGS 1987), which is accurate
+//! to well below a millimeter inside of a UTM zone.
+//! The special zones around Norway and Svalbard are not considered.
+
+use serde::{Deserialize, Serialize};
+
+const WGS84_A: f64 = 6_378_137.;
+const WGS84_F: f64 = 1. / 298.257_223_563;
+const UTM_K0: f64 = 0.9996;
+const FALSE_EASTING: f64 = 500_000.;
+const FALSE_NORTHING_SOUTH: f64 = 10_000_000.;
+
+/// Geographic WGS84 coordinate in degrees.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Wgs84 {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// Projected UTM coordinate in meters.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Utm {
+    /// UTM zone number in 1..=60.
+    pub zone: u8,
+    /// Whether the coordinate is on the northern hemisphere.
+    pub north: bool,
+    pub easting: f64,
+    pub northing: f64,
+}
+
+impl Default for Utm {
+    fn default() -> Self {
+        Utm {
+            zone: 32,
+            north: true,
+            easting: FALSE_EASTING,
+            northing: 0.,
+        }
+    }
+}
+
+/// Returns the standard UTM zone number for a longitude in degrees.
+pub fn zone_for_longitude(longitude: f64) -> u8 {
+    let zone = ((longitude + 180.) / 6.).floor() as i32 + 1;
+    zone.clamp(1, 60) as u8
+}
+
+fn central_meridian(zone: u8) -> f64 {
+    (zone as f64 - 1.) * 6. - 180. + 3.
+}
+
+fn eccentricity_squared() -> f64 {
+    WGS84_F * (2. - WGS84_F)
+}
+
+/// Meridian arc length from the equator to `phi` (radians).
+fn meridian_arc(phi: f64) -> f64 {
+    let e2 = eccentricity_squared();
+    let e4 = e2 * e2;
+    let e6 = e4 * e2;
+    WGS84_A
+        * ((1. - e2 / 4. - 3. * e4 / 64. - 5. * e6 / 256.) * phi
+            - (3. * e2 / 8. + 3. * e4 / 32. + 45. * e6 / 1024.) * (2. * phi).sin()
+            + (15. * e4 / 256. + 45. * e6 / 1024.) * (4. * phi).sin()
+            - (35. * e6 / 3072.) * (6. * phi).sin())
+}
+
+impl Wgs84 {
+    pub fn new(latitude: f64, longitude: f64) -> Wgs84 {
+        Wgs84 {
+            latitude,
+            longitude,
+        }
+    }
+
+    /// Projects the coordinate into its standard UTM zone.
+    pub fn to_utm(&self) -> Utm {
+        self.to_utm_zone(zone_for_longitude(self.longitude), self.latitude >= 0.)
+    }
+
+    /// Projects the coordinate into a given UTM zone and hemisphere.
+    /// Useful to keep a consistent zone for maps close to a zone border.
+    pub fn to_utm_zone(&self, zone: u8, north: bool) -> Utm {
+        let e2 = eccentricity_squared();
+        let ep2 = e2 / (1. - e2);
+        let phi = self.latitude.to_radians();
+        let lambda = self.longitude.to_radians();
+        let lambda0 = central_meridian(zone).to_radians();
+
+        let n = WGS84_A / (1. - e2 * phi.sin().powi(2)).sqrt();
+        let t = phi.tan().powi(2);
+        let c = ep2 * phi.cos().powi(2);
+        let a = phi.cos() * (lambda - lambda0);
+        let m = meridian_arc(phi);
+
+        let easting = UTM_K0
+            * n
+            * (a + (1. - t + c) * a.powi(3) / 6.
+                + (5. - 18. * t + t * t + 72. * c - 58. * ep2) * a.powi(5) / 120.)
+            + FALSE_EASTING;
+        let mut northing = UTM_K0
+            * (m + n
+                * phi.tan()
+                * (a * a / 2.
+                    + (5. - t + 9. * c + 4. * c * c) * a.powi(4) / 24.
+                    + (61. - 58. * t + t * t + 600. * c - 330. * ep2) * a.powi(6) / 720.));
+        if !north {
+            northing += FALSE_NORTHING_SOUTH;
+        }
+
+        Utm {
+            zone,
+            north,
+            easting,
+            northing,
+        }
+    }
+}
+
+impl Utm {
+    /// Converts the projected coordinate back to WGS84.
+    pub fn to_wgs84(&self) -> Wgs84 {
+        let e2 = eccentricity_squared();
+        let ep2 = e2 / (1. - e2);
+        let x = self.easting - FALSE_EASTING;
+        let y = if self.north {
+            self.northing
+        } else {
+            self.northing - FALSE_NORTHING_SOUTH
+        };
+
+        let m = y / UTM_K0;
+        let mu = m / (WGS84_A * (1. - e2 / 4. - 3. * e2 * e2 / 64. - 5. * e2.powi(3) / 256.));
+        let e1 = (1. - (1. - e2).sqrt()) / (1. + (1. - e2).sqrt());
+        let phi1 = mu
+            + (3. * e1 / 2. - 27. * e1.powi(3) / 32.) * (2. * mu).sin()
+            + (21. * e1 * e1 / 16. - 55. * e1.powi(4) / 32.) * (4. * mu).sin()
+            + (151. * e1.powi(3) / 96.) * (6. * mu).sin()
+            + (1097. * e1.powi(4) / 512.) * (8. * mu).sin();
+
+        let sin_phi1 = phi1.sin();
+        let n1 = WGS84_A / (1. - e2 * sin_phi1 * sin_phi1).sqrt();
+        let t1 = phi1.tan().powi(2);
+        let c1 = ep2 * phi1.cos().powi(2);
+        let r1 = WGS84_A * (1. - e2) / (1. - e2 * sin_phi1 * sin_phi1).powf(1.5);
+        let d = x / (n1 * UTM_K0);
+
+        let phi = phi1
+            - (n1 * phi1.tan() / r1)
+                * (d * d / 2.
+                    - (5. + 3. * t1 + 10. * c1 - 4. * c1 * c1 - 9. * ep2) * d.powi(4) / 24.
+                    + (61. + 90. * t1 + 298. * c1 + 45. * t1 * t1 - 252. * ep2 - 3. * c1 * c1)
+                        * d.powi(6)
+                        / 720.);
+        let lambda = (d - (1. + 2. * t1 + c1) * d.powi(3) / 6.
+            + (5. - 2. * c1 + 28. * t1 - 3. * c1 * c1 + 8. * ep2 + 24. * t1 * t1) * d.powi(5)
+                / 120.)
+            / phi1.cos();
+
+        Wgs84 {
+            latitude: phi.to_degrees(),
+            longitude: central_meridian(self.zone) + lambda.to_degrees(),
+        }
+    }
+
+    /// EPSG code of the WGS84 / UTM zone of this coordinate.
+    pub fn epsg_code(&self) -> u16 {
+        if self.north {
+            32600 + self.zone as u16
+        } else {
+            32700 + self.zone as u16
+        }
+    }
+
+    /// Creates a coordinate in the WGS84 / UTM zone of an EPSG code, inverse of [`Utm::epsg_code`].
+    /// Returns `None` for codes of other coordinate systems.
+    pub fn from_epsg_code(epsg_code: u16, easting: f64, northing: f64) -> Option<Utm> {
+        let (north, zone) = match epsg_code {
+            32601..=32660 => (true, epsg_code - 32600),
+            32701..=32760 => (false, epsg_code - 32700),
+            _ => return None,
+        };
+        Some(Utm {
+            zone: zone as u8,
+            north,
+            easting,
+            northing,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn central_meridian_on_equator() {
+        let utm = Wgs84::new(0., 9.).to_utm();
+        assert_eq!(utm.zone, 32);
+        assert!(utm.north);
+        assert!((utm.easting - FALSE_EASTING).abs() < 1e-6);
+        assert!(utm.northing.abs() < 1e-6);
+    }
+
+    #[test]
+    fn meridian_distance() {
+        // Scaled meridian arc length from the equator to 45°N.
+        let utm = Wgs84::new(45., 3.).to_utm();
+        assert_eq!(utm.zone, 31);
+        assert!((utm.northing - UTM_K0 * 4_984_944.378).abs() < 0.01);
+    }
+
+    #[test]
+    fn roundtrip() {
+        for (lat, lon) in [
+            (48.137, 11.575),
+            (-33.857, 151.215),
+            (64.1, -21.9),
+            (0.5, -0.5),
+        ] {
+            let utm = Wgs84::new(lat, lon).to_utm();
+            assert_eq!(utm.north, lat >= 0.);
+            let wgs84 = utm.to_wgs84();
+            assert!((wgs84.latitude - lat).abs() < 1e-8);
+            assert!((wgs84.longitude - lon).abs() < 1e-8);
+        }
+    }
+
+    #[test]
+    fn epsg_codes() {
+        assert_eq!(Wgs84::new(48., 11.).to_utm().epsg_code(), 32632);
+        assert_eq!(Wgs84::new(-33., 151.).to_utm().epsg_code(), 32756);
+        for utm in [
+            Wgs84::new(48., 11.).to_utm(),
+            Wgs84::new(-33., 151.).to_utm(),
+        ] {
+            let from_code = Utm::from_epsg_code(utm.epsg_code(), utm.easting, utm.northing);
+            assert_eq!(from_code, Some(utm));
+        }
+        assert_eq!(Utm::from_epsg_code(32600, 0., 0.), None);
+        assert_eq!(Utm::from_epsg_code(3857, 0., 0.), None);
+    }
+}
@@ -1,14 +1,28 @@
 //! Color map implementations.
-//! Includes reimplementations of the classic RViz colormaps for occupancy grids.
+//! Includes reimplementations of the classic RViz colormaps for occupancy grids,
+//! perceptual gradients for continuous data and user-defined lookup tables.
+
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::{Arc, PoisonError, RwLock};
 
 use image::Rgba;
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 use strum_macros::Display;
 
+use crate::error::{Error, Result};
+
 /// Trait for color mapping from cell values to RGBA colors.
 pub trait ValueColorMap {
     fn map(&self, value: u8) -> Rgba<u8>;
+
+    /// Maps a normalized value in `[0, 1]` to the full range of the colormap.
+    /// Used for images with more than 8 bits per channel.
+    fn map_normalized(&self, value: f32) -> Rgba<u8> {
+        self.map((value.clamp(0., 1.) * 255.).round() as u8)
+    }
 }
 
 /// Color map options. Includes the classic RViz colormaps.
@@ -27,19 +41,82 @@ pub enum ColorMap {
     /// An alternative costmap color map with less screaming colors.
     #[strum(to_string = "Cool Costmap")]
     CoolCostmap,
+    /// Perceptually uniform gradient from dark blue over green to yellow.
+    #[strum(to_string = "Viridis")]
+    Viridis,
+    /// Rainbow-like gradient with improved perceptual ordering.
+    #[strum(to_string = "Turbo")]
+    Turbo,
+    /// Perceptually uniform gradient from black over red to light yellow.
+    #[strum(to_string = "Inferno")]
+    Inferno,
+    /// Grayscale gradient from white to black.
+    #[strum(to_string = "Grayscale inverted")]
+    GrayscaleInverted,
+    /// User-defined lookup table, see [`register_custom_colormap`].
+    #[strum(to_string = "Custom")]
+    Custom(CustomColorMapId),
+}
+
+/// Handle of a user-defined colormap that is registered at runtime.
+/// Derived from the colormap name to stay valid across sessions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CustomColorMapId(u32);
+
+impl CustomColorMapId {
+    fn from_name(name: &str) -> CustomColorMapId {
+        // 32-bit FNV-1a, stable across platforms and Rust versions.
+        let hash = name.bytes().fold(0x811c_9dc5_u32, |hash, byte| {
+            (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+        });
+        CustomColorMapId(hash)
+    }
 }
 
 impl ColorMap {
     /// Gives access to the corresponding color map trait implementations
     /// that are implemented in this module.
-    pub fn get(&self) -> &dyn ValueColorMap {
-        match self {
+    /// Custom colormaps that are not registered fall back to raw colors.
+    pub fn get(&self) -> ColorMapRef {
+        ColorMapRef::Builtin(match self {
             ColorMap::RvizMap => &*RVIZ_MAP,
             ColorMap::RvizCostmap => &*RVIZ_COSTMAP,
             ColorMap::Raw => &*RAW,
             ColorMap::CoolCostmap => &*COOL_COSTMAP,
+            ColorMap::Viridis => &*VIRIDIS,
+            ColorMap::Turbo => &*TURBO,
+            ColorMap::Inferno => &*INFERNO,
+            ColorMap::GrayscaleInverted => &*GRAYSCALE_INVERTED,
+            ColorMap::Custom(id) => match custom_colormap(*id) {
+                Some(colormap) => return ColorMapRef::Custom(colormap),
+                None => &*RAW,
+            },
+        })
+    }
+
+    /// Name for display, including the names of custom colormaps.
+    pub fn display_name(&self) -> String {
+        match self {
+            ColorMap::Custom(id) => custom_colormap(*id).map_or_else(
+                || "Custom (not loaded)".to_string(),
+                |colormap| colormap.name.clone(),
+            ),
+            _ => self.to_string(),
         }
     }
+
+    /// Whether the colormap is a gradient for continuous data,
+    /// as opposed to the occupancy grid colormaps.
+    pub fn is_continuous(&self) -> bool {
+        matches!(
+            self,
+            ColorMap::Viridis
+                | ColorMap::Turbo
+                | ColorMap::Inferno
+                | ColorMap::GrayscaleInverted
+                | ColorMap::Custom(_)
+        )
+    }
 }
 
 lazy_static! {
@@ -47,6 +124,61 @@ lazy_static! {
     static ref RVIZ_COSTMAP: CostmapColors = CostmapColors::new();
     static ref RAW: Raw = Raw;
     static ref COOL_COSTMAP: CoolCostmapColors = CoolCostmapColors::new();
+    static ref VIRIDIS: GradientColors = GradientColors::new(viridis);
+    static ref TURBO: GradientColors = GradientColors::new(turbo);
+    static ref INFERNO: GradientColors = GradientColors::new(inferno);
+    static ref GRAYSCALE_INVERTED: GradientColors = GradientColors::new(grayscale_inverted);
+    static ref CUSTOM_COLORMAPS: RwLock<HashMap<CustomColorMapId, Arc<LookupColorMap>>> =
+        RwLock::new(HashMap::new());
+}
+
+/// Colormap implementation returned by [`ColorMap::get`].
+/// Keeps a custom colormap alive while it's used, even if it's replaced meanwhile.
+pub enum ColorMapRef {
+    Builtin(&'static dyn ValueColorMap),
+    Custom(Arc<LookupColorMap>),
+}
+
+impl Deref for ColorMapRef {
+    type Target = dyn ValueColorMap;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            ColorMapRef::Builtin(colormap) => *colormap,
+            ColorMapRef::Custom(colormap) => colormap.as_ref(),
+        }
+    }
+}
+
+/// Registers a user-defined colormap and returns the corresponding [`ColorMap`].
+/// A colormap with the same name is replaced.
+pub fn register_custom_colormap(colormap: LookupColorMap) -> ColorMap {
+    let id = CustomColorMapId::from_name(&colormap.name);
+    CUSTOM_COLORMAPS
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(id, Arc::new(colormap));
+    ColorMap::Custom(id)
+}
+
+/// Returns all registered custom colormaps with their names, sorted by name.
+pub fn custom_colormaps() -> Vec<(ColorMap, String)> {
+    let mut colormaps: Vec<_> = CUSTOM_COLORMAPS
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .map(|(id, colormap)| (ColorMap::Custom(*id), colormap.name.clone()))
+        .collect();
+    colormaps.sort_by(|a, b| a.1.cmp(&b.1));
+    colormaps
+}
+
+fn custom_colormap(id: CustomColorMapId) -> Option<Arc<LookupColorMap>> {
+    CUSTOM_COLORMAPS
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&id)
+        .cloned()
 }
 
 struct RvizMapColors {
@@ -161,6 +293,258 @@ impl ValueColorMap for CoolCostmapColors {
     }
 }
 
+fn to_rgba(rgb: [f32; 3]) -> Rgba<u8> {
+    let [r, g, b] = rgb.map(|c| (c.clamp(0., 1.) * 255.).round() as u8);
+    Rgba([r, g, b, 255])
+}
+
+/// Evaluates a polynomial per color channel, coefficients in ascending order.
+fn polynomial(coefficients: &[[f32; 3]], t: f32) -> [f32; 3] {
+    coefficients.iter().rev().fold([0.; 3], |acc, c| {
+        [acc[0] * t + c[0], acc[1] * t + c[1], acc[2] * t + c[2]]
+    })
+}
+
+// Polynomial fits of the matplotlib colormaps by Matt Zucker (CC0).
+fn viridis(t: f32) -> Rgba<u8> {
+    to_rgba(polynomial(
+        &[
+            [0.277_727_33, 0.005_407_344_5, 0.334_099_8],
+            [0.105_093_04, 1.404_613_5, 1.384_590_2],
+            [-0.330_861_83, 0.214_847_56, 0.095_095_16],
+            [-4.634_230_5, -5.799_101, -19.332_441],
+            [6.228_27, 14.179_933, 56.690_55],
+            [4.776_385, -13.745_145, -65.353_03],
+            [-5.435_456, 4.645_852_6, 26.312_435],
+        ],
+        t,
+    ))
+}
+
+fn inferno(t: f32) -> Rgba<u8> {
+    to_rgba(polynomial(
+        &[
+            [0.000_218_940_37, 0.001_651_004_6, -0.019_480_898],
+            [0.106_513_42, 0.563_956_44, 3.932_712_4],
+            [11.602_493, -3.972_854, -15.942_394],
+            [-41.703_995, 17.436_4, 44.354_145],
+            [77.162_94, -33.402_36, -81.807_31],
+            [-71.319_43, 32.626_064, 73.209_52],
+            [25.131_126, -12.242_669, -23.070_326],
+        ],
+        t,
+    ))
+}
+
+// Polynomial approximation of Turbo by Anton Mikhailov (Apache-2.0).
+fn turbo(t: f32) -> Rgba<u8> {
+    to_rgba(polynomial(
+        &[
+            [0.135_721_38, 0.091_402_61, 0.106_673_3],
+            [4.615_392_6, 2.194_188_4, 12.641_946],
+            [-42.660_323, 4.842_966_6, -60.582_05],
+            [132.131_08, -14.185_033, 110.362_77],
+            [-152.942_39, 4.277_298_6, -89.903_11],
+            [59.286_38, 2.829_566, 27.348_25],
+        ],
+        t,
+    ))
+}
+
+fn grayscale_inverted(t: f32) -> Rgba<u8> {
+    to_rgba([1. - t; 3])
+}
+
+/// Continuous gradient given by a function on `[0, 1]`,
+/// with a precomputed lookup table for `u8` values.
+struct GradientColors {
+    function: fn(f32) -> Rgba<u8>,
+    mapped: [Rgba<u8>; 256],
+}
+
+impl GradientColors {
+    fn new(function: fn(f32) -> Rgba<u8>) -> Self {
+        let mut mapped: [Rgba<u8>; 256] = [Rgba([0, 0, 0, 255]); 256];
+        for (i, value) in mapped.iter_mut().enumerate() {
+            *value = function(i as f32 / 255.);
+        }
+        GradientColors { function, mapped }
+    }
+}
+
+impl ValueColorMap for GradientColors {
+    fn map(&self, value: u8) -> Rgba<u8> {
+        self.mapped[value as usize]
+    }
+
+    fn map_normalized(&self, value: f32) -> Rgba<u8> {
+        (self.function)(value.clamp(0., 1.))
+    }
+}
+
+#[derive(Deserialize)]
+struct LookupYaml {
+    name: Option<String>,
+    stops: Vec<Vec<f32>>,
+}
+
+/// User-defined colormap with linear interpolation between color stops.
+///
+/// Can be loaded from YAML or CSV files. Each stop is a row of
+/// `value, r, g, b[, a]` with the value in `[0, 1]` and colors in `0..=255`.
+///
+/// YAML:
+/// ```yaml
+/// name: elevation  # optional, defaults to the file name
+/// stops:
+///   - [0.0, 0, 0, 128]
+///   - [0.5, 0, 200, 0]
+///   - [1.0, 255, 255, 255]
+/// ```
+///
+/// CSV (an optional header row is skipped, `#` starts a comment):
+/// ```text
+/// value,r,g,b,a
+/// 0.0,0,0,128,255
+/// 1.0,255,255,255,255
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct LookupColorMap {
+    pub name: String,
+    stops: Vec<(f32, Rgba<u8>)>,
+    mapped: Vec<Rgba<u8>>,
+}
+
+impl LookupColorMap {
+    /// Creates a colormap from at least two `(value, color)` stops.
+    /// Returns `None` if there are less than two stops or values are not finite.
+    pub fn new(name: String, mut stops: Vec<(f32, Rgba<u8>)>) -> Option<LookupColorMap> {
+        if stops.len() < 2 || stops.iter().any(|(value, _)| !value.is_finite()) {
+            return None;
+        }
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut colormap = LookupColorMap {
+            name,
+            stops,
+            mapped: Vec::new(),
+        };
+        colormap.mapped = (0..=255)
+            .map(|i| colormap.interpolate(i as f32 / 255.))
+            .collect();
+        Some(colormap)
+    }
+
+    /// Loads a colormap from a `.yaml` / `.yml` or `.csv` file.
+    pub fn load_from_file(path: &Path) -> Result<LookupColorMap> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| Error::io(format!("Cannot read colormap {path:?}"), e))?;
+        let file_name = path
+            .file_stem()
+            .map_or_else(String::new, |s| s.to_string_lossy().to_string());
+        let is_yaml = path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("yaml") || e.eq_ignore_ascii_case("yml"));
+
+        let (name, rows) = if is_yaml {
+            let yaml = serde_yaml_ng::from_str::<LookupYaml>(&content)
+                .map_err(|e| Error::yaml(format!("Cannot parse colormap {path:?}"), e))?;
+            (yaml.name.unwrap_or(file_name), yaml.stops)
+        } else {
+            (file_name, parse_csv(&content))
+        };
+
+        let stops = rows
+            .iter()
+            .map(|row| parse_stop(row))
+            .collect::<Option<Vec<_>>>();
+        stops
+            .and_then(|stops| LookupColorMap::new(name, stops))
+            .ok_or_else(|| {
+                Error::io(
+                    format!("Invalid colormap {path:?}"),
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "expected at least two rows of: value, r, g, b[, a]",
+                    ),
+                )
+            })
+    }
+
+    /// The color stops of the colormap, sorted by value.
+    pub fn stops(&self) -> &[(f32, Rgba<u8>)] {
+        &self.stops
+    }
+
+    fn interpolate(&self, value: f32) -> Rgba<u8> {
+        let value = value.clamp(0., 1.);
+        let (first, last) = (self.stops[0], self.stops[self.stops.len() - 1]);
+        let Some(upper) = self.stops.iter().position(|(v, _)| *v >= value) else {
+            return last.1;
+        };
+        if upper == 0 {
+            return first.1;
+        }
+        let (v0, c0) = self.stops[upper - 1];
+        let (v1, c1) = self.stops[upper];
+        let t = if v1 > v0 {
+            (value - v0) / (v1 - v0)
+        } else {
+            1.
+        };
+        Rgba(std::array::from_fn(|i| {
+            (c0[i] as f32 + t * (c1[i] as f32 - c0[i] as f32)).round() as u8
+        }))
+    }
+}
+
+/// Parses CSV rows of numbers, skipping comments, empty lines and a header.
+fn parse_csv(content: &str) -> Vec<Vec<f32>> {
+    content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let row = line
+                .split(',')
+                .map(|field| field.trim().parse::<f32>())
+                .collect::<std::result::Result<Vec<_>, _>>();
+            match row {
+                Ok(row) => Some(row),
+                // Allow a header in the first row, otherwise keep it to fail later.
+                Err(_) if i == 0 => None,
+                Err(_) => Some(Vec::new()),
+            }
+        })
+        .collect()
+}
+
+fn parse_stop(row: &[f32]) -> Option<(f32, Rgba<u8>)> {
+    let channel = |i: usize| row.get(i).map(|c| c.clamp(0., 255.).round() as u8);
+    match row.len() {
+        4 | 5 => Some((
+            row[0],
+            Rgba([
+                channel(1)?,
+                channel(2)?,
+                channel(3)?,
+                channel(4).unwrap_or(255),
+            ]),
+        )),
+        _ => None,
+    }
+}
+
+impl ValueColorMap for LookupColorMap {
+    fn map(&self, value: u8) -> Rgba<u8> {
+        self.mapped[value as usize]
+    }
+
+    fn map_normalized(&self, value: f32) -> Rgba<u8> {
+        self.interpolate(value)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -197,4 +581,72 @@ mod tests {
         assert_eq!(palette.map(100), Rgba([100, 100, 100, 255]));
         assert_eq!(palette.map(255), Rgba([255, 255, 255, 255]));
     }
+
+    #[test]
+    fn test_gradient_endpoints() {
+        // Matplotlib's viridis starts at #440154 and ends at #fde725.
+        let close = |a: Rgba<u8>, b: Rgba<u8>| (0..4).all(|i| a[i].abs_diff(b[i]) <= 4);
+        assert!(close(VIRIDIS.map(0), Rgba([68, 1, 84, 255])));
+        assert!(close(VIRIDIS.map(255), Rgba([253, 231, 37, 255])));
+        assert!(close(INFERNO.map(255), Rgba([252, 255, 164, 255])));
+        assert_eq!(GRAYSCALE_INVERTED.map(0), Rgba([255, 255, 255, 255]));
+        assert_eq!(GRAYSCALE_INVERTED.map_normalized(1.), Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_lookup_colormap_csv() {
+        let rows = parse_csv("value, r, g, b\n# comment\n1.0, 200, 100, 0\n0.0, 0, 0, 0 # black\n");
+        let stops = rows
+            .iter()
+            .map(|row| parse_stop(row))
+            .collect::<Option<Vec<_>>>();
+        let colormap = LookupColorMap::new("test".to_string(), stops.unwrap()).unwrap();
+        assert_eq!(colormap.stops()[0].0, 0.);
+        assert_eq!(colormap.map(0), Rgba([0, 0, 0, 255]));
+        assert_eq!(colormap.map_normalized(0.5), Rgba([100, 50, 0, 255]));
+        assert_eq!(colormap.map(255), Rgba([200, 100, 0, 255]));
+
+        // Malformed rows are rejected.
+        assert_eq!(parse_stop(&[0., 1., 2.]), None);
+        assert_eq!(parse_csv("0.0, 0, 0, 0\nfoo\n")[1], Vec::<f32>::new());
+    }
+
+    #[test]
+    fn test_custom_colormap_registry() {
+        let stops = vec![(0., Rgba([0, 0, 0, 255])), (1., Rgba([0, 0, 255, 255]))];
+        let colormap = LookupColorMap::new("blues".to_string(), stops).unwrap();
+        let registered = register_custom_colormap(colormap);
+        assert_eq!(
+            registered,
+            ColorMap::Custom(CustomColorMapId::from_name("blues"))
+        );
+        assert_eq!(registered.display_name(), "blues");
+        assert_eq!(registered.get().map(255), Rgba([0, 0, 255, 255]));
+        assert!(custom_colormaps().contains(&(registered, "blues".to_string())));
+    }
+
+    #[test]
+    fn test_custom_colormap_is_replaced() {
+        let reds = |max| vec![(0., Rgba([0, 0, 0, 255])), (1., Rgba([max, 0, 0, 255]))];
+        let colormap = LookupColorMap::new("reds".to_string(), reds(255)).unwrap();
+        let registered = register_custom_colormap(colormap);
+        let in_use = registered.get();
+
+        let colormap = LookupColorMap::new("reds".to_string(), reds(100)).unwrap();
+        assert_eq!(register_custom_colormap(colormap), registered);
+        assert_eq!(registered.get().map(255), Rgba([100, 0, 0, 255]));
+        // A colormap that is still in use stays valid until it's dropped.
+        assert_eq!(in_use.map(255), Rgba([255, 0, 0, 255]));
+        let ColorMapRef::Custom(replaced) = in_use else {
+            panic!("expected a custom colormap");
+        };
+        assert_eq!(Arc::strong_count(&replaced), 1);
+        assert_eq!(
+            custom_colormaps()
+                .iter()
+                .filter(|(_, name)| name == "reds")
+                .count(),
+            1
+        );
+    }
 }
@@ -6,18 +6,18 @@
 
 use serde::{Deserialize, Serialize};
 
-const TRINARY_FREE: u8 = 0;
-const TRINARY_OCCUPIED: u8 = 100;
-const TRINARY_UNKNOWN: u8 = 255;
+pub(crate) const TRINARY_FREE: u8 = 0;
+pub(crate) const TRINARY_OCCUPIED: u8 = 100;
+pub(crate) const TRINARY_UNKNOWN: u8 = 255;
 
 const MAP_SERVER_FREE_DEFAULT: f32 = 0.196;
 const MAP_SERVER_OCCUPIED_DEFAULT: f32 = 0.65;
 
-use image::{DynamicImage, Rgba};
+use image::{ColorType, DynamicImage, ImageBuffer, Pixel, Primitive, Rgba, RgbaImage};
 use imageproc::{integral_image::ArrayData, map::map_colors_mut};
 
 use crate::meta::MetaYaml;
-use crate::value_colormap::ColorMap;
+use crate::value_colormap::{ColorMap, ValueColorMap};
 
 /// Value interpretation modes.
 /// Corresponds to the optional `mode` field in the map metadata.
@@ -47,6 +47,96 @@ pub enum Quirks {
     Ros2MapServer,
 }
 
+/// Range of raw pixel values that is mapped to the full value range,
+/// for images with more than 8 bits per channel (e.g. 16-bit elevation data).
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ValueRange {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl ValueRange {
+    /// Minimum and maximum of the pixel values of an image, ignoring NaN values.
+    /// Color channels are averaged per pixel, alpha is ignored.
+    /// Returns `None` if the image contains no valid values.
+    pub fn from_image(img: &DynamicImage) -> Option<ValueRange> {
+        fn min_max<P>(buffer: &ImageBuffer<P, Vec<P::Subpixel>>) -> Option<ValueRange>
+        where
+            P: Pixel,
+            P::Subpixel: Into<f32>,
+        {
+            buffer
+                .pixels()
+                .map(|pixel| color_average(pixel.channels(), P::HAS_ALPHA))
+                .filter(|value| !value.is_nan())
+                .fold(None, |range, value| match range {
+                    None => Some(ValueRange {
+                        min: value,
+                        max: value,
+                    }),
+                    Some(ValueRange { min, max }) => Some(ValueRange {
+                        min: min.min(value),
+                        max: max.max(value),
+                    }),
+                })
+        }
+
+        match img {
+            DynamicImage::ImageLuma16(buffer) => min_max(buffer),
+            DynamicImage::ImageLumaA16(buffer) => min_max(buffer),
+            DynamicImage::ImageRgb16(buffer) => min_max(buffer),
+            DynamicImage::ImageRgba16(buffer) => min_max(buffer),
+            DynamicImage::ImageRgb32F(buffer) => min_max(buffer),
+            DynamicImage::ImageRgba32F(buffer) => min_max(buffer),
+            _ => min_max(&img.to_rgba8()),
+        }
+    }
+
+    /// Maps a value into `[0, 1]`, clamping values outside of the range.
+    /// Returns `None` for NaN values.
+    pub fn normalize(&self, value: f32) -> Option<f32> {
+        if value.is_nan() {
+            return None;
+        }
+        if self.max <= self.min {
+            return Some(if value >= self.max { 1. } else { 0. });
+        }
+        Some(((value - self.min) / (self.max - self.min)).clamp(0., 1.))
+    }
+}
+
+/// Average of the color channels of a pixel, i.e. excluding alpha.
+pub(crate) fn color_average<T: Into<f32> + Copy>(channels: &[T], has_alpha: bool) -> f32 {
+    let num_colors = if has_alpha {
+        channels.len() - 1
+    } else {
+        channels.len()
+    };
+    channels[..num_colors]
+        .iter()
+        .map(|&c| c.into())
+        .sum::<f32>()
+        / num_colors as f32
+}
+
+/// Normalized value (see [`ValueRange::normalize`]) and 8-bit alpha of a high depth pixel.
+fn normalize_high_depth<P>(pixel: &P, range: ValueRange) -> (Option<f32>, u8)
+where
+    P: Pixel,
+    P::Subpixel: Into<f32>,
+{
+    let channels = pixel.channels();
+    let max_value: f32 = P::Subpixel::DEFAULT_MAX_VALUE.into();
+    let alpha = match channels.last() {
+        Some(&alpha) if P::HAS_ALPHA => (alpha.into() / max_value * 255.) as u8,
+        _ => 255,
+    };
+    (
+        range.normalize(color_average(channels, P::HAS_ALPHA)),
+        alpha,
+    )
+}
+
 /// Value interpretation with colormap that can be applied to images.
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ValueInterpretation {
@@ -62,6 +152,11 @@ pub struct ValueInterpretation {
     pub quirks: Quirks,
     #[serde(default)]
     pub colormap: ColorMap,
+    /// Value range for images with more than 8 bits per channel.
+    /// Uses the full range of the pixel type if not set,
+    /// i.e. `0..65535` for 16-bit and `0..1` for float images.
+    #[serde(default)]
+    pub range: Option<ValueRange>,
 }
 
 impl Default for ValueInterpretation {
@@ -74,6 +169,7 @@ impl Default for ValueInterpretation {
             explicit_mode: false,
             quirks: Quirks::default(),
             colormap: ColorMap::default(),
+            range: None,
         }
     }
 }
@@ -88,6 +184,7 @@ impl ValueInterpretation {
             explicit_mode: mode.is_some(),
             quirks: Quirks::default(),
             colormap: ColorMap::default(),
+            range: None,
         }
     }
 
@@ -113,11 +210,92 @@ impl ValueInterpretation {
         self
     }
 
+    /// Sets the value range for images with more than 8 bits per channel.
+    pub fn with_range(mut self, range: ValueRange) -> Self {
+        self.range = Some(range);
+        self
+    }
+
     /// Modifies the image according to the value interpretation and colormap.
     ///
     /// The `original_has_alpha` parameter is used to determine if the source
     /// image had an alpha channel. This is necessary for some implementation quirks.
+    ///
+    /// Images with more than 8 bits per channel are normalized with the value range
+    /// and replaced by an RGBA8 image.
     pub fn apply(&self, img: &mut DynamicImage, original_has_alpha: bool) {
+        let mapped = match img {
+            DynamicImage::ImageLuma16(buffer) => self.apply_high_depth(buffer),
+            DynamicImage::ImageLumaA16(buffer) => self.apply_high_depth(buffer),
+            DynamicImage::ImageRgb16(buffer) => self.apply_high_depth(buffer),
+            DynamicImage::ImageRgba16(buffer) => self.apply_high_depth(buffer),
+            DynamicImage::ImageRgb32F(buffer) => self.apply_high_depth(buffer),
+            DynamicImage::ImageRgba32F(buffer) => self.apply_high_depth(buffer),
+            _ => {
+                self.apply_8bit(img, original_has_alpha);
+                return;
+            }
+        };
+        *img = DynamicImage::ImageRgba8(mapped);
+    }
+
+    fn apply_high_depth<P>(&self, buffer: &ImageBuffer<P, Vec<P::Subpixel>>) -> RgbaImage
+    where
+        P: Pixel,
+        P::Subpixel: Into<f32>,
+    {
+        let colormap = self.colormap.get();
+        let range = self.high_depth_range::<P>();
+        RgbaImage::from_fn(buffer.width(), buffer.height(), |x, y| {
+            let (value, alpha) = normalize_high_depth(buffer.get_pixel(x, y), range);
+            self.map_normalized(&*colormap, value, alpha)
+        })
+    }
+
+    /// Value range of high depth pixels, the full range of the type if none is set.
+    fn high_depth_range<P>(&self) -> ValueRange
+    where
+        P: Pixel,
+        P::Subpixel: Into<f32>,
+    {
+        self.range.unwrap_or(ValueRange {
+            min: 0.,
+            max: P::Subpixel::DEFAULT_MAX_VALUE.into(),
+        })
+    }
+
+    /// Interprets and colormaps a normalized value, `None` is treated as unknown.
+    fn map_normalized(
+        &self,
+        colormap: &dyn ValueColorMap,
+        value: Option<f32>,
+        alpha: u8,
+    ) -> Rgba<u8> {
+        match (self.mode, value) {
+            (Mode::Raw, None) => Rgba([0, 0, 0, 0]),
+            (Mode::Raw, Some(value)) => {
+                colormap.map_normalized(if self.negate { 1. - value } else { value })
+            }
+            (Mode::Trinary | Mode::Scale, _) => {
+                colormap.map(self.occupancy_normalized(value, alpha))
+            }
+        }
+    }
+
+    /// Occupancy of a normalized value with the thresholds of the mode, `None` is unknown.
+    fn occupancy_normalized(&self, value: Option<f32>, alpha: u8) -> u8 {
+        match value {
+            Some(value) => {
+                // Same convention as for 8-bit images: dark means occupied.
+                let p = if self.negate { value } else { 1. - value };
+                self.interpret_probability(p, alpha)[0]
+            }
+            None => TRINARY_UNKNOWN,
+        }
+    }
+
+    fn apply_8bit(&self, img: &mut DynamicImage, original_has_alpha: bool) {
+        let colormap = self.colormap.get();
         match self.mode {
             Mode::Raw => {
                 map_colors_mut(img, |mut c| {
@@ -125,19 +303,99 @@ impl ValueInterpretation {
                     if self.negate {
                         c[0] = 255 - c[0];
                     }
-                    self.colormap.get().map(c[0])
+                    colormap.map(c[0])
                 });
             }
             Mode::Trinary | Mode::Scale => {
                 map_colors_mut(img, |c| {
-                    self.colormap
-                        .get()
-                        .map(self.interpret(c, original_has_alpha)[0])
+                    colormap.map(self.interpret(c, original_has_alpha)[0])
                 });
             }
         }
     }
 
+    /// Pixel that is interpreted as unknown in an image of the given color type.
+    /// Channels are normalized to the range of the pixel type, i.e. 1 is 255 for 8-bit
+    /// and 65535 for 16-bit images, float images use NaN.
+    ///
+    /// - Raw: 255, i.e. -1 in the occupancy grid.
+    /// - Trinary: the middle between the free and occupied thresholds.
+    /// - Scale: a transparent pixel, the color type needs an alpha channel.
+    pub fn unknown_pixel(&self, color: ColorType) -> Rgba<f32> {
+        let p = (self.free + self.occupied) / 2.;
+        let middle = if self.negate { p } else { 1. - p };
+        let value = match color {
+            ColorType::Rgb32F | ColorType::Rgba32F => f32::NAN,
+            ColorType::L16 | ColorType::La16 | ColorType::Rgb16 | ColorType::Rgba16 => {
+                let max = u16::MAX as f32;
+                let range = self.range.unwrap_or(ValueRange { min: 0., max });
+                (range.min + middle * (range.max - range.min)) / max
+            }
+            _ => match self.mode {
+                Mode::Raw => 1.,
+                // map_server averages the opaque alpha into the color channels.
+                Mode::Trinary if color.has_alpha() && self.quirks != Quirks::Ros1Wiki => {
+                    ((4. * middle - 1.) / 3.).max(0.)
+                }
+                Mode::Trinary | Mode::Scale => middle,
+            },
+        };
+        let alpha = if self.mode == Mode::Scale { 0. } else { 1. };
+        Rgba([value, value, value, alpha])
+    }
+
+    /// Calls `f` with the position and the occupancy grid value of each pixel,
+    /// i.e. 0 (free) to 100 (occupied) or 255 (unknown) as published by map_server.
+    /// In raw mode the thresholds are applied as in trinary mode.
+    ///
+    /// `original_has_alpha` tells if the source image had an alpha channel, see [`Self::apply`].
+    pub fn for_each_occupancy(
+        &self,
+        img: &DynamicImage,
+        original_has_alpha: bool,
+        mut f: impl FnMut(u32, u32, u8),
+    ) {
+        let interpretation = match self.mode {
+            Mode::Raw => ValueInterpretation {
+                mode: Mode::Trinary,
+                ..*self
+            },
+            Mode::Trinary | Mode::Scale => *self,
+        };
+        match img {
+            DynamicImage::ImageLuma16(buffer) => interpretation.occupancy_high_depth(buffer, f),
+            DynamicImage::ImageLumaA16(buffer) => interpretation.occupancy_high_depth(buffer, f),
+            DynamicImage::ImageRgb16(buffer) => interpretation.occupancy_high_depth(buffer, f),
+            DynamicImage::ImageRgba16(buffer) => interpretation.occupancy_high_depth(buffer, f),
+            DynamicImage::ImageRgb32F(buffer) => interpretation.occupancy_high_depth(buffer, f),
+            DynamicImage::ImageRgba32F(buffer) => interpretation.occupancy_high_depth(buffer, f),
+            _ => {
+                for (x, y, pixel) in img.to_rgba8().enumerate_pixels() {
+                    f(
+                        x,
+                        y,
+                        interpretation.interpret(*pixel, original_has_alpha)[0],
+                    );
+                }
+            }
+        }
+    }
+
+    fn occupancy_high_depth<P>(
+        &self,
+        buffer: &ImageBuffer<P, Vec<P::Subpixel>>,
+        mut f: impl FnMut(u32, u32, u8),
+    ) where
+        P: Pixel,
+        P::Subpixel: Into<f32>,
+    {
+        let range = self.high_depth_range::<P>();
+        for (x, y, pixel) in buffer.enumerate_pixels() {
+            let (value, alpha) = normalize_high_depth(pixel, range);
+            f(x, y, self.occupancy_normalized(value, alpha));
+        }
+    }
+
     fn avg_float(&self, pixel: Rgba<u8>, has_alpha: bool) -> f32 {
         let num_channels = match self.quirks {
             // Nothing documented about alpha averaging in ROS 1 Wiki.
@@ -165,9 +423,11 @@ impl ValueInterpretation {
     }
 
     fn interpret(&self, pixel: Rgba<u8>, has_alpha: bool) -> Rgba<u8> {
-        let p = self.avg_float(pixel, has_alpha);
-        let alpha = pixel[3];
+        self.interpret_probability(self.avg_float(pixel, has_alpha), pixel[3])
+    }
 
+    /// Thresholds an occupancy probability `p` in `[0, 1]` according to the mode.
+    fn interpret_probability(&self, p: f32, alpha: u8) -> Rgba<u8> {
         // In scale mode, any pixel with transparency is considered unknown.
         let scale_unknown = self.mode == Mode::Scale && alpha != 255;
 
@@ -315,4 +575,95 @@ mod tests {
             Rgba([TRINARY_UNKNOWN, TRINARY_UNKNOWN, TRINARY_UNKNOWN, 255])
         );
     }
+
+    #[test]
+    fn value_range() {
+        let range = ValueRange {
+            min: 1000.,
+            max: 3000.,
+        };
+        assert_eq!(range.normalize(2000.), Some(0.5));
+        assert_eq!(range.normalize(0.), Some(0.));
+        assert_eq!(range.normalize(5000.), Some(1.));
+        assert_eq!(range.normalize(f32::NAN), None);
+
+        let img =
+            DynamicImage::ImageLuma16(ImageBuffer::from_raw(3, 1, vec![7, 1000, 300]).unwrap());
+        assert_eq!(
+            ValueRange::from_image(&img),
+            Some(ValueRange {
+                min: 7.,
+                max: 1000.
+            })
+        );
+    }
+
+    #[test]
+    fn raw_16bit_full_range() {
+        let interpretation = ValueInterpretation::new(0.196, 0.65, false, Some(Mode::Raw))
+            .with_colormap(ColorMap::Raw)
+            .with_range(ValueRange {
+                min: 1000.,
+                max: 2000.,
+            });
+        // Values that would all be black when truncated to 8 bits.
+        let mut img =
+            DynamicImage::ImageLuma16(ImageBuffer::from_raw(3, 1, vec![1000, 1500, 2000]).unwrap());
+        interpretation.apply(&mut img, false);
+        assert_eq!(img.color(), image::ColorType::Rgba8);
+        assert_eq!(img.get_pixel(0, 0), Rgba([0, 0, 0, 255]));
+        assert_eq!(img.get_pixel(1, 0), Rgba([128, 128, 128, 255]));
+        assert_eq!(img.get_pixel(2, 0), Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn trinary_float_with_nan() {
+        let interpretation = ValueInterpretation::new(0.196, 0.65, false, Some(Mode::Trinary))
+            .with_colormap(ColorMap::Raw);
+        let values = [1., 0., f32::NAN];
+        let mut img = DynamicImage::ImageRgb32F(ImageBuffer::from_fn(3, 1, |x, _| {
+            image::Rgb([values[x as usize]; 3])
+        }));
+        interpretation.apply(&mut img, false);
+        assert_eq!(
+            img.get_pixel(0, 0),
+            Rgba([TRINARY_FREE, TRINARY_FREE, TRINARY_FREE, 255])
+        );
+        assert_eq!(
+            img.get_pixel(1, 0),
+            Rgba([TRINARY_OCCUPIED, TRINARY_OCCUPIED, TRINARY_OCCUPIED, 255])
+        );
+        assert_eq!(
+            img.get_pixel(2, 0),
+            Rgba([TRINARY_UNKNOWN, TRINARY_UNKNOWN, TRINARY_UNKNOWN, 255])
+        );
+    }
+
+    #[test]
+    fn high_depth_occupancy_matches_rendering() {
+        let values: Vec<u16> = (0..=10)
+            .flat_map(|i| [i * 6000, u16::MAX, i * 6000, 30000])
+            .collect();
+        let image = DynamicImage::ImageLumaA16(ImageBuffer::from_raw(22, 1, values).unwrap());
+        for mode in [Mode::Trinary, Mode::Scale] {
+            let interpretation = ValueInterpretation::new(0.196, 0.65, false, Some(mode))
+                .with_colormap(ColorMap::Raw)
+                .with_range(ValueRange {
+                    min: 6000.,
+                    max: 54000.,
+                });
+            let mut rendered = image.clone();
+            interpretation.apply(&mut rendered, true);
+            let mut num_pixels = 0;
+            interpretation.for_each_occupancy(&image, true, |x, y, occupancy| {
+                assert_eq!(
+                    rendered.get_pixel(x, y)[0],
+                    occupancy,
+                    "{mode:?} ({x}, {y})"
+                );
+                num_pixels += 1;
+            });
+            assert_eq!(num_pixels, 22);
+        }
+    }
 }
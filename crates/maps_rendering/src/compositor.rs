@@ -0,0 +1,449 @@
+//! CPU compositing of images into an RGBA image, without egui UI or textures.
+//!
+//! Uses the same composition rules as the textures that are put into the UI:
+//! placement, pyramid level selection, appearance, tint, texture filter and
+//! alpha blending in draw order. E.g. for headless exports or other tools.
+
+use std::sync::Arc;
+
+use eframe::egui;
+use image::GenericImageView as _;
+use log::error;
+
+use crate::image::apply_appearance;
+use crate::image_pyramid::{AppearanceKey, ImagePyramid};
+use crate::render_options::TextureFilter;
+use crate::texture_request::{ImagePlacement, NO_TINT, flip};
+use maps_io_ros::{MapPose, Meta, ValueInterpretation};
+
+/// Metric area that is rendered, like the grid of the aligned view.
+/// One pixel of the output image corresponds to one point of the grid.
+#[derive(Clone, Copy, Debug)]
+pub struct MetricViewport {
+    /// Metric position in the root frame that is shown in the center.
+    pub center: egui::Pos2,
+    /// Display scale, i.e. how many pixels per meter.
+    pub pixels_per_meter: f32,
+    /// Width and height of the output image in pixels.
+    pub size: [u32; 2],
+}
+
+impl MetricViewport {
+    pub fn new(center: egui::Pos2, pixels_per_meter: f32, size: [u32; 2]) -> MetricViewport {
+        MetricViewport {
+            center,
+            pixels_per_meter,
+            size,
+        }
+    }
+
+    /// Position of the metric origin in pixels of the output image.
+    pub fn origin_in_pixels(&self) -> egui::Pos2 {
+        let half_size = egui::vec2(self.size[0] as f32, self.size[1] as f32) / 2.;
+        (half_size + flip(-self.center.to_vec2()) * self.pixels_per_meter).to_pos2()
+    }
+
+    /// Converts a metric root frame position to pixels of the output image.
+    pub fn to_pixel(&self, metric: egui::Pos2) -> egui::Pos2 {
+        self.origin_in_pixels() + flip(metric.to_vec2()) * self.pixels_per_meter
+    }
+
+    /// Placement of a map image in the viewport, see [`ImagePlacement::from_map`].
+    pub fn placement(
+        &self,
+        meta: &Meta,
+        pose: &MapPose,
+        original_image_size: egui::Vec2,
+    ) -> ImagePlacement {
+        ImagePlacement::from_map(
+            meta,
+            pose,
+            original_image_size,
+            self.pixels_per_meter,
+            self.origin_in_pixels(),
+        )
+    }
+}
+
+/// An image that is composited with a placement and display options,
+/// the CPU counterpart of a [`crate::TransformedTextureRequest`].
+pub struct CompositeLayer<'a> {
+    pub image_pyramid: &'a ImagePyramid,
+    pub placement: ImagePlacement,
+    /// Color tint of the image.
+    pub tint: egui::Color32,
+    /// Color of the image that shall be transparent.
+    pub color_to_alpha: Option<egui::Color32>,
+    /// Optional value-interpretation-based thresholding of the image.
+    pub thresholding: Option<ValueInterpretation>,
+    pub texture_filter: TextureFilter,
+}
+
+impl CompositeLayer<'_> {
+    pub fn new(image_pyramid: &ImagePyramid, placement: ImagePlacement) -> CompositeLayer<'_> {
+        CompositeLayer {
+            image_pyramid,
+            placement,
+            tint: NO_TINT,
+            color_to_alpha: None,
+            thresholding: None,
+            texture_filter: TextureFilter::default(),
+        }
+    }
+
+    pub fn with_tint(mut self, tint: Option<egui::Color32>) -> Self {
+        self.tint = tint.unwrap_or(NO_TINT);
+        self
+    }
+
+    pub fn with_color_to_alpha(mut self, color_to_alpha: Option<egui::Color32>) -> Self {
+        self.color_to_alpha = color_to_alpha;
+        self
+    }
+
+    pub fn with_thresholding(mut self, thresholding: Option<&ValueInterpretation>) -> Self {
+        self.thresholding = thresholding.copied();
+        self
+    }
+
+    pub fn with_texture_filter(mut self, texture_filter: TextureFilter) -> Self {
+        self.texture_filter = texture_filter;
+        self
+    }
+
+    fn appearance_key(&self) -> AppearanceKey {
+        AppearanceKey {
+            color_to_alpha: self.color_to_alpha,
+            thresholding: self.thresholding,
+        }
+    }
+}
+
+/// Premultiplied RGBA color with channels in `0..=1`.
+type Premultiplied = [f32; 4];
+
+fn premultiplied(pixel: image::Rgba<u8>) -> Premultiplied {
+    let [r, g, b, a] = pixel.0.map(|c| f32::from(c) / 255.);
+    [r * a, g * a, b * a, a]
+}
+
+/// Part of a pyramid level or of the original image that a layer is sampled from,
+/// with the appearance applied.
+struct LayerSource {
+    image: Arc<image::DynamicImage>,
+    /// Position of the source in pixels of the original image.
+    offset: egui::Vec2,
+    /// Source pixels per pixel of the original image.
+    scale: egui::Vec2,
+}
+
+impl LayerSource {
+    fn pixel(&self, x: i64, y: i64) -> Premultiplied {
+        let x = x.clamp(0, i64::from(self.image.width()) - 1) as u32;
+        let y = y.clamp(0, i64::from(self.image.height()) - 1) as u32;
+        premultiplied(self.image.get_pixel(x, y))
+    }
+
+    /// Samples the source at continuous pixel coordinates of the original image.
+    /// Like texture sampling with clamp to edge, texel centers are at half pixels.
+    fn sample(&self, original_pos: egui::Pos2, nearest: bool) -> Premultiplied {
+        let pos = (original_pos.to_vec2() - self.offset) * self.scale;
+        if nearest {
+            return self.pixel(pos.x.floor() as i64, pos.y.floor() as i64);
+        }
+        let pos = pos - egui::vec2(0.5, 0.5);
+        let (x, y) = (pos.x.floor(), pos.y.floor());
+        let (fx, fy) = (pos.x - x, pos.y - y);
+        let (x, y) = (x as i64, y as i64);
+        let corners = [
+            (self.pixel(x, y), (1. - fx) * (1. - fy)),
+            (self.pixel(x + 1, y), fx * (1. - fy)),
+            (self.pixel(x, y + 1), (1. - fx) * fy),
+            (self.pixel(x + 1, y + 1), fx * fy),
+        ];
+        let mut color = [0.; 4];
+        for (pixel, weight) in corners {
+            for (channel, value) in color.iter_mut().zip(pixel) {
+                *channel += value * weight;
+            }
+        }
+        color
+    }
+}
+
+/// Composites the layers in the given order over the background color into an image
+/// of the viewport size. Layer placements are in pixels of the output image,
+/// e.g. from [`MetricViewport::placement`].
+///
+/// The same pyramid levels are used as for the textures in the UI, and the appearance
+/// of the layers is applied to them like for textures. Streamed images are read
+/// in full resolution only if the scale requires it, and only the visible region.
+pub fn composite(
+    viewport: &MetricViewport,
+    background: egui::Color32,
+    layers: &[CompositeLayer],
+) -> image::RgbaImage {
+    let [width, height] = viewport.size;
+    let background = background.to_array().map(|c| f32::from(c) / 255.);
+    let mut canvas = vec![background; width as usize * height as usize];
+    for layer in layers {
+        composite_layer(&mut canvas, viewport.size, layer);
+    }
+
+    let mut output = image::RgbaImage::new(width, height);
+    for (pixel, color) in output.pixels_mut().zip(canvas) {
+        let [r, g, b, a] = color.map(|c| (c * 255.).round().clamp(0., 255.) as u8);
+        pixel.0 = egui::Color32::from_rgba_premultiplied(r, g, b, a).to_srgba_unmultiplied();
+    }
+    output
+}
+
+fn corners(rect: egui::Rect) -> [egui::Pos2; 4] {
+    [
+        rect.left_top(),
+        rect.right_top(),
+        rect.left_bottom(),
+        rect.right_bottom(),
+    ]
+}
+
+fn composite_layer(canvas: &mut [Premultiplied], size: [u32; 2], layer: &CompositeLayer) {
+    let placement = &layer.placement;
+    let original_size = placement.original_image_size;
+    if placement.points_per_texel <= 0. || original_size.min_elem() <= 0. {
+        return;
+    }
+    let center = placement.rotation_center_in_viewport();
+    let to_output = |original: egui::Pos2| {
+        let unrotated = placement.translation + original.to_vec2() * placement.points_per_texel;
+        center + placement.rotation * (unrotated.to_pos2() - center)
+    };
+    let to_original = |output: egui::Pos2| {
+        let unrotated = center + placement.rotation.inverse() * (output - center);
+        ((unrotated.to_vec2() - placement.translation) / placement.points_per_texel).to_pos2()
+    };
+
+    // Bounding box of the rotated image in the output, and of the output in the image.
+    let output_rect =
+        egui::Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(size[0] as f32, size[1] as f32));
+    let image_rect = egui::Rect::from_min_size(egui::Pos2::ZERO, original_size);
+    let covered =
+        egui::Rect::from_points(&corners(image_rect).map(to_output)).intersect(output_rect);
+    let visible =
+        egui::Rect::from_points(&corners(output_rect).map(to_original)).intersect(image_rect);
+    if !covered.is_positive() || !visible.is_positive() {
+        return;
+    }
+    let Some(source) = layer_source(layer, visible) else {
+        return;
+    };
+    let nearest = match layer.texture_filter {
+        TextureFilter::Crisp => true,
+        TextureFilter::Smooth => false,
+        // Magnification is crisp, minification smooth.
+        TextureFilter::Auto => placement.points_per_texel >= source.scale.x,
+    };
+    let tint = layer.tint.to_array().map(|c| f32::from(c) / 255.);
+
+    let rows = covered.min.y.floor() as u32..covered.max.y.ceil() as u32;
+    let columns = covered.min.x.floor() as u32..covered.max.x.ceil() as u32;
+    for y in rows {
+        for x in columns.clone() {
+            let original = to_original(egui::pos2(x as f32 + 0.5, y as f32 + 0.5));
+            let inside = original.x >= 0.
+                && original.y >= 0.
+                && original.x < original_size.x
+                && original.y < original_size.y;
+            if !inside {
+                continue;
+            }
+            let mut color = source.sample(original, nearest);
+            for (channel, factor) in color.iter_mut().zip(tint) {
+                *channel *= factor;
+            }
+            let target = &mut canvas[y as usize * size[0] as usize + x as usize];
+            let inverse_alpha = 1. - color[3];
+            for (channel, value) in target.iter_mut().zip(color) {
+                *channel = value + *channel * inverse_alpha;
+            }
+        }
+    }
+}
+
+/// Returns the source that covers the `visible` rectangle of the original image,
+/// from the pyramid level that a texture of the layer would use.
+fn layer_source(layer: &CompositeLayer, visible: egui::Rect) -> Option<LayerSource> {
+    let image_pyramid = layer.image_pyramid;
+    let original_size = layer.placement.original_image_size;
+    let appearance = layer.appearance_key();
+    if let Some(level) =
+        image_pyramid.get_level_with_appearance(layer.placement.scaled_size(), &appearance)
+    {
+        let scale = egui::vec2(level.width() as f32, level.height() as f32) / original_size;
+        return Some(LayerSource {
+            image: level,
+            offset: egui::Vec2::ZERO,
+            scale,
+        });
+    }
+
+    // Full resolution: only the visible region, with one pixel for interpolation.
+    let min = (visible.min - egui::vec2(1., 1.)).max(egui::Pos2::ZERO);
+    let max = (visible.max + egui::vec2(1., 1.)).min(original_size.to_pos2());
+    let (x, y) = (min.x.floor() as u32, min.y.floor() as u32);
+    let (width, height) = (max.x.ceil() as u32 - x, max.y.ceil() as u32 - y);
+    let mut region = match image_pyramid.read_original_region(x, y, width, height) {
+        Ok(region) => region,
+        Err(e) => {
+            error!("{e}");
+            return None;
+        }
+    };
+    apply_appearance(
+        &mut region,
+        layer.color_to_alpha,
+        layer.thresholding.as_ref(),
+        image_pyramid.original_has_alpha,
+    );
+    Some(LayerSource {
+        image: Arc::new(region),
+        offset: egui::vec2(x as f32, y as f32),
+        scale: egui::Vec2::splat(1.),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: image::Rgba<u8> = image::Rgba([255, 0, 0, 255]);
+    const GREEN: image::Rgba<u8> = image::Rgba([0, 255, 0, 255]);
+    const BLACK: image::Rgba<u8> = image::Rgba([0, 0, 0, 255]);
+    const WHITE: image::Rgba<u8> = image::Rgba([255, 255, 255, 255]);
+
+    /// Pyramid of an image whose columns have the given colors.
+    fn columns_pyramid(columns: &[image::Rgba<u8>], height: u32) -> ImagePyramid {
+        let image =
+            image::RgbaImage::from_fn(columns.len() as u32, height, |x, _| columns[x as usize]);
+        ImagePyramid::new(image::DynamicImage::ImageRgba8(image))
+    }
+
+    /// Placement of the unrotated image with its upper left corner at `translation`.
+    fn placement(
+        translation: egui::Vec2,
+        points_per_texel: f32,
+        original_image_size: egui::Vec2,
+    ) -> ImagePlacement {
+        ImagePlacement {
+            rotation: egui::emath::Rot2::IDENTITY,
+            translation,
+            rotation_center: egui::Vec2::ZERO,
+            points_per_texel,
+            original_image_size,
+        }
+    }
+
+    fn viewport(size: [u32; 2]) -> MetricViewport {
+        MetricViewport::new(egui::Pos2::ZERO, 10., size)
+    }
+
+    #[test]
+    fn rotated_map_with_origin_is_placed_like_its_metadata() {
+        let pyramid = columns_pyramid(&[RED, GREEN], 1);
+        let meta = Meta::new(
+            "map.png".into(),
+            "map.yaml".into(),
+            1.,
+            egui::vec2(1., 2.),
+            egui::emath::Rot2::from_angle(std::f32::consts::FRAC_PI_2),
+        );
+        let mut pose = MapPose::default();
+        pose.with_vec2(egui::vec2(-0.5, 1.))
+            .with_rot2(egui::emath::Rot2::from_angle(0.3));
+        let viewport = viewport([100, 100]);
+        let layer = CompositeLayer::new(
+            &pyramid,
+            viewport.placement(&meta, &pose, pyramid.original_size),
+        );
+        let output = composite(&viewport, egui::Color32::BLACK, &[layer]);
+
+        // Centers of the map pixels, in the root frame and then in the output.
+        for (column, color) in [(0.5, RED), (1.5, GREEN)] {
+            let in_map = meta.pixel_to_map(egui::pos2(column, 0.5), 1.);
+            let in_root = pose.rot2() * in_map.to_vec2() + pose.vec2();
+            let pixel = viewport.to_pixel(in_root.to_pos2());
+            assert_eq!(output[(pixel.x as u32, pixel.y as u32)], color, "{pixel:?}");
+        }
+        // The map covers two squares of 10 x 10 pixels.
+        let covered = output.pixels().filter(|pixel| **pixel != BLACK).count();
+        assert!((180..=220).contains(&covered), "{covered}");
+    }
+
+    #[test]
+    fn tinted_layers_are_blended_with_premultiplied_alpha() {
+        let translucent = image::Rgba([255, 255, 255, 128]);
+        let pyramid = columns_pyramid(&[translucent], 1);
+        let placement = || placement(egui::Vec2::ZERO, 2., egui::vec2(1., 1.));
+        let layers = || {
+            [CompositeLayer::new(&pyramid, placement())
+                .with_tint(Some(egui::Color32::from_rgb(255, 0, 0)))]
+        };
+
+        let output = composite(&viewport([2, 2]), egui::Color32::BLACK, &layers());
+        assert!(output.pixels().all(|pixel| pixel.0 == [128, 0, 0, 255]));
+
+        let output = composite(&viewport([2, 2]), egui::Color32::TRANSPARENT, &layers());
+        assert!(output.pixels().all(|pixel| pixel.0 == [255, 0, 0, 128]));
+
+        // Layers are blended in the given order.
+        let opaque = columns_pyramid(&[GREEN], 1);
+        let output = composite(
+            &viewport([2, 2]),
+            egui::Color32::BLACK,
+            &[
+                CompositeLayer::new(&opaque, placement()),
+                CompositeLayer::new(&pyramid, placement()),
+            ],
+        );
+        assert!(output.pixels().all(|pixel| pixel.0 == [128, 255, 128, 255]));
+    }
+
+    #[test]
+    fn auto_filter_is_crisp_when_magnified_and_smooth_when_minified() {
+        let pyramid = columns_pyramid(&[BLACK, WHITE, BLACK, WHITE], 2);
+        let size = egui::vec2(4., 2.);
+
+        // 10 pixels per texel, the column edges stay sharp.
+        let layer = CompositeLayer::new(&pyramid, placement(egui::Vec2::ZERO, 10., size));
+        let output = composite(&viewport([40, 20]), egui::Color32::BLACK, &[layer]);
+        for x in 0..40 {
+            let expected = if (x / 10) % 2 == 0 { BLACK } else { WHITE };
+            assert_eq!(output[(x, 10)], expected, "{x}");
+        }
+
+        // 2 texels per pixel, each pixel is sampled between a black and a white column.
+        let minified = || placement(egui::Vec2::ZERO, 0.5, size);
+        let layer = CompositeLayer::new(&pyramid, minified());
+        let output = composite(&viewport([2, 1]), egui::Color32::BLACK, &[layer]);
+        for pixel in output.pixels() {
+            assert!((120..=136).contains(&pixel.0[0]), "{pixel:?}");
+        }
+        // Unlike with the crisp filter.
+        let layer =
+            CompositeLayer::new(&pyramid, minified()).with_texture_filter(TextureFilter::Crisp);
+        let output = composite(&viewport([2, 1]), egui::Color32::BLACK, &[layer]);
+        assert!(output.pixels().all(|pixel| *pixel == WHITE));
+    }
+
+    #[test]
+    fn layer_outside_of_the_viewport_is_not_drawn() {
+        let pyramid = columns_pyramid(&[RED, GREEN], 2);
+        let size = egui::vec2(2., 2.);
+        let background = egui::Color32::from_rgb(10, 20, 30);
+        for translation in [egui::vec2(100., 0.), egui::vec2(-30., -30.)] {
+            let layer = CompositeLayer::new(&pyramid, placement(translation, 10., size));
+            let output = composite(&viewport([50, 50]), background, &[layer]);
+            assert!(output.pixels().all(|pixel| pixel.0 == [10, 20, 30, 255]));
+        }
+    }
+}
@@ -1,8 +1,8 @@
 use eframe::egui;
-use fast_image_resize::images::Image as ResizeImage;
-use fast_image_resize::{FilterType, IntoImageView, ResizeAlg, ResizeOptions, Resizer};
+use fast_image_resize::{FilterType, ResizeAlg, ResizeOptions, Resizer};
 use image::{GenericImageView, ImageBuffer};
 use imageproc::map::map_colors_mut;
+use maps_io_ros::ValueInterpretation;
 
 #[allow(unused_imports)]
 use fast_image_resize::CpuExtensions;
@@ -22,12 +22,13 @@ pub fn from_egui_image(egui_img: &egui::ColorImage) -> image::DynamicImage {
     image::DynamicImage::ImageRgba8(buffer)
 }
 
-fn fast_resize(img: &image::DynamicImage, width: u32, height: u32) -> image::DynamicImage {
-    let mut resized_img = ResizeImage::new(
-        width,
-        height,
-        img.pixel_type().expect("can't determine pixel type"),
-    );
+pub(crate) fn fast_resize(
+    img: &image::DynamicImage,
+    width: u32,
+    height: u32,
+) -> image::DynamicImage {
+    // Resize into an image of the same type to keep the bit depth.
+    let mut resized_img = image::DynamicImage::new(width, height, img.color());
     let mut resizer = Resizer::new();
 
     let options = ResizeOptions {
@@ -39,34 +40,7 @@ fn fast_resize(img: &image::DynamicImage, width: u32, height: u32) -> image::Dyn
     resizer
         .resize(img, &mut resized_img, &options)
         .expect("failed to resize image");
-
-    match img.color() {
-        image::ColorType::L8 => {
-            let buffer: ImageBuffer<image::Luma<u8>, _> =
-                image::ImageBuffer::from_raw(width, height, resized_img.into_vec())
-                    .expect("failed to create L8 image buffer");
-            image::DynamicImage::ImageLuma8(buffer)
-        }
-        image::ColorType::La8 => {
-            let buffer: ImageBuffer<image::LumaA<u8>, _> =
-                image::ImageBuffer::from_raw(width, height, resized_img.into_vec())
-                    .expect("failed to create La8 image buffer");
-            image::DynamicImage::ImageLumaA8(buffer)
-        }
-        image::ColorType::Rgb8 => {
-            let buffer: ImageBuffer<image::Rgb<u8>, _> =
-                image::ImageBuffer::from_raw(width, height, resized_img.into_vec())
-                    .expect("failed to create Rgb8 image buffer");
-            image::DynamicImage::ImageRgb8(buffer)
-        }
-        image::ColorType::Rgba8 => {
-            let buffer: ImageBuffer<image::Rgba<u8>, _> =
-                image::ImageBuffer::from_raw(width, height, resized_img.into_vec())
-                    .expect("failed to create Rgba8 image buffer");
-            image::DynamicImage::ImageRgba8(buffer)
-        }
-        _ => panic!("Unsupported color type: {:?}", img.color()),
-    }
+    resized_img
 }
 
 /// Fit the image into the desired size while keeping the aspect ratio.
@@ -105,10 +79,38 @@ pub fn color_to_alpha(img: &mut image::DynamicImage, color: Option<egui::Color32
 
 pub fn to_rgba8(img: image::DynamicImage) -> image::DynamicImage {
     match img.color() {
-        image::ColorType::L8 | image::ColorType::La8 | image::ColorType::Rgb8 => {
-            image::DynamicImage::from(img.to_rgba8())
-        }
         image::ColorType::Rgba8 => img,
-        _ => panic!("Unsupported color type: {:?}", img.color()),
+        _ => image::DynamicImage::from(img.to_rgba8()),
+    }
+}
+
+/// Returns true if the image has more than 8 bits per channel, e.g. 16-bit or float.
+pub fn is_high_depth(img: &image::DynamicImage) -> bool {
+    let color = img.color();
+    color.bytes_per_pixel() > color.channel_count()
+}
+
+/// Applies color to alpha and value interpretation to an image before it's rendered.
+///
+/// High bit depth images are converted to RGBA8 first, either through the
+/// value interpretation (using its value range) or by plain conversion.
+/// Color to alpha is then applied to the converted colors.
+pub fn apply_appearance(
+    img: &mut image::DynamicImage,
+    color: Option<egui::Color32>,
+    value_interpretation: Option<&ValueInterpretation>,
+    original_has_alpha: bool,
+) {
+    if is_high_depth(img) {
+        match value_interpretation {
+            Some(value_interpretation) => value_interpretation.apply(img, original_has_alpha),
+            None => *img = to_rgba8(std::mem::take(img)),
+        }
+        color_to_alpha(img, color);
+        return;
+    }
+    color_to_alpha(img, color);
+    if let Some(value_interpretation) = value_interpretation {
+        value_interpretation.apply(img, original_has_alpha);
     }
 }
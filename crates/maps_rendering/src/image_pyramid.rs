@@ -1,70 +1,432 @@
 use std::cmp::max;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 
 use eframe::egui;
-use log::debug;
+use image::GenericImage as _;
+use log::{debug, error, trace, warn};
+use serde::{Deserialize, Serialize};
 
-use crate::image::{fit_image, to_rgba8};
+use crate::image::{apply_appearance, fast_resize, fit_image, is_high_depth, to_rgba8};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::pyramid_cache::PyramidDiskCache;
+use maps_io_ros::{RegionReader, ValueInterpretation};
 
-// Side lengths used for the image pyramid levels.
+// Side lengths used for the fixed image pyramid levels.
 // These shall correspond roughly to zoom levels w.r.t. original images.
-const SIZES: [u32; 5] = [8000, 4000, 2000, 1000, 500];
+const FIXED_SIZES: [u32; 5] = [8000, 4000, 2000, 1000, 500];
+
+/// Default size in pixels of the longer image side from which on images
+/// are streamed from their file, see [`ImagePyramid::streamed_in_background`].
+pub const fn default_streaming_threshold() -> u32 {
+    16384
+}
+
+/// Approximate size of the bands that streamed images are read in, in bytes.
+const STREAMING_BAND_BYTES: usize = 64 * 1024 * 1024;
+
+/// Maximum side length of the preview of streamed images, i.e. the number of rows
+/// that are read for it, see [`streamed_preview`].
+const STREAMED_PREVIEW_SIZE: u32 = 256;
+
+/// Default side length of the coarsest power-of-two pyramid level.
+pub const fn default_min_level_size() -> u32 {
+    256
+}
+
+/// How the levels of an [`ImagePyramid`] are generated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PyramidLevels {
+    /// Levels with fixed side lengths of 8000, 4000, 2000, 1000 and 500 pixels.
+    #[default]
+    Fixed,
+    /// Each level halves the size of the previous one, starting from the original image.
+    /// Levels are generated until the longer side would be smaller than `min_size`.
+    PowerOfTwo {
+        #[serde(default = "default_min_level_size")]
+        min_size: u32,
+    },
+}
+
+impl PyramidLevels {
+    /// Side lengths of the levels for an image whose longer side has `original_size`
+    /// pixels, in descending order. Only levels smaller than the original are generated.
+    pub fn sizes(self, original_size: u32) -> Vec<u32> {
+        match self {
+            PyramidLevels::Fixed => FIXED_SIZES
+                .into_iter()
+                .filter(|&size| original_size > size)
+                .collect(),
+            PyramidLevels::PowerOfTwo { min_size } => {
+                let mut sizes = Vec::new();
+                let mut size = original_size / 2;
+                while size >= min_size.max(1) {
+                    sizes.push(size);
+                    size /= 2;
+                }
+                sizes
+            }
+        }
+    }
+}
+
+/// Appearance settings that change the pixels of an image before it's rendered,
+/// see [`crate::image::apply_appearance`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AppearanceKey {
+    pub color_to_alpha: Option<egui::Color32>,
+    pub thresholding: Option<ValueInterpretation>,
+}
+
+impl AppearanceKey {
+    /// Returns true if the appearance doesn't change the pixels of 8-bit images.
+    fn is_identity(&self) -> bool {
+        self.color_to_alpha.is_none() && self.thresholding.is_none()
+    }
+}
+
+/// Pyramid levels with an appearance applied, see [`ImagePyramid::get_level_with_appearance`].
+#[derive(Default)]
+struct AppearanceCache {
+    key: AppearanceKey,
+    /// Generation of the pyramid that the levels were created from.
+    generation: usize,
+    levels_by_size: HashMap<u32, Arc<image::DynamicImage>>,
+}
+
+/// The full-resolution image of a pyramid.
+#[derive(Clone)]
+enum Original {
+    /// Decoded completely and kept in memory.
+    InMemory(Arc<image::DynamicImage>),
+    /// Read from the image file region by region, see [`ImagePyramid::streamed_in_background`].
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    Streamed(Arc<StreamedOriginal>),
+}
+
+/// File readers of a streamed image. Regions that are requested by the UI are read
+/// with their own file handle, such that they don't wait for the large bands
+/// that are read in the background to build the levels.
+struct StreamedOriginal {
+    /// Reads the bands of the levels and the preview.
+    levels: RegionReader,
+    /// Reads regions on demand, `None` if the file couldn't be opened again.
+    regions: Option<RegionReader>,
+}
+
+impl StreamedOriginal {
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    fn new(reader: RegionReader) -> StreamedOriginal {
+        let regions = reader
+            .reopen()
+            .inspect_err(|e| warn!("{e}, regions are read with the same file handle as levels."))
+            .ok();
+        StreamedOriginal {
+            levels: reader,
+            regions,
+        }
+    }
+
+    fn regions(&self) -> &RegionReader {
+        self.regions.as_ref().unwrap_or(&self.levels)
+    }
+}
+
+impl Default for Original {
+    fn default() -> Self {
+        Original::InMemory(Arc::default())
+    }
+}
 
 /// Stores downscaled versions of an image for discrete sizes.
 /// Intended for efficient editing & rendering of images at different zoom levels.
+///
+/// The levels can be built in a background thread, see [`ImagePyramid::new_in_background`].
+/// Until then, [`ImagePyramid::get_level`] returns the closest available level.
+///
+/// Huge images can be streamed from their file instead of being kept in memory,
+/// see [`ImagePyramid::streamed_in_background`].
 #[derive(Default)]
 pub struct ImagePyramid {
-    pub original: image::DynamicImage,
-    levels_by_size: HashMap<u32, image::DynamicImage>,
+    original: Original,
+    levels: PyramidLevels,
+    /// Sizes of all levels of this pyramid, built or not, in descending order.
+    sizes: Vec<u32>,
+    levels_by_size: RwLock<HashMap<u32, Arc<image::DynamicImage>>>,
+    /// Number of levels that are completely built.
+    num_built: AtomicUsize,
+    /// Incremented whenever a level is added or replaced.
+    generation: AtomicUsize,
+    appearance_cache: Mutex<AppearanceCache>,
     aspect_ratio: f32,
     pub original_size: egui::Vec2,
     pub original_has_alpha: bool,
 }
 
 impl ImagePyramid {
+    /// Creates the pyramid with the default levels and builds them before returning.
     pub fn new(original: image::DynamicImage) -> ImagePyramid {
-        // Always add an alpha channel, if not present, to support our image operations.
-        // DynamicImage allows conversions, but we do it once here for performance reasons.
-        // Use always RGBA8 internally.
-        let original_has_alpha = original.color().has_alpha();
-        let original = to_rgba8(original);
+        ImagePyramid::with_levels(original, PyramidLevels::default())
+    }
 
-        let original_size = egui::Vec2::new(original.width() as f32, original.height() as f32);
-        ImagePyramid {
-            levels_by_size: {
-                let mut levels: HashMap<u32, image::DynamicImage> = HashMap::new();
-                let mut parent = None;
-                for size in SIZES {
-                    let image_to_downscale = match levels.get(&parent.unwrap_or(0)) {
-                        Some(parent_level) => parent_level,
-                        None => &original,
-                    };
-                    if max(original.width(), original.height()) <= size {
-                        continue;
-                    }
-                    debug!(
-                        "Creating pyramid level for target size {} from {} image size {:?}",
-                        size,
-                        parent.map_or("original".to_string(), |_| "parent".to_string()),
-                        (image_to_downscale.width(), image_to_downscale.height())
-                    );
-                    let level = fit_image(
-                        image_to_downscale,
-                        egui::Vec2::new(size as f32, size as f32),
-                    );
-                    levels.insert(size, level);
-                    parent = Some(size);
+    /// Creates the pyramid with the given levels and builds them before returning.
+    pub fn with_levels(original: image::DynamicImage, levels: PyramidLevels) -> ImagePyramid {
+        let (original, original_has_alpha) = prepare_original(original);
+        ImagePyramid::rebuild_from(Original::InMemory(original), original_has_alpha, levels)
+    }
+
+    /// Creates a new pyramid from the same original image with other levels,
+    /// and builds them before returning.
+    pub fn rebuild(&self, levels: PyramidLevels) -> ImagePyramid {
+        ImagePyramid::rebuild_from(self.original.clone(), self.original_has_alpha, levels)
+    }
+
+    fn rebuild_from(
+        original: Original,
+        original_has_alpha: bool,
+        levels: PyramidLevels,
+    ) -> ImagePyramid {
+        let pyramid = ImagePyramid::without_levels(original, original_has_alpha, levels);
+        pyramid.build_levels(
+            #[cfg(not(target_arch = "wasm32"))]
+            None,
+        );
+        pyramid
+    }
+
+    /// Creates the pyramid and builds its levels in a background thread.
+    /// A quick low-quality preview is available as coarsest level right away,
+    /// finer levels are swapped in as soon as they are built.
+    ///
+    /// If a disk cache is given, levels are read from it instead of being built,
+    /// and newly built levels are added to it.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new_in_background(
+        original: image::DynamicImage,
+        levels: PyramidLevels,
+        disk_cache: Option<PyramidDiskCache>,
+    ) -> Arc<ImagePyramid> {
+        let (original, original_has_alpha) = prepare_original(original);
+        ImagePyramid::rebuild_from_in_background(
+            Original::InMemory(original),
+            original_has_alpha,
+            levels,
+            disk_cache,
+        )
+    }
+
+    /// Creates the pyramid of an image that is read region by region from its file,
+    /// without keeping the full-resolution image in memory.
+    /// The levels are built in a background thread from horizontal bands of the image,
+    /// a coarse preview from a subset of the rows is shown until the finest level is built.
+    /// Full-resolution regions are read on demand, see [`ImagePyramid::read_original_region`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn streamed_in_background(
+        reader: RegionReader,
+        levels: PyramidLevels,
+    ) -> Arc<ImagePyramid> {
+        let original_has_alpha = reader.has_alpha();
+        ImagePyramid::rebuild_from_in_background(
+            Original::Streamed(Arc::new(StreamedOriginal::new(reader))),
+            original_has_alpha,
+            levels,
+            None,
+        )
+    }
+
+    /// Like [`ImagePyramid::rebuild`], but builds the levels in a background thread,
+    /// see [`ImagePyramid::new_in_background`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn rebuild_in_background(
+        &self,
+        levels: PyramidLevels,
+        disk_cache: Option<PyramidDiskCache>,
+    ) -> Arc<ImagePyramid> {
+        ImagePyramid::rebuild_from_in_background(
+            self.original.clone(),
+            self.original_has_alpha,
+            levels,
+            disk_cache,
+        )
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn rebuild_from_in_background(
+        original: Original,
+        original_has_alpha: bool,
+        levels: PyramidLevels,
+        disk_cache: Option<PyramidDiskCache>,
+    ) -> Arc<ImagePyramid> {
+        let pyramid = Arc::new(ImagePyramid::without_levels(
+            original,
+            original_has_alpha,
+            levels,
+        ));
+        if let Some(&coarsest) = pyramid.sizes.last() {
+            let preview = match &pyramid.original {
+                // Nearest neighbor sampling only touches the target pixels, i.e. is fast.
+                Original::InMemory(original) => {
+                    original.resize(coarsest, coarsest, image::imageops::FilterType::Nearest)
                 }
-                levels
-            },
+                // Reading a streamed image takes a while, its preview is read in the
+                // background thread. Until then, a transparent placeholder is shown.
+                Original::Streamed(_) => image::DynamicImage::new_rgba8(1, 1),
+            };
+            pyramid.insert_level(coarsest, Arc::new(preview));
+
+            let worker = pyramid.clone();
+            std::thread::spawn(move || {
+                if let Original::Streamed(streamed) = &worker.original {
+                    let preview = streamed_preview(coarsest, &streamed.levels);
+                    worker.insert_level(coarsest, Arc::new(preview));
+                }
+                worker.build_levels(disk_cache.as_ref());
+            });
+        }
+        pyramid
+    }
+
+    fn without_levels(
+        original: Original,
+        original_has_alpha: bool,
+        levels: PyramidLevels,
+    ) -> ImagePyramid {
+        let (width, height) = match &original {
+            Original::InMemory(image) => (image.width(), image.height()),
+            Original::Streamed(streamed) => streamed.levels.dimensions(),
+        };
+        let mut sizes = levels.sizes(max(width, height));
+        if sizes.is_empty() && matches!(original, Original::Streamed(_)) {
+            // Streamed pyramids need at least one level in memory.
+            sizes.push(max(width, height).div_ceil(2));
+        }
+        let original_size = egui::Vec2::new(width as f32, height as f32);
+        ImagePyramid {
+            sizes,
+            levels,
             original,
             aspect_ratio: original_size.x / original_size.y,
             original_size,
             original_has_alpha,
+            ..Default::default()
+        }
+    }
+
+    fn insert_level(&self, size: u32, level: Arc<image::DynamicImage>) {
+        self.levels_by_size
+            .write()
+            .expect("poisoned pyramid lock")
+            .insert(size, level);
+        self.generation.fetch_add(1, Ordering::Release);
+    }
+
+    /// Builds a level downscaled from `parent` and inserts it.
+    fn build_level(
+        &self,
+        size: u32,
+        parent: &Arc<image::DynamicImage>,
+    ) -> Arc<image::DynamicImage> {
+        debug!(
+            "Creating pyramid level for target size {} from image size {:?}",
+            size,
+            (parent.width(), parent.height())
+        );
+        let level = Arc::new(fit_image(parent, egui::Vec2::new(size as f32, size as f32)));
+        self.insert_level(size, level.clone());
+        self.num_built.fetch_add(1, Ordering::Release);
+        level
+    }
+
+    /// Builds a level from horizontal bands of a streamed image and inserts it.
+    /// Each band is downscaled by the same integer factor, such that only
+    /// a band and the level are in memory at the same time.
+    /// The factor is rounded down, i.e. the level can be slightly larger than `size`.
+    fn build_streamed_level(&self, size: u32, reader: &RegionReader) -> Arc<image::DynamicImage> {
+        let (width, height) = reader.dimensions();
+        let factor = (max(width, height) / size).max(1);
+        let level_width = width.div_ceil(factor);
+        let level_height = height.div_ceil(factor);
+        let bytes_per_level_row =
+            width as usize * factor as usize * usize::from(reader.color_type().bytes_per_pixel());
+        let band_level_rows = (STREAMING_BAND_BYTES / bytes_per_level_row.max(1)).max(1) as u32;
+        debug!(
+            "Creating pyramid level for target size {size} from {:?} in bands of {} rows",
+            reader.path(),
+            band_level_rows * factor
+        );
+
+        let mut level: Option<image::DynamicImage> = None;
+        for level_row in (0..level_height).step_by(band_level_rows as usize) {
+            let rows = band_level_rows.min(level_height - level_row);
+            let band = match reader.read_region(0, level_row * factor, width, rows * factor) {
+                Ok(band) => band,
+                Err(e) => {
+                    error!("{e}");
+                    continue;
+                }
+            };
+            // The last band can have less rows than a multiple of the factor.
+            let band_rows = band.height().div_ceil(factor);
+            let band = prepare_region(fast_resize(&band, level_width, band_rows));
+            let level = level.get_or_insert_with(|| {
+                image::DynamicImage::new(level_width, level_height, band.color())
+            });
+            if let Err(e) = level.copy_from(&band, 0, level_row) {
+                error!("Failed to copy band into pyramid level: {e}");
+            }
         }
+        let level = Arc::new(
+            level.unwrap_or_else(|| image::DynamicImage::new_rgba8(level_width, level_height)),
+        );
+        self.insert_level(size, level.clone());
+        self.num_built.fetch_add(1, Ordering::Release);
+        level
     }
 
-    pub fn get_level(&self, size: egui::Vec2) -> &image::DynamicImage {
+    /// Builds all levels, each one downscaled from the next larger one.
+    /// Levels that are in the disk cache are loaded from there instead,
+    /// streamed images are not cached.
+    fn build_levels(
+        &self,
+        #[cfg(not(target_arch = "wasm32"))] disk_cache: Option<&PyramidDiskCache>,
+    ) {
+        let (mut parent, sizes) = match &self.original {
+            Original::InMemory(original) => (original.clone(), self.sizes.as_slice()),
+            Original::Streamed(streamed) => {
+                let Some((&finest, coarser)) = self.sizes.split_first() else {
+                    return;
+                };
+                (self.build_streamed_level(finest, &streamed.levels), coarser)
+            }
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        let disk_cache = match &self.original {
+            Original::InMemory(original) => {
+                disk_cache.map(|cache| (cache, PyramidDiskCache::image_key(original)))
+            }
+            Original::Streamed(_) => None,
+        };
+        for &size in sizes {
+            #[cfg(not(target_arch = "wasm32"))]
+            if let Some(level) = disk_cache.and_then(|(cache, key)| cache.load(key, size)) {
+                let level = Arc::new(level);
+                self.insert_level(size, level.clone());
+                self.num_built.fetch_add(1, Ordering::Release);
+                parent = level;
+                continue;
+            }
+            parent = self.build_level(size, &parent);
+            #[cfg(not(target_arch = "wasm32"))]
+            if let Some((cache, key)) = disk_cache {
+                cache.store(key, size, &parent);
+            }
+        }
+    }
+
+    /// Returns the closest level for the size,
+    /// or `None` if the size requires the original resolution.
+    fn find_level(&self, size: egui::Vec2) -> Option<Arc<image::DynamicImage>> {
         // Get the closest size that is larger or equal to the requested size,
         // considering the aspect ratio of the original image for the dimension.
         let scale = (size.x / self.original_size.x).min(size.y / self.original_size.y);
@@ -73,20 +435,336 @@ impl ImagePyramid {
         } else {
             scale * self.original_size.y
         };
-        match SIZES
+        let levels = self.levels_by_size.read().expect("poisoned pyramid lock");
+        let closest = self.sizes.iter().rev().find(|&&s| s >= dim as u32)?;
+        if let Some(level) = levels.get(closest) {
+            return Some(level.clone());
+        }
+        // Not built yet: use the closest larger level that is available,
+        // or else the finest available one until the requested one is built.
+        self.sizes
             .iter()
             .rev()
-            .find(|&&s| s >= dim as u32 && self.levels_by_size.contains_key(&s))
-        {
-            Some(closest) => self
-                .levels_by_size
-                .get(closest)
-                .expect("non-existing pyramid level"),
-            None => &self.original,
+            .filter(|&&s| s >= *closest)
+            .chain(self.sizes.iter())
+            .find_map(|s| levels.get(s))
+            .cloned()
+    }
+
+    /// Returns the closest level for the size. That's the original image if it's
+    /// in memory and the size requires its resolution, else the finest level.
+    pub fn get_level(&self, size: egui::Vec2) -> Arc<image::DynamicImage> {
+        self.find_level(size).unwrap_or_else(|| self.finest_image())
+    }
+
+    /// Returns the original image if it's in memory, otherwise the finest level
+    /// that is available. Use this for operations on the whole image, like statistics,
+    /// and [`ImagePyramid::read_original_region`] where the full resolution is needed.
+    pub fn finest_image(&self) -> Arc<image::DynamicImage> {
+        match &self.original {
+            Original::InMemory(original) => original.clone(),
+            Original::Streamed(_) => {
+                let levels = self.levels_by_size.read().expect("poisoned pyramid lock");
+                self.sizes
+                    .iter()
+                    .find_map(|s| levels.get(s))
+                    .cloned()
+                    .unwrap_or_default()
+            }
         }
     }
 
+    /// Returns the original image if it's in memory, i.e. not streamed.
+    pub fn original(&self) -> Option<&Arc<image::DynamicImage>> {
+        match &self.original {
+            Original::InMemory(original) => Some(original),
+            Original::Streamed(_) => None,
+        }
+    }
+
+    /// Returns true if the full-resolution image is read from its file on demand.
+    pub fn is_streamed(&self) -> bool {
+        matches!(self.original, Original::Streamed(_))
+    }
+
+    /// Returns true if the original image has more than 8 bits per channel.
+    /// Such images are only displayed meaningfully with a value interpretation.
+    pub fn is_high_depth(&self) -> bool {
+        let color = match &self.original {
+            Original::InMemory(original) => original.color(),
+            Original::Streamed(streamed) => streamed.levels.color_type(),
+        };
+        color.bytes_per_pixel() > color.channel_count()
+    }
+
+    /// Returns a region of the original image in full resolution, clamped to its bounds.
+    /// Streamed images are read from their file, which can fail e.g. if it was removed.
+    pub fn read_original_region(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> maps_io_ros::Result<image::DynamicImage> {
+        match &self.original {
+            Original::InMemory(original) => Ok(original.crop_imm(x, y, width, height)),
+            Original::Streamed(streamed) => streamed
+                .regions()
+                .read_region(x, y, width, height)
+                .map(prepare_region),
+        }
+    }
+
+    /// Like [`ImagePyramid::get_level`], but with the appearance applied to the level.
+    /// The levels are only computed once and cached until another appearance is requested
+    /// or the pyramid changes, i.e. they can be cropped and resized without repeating
+    /// the value interpretation and color to alpha for every texture.
+    ///
+    /// Returns `None` if the size requires the original resolution. The original isn't
+    /// cached to not duplicate it in memory, the appearance has to be applied to
+    /// crops of it instead, see [`ImagePyramid::read_original_region`].
+    pub fn get_level_with_appearance(
+        &self,
+        size: egui::Vec2,
+        key: &AppearanceKey,
+    ) -> Option<Arc<image::DynamicImage>> {
+        // Read the generation first, a level that is added meanwhile invalidates the cache.
+        let generation = self.generation();
+        let level = self.find_level(size)?;
+        if key.is_identity() && !is_high_depth(&level) {
+            return Some(level);
+        }
+
+        let mut cache = self.appearance_cache.lock().expect("poisoned pyramid lock");
+        if cache.key != *key || cache.generation != generation {
+            cache.levels_by_size.clear();
+            cache.key = *key;
+            cache.generation = generation;
+        }
+        let level_size = level.width().max(level.height());
+        let cached = cache.levels_by_size.entry(level_size).or_insert_with(|| {
+            trace!("Applying appearance to pyramid level {level_size}");
+            let mut image = (*level).clone();
+            apply_appearance(
+                &mut image,
+                key.color_to_alpha,
+                key.thresholding.as_ref(),
+                self.original_has_alpha,
+            );
+            Arc::new(image)
+        });
+        Some(cached.clone())
+    }
+
+    /// How the levels of this pyramid are generated.
+    pub fn levels(&self) -> PyramidLevels {
+        self.levels
+    }
+
     pub fn num_levels(&self) -> usize {
-        self.levels_by_size.len()
+        self.levels_by_size
+            .read()
+            .expect("poisoned pyramid lock")
+            .len()
+    }
+
+    /// Fraction of levels that are built, in `[0, 1]`.
+    pub fn progress(&self) -> f32 {
+        match self.sizes.len() {
+            0 => 1.,
+            n => self.num_built.load(Ordering::Acquire) as f32 / n as f32,
+        }
+    }
+
+    /// Returns true if all levels are built.
+    pub fn is_complete(&self) -> bool {
+        self.num_built.load(Ordering::Acquire) == self.sizes.len()
+    }
+
+    /// Changes whenever a level is added or replaced,
+    /// i.e. textures created from [`ImagePyramid::get_level`] may be outdated.
+    pub fn generation(&self) -> usize {
+        self.generation.load(Ordering::Acquire)
+    }
+}
+
+/// Always adds an alpha channel to 8-bit images, if not present, to support our image operations.
+/// DynamicImage allows conversions, but we do it once here for performance reasons.
+/// Images with higher bit depth are kept as they are to not lose precision,
+/// they get converted to RGBA8 when rendered.
+fn prepare_original(original: image::DynamicImage) -> (Arc<image::DynamicImage>, bool) {
+    let original_has_alpha = original.color().has_alpha();
+    (Arc::new(prepare_region(original)), original_has_alpha)
+}
+
+/// Converts regions of streamed images like [`prepare_original`].
+fn prepare_region(region: image::DynamicImage) -> image::DynamicImage {
+    if is_high_depth(&region) {
+        region
+    } else {
+        to_rgba8(region)
+    }
+}
+
+/// Builds a coarse preview of a streamed image from evenly spaced rows,
+/// which is much faster than reading the whole image for the levels.
+/// The longer side of the preview has at most `size` pixels.
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+fn streamed_preview(size: u32, reader: &RegionReader) -> image::DynamicImage {
+    let (width, height) = reader.dimensions();
+    let factor = (max(width, height) / size.clamp(1, STREAMED_PREVIEW_SIZE)).max(1);
+    let preview_width = width.div_ceil(factor);
+    let preview_height = height.div_ceil(factor);
+    debug!(
+        "Creating preview of {:?} from every {factor}th row",
+        reader.path()
+    );
+    let mut preview: Option<image::DynamicImage> = None;
+    for preview_row in 0..preview_height {
+        let row = match reader.read_region(0, preview_row * factor, width, 1) {
+            Ok(row) => row,
+            Err(e) => {
+                error!("{e}");
+                // Likely the whole file can't be read, don't repeat the error for each row.
+                break;
+            }
+        };
+        let row = prepare_region(fast_resize(&row, preview_width, 1));
+        let preview = preview.get_or_insert_with(|| {
+            image::DynamicImage::new(preview_width, preview_height, row.color())
+        });
+        if let Err(e) = preview.copy_from(&row, 0, preview_row) {
+            error!("Failed to copy row into preview: {e}");
+        }
+    }
+    preview.unwrap_or_else(|| image::DynamicImage::new_rgba8(1, 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_level_sizes() {
+        assert_eq!(PyramidLevels::Fixed.sizes(8001), FIXED_SIZES);
+        assert_eq!(PyramidLevels::Fixed.sizes(2500), [2000, 1000, 500]);
+        assert_eq!(PyramidLevels::Fixed.sizes(500), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn power_of_two_level_sizes() {
+        let levels = PyramidLevels::PowerOfTwo { min_size: 256 };
+        assert_eq!(levels.sizes(4096), [2048, 1024, 512, 256]);
+        // Odd sizes are rounded down, levels stop above the minimum size.
+        assert_eq!(levels.sizes(3001), [1500, 750, 375]);
+        assert_eq!(levels.sizes(511), Vec::<u32>::new());
+        // A minimum size of 0 doesn't generate empty levels.
+        let levels = PyramidLevels::PowerOfTwo { min_size: 0 };
+        assert_eq!(levels.sizes(5), [2, 1]);
+    }
+
+    fn test_pyramid() -> ImagePyramid {
+        let original = image::RgbaImage::from_fn(16, 16, |x, _| {
+            if x < 8 {
+                image::Rgba([0, 0, 0, 255])
+            } else {
+                image::Rgba([255, 255, 255, 255])
+            }
+        });
+        ImagePyramid::with_levels(
+            image::DynamicImage::ImageRgba8(original),
+            PyramidLevels::PowerOfTwo { min_size: 4 },
+        )
+    }
+
+    #[test]
+    fn streamed_preview_samples_rows() {
+        // 16x8 PGM, each row has the value 10 * y.
+        let path = std::env::temp_dir().join("maps_rendering_test_streamed_preview.pgm");
+        let mut bytes = b"P5 16 8 255\n".to_vec();
+        bytes.extend((0..8u8).flat_map(|y| [10 * y; 16]));
+        std::fs::write(&path, bytes).expect("Failed to write test image");
+        let reader = RegionReader::open(&path)
+            .expect("Failed to open test image")
+            .expect("PGM supports regions");
+
+        let preview = streamed_preview(4, &reader);
+        assert_eq!((preview.width(), preview.height()), (4, 2));
+        let preview = preview.to_luma8();
+        assert_eq!(preview[(0, 0)].0, [0]);
+        assert_eq!(preview[(3, 1)].0, [40]);
+
+        // Regions are read with another file handle than the levels.
+        let streamed = StreamedOriginal::new(reader);
+        assert!(streamed.regions.is_some());
+        std::fs::remove_file(&path).expect("Failed to remove test image");
+    }
+
+    fn color_to_alpha(color: egui::Color32) -> AppearanceKey {
+        AppearanceKey {
+            color_to_alpha: Some(color),
+            thresholding: None,
+        }
+    }
+
+    #[test]
+    fn appearance_levels_are_cached_per_key() {
+        let pyramid = test_pyramid();
+        let size = egui::vec2(8., 8.);
+        let black_to_alpha = color_to_alpha(egui::Color32::BLACK);
+
+        let level = pyramid
+            .get_level_with_appearance(size, &black_to_alpha)
+            .expect("No level");
+        assert_eq!(level.width(), 8);
+        assert_eq!(level.as_rgba8().expect("RGBA")[(0, 0)].0[3], 0);
+        let cached = pyramid
+            .get_level_with_appearance(size, &black_to_alpha)
+            .expect("No level");
+        assert!(Arc::ptr_eq(&level, &cached));
+
+        let white_to_alpha = color_to_alpha(egui::Color32::WHITE);
+        let rebuilt = pyramid
+            .get_level_with_appearance(size, &white_to_alpha)
+            .expect("No level");
+        assert!(!Arc::ptr_eq(&level, &rebuilt));
+        assert_eq!(rebuilt.as_rgba8().expect("RGBA")[(0, 0)].0[3], 255);
+        assert_eq!(rebuilt.as_rgba8().expect("RGBA")[(7, 0)].0[3], 0);
+
+        // Without appearance, the level itself is returned.
+        let plain = pyramid
+            .get_level_with_appearance(size, &AppearanceKey::default())
+            .expect("No level");
+        assert!(Arc::ptr_eq(&plain, &pyramid.get_level(size)));
+        // The original resolution is not cached.
+        assert!(
+            pyramid
+                .get_level_with_appearance(egui::vec2(16., 16.), &black_to_alpha)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn appearance_levels_are_rebuilt_when_levels_change() {
+        let pyramid = test_pyramid();
+        let size = egui::vec2(8., 8.);
+        let key = color_to_alpha(egui::Color32::BLACK);
+        let level = pyramid
+            .get_level_with_appearance(size, &key)
+            .expect("No level");
+
+        let generation = pyramid.generation();
+        let replacement = image::RgbaImage::from_pixel(8, 8, image::Rgba([255, 0, 0, 255]));
+        pyramid.insert_level(8, Arc::new(image::DynamicImage::ImageRgba8(replacement)));
+        assert_ne!(pyramid.generation(), generation);
+
+        let rebuilt = pyramid
+            .get_level_with_appearance(size, &key)
+            .expect("No level");
+        assert!(!Arc::ptr_eq(&level, &rebuilt));
+        assert_eq!(
+            rebuilt.as_rgba8().expect("RGBA")[(0, 0)].0,
+            [255, 0, 0, 255]
+        );
     }
 }
@@ -3,16 +3,27 @@
 //! For fast image editing previews in egui, with arbitrary scale & pose,
 //! and potentially very large source images.
 
+pub mod compositor;
 pub mod image;
 pub mod image_pyramid;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod pyramid_cache;
 mod rect_helpers;
 pub mod render_options;
+pub mod texture_budget;
 mod texture_cache;
 pub mod texture_request;
 pub mod texture_state;
+mod texture_tiles;
 
 // Re-export commonly used structs and types.
-pub use image_pyramid::ImagePyramid;
+pub use compositor::{CompositeLayer, MetricViewport, composite};
+pub use image_pyramid::{ImagePyramid, PyramidLevels};
+#[cfg(not(target_arch = "wasm32"))]
+pub use pyramid_cache::PyramidDiskCache;
 pub use render_options::TextureFilter;
-pub use texture_request::{ImagePlacement, NO_TINT, TextureRequest, TransformedTextureRequest};
+pub use texture_budget::{TextureBudget, TextureMemoryUsage};
+pub use texture_request::{
+    ImagePlacement, NO_TINT, TextureRequest, TransformedTextureRequest, flip,
+};
 pub use texture_state::TextureState;
@@ -0,0 +1,303 @@
+//! Optional on-disk cache of [`crate::ImagePyramid`] levels.
+//!
+//! Levels are stored as PNG files named after a hash of the original image and the
+//! level size, such that reopening sessions with large maps doesn't rebuild the levels.
+//! The cache is limited in size, the least recently used levels are removed when a
+//! level is stored.
+
+use std::fs;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use image::ImageEncoder;
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use log::{debug, warn};
+
+/// Bump this when the way levels are generated changes, to ignore outdated files.
+const CACHE_VERSION: u32 = 1;
+
+/// Default size limit of the cache directory.
+pub const DEFAULT_MAX_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// 64-bit FNV-1a, continuing from `hash`.
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Sets the modification time of a cached file to now, such that the eviction
+/// by modification time removes the least recently used levels first.
+/// Access times are not reliable for this, e.g. with `noatime` mounts.
+fn touch(path: &Path) {
+    let result = fs::File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_modified(SystemTime::now()));
+    if let Err(e) = result {
+        debug!("Can't update the modification time of {path:?}: {e}");
+    }
+}
+
+/// Directory with cached pyramid levels.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PyramidDiskCache {
+    dir: PathBuf,
+    max_bytes: u64,
+}
+
+impl PyramidDiskCache {
+    pub fn new(dir: impl Into<PathBuf>) -> PyramidDiskCache {
+        PyramidDiskCache {
+            dir: dir.into(),
+            max_bytes: DEFAULT_MAX_BYTES,
+        }
+    }
+
+    /// Sets the size limit of the cache directory, see [`PyramidDiskCache::store`].
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> PyramidDiskCache {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Hash of the image content that identifies its levels in the cache.
+    /// Stable across platforms and Rust versions, such that the cache survives updates.
+    pub fn image_key(image: &image::DynamicImage) -> u64 {
+        [
+            &CACHE_VERSION.to_le_bytes()[..],
+            &image.width().to_le_bytes(),
+            &image.height().to_le_bytes(),
+            format!("{:?}", image.color()).as_bytes(),
+            image.as_bytes(),
+        ]
+        .iter()
+        .fold(FNV_OFFSET_BASIS, |hash, bytes| fnv1a(hash, bytes))
+    }
+
+    fn path(&self, key: u64, size: u32) -> PathBuf {
+        self.dir.join(format!("{key:016x}_{size}.png"))
+    }
+
+    /// Returns the cached level of the given size, if any.
+    pub fn load(&self, key: u64, size: u32) -> Option<image::DynamicImage> {
+        let path = self.path(key, size);
+        if !path.exists() {
+            return None;
+        }
+        match image::open(&path) {
+            Ok(level) if level.width().max(level.height()) <= size => {
+                debug!("Loaded pyramid level {size} from {path:?}");
+                touch(&path);
+                Some(level)
+            }
+            Ok(_) => {
+                warn!("Ignoring cached pyramid level with unexpected size: {path:?}");
+                None
+            }
+            Err(e) => {
+                warn!("Ignoring broken cached pyramid level {path:?}: {e}");
+                None
+            }
+        }
+    }
+
+    /// Adds a level to the cache. Failures are only logged, the cache is optional.
+    /// Afterwards, the oldest files are removed until the cache is within its size limit.
+    pub fn store(&self, key: u64, size: u32, level: &image::DynamicImage) {
+        let path = self.path(key, size);
+        // Write to a temporary file first, such that other instances of the app
+        // never read partially written levels.
+        let tmp_path = path.with_extension("png.tmp");
+        let result = fs::create_dir_all(&self.dir)
+            .map_err(|e| e.to_string())
+            .and_then(|()| fs::File::create(&tmp_path).map_err(|e| e.to_string()))
+            .and_then(|file| {
+                // Fast compression, writing shall not take longer than building the level.
+                PngEncoder::new_with_quality(
+                    BufWriter::new(file),
+                    CompressionType::Fast,
+                    FilterType::Adaptive,
+                )
+                .write_image(
+                    level.as_bytes(),
+                    level.width(),
+                    level.height(),
+                    level.color().into(),
+                )
+                .map_err(|e| e.to_string())
+            })
+            .and_then(|()| fs::rename(&tmp_path, &path).map_err(|e| e.to_string()));
+        match result {
+            Ok(()) => {
+                debug!("Stored pyramid level {size} in {path:?}");
+                self.remove_least_recently_used(&path);
+            }
+            Err(e) => {
+                // E.g. float images can't be stored as PNG.
+                debug!("Can't store pyramid level {size} in {path:?}: {e}");
+                let _ = fs::remove_file(&tmp_path);
+            }
+        }
+    }
+
+    /// Files in the cache directory with their metadata.
+    fn files(&self) -> Vec<(PathBuf, fs::Metadata)> {
+        fs::read_dir(&self.dir).map_or_else(
+            |_| Vec::new(),
+            |entries| {
+                entries
+                    .filter_map(|entry| {
+                        let entry = entry.ok()?;
+                        Some((entry.path(), entry.metadata().ok()?))
+                    })
+                    .filter(|(_, metadata)| metadata.is_file())
+                    .collect()
+            },
+        )
+    }
+
+    /// Size of all files in the cache directory, in bytes.
+    pub fn size_on_disk(&self) -> u64 {
+        self.files()
+            .iter()
+            .map(|(_, metadata)| metadata.len())
+            .sum()
+    }
+
+    /// Removes the least recently used files until the cache is within its size limit.
+    /// The file at `keep` is not removed, even if it alone exceeds the limit.
+    fn remove_least_recently_used(&self, keep: &Path) {
+        let mut files = self.files();
+        let mut size: u64 = files.iter().map(|(_, metadata)| metadata.len()).sum();
+        if size <= self.max_bytes {
+            return;
+        }
+        files.sort_by_key(|(_, metadata)| metadata.modified().ok());
+        for (path, metadata) in files {
+            if size <= self.max_bytes {
+                break;
+            }
+            if path == keep {
+                continue;
+            }
+            match fs::remove_file(&path) {
+                Ok(()) => {
+                    debug!("Removed {path:?} to stay within the cache size limit.");
+                    size -= metadata.len();
+                }
+                Err(e) => warn!("Failed to remove cached pyramid level {path:?}: {e}"),
+            }
+        }
+    }
+
+    /// Removes all cached levels.
+    pub fn clear(&self) -> std::io::Result<()> {
+        if self.dir.exists() {
+            fs::remove_dir_all(&self.dir)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cache in an empty temporary directory.
+    fn test_cache(name: &str) -> PyramidDiskCache {
+        let dir =
+            std::env::temp_dir().join(format!("maps_rendering_test_{name}_{}", std::process::id()));
+        let cache = PyramidDiskCache::new(dir);
+        cache.clear().expect("Failed to clear test cache");
+        cache
+    }
+
+    fn test_image(width: u32, height: u32) -> image::DynamicImage {
+        image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(width, height, |x, y| {
+            image::Rgba([x as u8, y as u8, (x + y) as u8, 255])
+        }))
+    }
+
+    #[test]
+    fn store_and_load() {
+        let cache = test_cache("round_trip");
+        let level = test_image(20, 10);
+        let key = PyramidDiskCache::image_key(&level);
+        assert!(cache.load(key, 20).is_none());
+
+        cache.store(key, 20, &level);
+        let loaded = cache.load(key, 20).expect("Level not cached");
+        assert_eq!(loaded.as_bytes(), level.as_bytes());
+        assert_eq!(loaded.color(), level.color());
+        // Other sizes and images are not mixed up.
+        assert!(cache.load(key, 10).is_none());
+        assert!(cache.load(key + 1, 20).is_none());
+        assert!(cache.size_on_disk() > 0);
+
+        cache.clear().expect("Failed to clear test cache");
+        assert!(cache.load(key, 20).is_none());
+        assert_eq!(cache.size_on_disk(), 0);
+    }
+
+    #[test]
+    fn image_key_is_stable() {
+        // Changes of the key invalidate existing caches, bump CACHE_VERSION instead.
+        assert_eq!(fnv1a(FNV_OFFSET_BASIS, b""), FNV_OFFSET_BASIS);
+        assert_eq!(fnv1a(FNV_OFFSET_BASIS, b"a"), 0xaf63_dc4c_8601_ec8c);
+        let image = test_image(2, 1);
+        assert_eq!(
+            PyramidDiskCache::image_key(&image),
+            PyramidDiskCache::image_key(&image.clone())
+        );
+        assert_ne!(
+            PyramidDiskCache::image_key(&image),
+            PyramidDiskCache::image_key(&test_image(1, 2))
+        );
+    }
+
+    #[test]
+    fn levels_of_unexpected_size_are_ignored() {
+        let cache = test_cache("size_mismatch");
+        let level = test_image(20, 10);
+        // E.g. an outdated or foreign file with a matching name.
+        cache.store(1, 16, &level);
+        assert!(cache.load(1, 16).is_none());
+        cache.clear().expect("Failed to clear test cache");
+    }
+
+    #[test]
+    fn least_recently_used_levels_are_removed_above_the_size_limit() {
+        let cache = test_cache("size_limit");
+        let level = test_image(64, 64);
+        cache.store(1, 64, &level);
+        let level_bytes = cache.size_on_disk();
+        let cache = cache.with_max_bytes(2 * level_bytes);
+
+        // Distinct modification times, the order is not defined otherwise.
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        cache.store(2, 64, &level);
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        // Loading a level marks it as recently used, i.e. the unused level 2 is removed.
+        assert!(cache.load(1, 64).is_some());
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        cache.store(3, 64, &level);
+        assert!(cache.load(2, 64).is_none());
+        assert!(cache.load(1, 64).is_some());
+        assert!(cache.load(3, 64).is_some());
+        assert_eq!(cache.size_on_disk(), 2 * level_bytes);
+
+        // The newest level is kept even if it exceeds the limit alone.
+        let cache = cache.with_max_bytes(0);
+        cache.store(4, 64, &level);
+        assert!(cache.load(4, 64).is_some());
+        assert_eq!(cache.size_on_disk(), level_bytes);
+        cache.clear().expect("Failed to clear test cache");
+    }
+}
@@ -1,7 +1,7 @@
 use eframe::egui;
 use serde::{Deserialize, Serialize};
 
-/// Textures above this size should be cropped.
+/// Textures above this size should be cropped, i.e. shown as tiles of the visible region.
 pub const fn default_crop_threshold() -> u32 {
     6000
 }
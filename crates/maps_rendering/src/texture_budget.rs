@@ -0,0 +1,358 @@
+//! Memory budget for the textures of all [`crate::TextureState`]s of an egui context.
+//!
+//! The budget owns the texture handles that texture states upload, texture states only
+//! keep the names of their textures. When the budget is exceeded, the least recently
+//! used textures are freed and re-uploaded by their texture state when needed again.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+
+use eframe::egui;
+use log::trace;
+
+/// Default texture memory budget in MiB.
+pub const fn default_texture_budget_mb() -> u32 {
+    1024
+}
+
+/// Upper limit for the texture memory budget in MiB, e.g. for UI inputs.
+pub const MAX_TEXTURE_BUDGET_MB: u32 = 64 * 1024;
+
+const BYTES_PER_MB: usize = 1024 * 1024;
+
+/// Converts MiB to bytes, saturating where `usize` is 32 bits (wasm).
+fn mb_to_bytes(mb: u32) -> usize {
+    usize::try_from(mb)
+        .unwrap_or(usize::MAX)
+        .saturating_mul(BYTES_PER_MB)
+}
+
+/// Identifies the texture state that uploaded a texture.
+/// Textures of dropped owners are freed with the next [`TextureBudget::update`].
+pub(crate) struct TextureOwner {
+    id: u64,
+    alive: Arc<()>,
+}
+
+impl Default for TextureOwner {
+    fn default() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        TextureOwner {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            alive: Arc::new(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct TextureKey {
+    owner: u64,
+    name: String,
+}
+
+#[derive(Clone)]
+struct BudgetEntry {
+    texture_handle: egui::TextureHandle,
+    bytes: usize,
+    last_used_pass: u64,
+    owner_alive: Weak<()>,
+}
+
+impl BudgetEntry {
+    fn usage(&self) -> TextureUsage {
+        TextureUsage {
+            bytes: self.bytes,
+            last_used_pass: self.last_used_pass,
+            owner_alive: self.owner_alive.strong_count() > 0,
+        }
+    }
+}
+
+/// What's known about a texture to decide whether it's freed, see [`select_textures_to_free`].
+#[derive(Clone, Copy, Debug)]
+struct TextureUsage {
+    bytes: usize,
+    last_used_pass: u64,
+    owner_alive: bool,
+}
+
+/// Textures selected by [`select_textures_to_free`].
+#[derive(Debug, PartialEq)]
+struct TexturesToFree<K> {
+    /// Textures of dropped owners.
+    dropped: Vec<K>,
+    /// Least recently used textures that are evicted to stay within the budget.
+    evicted: Vec<K>,
+}
+
+/// Selects all textures of dropped owners, and the least recently used textures
+/// until the remaining ones fit into `max_bytes`.
+/// Textures used in the current `pass` are kept, i.e. the budget can be exceeded
+/// temporarily if the visible textures alone need more memory.
+fn select_textures_to_free<'a, K: Clone + 'a>(
+    textures: impl Iterator<Item = (&'a K, TextureUsage)> + Clone,
+    max_bytes: usize,
+    pass: u64,
+) -> TexturesToFree<K> {
+    let mut used_bytes: usize = textures.clone().map(|(_, usage)| usage.bytes).sum();
+    let mut to_free = TexturesToFree {
+        dropped: Vec::new(),
+        evicted: Vec::new(),
+    };
+    for (key, usage) in textures.clone().filter(|(_, usage)| !usage.owner_alive) {
+        used_bytes -= usage.bytes;
+        to_free.dropped.push(key.clone());
+    }
+    if used_bytes <= max_bytes {
+        return to_free;
+    }
+
+    let mut candidates: Vec<_> = textures
+        .filter(|(_, usage)| usage.owner_alive && usage.last_used_pass != pass)
+        .collect();
+    candidates.sort_by_key(|(_, usage)| usage.last_used_pass);
+    for (key, usage) in candidates {
+        if used_bytes <= max_bytes {
+            break;
+        }
+        used_bytes -= usage.bytes;
+        to_free.evicted.push(key.clone());
+    }
+    to_free
+}
+
+/// Texture memory usage of an egui context, see [`TextureBudget::usage`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TextureMemoryUsage {
+    pub used_bytes: usize,
+    pub max_bytes: usize,
+    pub num_textures: usize,
+    /// Number of textures that were evicted to stay within the budget, in total.
+    pub num_evicted: usize,
+}
+
+/// Tracks the bytes of all uploaded textures and evicts least-recently-used ones.
+/// Stored in the temporary data of the egui context, i.e. there is one per context.
+#[derive(Clone)]
+pub struct TextureBudget {
+    entries: HashMap<TextureKey, BudgetEntry>,
+    used_bytes: usize,
+    max_bytes: usize,
+    num_evicted: usize,
+}
+
+impl Default for TextureBudget {
+    fn default() -> Self {
+        TextureBudget {
+            entries: HashMap::new(),
+            used_bytes: 0,
+            max_bytes: mb_to_bytes(default_texture_budget_mb()),
+            num_evicted: 0,
+        }
+    }
+}
+
+impl TextureBudget {
+    fn with<R>(ctx: &egui::Context, f: impl FnOnce(&mut TextureBudget) -> R) -> R {
+        ctx.data_mut(|data| {
+            f(data.get_temp_mut_or_default::<TextureBudget>(egui::Id::new(
+                "maps_rendering_texture_budget",
+            )))
+        })
+    }
+
+    /// Sets the budget in MiB and frees textures of dropped texture states.
+    /// Evicts textures if the budget is exceeded. Call this once per frame.
+    pub fn update(ctx: &egui::Context, max_mb: u32) {
+        let pass = ctx.cumulative_pass_nr();
+        // Drop the handles outside of the context lock.
+        let _freed = Self::with(ctx, |budget| {
+            budget.max_bytes = mb_to_bytes(max_mb);
+            budget.free_unused(pass)
+        });
+    }
+
+    /// Returns the current usage of the texture budget.
+    pub fn usage(ctx: &egui::Context) -> TextureMemoryUsage {
+        Self::with(ctx, |budget| TextureMemoryUsage {
+            used_bytes: budget.used_bytes,
+            max_bytes: budget.max_bytes,
+            num_textures: budget.entries.len(),
+            num_evicted: budget.num_evicted,
+        })
+    }
+
+    /// Returns a texture of the owner if it was not evicted, and marks it as used.
+    pub(crate) fn get(
+        ctx: &egui::Context,
+        owner: &TextureOwner,
+        name: &str,
+    ) -> Option<egui::TextureHandle> {
+        let pass = ctx.cumulative_pass_nr();
+        let key = TextureKey {
+            owner: owner.id,
+            name: name.to_string(),
+        };
+        Self::with(ctx, |budget| {
+            let entry = budget.entries.get_mut(&key)?;
+            entry.last_used_pass = pass;
+            Some(entry.texture_handle.clone())
+        })
+    }
+
+    /// Uploads a texture for the owner, replacing one with the same name.
+    /// Least recently used textures are evicted if the budget is exceeded.
+    pub(crate) fn load(
+        ctx: &egui::Context,
+        owner: &TextureOwner,
+        name: String,
+        image: egui::ColorImage,
+        options: egui::TextureOptions,
+    ) -> egui::TextureHandle {
+        let bytes = image.width() * image.height() * std::mem::size_of::<egui::Color32>();
+        let texture_handle = ctx.load_texture(name.clone(), image, options);
+        let pass = ctx.cumulative_pass_nr();
+        let entry = BudgetEntry {
+            texture_handle: texture_handle.clone(),
+            bytes,
+            last_used_pass: pass,
+            owner_alive: Arc::downgrade(&owner.alive),
+        };
+        let key = TextureKey {
+            owner: owner.id,
+            name,
+        };
+        // Drop the handles outside of the context lock.
+        let _freed = Self::with(ctx, |budget| {
+            let mut freed: Vec<_> = budget.remove(&key).into_iter().collect();
+            budget.used_bytes += entry.bytes;
+            budget.entries.insert(key, entry);
+            freed.extend(budget.free_unused(pass));
+            freed
+        });
+        texture_handle
+    }
+
+    /// Frees a texture of the owner, if it exists.
+    pub(crate) fn free(ctx: &egui::Context, owner: &TextureOwner, name: &str) {
+        let key = TextureKey {
+            owner: owner.id,
+            name: name.to_string(),
+        };
+        let _freed = Self::with(ctx, |budget| budget.remove(&key));
+    }
+
+    fn remove(&mut self, key: &TextureKey) -> Option<BudgetEntry> {
+        let entry = self.entries.remove(key)?;
+        self.used_bytes -= entry.bytes;
+        Some(entry)
+    }
+
+    /// Removes textures of dropped owners, and least recently used textures
+    /// until the budget is met, see [`select_textures_to_free`].
+    fn free_unused(&mut self, pass: u64) -> Vec<BudgetEntry> {
+        let textures = self.entries.iter().map(|(key, entry)| (key, entry.usage()));
+        let to_free = select_textures_to_free(textures, self.max_bytes, pass);
+        for key in &to_free.evicted {
+            trace!("Evicting texture {} to stay within budget.", key.name);
+        }
+        self.num_evicted += to_free.evicted.len();
+        to_free
+            .dropped
+            .iter()
+            .chain(&to_free.evicted)
+            .filter_map(|key| self.remove(key))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(bytes: usize, last_used_pass: u64) -> TextureUsage {
+        TextureUsage {
+            bytes,
+            last_used_pass,
+            owner_alive: true,
+        }
+    }
+
+    fn select(
+        textures: &[(&'static str, TextureUsage)],
+        max_bytes: usize,
+    ) -> TexturesToFree<&'static str> {
+        let textures = textures.iter().map(|(key, usage)| (key, *usage));
+        select_textures_to_free(textures, max_bytes, 10)
+    }
+
+    #[test]
+    fn nothing_is_freed_within_budget() {
+        let textures = [("a", usage(100, 1)), ("b", usage(100, 2))];
+        assert_eq!(
+            select(&textures, 200),
+            TexturesToFree {
+                dropped: vec![],
+                evicted: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn least_recently_used_textures_are_evicted_first() {
+        let textures = [
+            ("recent", usage(100, 9)),
+            ("oldest", usage(100, 1)),
+            ("old", usage(100, 5)),
+            ("older", usage(100, 3)),
+        ];
+        let to_free = select(&textures, 200);
+        assert_eq!(to_free.evicted, vec!["oldest", "older"]);
+        assert!(to_free.dropped.is_empty());
+    }
+
+    #[test]
+    fn textures_of_the_current_pass_are_kept() {
+        let textures = [
+            ("visible_1", usage(100, 10)),
+            ("visible_2", usage(100, 10)),
+            ("hidden", usage(100, 9)),
+        ];
+        // The visible textures alone exceed the budget.
+        assert_eq!(select(&textures, 150).evicted, vec!["hidden"]);
+    }
+
+    #[test]
+    fn textures_of_dropped_owners_are_freed() {
+        let dropped = TextureUsage {
+            owner_alive: false,
+            ..usage(300, 10)
+        };
+        let textures = [("old", usage(100, 1)), ("dropped", dropped)];
+        // Freeing the dropped owner's texture is enough to meet the budget.
+        assert_eq!(
+            select(&textures, 100),
+            TexturesToFree {
+                dropped: vec!["dropped"],
+                evicted: vec![],
+            }
+        );
+        // Also within the budget.
+        assert_eq!(select(&textures, 1000).dropped, vec!["dropped"]);
+    }
+
+    #[test]
+    fn budget_in_bytes() {
+        assert_eq!(mb_to_bytes(3), 3 * 1024 * 1024);
+        assert_eq!(
+            mb_to_bytes(MAX_TEXTURE_BUDGET_MB) as u64,
+            u64::from(MAX_TEXTURE_BUDGET_MB) * 1024 * 1024
+        );
+        // Saturates instead of overflowing with 32-bit usize.
+        assert_eq!(
+            mb_to_bytes(u32::MAX) as u64,
+            (u64::from(u32::MAX) * 1024 * 1024).min(usize::MAX as u64)
+        );
+    }
+}
@@ -3,13 +3,13 @@ use std::collections::HashMap;
 use eframe::egui;
 use log::trace;
 
+use crate::texture_budget::{TextureBudget, TextureOwner};
 use crate::texture_request::TextureRequest;
 use maps_io_ros::ValueInterpretation;
 
-/// A cached texture handle with its appearance properties.
+/// Appearance properties of a cached texture.
 #[derive(Clone)]
 struct CachedTexture {
-    texture_handle: egui::TextureHandle,
     color_to_alpha: Option<egui::Color32>,
     thresholding: Option<ValueInterpretation>,
     texture_options: Option<egui::TextureOptions>,
@@ -24,10 +24,12 @@ impl CachedTexture {
     }
 }
 
-/// Cache for texture handles to avoid reloading textures when switching resolution levels,
+/// Cache for textures to avoid reloading textures when switching resolution levels,
 /// unless some appearance properties change and a new texture needs to be created.
+/// The textures are owned by the [`TextureBudget`], i.e. they may be evicted.
 #[derive(Default)]
 pub struct TextureCache {
+    /// Cached textures by their name in the texture budget.
     cache: HashMap<String, CachedTexture>,
 }
 
@@ -43,41 +45,49 @@ impl TextureCache {
     }
 
     /// Queries the cache for a texture that matches the client, level, and appearance requirements.
-    /// Returns None on cache-miss.
+    /// Returns the texture name and handle, or None on cache-miss.
     pub fn query(
         &mut self,
+        ctx: &egui::Context,
+        owner: &TextureOwner,
         client: &str,
         pyramid_level: u32,
         request: &TextureRequest,
-    ) -> Option<egui::TextureHandle> {
+    ) -> Option<(String, egui::TextureHandle)> {
         let cache_key = Self::generate_key(client, pyramid_level);
+        let cached_texture = self.cache.get(&cache_key)?;
 
-        if let Some(cached_texture) = self.cache.get(&cache_key) {
-            if cached_texture.matches_appearance(request) {
-                return Some(cached_texture.texture_handle.clone());
+        if cached_texture.matches_appearance(request) {
+            if let Some(texture_handle) = TextureBudget::get(ctx, owner, &cache_key) {
+                return Some((cache_key, texture_handle));
             }
+            trace!("Cached texture of client {client} at level {pyramid_level} was evicted.");
+        } else {
             trace!("Cache miss for client {client} at level {pyramid_level}.");
-            // Remove outdated cache entry.
-            self.cache.remove(&cache_key);
+            TextureBudget::free(ctx, owner, &cache_key);
         }
-
+        // Remove outdated cache entry.
+        self.cache.remove(&cache_key);
         None
     }
 
-    pub fn store(
-        &mut self,
-        client: &str,
-        pyramid_level: u32,
-        texture_handle: egui::TextureHandle,
-        request: &TextureRequest,
-    ) {
+    /// Remembers the appearance of a texture that is loaded into the texture budget
+    /// with the returned name.
+    pub fn store(&mut self, client: &str, pyramid_level: u32, request: &TextureRequest) -> String {
         let cache_key = Self::generate_key(client, pyramid_level);
         let cached_texture = CachedTexture {
-            texture_handle,
             color_to_alpha: request.color_to_alpha,
             thresholding: request.thresholding,
             texture_options: request.texture_options,
         };
-        self.cache.insert(cache_key, cached_texture);
+        self.cache.insert(cache_key.clone(), cached_texture);
+        cache_key
+    }
+
+    /// Frees all cached textures, e.g. when the image pyramid levels changed.
+    pub fn clear(&mut self, ctx: &egui::Context, owner: &TextureOwner) {
+        for (cache_key, _) in self.cache.drain() {
+            TextureBudget::free(ctx, owner, &cache_key);
+        }
     }
 }
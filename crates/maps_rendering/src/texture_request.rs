@@ -1,8 +1,9 @@
 use eframe::egui;
 use eframe::emath::GuiRounding as _;
 
+use crate::image_pyramid::AppearanceKey;
 use crate::rect_helpers::{debug_paint, quantized_intersection, rotate_aabb};
-use maps_io_ros::ValueInterpretation;
+use maps_io_ros::{MapPose, Meta, ValueInterpretation};
 
 pub const NO_TINT: egui::Color32 = egui::Color32::WHITE;
 
@@ -73,6 +74,14 @@ impl TextureRequest {
         self.texture_options = Some(texture_options);
         self
     }
+
+    /// The settings of the request that change the pixels of the image.
+    pub fn appearance_key(&self) -> AppearanceKey {
+        AppearanceKey {
+            color_to_alpha: self.color_to_alpha,
+            thresholding: self.thresholding,
+        }
+    }
 }
 
 /// Extended request for rendering scaled textures with arbitrary rotated pose
@@ -110,6 +119,54 @@ pub struct ImagePlacement {
     pub original_image_size: egui::Vec2,
 }
 
+/// Switches from left- to right-handed coordinate system or vice versa.
+/// Screen is a LHS system.
+pub fn flip(vec: egui::Vec2) -> egui::Vec2 {
+    vec * egui::vec2(1., -1.)
+}
+
+impl ImagePlacement {
+    /// Placement of a map image in a metric view with `points_per_meter` scale,
+    /// where the metric origin is at `origin_in_points`.
+    /// The image is placed according to the origin of its metadata and its map pose.
+    pub fn from_map(
+        meta: &Meta,
+        pose: &MapPose,
+        original_image_size: egui::Vec2,
+        points_per_meter: f32,
+        origin_in_points: egui::Pos2,
+    ) -> ImagePlacement {
+        let points_per_texel = points_per_meter * meta.resolution;
+        let scaled_size = original_image_size * points_per_texel;
+
+        // Meta origin is lower left corner of image in ROS.
+        let llc_to_origin_in_points = flip(meta.origin_xy) * points_per_meter;
+        let ulc_to_origin_in_points = llc_to_origin_in_points - egui::Vec2::new(0., scaled_size.y);
+        let translation_in_points = flip(pose.vec2()) * points_per_meter;
+
+        ImagePlacement {
+            // RHS to LHS.
+            rotation: pose.rot2().inverse() * meta.origin_theta.inverse(),
+            translation: origin_in_points.to_vec2()
+                + translation_in_points
+                + ulc_to_origin_in_points,
+            rotation_center: ulc_to_origin_in_points,
+            points_per_texel,
+            original_image_size,
+        }
+    }
+
+    /// Size of the placed image in points, before rotation.
+    pub fn scaled_size(&self) -> egui::Vec2 {
+        self.original_image_size * self.points_per_texel
+    }
+
+    /// Position of the rotation center in points relative to the viewport.
+    pub(crate) fn rotation_center_in_viewport(&self) -> egui::Pos2 {
+        (self.translation - self.rotation_center).to_pos2()
+    }
+}
+
 impl TransformedTextureRequest {
     /// Returns true if this request represents a full texture (not a crop).
     pub fn is_full_texture(&self) -> bool {
@@ -180,7 +237,8 @@ impl TransformedTextureRequest {
     /// in the visible viewport of the `ui`.
     /// `crop_threshold` controls the maximum size of a texture before it gets
     /// cropped to the viewport. Use this to support displaying large images
-    /// at high zoom levels as cropped textures to avoid texture buffer size limits.
+    /// at high zoom levels to avoid texture buffer size limits.
+    /// [`crate::TextureState::transform_and_put`] shows cropped requests as tiles.
     pub fn from_visible(
         paint_context: &egui::Painter,
         base_request: TextureRequest,
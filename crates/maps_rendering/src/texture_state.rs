@@ -1,16 +1,22 @@
 use std::sync::Arc;
 
 use eframe::egui;
+use eframe::emath::GuiRounding as _;
 use log::trace;
 
-use crate::image::{color_to_alpha, fit_image, to_egui_image};
+use crate::image::{apply_appearance, fit_image, to_egui_image};
 use crate::image_pyramid::ImagePyramid;
+use crate::texture_budget::{TextureBudget, TextureOwner};
 use crate::texture_cache::TextureCache;
 use crate::texture_request::{TextureRequest, TransformedTextureRequest};
+use crate::texture_tiles::{TileCache, TileKey, TileSource, tiles_covering};
 use maps_io_ros::ValueInterpretation;
 
 /// Manages the state of a texture across its lifetime.
 /// Has to be updated every frame using texture requests.
+///
+/// The uploaded textures are owned by the [`TextureBudget`] of the egui context,
+/// evicted textures are uploaded again when they are needed.
 #[derive(Default)]
 pub struct TextureState {
     /// Image pyramid with source images for different zoom levels.
@@ -18,15 +24,21 @@ pub struct TextureState {
     // Use init() to set it.
     pub image_pyramid: Arc<ImagePyramid>,
     pub image_response: Option<egui::Response>,
+    /// Identifies the textures of this state in the texture budget.
+    owner: TextureOwner,
     /// Cache of textures for different sizes and appearance settings.
     texture_cache: TextureCache,
-    /// Currently active texture (reference to one in the cache).
-    pub texture_handle: Option<egui::TextureHandle>,
+    /// Tiles of the pyramid levels for textures that are cropped to the viewport.
+    tile_cache: TileCache,
+    /// Name of the currently active texture in the texture budget.
+    texture_name: Option<String>,
     pub desired_size: egui::Vec2,
     pub desired_crop_uv: [egui::Pos2; 2],
     pub desired_color_to_alpha: Option<egui::Color32>,
     pub desired_thresholding: Option<ValueInterpretation>,
     pub used_level: u32,
+    /// Generation of the image pyramid that the texture was created from.
+    pyramid_generation: usize,
     pub texture_options: egui::TextureOptions,
 }
 
@@ -41,7 +53,15 @@ impl TextureState {
 
     /// Returns true if the request changes the texture and requires re-rendering.
     fn changed(&self, request: &TextureRequest) -> bool {
-        self.desired_size != request.desired_rect.size() || self.changed_appearance(request)
+        self.desired_size != request.desired_rect.size()
+            || self.changed_appearance(request)
+            || self.changed_pyramid()
+    }
+
+    /// Returns true if pyramid levels were added or replaced since the texture was created,
+    /// e.g. by a background thread that is still building the pyramid.
+    fn changed_pyramid(&self) -> bool {
+        self.pyramid_generation != self.image_pyramid.generation()
     }
 
     /// Returns true if the appearance of the texture changed (not checking size).
@@ -51,52 +71,67 @@ impl TextureState {
             || self.texture_options != request.texture_options.unwrap_or_default()
     }
 
+    /// Returns the active texture, unless it was evicted from the texture budget.
+    fn active_texture(&self, ctx: &egui::Context) -> Option<egui::TextureHandle> {
+        let name = self.texture_name.as_ref()?;
+        TextureBudget::get(ctx, &self.owner, name)
+    }
+
     /// Updates the texture state for a new incoming request, if needed.
     /// Chooses the appropriate level from the image pyramid.
-    fn update(&mut self, ui: &egui::Ui, request: &TextureRequest) {
-        if self.changed(request) {
-            // Free the old texture if the size changed.
-            self.texture_handle = None;
-        }
+    fn update(&mut self, ui: &egui::Ui, request: &TextureRequest) -> egui::TextureHandle {
+        let active_texture = if self.changed(request) {
+            None
+        } else {
+            self.active_texture(ui.ctx())
+        };
         self.desired_size = request.desired_rect.size();
         self.desired_crop_uv = [egui::Pos2::ZERO, egui::pos2(1., 1.)];
         self.desired_color_to_alpha = request.color_to_alpha;
         self.desired_thresholding = request.thresholding;
         self.texture_options = request.texture_options.unwrap_or_default();
-        self.texture_handle.get_or_insert_with(|| {
-            // Load the texture only if needed.
-            trace!("Fitting and reloading texture for {request:?}");
+        self.pyramid_generation = self.image_pyramid.generation();
+        if let Some(texture_handle) = active_texture {
+            return texture_handle;
+        }
+
+        // Load the texture only if needed, replacing the old one.
+        trace!("Fitting and reloading texture for {request:?}");
+        let image = if let Some(level) = self
+            .image_pyramid
+            .get_level_with_appearance(self.desired_size, &request.appearance_key())
+        {
+            fit_image(&level, self.desired_size)
+        } else {
             let mut image = fit_image(
-                self.image_pyramid.get_level(self.desired_size),
+                &self.image_pyramid.get_level(self.desired_size),
                 self.desired_size,
             );
-            color_to_alpha(&mut image, request.color_to_alpha);
-            if let Some(thresholding) = &request.thresholding {
-                thresholding.apply(&mut image, self.image_pyramid.original_has_alpha);
-            }
-            ui.ctx().load_texture(
-                request.client.clone(),
-                to_egui_image(&image),
-                self.texture_options,
-            )
-        });
+            apply_appearance(
+                &mut image,
+                request.color_to_alpha,
+                request.thresholding.as_ref(),
+                self.image_pyramid.original_has_alpha,
+            );
+            image
+        };
+        self.texture_name = Some(request.client.clone());
+        TextureBudget::load(
+            ui.ctx(),
+            &self.owner,
+            request.client.clone(),
+            to_egui_image(&image),
+            self.texture_options,
+        )
     }
 
     /// Updates the state and puts the texture into the UI according to the request.
     pub fn put(&mut self, ui: &mut egui::Ui, request: &TextureRequest) {
-        self.update(ui, request);
-
-        match &self.texture_handle {
-            Some(texture) => {
-                self.image_response = Some(
-                    ui.add(egui::Image::new(texture).tint(request.tint))
-                        .interact(request.sense),
-                );
-            }
-            None => {
-                panic!("Missing texture handle for {}", request.client)
-            }
-        }
+        let texture = self.update(ui, request);
+        self.image_response = Some(
+            ui.add(egui::Image::new(&texture).tint(request.tint))
+                .interact(request.sense),
+        );
     }
 
     /// Returns true if the request changes the image cropping.
@@ -104,54 +139,58 @@ impl TextureState {
         self.desired_crop_uv != request.crop_uv
     }
 
-    /// Tries to find and use a cached texture for full (non-cropped) textures.
-    /// Returns true if a cached texture was found and applied, false otherwise.
+    /// Tries to find and use a cached texture of the pyramid level for the desired size.
+    /// Returns the texture if a cached one was found and applied.
     fn try_use_cached_texture(
         &mut self,
+        ctx: &egui::Context,
         request: &TransformedTextureRequest,
         desired_size: egui::Vec2,
-    ) -> bool {
-        // Only cache full textures (not crops).
-        if !request.is_full_texture() {
-            return false;
-        }
-
+    ) -> Option<egui::TextureHandle> {
         let uncropped = self.image_pyramid.get_level(desired_size);
         let level = uncropped.width().max(uncropped.height());
 
         // Check if we have a cached texture that matches appearance.
-        if let Some(texture_handle) =
-            self.texture_cache
-                .query(&request.base_request.client, level, &request.base_request)
-        {
-            // Reuse cached texture and update state.
-            self.texture_handle = Some(texture_handle);
-            self.used_level = level;
-            self.desired_size = desired_size;
-            self.desired_crop_uv = request.crop_uv;
-            self.desired_color_to_alpha = request.base_request.color_to_alpha;
-            self.desired_thresholding = request.base_request.thresholding;
-            self.texture_options = request.base_request.texture_options.unwrap_or_default();
-            return true;
-        }
-
-        false
+        let (name, texture_handle) = self.texture_cache.query(
+            ctx,
+            &self.owner,
+            &request.base_request.client,
+            level,
+            &request.base_request,
+        )?;
+        // Reuse cached texture and update state.
+        self.texture_name = Some(name);
+        self.used_level = level;
+        self.desired_size = desired_size;
+        self.desired_crop_uv = request.crop_uv;
+        self.desired_color_to_alpha = request.base_request.color_to_alpha;
+        self.desired_thresholding = request.base_request.thresholding;
+        self.texture_options = request.base_request.texture_options.unwrap_or_default();
+        Some(texture_handle)
     }
 
-    /// Updates the texture state for a new incoming crop/rotate request, if needed.
-    /// Chooses the appropriate level from the image pyramid and crops if required.
-    /// Full (non-cropped) textures are cached to avoid reloading when zooming.
+    /// Updates the texture state for a new incoming full (non-cropped) request, if needed.
+    /// Chooses the appropriate level from the image pyramid.
+    /// Full textures are cached to avoid reloading when zooming.
     ///
     /// Process:
-    /// 1. Try to reuse cached texture for full textures.
+    /// 1. Try to reuse a cached texture.
     /// 2. Check if any changes require creating a new texture.
-    /// 3. Create and optionally cache the new texture.
-    fn maybe_update_crop(&mut self, ui: &mut egui::Ui, request: &TransformedTextureRequest) {
+    /// 3. Create and cache the new texture.
+    fn maybe_update_full(
+        &mut self,
+        ui: &mut egui::Ui,
+        request: &TransformedTextureRequest,
+    ) -> Option<egui::TextureHandle> {
         let desired_size = request.base_request.desired_rect.size();
 
-        // Try to use cached texture for full textures.
-        if self.try_use_cached_texture(request, desired_size) {
-            return;
+        // Cached textures may be created from outdated pyramid levels.
+        if self.changed_pyramid() {
+            self.texture_cache.clear(ui.ctx(), &self.owner);
+        }
+
+        if let Some(texture_handle) = self.try_use_cached_texture(ui.ctx(), request, desired_size) {
+            return Some(texture_handle);
         }
 
         // Check if we need to create a new texture.
@@ -159,8 +198,10 @@ impl TextureState {
         let changed_crop = self.changed_crop(request);
         let changed_appearance = self.changed_appearance(&request.base_request);
 
-        if !(changed_base_request || changed_crop || changed_appearance) {
-            return;
+        if !(changed_base_request || changed_crop || changed_appearance)
+            && let Some(texture_handle) = self.active_texture(ui.ctx())
+        {
+            return Some(texture_handle);
         }
 
         self.desired_size = desired_size;
@@ -168,58 +209,133 @@ impl TextureState {
         self.desired_color_to_alpha = request.base_request.color_to_alpha;
         self.desired_thresholding = request.base_request.thresholding;
         self.texture_options = request.base_request.texture_options.unwrap_or_default();
+        self.pyramid_generation = self.image_pyramid.generation();
 
-        if request.crop_rect.is_negative() || request.crop_uv[0] == request.crop_uv[1] {
-            self.texture_handle = None;
-            return;
+        if request.crop_rect.is_negative() {
+            self.texture_name = None;
+            return None;
         }
 
-        let uncropped = self.image_pyramid.get_level(self.desired_size);
-        let level = uncropped.width().max(uncropped.height());
+        trace!("Reloading texture for {request:?}");
+        let image = self
+            .image_pyramid
+            .get_level_with_appearance(self.desired_size, &request.base_request.appearance_key())
+            .unwrap_or_else(|| {
+                let mut image = (*self.image_pyramid.get_level(self.desired_size)).clone();
+                apply_appearance(
+                    &mut image,
+                    request.base_request.color_to_alpha,
+                    request.base_request.thresholding.as_ref(),
+                    self.image_pyramid.original_has_alpha,
+                );
+                Arc::new(image)
+            });
+        let level = image.width().max(image.height());
         self.used_level = level;
 
-        trace!("Cropping and reloading texture for {request:?}");
-        let uv_min = request.crop_uv[0];
-        let uv_max = request.crop_uv[1];
-        let min_x = (uv_min.x * uncropped.width() as f32).round() as u32;
-        let min_y = (uv_min.y * uncropped.height() as f32).round() as u32;
-        let max_x = (uv_max.x * uncropped.width() as f32).round() as u32;
-        let max_y = (uv_max.y * uncropped.height() as f32).round() as u32;
-        let mut cropped_image = uncropped.crop_imm(min_x, min_y, max_x - min_x, max_y - min_y);
-        if cropped_image.width() == 0 || cropped_image.height() == 0 {
-            trace!("Crop resulted in empty image.");
-            self.texture_handle = None;
-            return;
+        let name =
+            self.texture_cache
+                .store(&request.base_request.client, level, &request.base_request);
+        let texture_handle = TextureBudget::load(
+            ui.ctx(),
+            &self.owner,
+            name.clone(),
+            to_egui_image(&image),
+            self.texture_options,
+        );
+        self.texture_name = Some(name);
+        Some(texture_handle)
+    }
+
+    /// Puts the visible part of a cropped texture into the UI as tiles of the pyramid level.
+    /// Tiles are uploaded when they become visible and are reused while panning,
+    /// instead of creating a new cropped texture whenever the crop changes.
+    fn put_tiles(&mut self, ui: &mut egui::Ui, request: &TransformedTextureRequest) {
+        let base_request = &request.base_request;
+        if self.changed_pyramid() {
+            // Cached textures and tiles may be created from outdated pyramid levels.
+            self.texture_cache.clear(ui.ctx(), &self.owner);
+            self.tile_cache.clear(ui.ctx(), &self.owner);
+        } else if self.changed_appearance(base_request) {
+            self.tile_cache.clear(ui.ctx(), &self.owner);
         }
-        color_to_alpha(&mut cropped_image, request.base_request.color_to_alpha);
-        if let Some(thresholding) = &request.base_request.thresholding {
-            thresholding.apply(&mut cropped_image, self.image_pyramid.original_has_alpha);
+        self.desired_size = base_request.desired_rect.size();
+        self.desired_crop_uv = request.crop_uv;
+        self.desired_color_to_alpha = base_request.color_to_alpha;
+        self.desired_thresholding = base_request.thresholding;
+        self.texture_options = base_request.texture_options.unwrap_or_default();
+        self.pyramid_generation = self.image_pyramid.generation();
+        // Tiles replace the single texture.
+        self.texture_name = None;
+        self.image_response = None;
+
+        if request.crop_rect.is_negative() || request.crop_uv[0] == request.crop_uv[1] {
+            return;
         }
 
-        let texture_handle = ui.ctx().load_texture(
-            format!("{}_{}", request.base_request.client, level),
-            to_egui_image(&cropped_image),
-            self.texture_options,
+        // Tiles of pyramid levels are cut from the level with the appearance applied,
+        // only tiles of the original image get the appearance applied individually.
+        let appearance = base_request.appearance_key();
+        let level_image = self
+            .image_pyramid
+            .get_level_with_appearance(self.desired_size, &appearance);
+        let source = match &level_image {
+            Some(level_image) => TileSource::Level(level_image),
+            None => TileSource::Original(&self.image_pyramid, &appearance),
+        };
+        let level_size = source.size();
+        let level = level_size[0].max(level_size[1]);
+        self.used_level = level;
+
+        // Scaled image coordinates (points) per texel of the level.
+        let scaled_rect = base_request.desired_rect;
+        let texel_size =
+            scaled_rect.size() / egui::vec2(level_size[0] as f32, level_size[1] as f32);
+        let visible_texels = egui::Rect::from_min_max(
+            ((request.crop_rect.min - scaled_rect.min) / texel_size).to_pos2(),
+            ((request.crop_rect.max - scaled_rect.min) / texel_size).to_pos2(),
         );
+        // The rotation center doesn't depend on the crop, all tiles rotate around it.
+        let rotation_center = request.crop_rect.min
+            + request.translation
+            + request.rotation_center_in_uv * request.crop_rect.size();
+        let pixels_per_point = ui.ctx().pixels_per_point();
 
-        // Cache full textures for zoom performance.
-        if request.is_full_texture() {
-            self.texture_cache.store(
-                &request.base_request.client,
-                level,
-                texture_handle.clone(),
-                &request.base_request,
-            );
+        let (columns, rows) = tiles_covering(visible_texels, level_size);
+        for row in rows {
+            for column in columns.clone() {
+                let (tile, texture_handle) = self.tile_cache.get_or_load(
+                    ui.ctx(),
+                    &self.owner,
+                    TileKey { level, column, row },
+                    &source,
+                    base_request,
+                );
+                let rect = egui::Rect::from_min_max(
+                    scaled_rect.min + tile.texel_rect.min.to_vec2() * texel_size,
+                    scaled_rect.min + tile.texel_rect.max.to_vec2() * texel_size,
+                )
+                .translate(request.translation)
+                .round_to_pixels(pixels_per_point);
+                let mut mesh = egui::Mesh::with_texture(texture_handle.id());
+                mesh.add_rect_with_uv(rect, tile.uv, base_request.tint);
+                mesh.rotate(request.rotation, rotation_center);
+                ui.painter().add(mesh);
+            }
         }
-
-        self.texture_handle = Some(texture_handle);
     }
 
     /// Updates the state and puts the texture into the UI according to the request.
+    /// Textures that are cropped to the viewport are put as tiles.
     pub fn transform_and_put(&mut self, ui: &mut egui::Ui, request: &TransformedTextureRequest) {
-        self.maybe_update_crop(ui, request);
+        if !request.is_full_texture() {
+            self.put_tiles(ui, request);
+            return;
+        }
+        // Free the tiles when the whole image fits into a single texture again.
+        self.tile_cache.clear(ui.ctx(), &self.owner);
 
-        if let Some(texture) = &self.texture_handle {
+        if let Some(texture) = &self.maybe_update_full(ui, request) {
             // Manually paint and get response.
             // ui.put() clips to the viewport, which is bad for rotated images.
             let image = egui::Image::new(texture)
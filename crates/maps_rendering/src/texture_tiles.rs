@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+
+use eframe::egui;
+use log::{error, trace};
+
+use crate::image::{apply_appearance, to_egui_image};
+use crate::image_pyramid::{AppearanceKey, ImagePyramid};
+use crate::texture_budget::{TextureBudget, TextureOwner};
+use crate::texture_request::TextureRequest;
+
+/// Width and height of the texture tiles, in texels of a pyramid level.
+pub const TILE_SIZE: u32 = 512;
+
+/// Identifies a tile by pyramid level and its column and row in that level.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TileKey {
+    pub level: u32,
+    pub column: u32,
+    pub row: u32,
+}
+
+impl TileKey {
+    /// Name of the tile's texture in the texture budget.
+    fn texture_name(&self, client: &str) -> String {
+        format!("{client}_{}_{}_{}", self.level, self.column, self.row)
+    }
+}
+
+/// Placement of an uploaded tile texture.
+pub struct Tile {
+    /// Name of the tile's texture in the texture budget.
+    texture_name: String,
+    /// UV rectangle of the tile in its texture, without the padding.
+    pub uv: egui::Rect,
+    /// Texel rectangle of the tile in its pyramid level, without the padding.
+    pub texel_rect: egui::Rect,
+}
+
+/// Returns the range of tile columns and rows that cover a texel rectangle
+/// of a pyramid level with the given size.
+pub fn tiles_covering(
+    texel_rect: egui::Rect,
+    level_size: [u32; 2],
+) -> (std::ops::Range<u32>, std::ops::Range<u32>) {
+    let range = |min: f32, max: f32, size: u32| {
+        // Only the part of the rectangle within the level is covered.
+        let (min, max) = (min.clamp(0., size as f32), max.clamp(0., size as f32));
+        if min >= max {
+            return 0..0;
+        }
+        let first = min as u32 / TILE_SIZE;
+        let end = (max / TILE_SIZE as f32).ceil() as u32;
+        first..end
+    };
+    (
+        range(texel_rect.min.x, texel_rect.max.x, level_size[0]),
+        range(texel_rect.min.y, texel_rect.max.y, level_size[1]),
+    )
+}
+
+/// Region of a tile in a pyramid level, see [`tile_region`].
+#[derive(Debug, PartialEq)]
+struct TileRegion {
+    /// Texel region that is read for the tile texture, including the padding.
+    padded_min: [u32; 2],
+    padded_size: [u32; 2],
+    /// UV rectangle of the tile in the padded texture, without the padding.
+    uv: egui::Rect,
+    /// Texel rectangle of the tile in its pyramid level, without the padding.
+    texel_rect: egui::Rect,
+}
+
+/// Returns the region of a tile in a pyramid level with the given size.
+/// Tiles at the right and bottom border of the level can be smaller than [`TILE_SIZE`].
+fn tile_region(key: TileKey, level_size: [u32; 2]) -> TileRegion {
+    let [width, height] = level_size;
+    let min = [key.column * TILE_SIZE, key.row * TILE_SIZE];
+    let max = [
+        (min[0] + TILE_SIZE).min(width),
+        (min[1] + TILE_SIZE).min(height),
+    ];
+    // One texel of padding towards neighboring tiles, such that the
+    // interpolation at the tile borders doesn't show seams.
+    let padded_min = [min[0].saturating_sub(1), min[1].saturating_sub(1)];
+    let padded_max = [(max[0] + 1).min(width), (max[1] + 1).min(height)];
+    let padded_size = [padded_max[0] - padded_min[0], padded_max[1] - padded_min[1]];
+
+    let uv = |texel: [u32; 2]| {
+        egui::pos2(
+            (texel[0] - padded_min[0]) as f32 / padded_size[0] as f32,
+            (texel[1] - padded_min[1]) as f32 / padded_size[1] as f32,
+        )
+    };
+    let texel = |texel: [u32; 2]| egui::pos2(texel[0] as f32, texel[1] as f32);
+    TileRegion {
+        padded_min,
+        padded_size,
+        uv: egui::Rect::from_min_max(uv(min), uv(max)),
+        texel_rect: egui::Rect::from_min_max(texel(min), texel(max)),
+    }
+}
+
+/// Image that tiles are cut from.
+pub enum TileSource<'a> {
+    /// A pyramid level that already has the appearance applied.
+    Level(&'a image::DynamicImage),
+    /// The full-resolution image of a pyramid, possibly streamed from its file.
+    /// The appearance is applied to each tile.
+    Original(&'a ImagePyramid, &'a AppearanceKey),
+}
+
+impl TileSource<'_> {
+    /// Width and height of the source in texels.
+    pub fn size(&self) -> [u32; 2] {
+        match self {
+            TileSource::Level(level_image) => [level_image.width(), level_image.height()],
+            TileSource::Original(image_pyramid, _) => [
+                image_pyramid.original_size.x as u32,
+                image_pyramid.original_size.y as u32,
+            ],
+        }
+    }
+
+    /// Returns a region of the source with the appearance applied.
+    /// Regions that can't be read are transparent.
+    fn read(&self, x: u32, y: u32, width: u32, height: u32) -> image::DynamicImage {
+        match self {
+            TileSource::Level(level_image) => level_image.crop_imm(x, y, width, height),
+            TileSource::Original(image_pyramid, appearance) => {
+                let mut image = image_pyramid
+                    .read_original_region(x, y, width, height)
+                    .unwrap_or_else(|e| {
+                        error!("{e}");
+                        image::DynamicImage::new_rgba8(width, height)
+                    });
+                apply_appearance(
+                    &mut image,
+                    appearance.color_to_alpha,
+                    appearance.thresholding.as_ref(),
+                    image_pyramid.original_has_alpha,
+                );
+                image
+            }
+        }
+    }
+}
+
+/// Lazily uploaded, fixed-size texture tiles of image pyramid levels.
+/// Tiles are reused while panning, only tiles that become visible are uploaded.
+/// The tile textures are owned by the [`TextureBudget`], evicted ones are uploaded again.
+#[derive(Default)]
+pub struct TileCache {
+    tiles: HashMap<TileKey, Tile>,
+}
+
+impl TileCache {
+    /// Returns the tile and its texture, uploads it from the `source`
+    /// if it's not cached yet or was evicted.
+    pub fn get_or_load(
+        &mut self,
+        ctx: &egui::Context,
+        owner: &TextureOwner,
+        key: TileKey,
+        source: &TileSource,
+        request: &TextureRequest,
+    ) -> (&Tile, egui::TextureHandle) {
+        let name = key.texture_name(&request.client);
+        let cached = self
+            .tiles
+            .contains_key(&key)
+            .then(|| TextureBudget::get(ctx, owner, &name))
+            .flatten();
+        let texture_handle = cached.unwrap_or_else(|| {
+            trace!("Loading tile {key:?} for {}", request.client);
+            let (tile, image) = Self::load(&name, key, source);
+            self.tiles.insert(key, tile);
+            TextureBudget::load(
+                ctx,
+                owner,
+                name,
+                image,
+                request.texture_options.unwrap_or_default(),
+            )
+        });
+        (&self.tiles[&key], texture_handle)
+    }
+
+    fn load(texture_name: &str, key: TileKey, source: &TileSource) -> (Tile, egui::ColorImage) {
+        let region = tile_region(key, source.size());
+        let [x, y] = region.padded_min;
+        let [width, height] = region.padded_size;
+        let image = source.read(x, y, width, height);
+        let tile = Tile {
+            texture_name: texture_name.to_string(),
+            uv: region.uv,
+            texel_rect: region.texel_rect,
+        };
+        (tile, to_egui_image(&image))
+    }
+
+    /// Frees all tiles, e.g. when the appearance or the pyramid levels changed.
+    pub fn clear(&mut self, ctx: &egui::Context, owner: &TextureOwner) {
+        for (_, tile) in self.tiles.drain() {
+            TextureBudget::free(ctx, owner, &tile.texture_name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(min: [f32; 2], max: [f32; 2]) -> egui::Rect {
+        egui::Rect::from_min_max(min.into(), max.into())
+    }
+
+    fn key(column: u32, row: u32) -> TileKey {
+        TileKey {
+            level: 0,
+            column,
+            row,
+        }
+    }
+
+    #[test]
+    fn tiles_covering_rects() {
+        let size = [1200, 600];
+        // The whole level, the last column and row are partial tiles.
+        assert_eq!(
+            tiles_covering(rect([0., 0.], [1200., 600.]), size),
+            (0..3, 0..2)
+        );
+        // Tile borders.
+        assert_eq!(
+            tiles_covering(rect([512., 0.], [1024., 512.]), size),
+            (1..2, 0..1)
+        );
+        assert_eq!(
+            tiles_covering(rect([511., 0.], [1025., 1.]), size),
+            (0..3, 0..1)
+        );
+        // Within one tile.
+        assert_eq!(
+            tiles_covering(rect([600., 10.], [700., 20.]), size),
+            (1..2, 0..1)
+        );
+    }
+
+    #[test]
+    fn tiles_covering_rects_outside_of_level() {
+        let size = [1200, 600];
+        // Partially outside, clamped to the level.
+        assert_eq!(
+            tiles_covering(rect([-100., -100.], [100., 100.]), size),
+            (0..1, 0..1)
+        );
+        assert_eq!(
+            tiles_covering(rect([1000., 500.], [5000., 5000.]), size),
+            (1..3, 0..2)
+        );
+        // Completely outside.
+        let (columns, rows) = tiles_covering(rect([-200., -200.], [-100., -100.]), size);
+        assert!(columns.is_empty() && rows.is_empty());
+        let (columns, rows) = tiles_covering(rect([2000., 700.], [3000., 800.]), size);
+        assert!(columns.is_empty() && rows.is_empty());
+    }
+
+    #[test]
+    fn interior_tile_is_padded_on_all_sides() {
+        let region = tile_region(key(1, 1), [2000, 2000]);
+        assert_eq!(region.padded_min, [511, 511]);
+        assert_eq!(region.padded_size, [514, 514]);
+        assert_eq!(region.texel_rect, rect([512., 512.], [1024., 1024.]));
+        assert_eq!(
+            region.uv,
+            rect([1. / 514., 1. / 514.], [513. / 514., 513. / 514.])
+        );
+    }
+
+    #[test]
+    fn edge_tiles_are_padded_towards_neighbors() {
+        // First tile: no padding at the left and top border of the level.
+        let region = tile_region(key(0, 0), [2000, 2000]);
+        assert_eq!(region.padded_min, [0, 0]);
+        assert_eq!(region.padded_size, [513, 513]);
+        assert_eq!(region.uv, rect([0., 0.], [512. / 513., 512. / 513.]));
+
+        // Partial last tiles: no padding at the right and bottom border of the level.
+        let region = tile_region(key(2, 1), [1200, 600]);
+        assert_eq!(region.padded_min, [1023, 511]);
+        assert_eq!(region.padded_size, [177, 89]);
+        assert_eq!(region.texel_rect, rect([1024., 512.], [1200., 600.]));
+        assert_eq!(region.uv, rect([1. / 177., 1. / 89.], [1., 1.]));
+    }
+
+    #[test]
+    fn single_tile_is_not_padded() {
+        let region = tile_region(key(0, 0), [100, 50]);
+        assert_eq!(region.padded_min, [0, 0]);
+        assert_eq!(region.padded_size, [100, 50]);
+        assert_eq!(region.texel_rect, rect([0., 0.], [100., 50.]));
+        assert_eq!(region.uv, rect([0., 0.], [1., 1.]));
+    }
+
+    #[test]
+    fn tiles_are_read_with_padding() {
+        let level = image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(600, 3, |x, _| {
+            image::Rgba([(x % 256) as u8, 0, 0, 255])
+        }));
+        let (tile, image) = TileCache::load("tile", key(1, 0), &TileSource::Level(&level));
+        assert_eq!(image.size, [89, 3]);
+        // The first texel is the padding from the previous tile.
+        assert_eq!(image.pixels[0].r(), (511 % 256) as u8);
+        assert_eq!(tile.texel_rect, rect([512., 0.], [600., 3.]));
+        assert_eq!(tile.texture_name, "tile");
+    }
+}